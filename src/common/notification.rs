@@ -16,45 +16,117 @@ use std::error::Error as StdError;
 
 use crate::common::json::{self, Value};
 use crate::meta::alert::{self, Alert};
+use crate::meta::saved_searches::SavedSearch;
 use crate::service::db;
 
 pub async fn send_notification(
     alert: &Alert,
     trigger: &alert::Trigger,
+) -> Result<(), Box<dyn StdError>> {
+    if let Err(err) = send(alert, trigger, &[]).await {
+        log::error!("Error sending notification {:?}", err);
+    }
+    Ok(())
+}
+
+/// Sends a notification about a single series of a PromQL alert. Fails if
+/// the destination doesn't accept it.
+///
+/// Besides the placeholders of [`send_notification`], the template can use
+/// `{alert_state}`, `{alert_value}`, `{alert_labels}`, `{alert_active_at}`
+/// and `{labels.<name>}` for each label of the series.
+pub async fn send_series_notification(
+    alert: &Alert,
+    trigger: &alert::Trigger,
+    series: &alert::SeriesState,
+) -> Result<(), Box<dyn StdError>> {
+    let mut vars = vec![
+        ("alert_state".to_string(), series.state.to_string()),
+        ("alert_value".to_string(), series.value.to_string()),
+        (
+            "alert_labels".to_string(),
+            alert::series_key(&series.labels),
+        ),
+        ("alert_active_at".to_string(), series.active_at.to_string()),
+    ];
+    for (name, value) in &series.labels {
+        vars.push((format!("labels.{name}"), value.clone()));
+    }
+    send(alert, trigger, &vars).await
+}
+
+/// Sends the report of a scheduled saved search to the destination of its
+/// schedule.
+///
+/// The template sees a report as an alert of type `Report` named after the
+/// saved search. Besides, it can use `{report_name}`, `{report_sql}`,
+/// `{report_rows}`, `{report_table}` and `{report_link}`.
+pub async fn send_report_notification(
+    org_id: &str,
+    search: &SavedSearch,
+    vars: &[(String, String)],
+) -> Result<(), Box<dyn StdError>> {
+    let Some(schedule) = search.schedule.as_ref() else {
+        return Ok(());
+    };
+    let curr_ts = chrono::Utc::now().timestamp_micros();
+    let stream_name = crate::meta::sql::Sql::new(&search.sql)
+        .map(|sql| sql.source)
+        .unwrap_or_default();
+    let replacements = [
+        ("stream_name", stream_name),
+        ("org_name", org_id.to_string()),
+        ("alert_name", search.name.clone()),
+        ("alert_type", "Report".to_string()),
+        ("timestamp", curr_ts.to_string()),
+    ];
+    let mut vars = vars.to_vec();
+    vars.push(("report_name".to_string(), search.name.clone()));
+    vars.push(("report_sql".to_string(), search.sql.clone()));
+    send_to_destination(org_id, &schedule.destination, &replacements, &vars).await
+}
+
+async fn send(
+    alert: &Alert,
+    trigger: &alert::Trigger,
+    vars: &[(String, String)],
 ) -> Result<(), Box<dyn StdError>> {
     let alert_type = match &trigger.is_ingest_time {
         true => "Real time",
         false => "Scheduled",
     };
     let curr_ts = chrono::Utc::now().timestamp_micros();
-    match db::alerts::destinations::get(&trigger.org, &alert.destination).await {
+    let mut replacements = vec![
+        ("stream_name", trigger.stream.clone()),
+        ("org_name", trigger.org.clone()),
+        ("alert_name", trigger.alert_name.clone()),
+        ("alert_type", alert_type.to_string()),
+        ("timestamp", curr_ts.to_string()),
+    ];
+    // Replace contextual information with values if any from alert
+    if let Some(attrs) = alert.context_attributes.as_ref() {
+        for (key, value) in attrs {
+            replacements.push((key.as_str(), value.clone()));
+        }
+    }
+    send_to_destination(&trigger.org, &alert.destination, &replacements, vars).await
+}
+
+/// Renders the template of a destination and sends it. Fails if the
+/// destination is missing or doesn't accept the notification.
+async fn send_to_destination(
+    org_id: &str,
+    destination: &str,
+    replacements: &[(&str, String)],
+    vars: &[(String, String)],
+) -> Result<(), Box<dyn StdError>> {
+    match db::alerts::destinations::get(org_id, destination).await {
         Ok(dest) => match dest {
             Some(local_dest) => {
-                let body = local_dest.template.unwrap().body;
-                let resp_str = json::to_string(&body).unwrap();
-
-                let mut resp = resp_str
-                    .replace("{stream_name}", &trigger.stream)
-                    .replace("{org_name}", &trigger.org)
-                    .replace("{alert_name}", &trigger.alert_name)
-                    .replace("{alert_type}", alert_type)
-                    .replace("{timestamp}", &curr_ts.to_string());
-
-                // Replace contextual information with values if any from alert
-                if alert.context_attributes.is_some() {
-                    for (key, value) in alert.context_attributes.as_ref().unwrap() {
-                        resp = resp.replace(&format!("{{{key}}}"), value)
-                    }
-                }
-
-                let msg: Value = json::from_str(&resp).unwrap();
-                let msg: Value = match &msg {
-                    Value::String(obj) => match json::from_str(obj) {
-                        Ok(obj) => obj,
-                        Err(_) => msg,
-                    },
-                    _ => msg,
+                let Some(template) = local_dest.template else {
+                    return Err(format!("destination {destination} has no template").into());
                 };
+                let msg = render(&template.body, replacements, vars)?;
                 let client = if local_dest.skip_tls_verify {
                     reqwest::Client::builder()
                         .danger_accept_invalid_certs(true)
@@ -79,23 +151,58 @@ pub async fn send_notification(
                     }
                 };
 
-                let resp = req.json(&msg).send().await;
-                match resp {
-                    Ok(resp) => {
-                        if !resp.status().is_success() {
-                            log::error!("Notification sent error: {:?}", resp.bytes().await);
-                        }
-                    }
-                    Err(err) => log::error!("Notification sending error {:?}", err),
+                let resp = req.json(&msg).send().await?;
+                if !resp.status().is_success() {
+                    let status = resp.status();
+                    log::error!("Notification sent error: {:?}", resp.bytes().await);
+                    return Err(format!("destination {destination} returned {status}").into());
                 }
+                Ok(())
             }
 
-            None => log::error!("Destination Not found"),
+            None => Err(format!("destination {destination} not found").into()),
         },
-        Err(err) => log::error!("Error sending notification {:?}", err),
+        Err(err) => Err(err.into()),
     }
+}
 
-    Ok(())
+/// Renders a template. A template in a string is parsed as JSON after the
+/// values are inserted, the values are escaped as they may contain quotes. A
+/// string which isn't JSON is a template in plain text.
+fn render(
+    body: &Value,
+    replacements: &[(&str, String)],
+    vars: &[(String, String)],
+) -> Result<Value, Box<dyn StdError>> {
+    let values = replacements
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .chain(
+            vars.iter()
+                .map(|(key, value)| (key.as_str(), value.as_str())),
+        )
+        .collect::<Vec<_>>();
+    let template = match body {
+        Value::String(body) => body.clone(),
+        body => json::to_string(body)?,
+    };
+
+    let mut resp = template.clone();
+    for (key, value) in values.iter() {
+        let value = json::to_string(value)?;
+        resp = resp.replace(&format!("{{{key}}}"), &value[1..value.len() - 1]);
+    }
+    match json::from_str(&resp) {
+        Ok(msg) => Ok(msg),
+        Err(_) if body.is_string() => {
+            let mut resp = template;
+            for (key, value) in values {
+                resp = resp.replace(&format!("{{{key}}}"), value);
+            }
+            Ok(Value::String(resp))
+        }
+        Err(e) => Err(e.into()),
+    }
 }
 
 #[cfg(test)]
@@ -134,6 +241,7 @@ mod tests {
             last_sent_at: chrono::Utc::now().timestamp_micros(),
             count: 1,
             is_ingest_time: true,
+            series: Default::default(),
         };
         let alert = Alert {
             name: "testAlert".to_string(),
@@ -151,6 +259,7 @@ mod tests {
                 query_context: None,
                 uses_zo_fn: false,
                 query_fn: None,
+                profile: false,
             }),
             condition: Condition {
                 column: "Country".to_string(),
@@ -165,8 +274,35 @@ mod tests {
             destination: "testDest".to_string(),
             is_real_time: true,
             context_attributes: None,
+            kind: alert::AlertKind::Sql,
+            promql: None,
+            for_duration: 0,
         };
 
         send_notification(&alert, &obj).await.unwrap();
     }
+
+    #[test]
+    fn test_render() {
+        let replacements = [("alert_name", "a\"b".to_string())];
+        let vars = [("rows".to_string(), "x\ny".to_string())];
+
+        let body = json::json!({ "text": "{alert_name}: {rows}" });
+        assert_eq!(
+            render(&body, &replacements, &vars).unwrap(),
+            json::json!({ "text": "a\"b: x\ny" })
+        );
+
+        let body = Value::String(r#"{"text": "{alert_name}"}"#.to_string());
+        assert_eq!(
+            render(&body, &replacements, &vars).unwrap(),
+            json::json!({ "text": "a\"b" })
+        );
+
+        let body = Value::String("alert {alert_name}".to_string());
+        assert_eq!(
+            render(&body, &replacements, &vars).unwrap(),
+            Value::String("alert a\"b".to_string())
+        );
+    }
 }
@@ -38,6 +38,7 @@ impl From<meta::search::Request> for cluster_rpc::SearchRequest {
             query_context: req.query.query_context.unwrap_or("".to_string()),
             uses_zo_fn: req.query.uses_zo_fn,
             query_fn: req.query.query_fn.unwrap_or("".to_string()),
+            profile: req.query.profile,
         };
 
         let job = cluster_rpc::Job {
@@ -64,6 +65,33 @@ impl From<meta::search::Request> for cluster_rpc::SearchRequest {
     }
 }
 
+impl From<cluster_rpc::SearchProfile> for meta::search::NodeProfile {
+    fn from(req: cluster_rpc::SearchProfile) -> Self {
+        meta::search::NodeProfile {
+            node: "".to_string(),
+            took: 0,
+            file_list_took: req.file_list_took as usize,
+            cache_took: req.cache_took as usize,
+            scan_took: req.scan_took as usize,
+            wal_took: req.wal_took as usize,
+            merge_took: req.merge_took as usize,
+            files: req.files as usize,
+            files_pruned: (req.files - req.files_scanned) as usize,
+            files_scanned: req.files_scanned as usize,
+            wal_files: req.wal_files as usize,
+            plans: req
+                .plans
+                .into_iter()
+                .map(|plan| meta::search::Plan {
+                    source: plan.source,
+                    name: plan.name,
+                    plan: plan.plan,
+                })
+                .collect(),
+        }
+    }
+}
+
 impl From<&meta::common::FileMeta> for cluster_rpc::FileMeta {
     fn from(req: &meta::common::FileMeta) -> Self {
         cluster_rpc::FileMeta {
@@ -193,6 +221,7 @@ mod test {
                 query_context: None,
                 uses_zo_fn: false,
                 query_fn: None,
+                profile: false,
             },
             aggs: HashMap::new(),
             encoding: "base64".into(),
@@ -0,0 +1,55 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::web;
+use opentelemetry_proto::tonic::collector::logs::v1::{
+    logs_service_server::LogsService, ExportLogsServiceRequest, ExportLogsServiceResponse,
+};
+use tonic::{codegen::*, Response, Status};
+
+use crate::infra::config::CONFIG;
+use crate::service::logs::otlp::handle_logs_request;
+
+#[derive(Default)]
+pub struct LogsServer {}
+#[async_trait]
+impl LogsService for LogsServer {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportLogsServiceRequest>,
+    ) -> Result<tonic::Response<ExportLogsServiceResponse>, tonic::Status> {
+        let metadata = request.metadata().clone();
+        let org_id = match metadata
+            .get(&CONFIG.grpc.org_header_key)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(org_id) => org_id,
+            None => {
+                return Err(Status::invalid_argument(format!(
+                    "Please specify organization id with header key '{}' ",
+                    &CONFIG.grpc.org_header_key
+                )))
+            }
+        };
+        let stream_name = metadata
+            .get(&CONFIG.grpc.stream_header_key)
+            .and_then(|v| v.to_str().ok());
+
+        let thread_id = web::Data::new(0);
+        match handle_logs_request(org_id, stream_name, request.into_inner(), thread_id).await {
+            Ok(_) => Ok(Response::new(ExportLogsServiceResponse {})),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+}
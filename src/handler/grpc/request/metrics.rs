@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use actix_web::web;
 use opentelemetry::global;
 use std::time::UNIX_EPOCH;
 use tonic::{Request, Response, Status};
@@ -20,11 +21,11 @@ use tracing_opentelemetry::OpenTelemetrySpanExt;
 use crate::common::file::{get_file_contents, get_file_meta, scan_files};
 use crate::handler::grpc::cluster_rpc::{
     metrics_server::Metrics, MetricsQueryRequest, MetricsQueryResponse, MetricsWalFile,
-    MetricsWalFileRequest, MetricsWalFileResponse,
+    MetricsWalFileRequest, MetricsWalFileResponse, MetricsWriteRequest, MetricsWriteResponse,
 };
 use crate::infra::{config::CONFIG, errors, file_lock, ider, metrics};
 use crate::meta;
-use crate::service::promql::search as SearchService;
+use crate::service::{metrics::prom, promql::search as SearchService};
 
 pub struct Querier;
 
@@ -159,4 +160,23 @@ impl Metrics for Querier {
 
         Ok(Response::new(resp))
     }
+    /// Ingests a remote write request forwarded by a node without the
+    /// ingester role, e.g. the results of recording rules.
+    #[tracing::instrument(name = "grpc:metrics:write", skip_all)]
+    async fn write(
+        &self,
+        req: Request<MetricsWriteRequest>,
+    ) -> Result<Response<MetricsWriteResponse>, Status> {
+        let req = req.into_inner();
+        let resp = prom::remote_write(&req.org_id, web::Data::new(0), req.data.into())
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+        if !resp.status().is_success() {
+            return Err(Status::internal(format!(
+                "remote write error: {}",
+                resp.status()
+            )));
+        }
+        Ok(Response::new(MetricsWriteResponse {}))
+    }
 }
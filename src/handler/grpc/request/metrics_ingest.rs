@@ -0,0 +1,53 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::web;
+use opentelemetry_proto::tonic::collector::metrics::v1::{
+    metrics_service_server::MetricsService, ExportMetricsServiceRequest,
+    ExportMetricsServiceResponse,
+};
+use tonic::{codegen::*, Response, Status};
+
+use crate::infra::config::CONFIG;
+use crate::service::metrics::otlp::handle_metrics_request;
+
+#[derive(Default)]
+pub struct MetricsIngester {}
+#[async_trait]
+impl MetricsService for MetricsIngester {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportMetricsServiceRequest>,
+    ) -> Result<tonic::Response<ExportMetricsServiceResponse>, tonic::Status> {
+        let metadata = request.metadata().clone();
+        let org_id = match metadata
+            .get(&CONFIG.grpc.org_header_key)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(org_id) => org_id,
+            None => {
+                return Err(Status::invalid_argument(format!(
+                    "Please specify organization id with header key '{}' ",
+                    &CONFIG.grpc.org_header_key
+                )))
+            }
+        };
+
+        let thread_id = web::Data::new(0);
+        match handle_metrics_request(org_id, thread_id, request.into_inner()).await {
+            Ok(_) => Ok(Response::new(ExportMetricsServiceResponse {})),
+            Err(e) => Err(Status::internal(e.to_string())),
+        }
+    }
+}
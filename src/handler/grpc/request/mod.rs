@@ -15,7 +15,9 @@
 use opentelemetry::propagation::Extractor;
 
 pub mod event;
+pub mod logs;
 pub mod metrics;
+pub mod metrics_ingest;
 pub mod search;
 pub mod traces;
 
@@ -19,6 +19,7 @@ use tracing_opentelemetry::OpenTelemetrySpanExt;
 use crate::handler::grpc::cluster_rpc::search_server::Search;
 use crate::handler::grpc::cluster_rpc::SearchRequest;
 use crate::handler::grpc::cluster_rpc::SearchResponse;
+use crate::handler::grpc::cluster_rpc::{CancelRequest, CancelResponse};
 use crate::infra::errors;
 use crate::infra::metrics;
 use crate::service::search as SearchService;
@@ -67,4 +68,15 @@ impl Search for Searcher {
 
         Ok(Response::new(result))
     }
+
+    #[tracing::instrument(name = "grpc:search:cancel", skip_all)]
+    async fn cancel(
+        &self,
+        req: Request<CancelRequest>,
+    ) -> Result<Response<CancelResponse>, Status> {
+        let canceled = SearchService::grpc::cancel(&req.get_ref().job);
+        Ok(Response::new(CancelResponse {
+            canceled: canceled as i32,
+        }))
+    }
 }
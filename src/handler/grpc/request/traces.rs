@@ -44,12 +44,30 @@ impl TraceService for TraceServer {
             return Err(Status::invalid_argument(msg));
         }
 
+        let stream_name = metadata
+            .get(&CONFIG.grpc.stream_header_key)
+            .and_then(|v| v.to_str().ok());
+
         let thread_id = Arc::new(0);
-        let resp = handle_trace_request(org_id.unwrap().to_str().unwrap(), thread_id, in_req).await;
-        if resp.is_ok() {
-            return Ok(Response::new(ExportTraceServiceResponse {}));
-        } else {
-            Err(Status::internal(resp.err().unwrap().to_string()))
+        let resp = handle_trace_request(
+            org_id.unwrap().to_str().unwrap(),
+            stream_name,
+            thread_id,
+            in_req,
+        )
+        .await;
+        match resp {
+            Ok(resp) if resp.status().is_success() => {
+                Ok(Response::new(ExportTraceServiceResponse {}))
+            }
+            // e.g. not an ingester, or a stream is being deleted
+            Ok(resp) => {
+                let body = actix_web::body::to_bytes(resp.into_body())
+                    .await
+                    .unwrap_or_default();
+                Err(Status::internal(String::from_utf8_lossy(&body)))
+            }
+            Err(e) => Err(Status::internal(e.to_string())),
         }
     }
 }
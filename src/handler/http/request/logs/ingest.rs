@@ -12,10 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use actix_web::{post, web, HttpResponse};
+use actix_web::{http, post, web, HttpRequest, HttpResponse};
 use std::io::Error;
 
-use crate::{meta::ingestion::KinesisFHRequest, service::logs};
+use crate::handler::http::request::traces::{CONTENT_TYPE_JSON, CONTENT_TYPE_PROTO};
+use crate::infra::config::CONFIG;
+use crate::{
+    meta::{self, ingestion::KinesisFHRequest},
+    service::logs,
+};
 
 /** _bulk ES compatible ingestion API */
 #[utoipa::path(
@@ -127,3 +132,52 @@ pub async fn handle_kinesis_request(
     let (org_id, stream_name) = path.into_inner();
     logs::kinesis_firehose::process(&org_id, &stream_name, post_data.into_inner(), thread_id).await
 }
+
+/** OTLP logs ingestion API */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Logs",
+    operation_id = "LogsIngestionOtlp",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream-name" = Option<String>, Header, description = "Stream name, the default stream if not set"),
+    ),
+    request_body(content = String, description = "ExportLogsServiceRequest", content_type = "application/x-protobuf"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = IngestionResponse, example = json!({"code": 200,"status": [{"name": "default","successful": 3,"failed": 0}]})),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/v1/logs")]
+pub async fn otlp_logs_write(
+    org_id: web::Path<String>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let stream_name = req
+        .headers()
+        .get(&CONFIG.grpc.stream_header_key)
+        .and_then(|v| v.to_str().ok());
+    let content_type = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if content_type.eq(CONTENT_TYPE_PROTO) {
+        logs::otlp::logs_proto(&org_id, stream_name, body, thread_id).await
+    } else if content_type.eq(CONTENT_TYPE_JSON) {
+        logs::otlp::logs_json(&org_id, stream_name, body, thread_id).await
+    } else {
+        Ok(
+            HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                "Bad Request".to_string(),
+            )),
+        )
+    }
+}
@@ -20,6 +20,7 @@ pub mod logs;
 pub mod lookup_table;
 pub mod organization;
 pub mod prom;
+pub mod saved_searches;
 pub mod search;
 pub mod status;
 pub mod stream;
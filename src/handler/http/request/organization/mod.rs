@@ -12,16 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use actix_web::{get, put, web, HttpResponse, Result};
+use actix_web::{delete, get, http, put, web, HttpResponse, Result};
 use actix_web_httpauth::extractors::basic::BasicAuth;
 use std::collections::HashSet;
 use std::io::Error;
 
 use crate::common::auth::is_root_user;
 use crate::infra::config::USERS;
+use crate::meta::http::HttpResponse as MetaHttpResponse;
 use crate::meta::organization::{
-    OrgDetails, OrgUser, OrganizationResponse, PasscodeResponse, CUSTOM, DEFAULT_ORG, THRESHOLD,
+    OrgDetails, OrgSearchLimits, OrgUser, OrganizationResponse, PasscodeResponse, CUSTOM,
+    DEFAULT_ORG, THRESHOLD,
 };
+use crate::meta::traces::TraceRoutes;
 use crate::service::organization::get_passcode;
 use crate::service::organization::{self, update_passcode};
 
@@ -182,3 +185,203 @@ async fn update_user_passcode(
     let passcode = update_passcode(org_id, user_id).await;
     Ok(HttpResponse::Ok().json(PasscodeResponse { data: passcode }))
 }
+
+/** GetOrganizationSearchLimits */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Organizations",
+    operation_id = "GetOrganizationSearchLimits",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = OrgSearchLimits),
+    )
+)]
+#[get("/{org_id}/settings/search_limits")]
+async fn get_search_limits(org_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    let limits = organization::get_search_limits(&org_id.into_inner());
+    Ok(HttpResponse::Ok().json(limits))
+}
+
+/** SetOrganizationSearchLimits */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Organizations",
+    operation_id = "SetOrganizationSearchLimits",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    request_body(content = OrgSearchLimits, description = "Search limits", content_type = "application/json"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 403, description="Forbidden", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[put("/{org_id}/settings/search_limits")]
+async fn set_search_limits(
+    credentials: BasicAuth,
+    org_id: web::Path<String>,
+    limits: web::Json<OrgSearchLimits>,
+) -> Result<HttpResponse, Error> {
+    if !is_root_user(credentials.user_id()) {
+        return Ok(forbidden());
+    }
+    match organization::set_search_limits(&org_id.into_inner(), &limits.into_inner()).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Search limits saved".to_string(),
+        ))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e.to_string(),
+        ))),
+    }
+}
+
+/** DeleteOrganizationSearchLimits */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Organizations",
+    operation_id = "DeleteOrganizationSearchLimits",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 403, description="Forbidden", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/settings/search_limits")]
+async fn delete_search_limits(
+    credentials: BasicAuth,
+    org_id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if !is_root_user(credentials.user_id()) {
+        return Ok(forbidden());
+    }
+    match organization::delete_search_limits(&org_id.into_inner()).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Search limits deleted".to_string(),
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+/** GetOrganizationTraceRoutes */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Organizations",
+    operation_id = "GetOrganizationTraceRoutes",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = TraceRoutes),
+    )
+)]
+#[get("/{org_id}/settings/trace_routes")]
+async fn get_trace_routes(org_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    let routes = organization::get_trace_routes(&org_id.into_inner());
+    Ok(HttpResponse::Ok().json(routes))
+}
+
+/** SetOrganizationTraceRoutes */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Organizations",
+    operation_id = "SetOrganizationTraceRoutes",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    request_body(content = TraceRoutes, description = "Trace routes", content_type = "application/json", example = json!({
+        "routes": [
+            {
+                "stream_name": "payments",
+                "attributes": {"service.name": "payment-*"}
+            }
+        ]
+    })),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[put("/{org_id}/settings/trace_routes")]
+async fn set_trace_routes(
+    org_id: web::Path<String>,
+    routes: web::Json<TraceRoutes>,
+) -> Result<HttpResponse, Error> {
+    match organization::set_trace_routes(&org_id.into_inner(), &routes.into_inner()).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Trace routes saved".to_string(),
+        ))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e.to_string(),
+        ))),
+    }
+}
+
+/** DeleteOrganizationTraceRoutes */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Organizations",
+    operation_id = "DeleteOrganizationTraceRoutes",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+      ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/settings/trace_routes")]
+async fn delete_trace_routes(org_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    match organization::delete_trace_routes(&org_id.into_inner()).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Trace routes deleted".to_string(),
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+fn forbidden() -> HttpResponse {
+    HttpResponse::Forbidden().json(MetaHttpResponse::error(
+        http::StatusCode::FORBIDDEN.into(),
+        "Only the root user can change the search limits".to_string(),
+    ))
+}
@@ -18,11 +18,14 @@ use std::io::Error;
 
 use crate::{
     common::time::{parse_milliseconds, parse_str_to_timestamp_micros},
-    infra::errors,
+    handler::http::request::traces::{CONTENT_TYPE_JSON, CONTENT_TYPE_PROTO},
+    infra::{config, errors},
     meta::{self, http::HttpResponse as MetaHttpResponse},
     service::{metrics, promql},
 };
 
+pub mod rules;
+
 /** prometheus remote-write endpoint for metrics */
 #[utoipa::path(
     context_path = "/api",
@@ -59,6 +62,90 @@ pub async fn remote_write(
     }
 }
 
+/** OTLP metrics endpoint, as protobuf or JSON, the data points are stored as prometheus series */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PostMetrics",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = String, description = "ExportMetricsServiceRequest", content_type = "application/x-protobuf"),
+    responses(
+        (status = 200, description="Success", content_type = "application/x-protobuf", body = String),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/v1/metrics")]
+pub async fn otlp_metrics_write(
+    org_id: web::Path<String>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let content_type = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if content_type.eq(CONTENT_TYPE_PROTO) {
+        metrics::otlp::metrics_proto(&org_id, thread_id, body).await
+    } else if content_type.eq(CONTENT_TYPE_JSON) {
+        metrics::otlp::metrics_json(&org_id, thread_id, body).await
+    } else {
+        Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            "Bad Request".to_string(),
+        )))
+    }
+}
+
+/** prometheus remote-read endpoint for metrics */
+// refer: https://prometheus.io/docs/prometheus/latest/querying/remote_read_api/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusRemoteRead",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = String, description = "prometheus ReadRequest", content_type = "application/x-protobuf"),
+    responses(
+        (status = 200, description="Success", content_type = "application/x-protobuf", body = String),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/prometheus/api/v1/read")]
+pub async fn remote_read(
+    org_id: web::Path<String>,
+    req: HttpRequest,
+    body: actix_web::web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let content_type = req
+        .headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if content_type == "application/x-protobuf" {
+        metrics::remote_read::remote_read(&org_id, body).await
+    } else {
+        Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            "Bad Request".to_string(),
+        )))
+    }
+}
+
 /** prometheus instant queries */
 // refer: https://prometheus.io/docs/prometheus/latest/querying/api/#instant-queries
 #[utoipa::path(
@@ -634,6 +721,290 @@ pub async fn label_values(
     )
 }
 
+/// The Prometheus version whose HTTP API we are compatible with. Grafana
+/// reads it from `buildinfo` to decide which features of the Prometheus data
+/// source to enable.
+const PROMETHEUS_COMPATIBLE_VERSION: &str = "2.40.0";
+
+/** prometheus build information */
+// refer: https://prometheus.io/docs/prometheus/latest/querying/api/#build-information
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusBuildInfo",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "status": "success",
+            "data": {
+                "version": "2.40.0",
+                "revision": "c1dd6e9ac8b1d9bd1a3f8a5a9cbfa4ec3aa31c0d",
+                "branch": "v0.4.0",
+                "buildUser": "zincobserve",
+                "buildDate": "2023-05-01T00:00:00Z",
+                "goVersion": ""
+            }
+        })),
+    )
+)]
+#[get("/{org_id}/prometheus/api/v1/status/buildinfo")]
+pub async fn buildinfo(_org_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    let info = meta::prom::BuildInfo {
+        version: PROMETHEUS_COMPATIBLE_VERSION.to_string(),
+        revision: config::COMMIT_HASH.to_string(),
+        branch: config::VERSION.to_string(),
+        build_user: "zincobserve".to_string(),
+        build_date: config::BUILD_DATE.to_string(),
+        go_version: String::new(),
+    };
+    Ok(HttpResponse::Ok().json(promql::ApiFuncResponse::ok(info)))
+}
+
+/** prometheus alerting and recording rules */
+// refer: https://prometheus.io/docs/prometheus/latest/querying/api/#rules
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusRules",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("type" = Option<String>, Query, description = "alert | record: return only the alerting rules or only the recording rules"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "status": "success",
+            "data": {
+                "groups": [
+                    {
+                        "name": "node",
+                        "file": "",
+                        "rules": [
+                            {
+                                "type": "recording",
+                                "name": "job:up:sum",
+                                "query": "sum by (job) (up)",
+                                "labels": {},
+                                "health": "ok",
+                                "evaluationTime": 0.0,
+                                "lastEvaluation": "2023-05-01T00:00:00.000000Z"
+                            }
+                        ],
+                        "interval": 60.0,
+                        "evaluationTime": 0.012,
+                        "lastEvaluation": "2023-05-01T00:00:00.000000Z"
+                    }
+                ]
+            }
+        })),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/api/v1/rules")]
+pub async fn list_rules(
+    org_id: web::Path<String>,
+    req: web::Query<meta::prom::RequestRules>,
+) -> Result<HttpResponse, Error> {
+    let (with_alerts, with_records) = match req.typ.as_deref() {
+        None | Some("") => (true, true),
+        Some("alert") => (true, false),
+        Some("record") => (false, true),
+        Some(typ) => {
+            return Ok(HttpResponse::BadRequest().json(
+                promql::ApiFuncResponse::<()>::err_bad_data(format!(
+                    "unsupported type: {typ:?}, must be \"alert\" or \"record\""
+                )),
+            ))
+        }
+    };
+    Ok(
+        match metrics::rules::get_rules(&org_id, with_alerts, with_records).await {
+            Ok(resp) => HttpResponse::Ok().json(promql::ApiFuncResponse::ok(resp)),
+            Err(err) => {
+                log::error!("get_rules failed: {err}");
+                HttpResponse::InternalServerError()
+                    .json(promql::ApiFuncResponse::<()>::err_internal(err.to_string()))
+            }
+        },
+    )
+}
+
+/** prometheus active alerts */
+// refer: https://prometheus.io/docs/prometheus/latest/querying/api/#alerts
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusAlerts",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "status": "success",
+            "data": {
+                "alerts": [
+                    {
+                        "labels": {
+                            "instance": "localhost:9100"
+                        },
+                        "annotations": {
+                            "summary": "high load"
+                        },
+                        "state": "firing",
+                        "activeAt": "2023-05-01T00:00:00.000000Z",
+                        "value": "4.2"
+                    }
+                ]
+            }
+        })),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/api/v1/alerts")]
+pub async fn list_alerts(org_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    Ok(match metrics::rules::get_alerts(&org_id).await {
+        Ok(resp) => HttpResponse::Ok().json(promql::ApiFuncResponse::ok(resp)),
+        Err(err) => {
+            log::error!("get_alerts failed: {err}");
+            HttpResponse::InternalServerError()
+                .json(promql::ApiFuncResponse::<()>::err_internal(err.to_string()))
+        }
+    })
+}
+
+/** prometheus querying exemplars */
+// refer: https://prometheus.io/docs/prometheus/latest/querying/api/#querying-exemplars
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusQueryExemplars",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("query" = String, Query, description = "Prometheus expression query string"),
+        ("start" = Option<String>, Query, description = "<rfc3339 | unix_timestamp>: Start timestamp"),
+        ("end" = Option<String>, Query, description = "<rfc3339 | unix_timestamp>: End timestamp"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "status": "success",
+            "data": []
+        })),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/api/v1/query_exemplars")]
+pub async fn query_exemplars_get(
+    org_id: web::Path<String>,
+    req: web::Query<meta::prom::RequestQueryExemplars>,
+) -> Result<HttpResponse, Error> {
+    query_exemplars(&org_id.into_inner(), req.into_inner()).await
+}
+
+#[post("/{org_id}/prometheus/api/v1/query_exemplars")]
+pub async fn query_exemplars_post(
+    org_id: web::Path<String>,
+    req: web::Query<meta::prom::RequestQueryExemplars>,
+    web::Form(form): web::Form<meta::prom::RequestQueryExemplars>,
+) -> Result<HttpResponse, Error> {
+    let req = if form.query.is_some() {
+        form
+    } else {
+        req.into_inner()
+    };
+    query_exemplars(&org_id.into_inner(), req).await
+}
+
+/// Exemplars are not stored, so a valid request always returns an empty list.
+async fn query_exemplars(
+    _org_id: &str,
+    req: meta::prom::RequestQueryExemplars,
+) -> Result<HttpResponse, Error> {
+    if let Err(err) = parser::parse(req.query.as_deref().unwrap_or_default()) {
+        return Ok(
+            HttpResponse::BadRequest().json(promql::ApiFuncResponse::<()>::err_bad_data(format!(
+                "parse promql error: {err}"
+            ))),
+        );
+    }
+    for ts in [req.start, req.end].into_iter().flatten() {
+        if let Err(err) = parse_str_to_timestamp_micros(&ts) {
+            return Ok(HttpResponse::BadRequest()
+                .json(promql::ApiFuncResponse::<()>::err_bad_data(err.to_string())));
+        }
+    }
+    Ok(HttpResponse::Ok().json(promql::ApiFuncResponse::ok(Vec::<()>::new())))
+}
+
+/** prometheus formatting query expressions */
+// refer: https://prometheus.io/docs/prometheus/latest/querying/api/#formatting-query-expressions
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "PrometheusFormatQuery",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("query" = String, Query, description = "Prometheus expression query string"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse, example = json!({
+            "status": "success",
+            "data": "foo / bar"
+        })),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/api/v1/format_query")]
+pub async fn format_query_get(
+    _org_id: web::Path<String>,
+    req: web::Query<meta::prom::RequestFormatQuery>,
+) -> Result<HttpResponse, Error> {
+    format_query(req.into_inner())
+}
+
+#[post("/{org_id}/prometheus/api/v1/format_query")]
+pub async fn format_query_post(
+    _org_id: web::Path<String>,
+    req: web::Query<meta::prom::RequestFormatQuery>,
+    web::Form(form): web::Form<meta::prom::RequestFormatQuery>,
+) -> Result<HttpResponse, Error> {
+    let req = if form.query.is_some() {
+        form
+    } else {
+        req.into_inner()
+    };
+    format_query(req)
+}
+
+fn format_query(req: meta::prom::RequestFormatQuery) -> Result<HttpResponse, Error> {
+    Ok(
+        match parser::parse(req.query.as_deref().unwrap_or_default()) {
+            Ok(expr) => {
+                HttpResponse::Ok().json(promql::ApiFuncResponse::ok(promql::format_expr(&expr)))
+            }
+            Err(err) => HttpResponse::BadRequest().json(
+                promql::ApiFuncResponse::<()>::err_bad_data(format!("parse promql error: {err}")),
+            ),
+        },
+    )
+}
+
 fn validate_metadata_params(
     matcher: Option<String>,
     start: Option<String>,
@@ -0,0 +1,112 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{delete, get, post, web, HttpResponse};
+use std::io::Error;
+
+use crate::{meta::prom::RuleGroup, service::metrics::rules};
+
+/** CreateRuleGroup */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "SaveRuleGroup",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("group_name" = String, Path, description = "Rule group name"),
+    ),
+    request_body(content = RuleGroup, description = "Rule group data", content_type = "application/json"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/prometheus/rules/{group_name}")]
+pub async fn save_rule_group(
+    path: web::Path<(String, String)>,
+    group: web::Json<RuleGroup>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    rules::save_rule_group(org_id, name, group.into_inner()).await
+}
+
+/** ListRuleGroups */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "ListRuleGroups",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = RuleGroupList),
+    )
+)]
+#[get("/{org_id}/prometheus/rules")]
+pub async fn list_rule_groups(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    let org_id = path.into_inner();
+    rules::list_rule_groups(org_id).await
+}
+
+/** GetRuleGroup */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "GetRuleGroup",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("group_name" = String, Path, description = "Rule group name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = RuleGroup),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/prometheus/rules/{group_name}")]
+pub async fn get_rule_group(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    rules::get_rule_group(org_id, name).await
+}
+
+/** DeleteRuleGroup */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Metrics",
+    operation_id = "DeleteRuleGroup",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("group_name" = String, Path, description = "Rule group name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/prometheus/rules/{group_name}")]
+pub async fn delete_rule_group(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    rules::delete_rule_group(org_id, name).await
+}
@@ -0,0 +1,189 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{delete, get, http, post, put, web, HttpResponse};
+use actix_web_httpauth::extractors::basic::BasicAuth;
+use std::io::Error;
+
+use crate::meta::{http::HttpResponse as MetaHttpResponse, saved_searches::SavedSearch};
+use crate::service::saved_searches;
+
+/** SaveSearch */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "SaveSearch",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("name" = String, Path, description = "Saved search name"),
+    ),
+    request_body(content = SavedSearch, description = "Saved search", content_type = "application/json", example = json!({
+        "sql": "select * from k8s where level = 'error'",
+        "duration": 1440,
+        "size": 100,
+        "schedule": {
+            "frequency": 1440,
+            "destination": "oncall",
+            "format": "csv"
+        }
+    })),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SavedSearch),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[put("/{org_id}/saved_searches/{name}")]
+pub async fn save_search(
+    credentials: BasicAuth,
+    path: web::Path<(String, String)>,
+    search: web::Json<SavedSearch>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    saved_searches::save(&org_id, &name, credentials.user_id(), search.into_inner()).await
+}
+
+/** ListSavedSearches */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "ListSavedSearches",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SavedSearchList),
+    )
+)]
+#[get("/{org_id}/saved_searches")]
+pub async fn list_saved_searches(path: web::Path<String>) -> Result<HttpResponse, Error> {
+    saved_searches::list(&path.into_inner()).await
+}
+
+/** GetSavedSearch */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "GetSavedSearch",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("name" = String, Path, description = "Saved search name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SavedSearch),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/saved_searches/{name}")]
+pub async fn get_saved_search(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    saved_searches::get(&org_id, &name).await
+}
+
+/** DeleteSavedSearch */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "DeleteSavedSearch",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("name" = String, Path, description = "Saved search name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/saved_searches/{name}")]
+pub async fn delete_saved_search(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    saved_searches::delete(&org_id, &name).await
+}
+
+/** RunSavedSearch */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "RunSavedSearch",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("name" = String, Path, description = "Saved search name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SearchResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/saved_searches/{name}/_run")]
+pub async fn run_saved_search(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name) = path.into_inner();
+    saved_searches::run(&org_id, &name).await
+}
+
+/** GetSavedSearchReport */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "GetSavedSearchReport",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("name" = String, Path, description = "Saved search name"),
+        ("report" = String, Path, description = "Report file name, as in the link of the notification"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "text/csv"),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/saved_searches/{name}/reports/{report}")]
+pub async fn get_report(path: web::Path<(String, String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, name, report) = path.into_inner();
+    match saved_searches::get_report(&org_id, &name, &report).await {
+        Ok(Some(data)) => Ok(HttpResponse::Ok()
+            .content_type("text/csv")
+            .insert_header((
+                http::header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}_{report}\""),
+            ))
+            .body(data)),
+        Ok(None) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            "Report not found".to_string(),
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
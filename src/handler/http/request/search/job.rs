@@ -0,0 +1,238 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::http::{self, StatusCode};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use std::collections::HashMap;
+use std::io::Error;
+
+use crate::common::http::get_stream_type_from_request;
+use crate::common::json;
+use crate::infra::errors;
+use crate::meta::http::HttpResponse as MetaHttpResponse;
+use crate::meta::{self, StreamType};
+use crate::service::search::job;
+
+/** SubmitSearchJob*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "SubmitSearchJob",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = SearchRequest, description = "Search query", content_type = "application/json", example = json!({
+        "query": {
+            "sql": "select count(*) as num from k8s where code >= 500",
+            "start_time": 1672531200000000i64,
+            "end_time": 1680307200000000i64,
+            "sql_mode": "full"
+        }
+    })),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SearchJob),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/_search_jobs")]
+pub async fn submit_job(
+    org_id: web::Path<String>,
+    in_req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let query = web::Query::<HashMap<String, String>>::from_query(in_req.query_string()).unwrap();
+    let stream_type = match get_stream_type_from_request(&query) {
+        Ok(v) => v.unwrap_or(StreamType::Logs),
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let mut req: meta::search::Request = match json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    if let Err(e) = req.decode() {
+        return Ok(bad_request(e));
+    }
+
+    match job::submit(&org_id, stream_type, &req).await {
+        Ok(job) => Ok(HttpResponse::Ok().json(job)),
+        Err(errors::Error::ErrorCode(code)) => {
+            Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error_code(code)))
+        }
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+/** ListSearchJobs*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "ListSearchJobs",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SearchJobList),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/_search_jobs")]
+pub async fn list_jobs(org_id: web::Path<String>) -> Result<HttpResponse, Error> {
+    match job::list(&org_id.into_inner()).await {
+        Ok(list) => Ok(HttpResponse::Ok().json(meta::search::JobList { list })),
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+/** GetSearchJob*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "GetSearchJob",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("job_id" = String, Path, description = "Search job id"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SearchJob),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/_search_jobs/{job_id}")]
+pub async fn get_job(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, job_id) = path.into_inner();
+    match job::get(&org_id, &job_id).await {
+        Ok(Some(job)) => Ok(HttpResponse::Ok().json(job)),
+        Ok(None) => Ok(not_found(&job_id)),
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+/** GetSearchJobResult*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "GetSearchJobResult",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("job_id" = String, Path, description = "Search job id"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SearchResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/_search_jobs/{job_id}/result")]
+pub async fn get_job_result(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, job_id) = path.into_inner();
+    match job::get_result(&org_id, &job_id).await {
+        Ok(Some(data)) => Ok(HttpResponse::Ok()
+            .content_type(http::header::ContentType::json())
+            .body(data)),
+        Ok(None) => Ok(not_found(&job_id)),
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+/** CancelSearchJob*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "CancelSearchJob",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("job_id" = String, Path, description = "Search job id"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SearchJob),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/_search_jobs/{job_id}/cancel")]
+pub async fn cancel_job(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, job_id) = path.into_inner();
+    match job::cancel(&org_id, &job_id).await {
+        Ok(Some(job)) => Ok(HttpResponse::Ok().json(job)),
+        Ok(None) => Ok(not_found(&job_id)),
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+/** DeleteSearchJob*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "DeleteSearchJob",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("job_id" = String, Path, description = "Search job id"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/_search_jobs/{job_id}")]
+pub async fn delete_job(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, job_id) = path.into_inner();
+    match job::delete(&org_id, &job_id).await {
+        Ok(true) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            StatusCode::OK.into(),
+            "search job deleted".to_string(),
+        ))),
+        Ok(false) => Ok(not_found(&job_id)),
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+fn bad_request(error: impl ToString) -> HttpResponse {
+    HttpResponse::BadRequest().json(MetaHttpResponse::error(
+        StatusCode::BAD_REQUEST.into(),
+        error.to_string(),
+    ))
+}
+
+fn not_found(job_id: &str) -> HttpResponse {
+    HttpResponse::NotFound().json(MetaHttpResponse::error(
+        StatusCode::NOT_FOUND.into(),
+        format!("search job {job_id} not found"),
+    ))
+}
+
+fn internal_error(error: impl ToString) -> HttpResponse {
+    HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+        StatusCode::INTERNAL_SERVER_ERROR.into(),
+        error.to_string(),
+    ))
+}
@@ -12,8 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use actix_web::http::StatusCode;
-use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use actix_web::http::{self, StatusCode};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
 use chrono::Duration;
 use std::collections::HashMap;
 use std::io::Error;
@@ -27,6 +27,9 @@ use crate::infra::{errors, metrics};
 use crate::meta::http::HttpResponse as MetaHttpResponse;
 use crate::meta::{self, StreamType};
 use crate::service::search as SearchService;
+use crate::service::search::export::ExportFormat;
+
+pub mod job;
 
 /** SearchStreamData*/
 #[utoipa::path(
@@ -38,6 +41,9 @@ use crate::service::search as SearchService;
     ),
     params(
         ("org_id" = String, Path, description = "Organization name"),
+        ("cursor" = Option<bool>, Query, description = "Open a cursor to fetch the next pages"),
+        ("cursor_ttl" = Option<u64>, Query, description = "Seconds the cursor is kept between pages"),
+        ("profile" = Option<bool>, Query, description = "Return the physical plans and the timings of the search"),
     ),
     request_body(content = SearchRequest, description = "Search query", content_type = "application/json", example = json!({
         "query": {
@@ -106,6 +112,14 @@ pub async fn search(
         Ok(v) => v.unwrap_or(StreamType::Logs),
         Err(e) => return Ok(bad_request(e)),
     };
+    let cursor = query
+        .get("cursor")
+        .map_or(false, |v| v.to_lowercase() == "true");
+    let cursor_ttl = match query.get("cursor_ttl").map(|v| v.parse::<u64>()) {
+        Some(Ok(v)) => Some(v),
+        Some(Err(e)) => return Ok(bad_request(e)),
+        None => None,
+    };
 
     // handle encoding for query and aggs
     let mut req: meta::search::Request = match json::from_slice(&body) {
@@ -117,6 +131,12 @@ pub async fn search(
     }
 
     req.query.query_fn = req.query.query_fn.and_then(|v| base64::decode(&v).ok());
+    if query
+        .get("profile")
+        .map_or(false, |v| v.to_lowercase() == "true")
+    {
+        req.query.profile = true;
+    }
 
     for fn_name in functions::get_all_transform_keys(&org_id).await {
         if req.query.sql.contains(&fn_name) {
@@ -125,13 +145,13 @@ pub async fn search(
         }
     }
 
-    // get a local search queue lock
-    let locker = SearchService::QUEUE_LOCKER.clone();
-    let _locker = locker.lock().await;
-    let took_wait = start.elapsed().as_millis() as usize;
-
     // do search
-    match SearchService::search(&org_id, stream_type, &req).await {
+    let res = if cursor {
+        SearchService::cursor::open(&org_id, stream_type, &req, cursor_ttl).await
+    } else {
+        SearchService::search(&org_id, stream_type, &req).await
+    };
+    match res {
         Ok(mut res) => {
             let time = start.elapsed().as_secs_f64();
             metrics::HTTP_RESPONSE_TIME
@@ -152,7 +172,7 @@ pub async fn search(
                     stream_type.to_string().as_str(),
                 ])
                 .inc();
-            res.set_local_took(start.elapsed().as_millis() as usize, took_wait);
+            res.set_local_took(start.elapsed().as_millis() as usize, 0);
             Ok(HttpResponse::Ok().json(res))
         }
         Err(err) => {
@@ -188,6 +208,274 @@ pub async fn search(
     }
 }
 
+/** SearchExport*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "SearchExport",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("format" = String, Query, description = "Export format: csv, ndjson or parquet"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of records, all by default"),
+    ),
+    request_body(content = SearchRequest, description = "Search query", content_type = "application/json", example = json!({
+        "query": {
+            "sql": "select * from k8s ",
+            "start_time": 1675182660872049i64,
+            "end_time": 1675185660872049i64
+        }
+    })),
+    responses(
+        (status = 200, description="Success", content_type = "text/csv"),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/_search/export")]
+pub async fn export(
+    org_id: web::Path<String>,
+    in_req: HttpRequest,
+    body: actix_web::web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let start = std::time::Instant::now();
+    let org_id = org_id.into_inner();
+    let query = web::Query::<HashMap<String, String>>::from_query(in_req.query_string()).unwrap();
+    let stream_type = match get_stream_type_from_request(&query) {
+        Ok(v) => v.unwrap_or(StreamType::Logs),
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let format = match query
+        .get("format")
+        .map_or("csv", |v| v.as_str())
+        .parse::<ExportFormat>()
+    {
+        Ok(v) => v,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let limit = match query.get("limit").map(|v| v.parse::<usize>()) {
+        Some(Ok(v)) => v,
+        Some(Err(e)) => return Ok(bad_request(e)),
+        None => 0,
+    };
+
+    let mut req: meta::search::Request = match json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    if let Err(e) = req.decode() {
+        return Ok(bad_request(e));
+    }
+
+    let res = SearchService::export::export(&org_id, stream_type, &req, format, limit).await;
+    let status = if res.is_ok() { "200" } else { "500" };
+    let time = start.elapsed().as_secs_f64();
+    metrics::HTTP_RESPONSE_TIME
+        .with_label_values(&[
+            "/_search/export",
+            status,
+            &org_id,
+            "",
+            stream_type.to_string().as_str(),
+        ])
+        .observe(time);
+    metrics::HTTP_INCOMING_REQUESTS
+        .with_label_values(&[
+            "/_search/export",
+            status,
+            &org_id,
+            "",
+            stream_type.to_string().as_str(),
+        ])
+        .inc();
+    match res {
+        Ok(stream) => Ok(HttpResponse::Ok()
+            .content_type(format.content_type())
+            .insert_header((
+                http::header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"export.{}\"", format.extension()),
+            ))
+            .streaming(stream)),
+        Err(err) => {
+            log::error!("search export error: {:?}", err);
+            Ok(match err {
+                errors::Error::ErrorCode(code) => HttpResponse::InternalServerError()
+                    .json(meta::http::HttpResponse::error_code(code)),
+                _ => HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR.into(),
+                    err.to_string(),
+                )),
+            })
+        }
+    }
+}
+
+/** SearchCursor*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "SearchCursor",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = SearchCursorRequest, description = "Search cursor", content_type = "application/json", example = json!({
+        "cursor_id": "3c4f8a8e-7a2e-4b1e-9d43-5c2f3c2b8f6e",
+        "size": 100
+    })),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SearchResponse),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/_search/cursor")]
+pub async fn search_cursor(
+    org_id: web::Path<String>,
+    body: actix_web::web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let start = std::time::Instant::now();
+    let org_id = org_id.into_inner();
+    let req: meta::search::CursorRequest = match json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Ok(bad_request(e)),
+    };
+
+    let res = SearchService::cursor::next(&org_id, &req.cursor_id, req.size).await;
+    let status = match &res {
+        Ok(_) => "200",
+        Err(errors::Error::ErrorCode(errors::ErrorCodes::SearchCursorNotFound(_))) => "404",
+        Err(_) => "500",
+    };
+    let time = start.elapsed().as_secs_f64();
+    metrics::HTTP_RESPONSE_TIME
+        .with_label_values(&["/_search/cursor", status, &org_id, "", ""])
+        .observe(time);
+    metrics::HTTP_INCOMING_REQUESTS
+        .with_label_values(&["/_search/cursor", status, &org_id, "", ""])
+        .inc();
+    match res {
+        Ok(mut res) => {
+            res.set_local_took(start.elapsed().as_millis() as usize, 0);
+            Ok(HttpResponse::Ok().json(res))
+        }
+        Err(errors::Error::ErrorCode(code @ errors::ErrorCodes::SearchCursorNotFound(_))) => {
+            Ok(HttpResponse::NotFound().json(meta::http::HttpResponse::error_code(code)))
+        }
+        Err(err) => {
+            log::error!("search cursor error: {:?}", err);
+            Ok(match err {
+                errors::Error::ErrorCode(code) => HttpResponse::InternalServerError()
+                    .json(meta::http::HttpResponse::error_code(code)),
+                _ => HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR.into(),
+                    err.to_string(),
+                )),
+            })
+        }
+    }
+}
+
+/** CloseSearchCursor*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "CloseSearchCursor",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("cursor_id" = String, Path, description = "Cursor id"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = HttpResponse),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[delete("/{org_id}/_search/cursor/{cursor_id}")]
+pub async fn close_cursor(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, cursor_id) = path.into_inner();
+    if SearchService::cursor::close(&org_id, &cursor_id).await {
+        Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            StatusCode::OK.into(),
+            "search cursor closed".to_string(),
+        )))
+    } else {
+        Ok(HttpResponse::NotFound().json(MetaHttpResponse::error_code(
+            errors::ErrorCodes::SearchCursorNotFound(cursor_id),
+        )))
+    }
+}
+
+/** SearchCost*/
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Search",
+    operation_id = "SearchCost",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+    ),
+    request_body(content = SearchRequest, description = "Search query", content_type = "application/json", example = json!({
+        "query": {
+            "sql": "select * from k8s ",
+            "start_time": 1675182660872049i64,
+            "end_time": 1675185660872049i64,
+            "from": 0,
+            "size": 10
+        }
+    })),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = SearchCost),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/_search/cost")]
+pub async fn search_cost(
+    org_id: web::Path<String>,
+    in_req: HttpRequest,
+    body: actix_web::web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let query = web::Query::<HashMap<String, String>>::from_query(in_req.query_string()).unwrap();
+    let stream_type = match get_stream_type_from_request(&query) {
+        Ok(v) => v.unwrap_or(StreamType::Logs),
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let mut req: meta::search::Request = match json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    if let Err(e) = req.decode() {
+        return Ok(bad_request(e));
+    }
+
+    match SearchService::cost::estimate(&org_id, stream_type, &req).await {
+        Ok(cost) => Ok(HttpResponse::Ok().json(cost)),
+        Err(err) => {
+            log::error!("search cost error: {:?}", err);
+            Ok(match err {
+                errors::Error::ErrorCode(code) => {
+                    HttpResponse::BadRequest().json(meta::http::HttpResponse::error_code(code))
+                }
+                _ => HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+                    StatusCode::INTERNAL_SERVER_ERROR.into(),
+                    err.to_string(),
+                )),
+            })
+        }
+    }
+}
+
 /** SearchAround*/
 #[utoipa::path(
     context_path = "/api",
@@ -277,9 +565,6 @@ pub async fn around(
         .get("size")
         .map_or(10, |v| v.parse::<usize>().unwrap_or(0));
 
-    // get a local search queue lock
-    let locker = SearchService::QUEUE_LOCKER.clone();
-    let _locker = locker.lock().await;
     let query_context = if uses_fn {
         Some(around_sql.clone())
     } else {
@@ -300,6 +585,7 @@ pub async fn around(
             query_context: query_context.clone(),
             uses_zo_fn: uses_fn,
             query_fn: query_fn.clone(),
+            profile: false,
         },
         aggs: HashMap::new(),
         encoding: meta::search::RequestEncoding::Empty,
@@ -352,6 +638,7 @@ pub async fn around(
             query_context,
             uses_zo_fn: uses_fn,
             query_fn: query_fn.clone(),
+            profile: false,
         },
         aggs: HashMap::new(),
         encoding: meta::search::RequestEncoding::Empty,
@@ -510,10 +797,6 @@ pub async fn values(
         end_time = chrono::Utc::now().timestamp_micros();
     }
 
-    // get a local search queue lock
-    let locker = SearchService::QUEUE_LOCKER.clone();
-    let _locker = locker.lock().await;
-
     // search
     let mut req = meta::search::Request {
         query: meta::search::Query {
@@ -528,6 +811,7 @@ pub async fn values(
             query_context,
             uses_zo_fn: uses_fn,
             query_fn: query_fn.clone(),
+            profile: false,
         },
         aggs: HashMap::new(),
         encoding: meta::search::RequestEncoding::Empty,
@@ -0,0 +1,199 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The Jaeger query HTTP API over a traces stream, use
+//! `/api/{org_id}/{stream_name}/jaeger` as the URL of a Jaeger data source.
+
+use actix_web::{get, web, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+use std::io::Error;
+
+use crate::infra::errors;
+use crate::meta::traces::TraceSearch;
+use crate::service::traces::{
+    jaeger::{self, parse_duration, span_kind_name, Response},
+    query,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchParams {
+    pub service: Option<String>,
+    pub operation: Option<String>,
+    pub tags: Option<String>,
+    pub min_duration: Option<String>,
+    pub max_duration: Option<String>,
+    pub limit: Option<usize>,
+    /// Microseconds.
+    pub start: Option<i64>,
+    /// Microseconds.
+    pub end: Option<i64>,
+    pub lookback: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationParams {
+    pub service: Option<String>,
+    pub span_kind: Option<String>,
+}
+
+#[get("/{org_id}/{stream_name}/jaeger/api/services")]
+pub async fn services(path: web::Path<(String, String)>) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    match query::services(&org_id, &stream_name, 0, 0).await {
+        Ok(services) => {
+            let total = services.len();
+            Ok(HttpResponse::Ok().json(Response::new(services, total)))
+        }
+        Err(e) => Ok(error::<Vec<String>>(e)),
+    }
+}
+
+#[get("/{org_id}/{stream_name}/jaeger/api/services/{service}/operations")]
+pub async fn service_operations(
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name, service) = path.into_inner();
+    match query::operations(&org_id, &stream_name, Some(&service), 0, 0).await {
+        Ok(operations) => {
+            let mut names = operations
+                .into_iter()
+                .map(|operation| operation.name)
+                .collect::<Vec<_>>();
+            names.dedup();
+            let total = names.len();
+            Ok(HttpResponse::Ok().json(Response::new(names, total)))
+        }
+        Err(e) => Ok(error::<Vec<String>>(e)),
+    }
+}
+
+#[get("/{org_id}/{stream_name}/jaeger/api/operations")]
+pub async fn operations(
+    path: web::Path<(String, String)>,
+    params: web::Query<OperationParams>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    match query::operations(&org_id, &stream_name, params.service.as_deref(), 0, 0).await {
+        Ok(operations) => {
+            let operations = operations
+                .into_iter()
+                .map(|operation| jaeger::Operation {
+                    span_kind: span_kind_name(&operation.span_kind).to_string(),
+                    name: operation.name,
+                })
+                .filter(|operation| {
+                    params
+                        .span_kind
+                        .as_ref()
+                        .map_or(true, |kind| kind.is_empty() || kind == &operation.span_kind)
+                })
+                .collect::<Vec<_>>();
+            let total = operations.len();
+            Ok(HttpResponse::Ok().json(Response::new(operations, total)))
+        }
+        Err(e) => Ok(error::<Vec<jaeger::Operation>>(e)),
+    }
+}
+
+#[get("/{org_id}/{stream_name}/jaeger/api/traces")]
+pub async fn search_traces(
+    path: web::Path<(String, String)>,
+    params: web::Query<SearchParams>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    let params = params.into_inner();
+    let tags = match query::parse_tags(params.tags.as_deref().unwrap_or_default()) {
+        Ok(tags) => tags,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let mut durations = [None, None];
+    for (i, duration) in [&params.min_duration, &params.max_duration]
+        .into_iter()
+        .enumerate()
+    {
+        let Some(duration) = duration.as_deref().filter(|v| !v.is_empty()) else {
+            continue;
+        };
+        match parse_duration(duration) {
+            Some(v) => durations[i] = Some(v),
+            None => return Ok(bad_request(format!("Invalid duration: {duration}"))),
+        }
+    }
+    let end_time = params.end.unwrap_or_else(|| Utc::now().timestamp_micros());
+    let start_time = match params.start {
+        Some(start) => start,
+        None => match params.lookback.as_deref().and_then(parse_duration) {
+            Some(lookback) => end_time - lookback,
+            // the search looks back a day
+            None => 0,
+        },
+    };
+    let search = TraceSearch {
+        service: params.service.filter(|v| !v.is_empty()),
+        operation: params.operation.filter(|v| !v.is_empty()),
+        min_duration: durations[0],
+        max_duration: durations[1],
+        tags,
+        start_time,
+        end_time,
+        size: params.limit.filter(|v| *v > 0).unwrap_or(20),
+    };
+    match query::search_traces(&org_id, &stream_name, &search).await {
+        Ok(traces) => {
+            let traces = traces
+                .iter()
+                .map(|(trace_id, spans)| jaeger::trace(trace_id, spans))
+                .collect::<Vec<_>>();
+            let total = traces.len();
+            Ok(HttpResponse::Ok().json(Response::new(traces, total)))
+        }
+        Err(e) => Ok(error::<Vec<jaeger::Trace>>(e)),
+    }
+}
+
+#[get("/{org_id}/{stream_name}/jaeger/api/traces/{trace_id}")]
+pub async fn get_trace(
+    path: web::Path<(String, String, String)>,
+    params: web::Query<SearchParams>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name, trace_id) = path.into_inner();
+    // the trace is searched in the day before the end, unless a start is given
+    let start_time = params.start.unwrap_or_default();
+    let end_time = params.end.unwrap_or_else(|| Utc::now().timestamp_micros());
+    match query::get_trace(&org_id, &stream_name, &trace_id, start_time, end_time).await {
+        Ok(Some(spans)) => {
+            let trace = jaeger::trace(&trace_id, &spans);
+            Ok(HttpResponse::Ok().json(Response::new(vec![trace], 1)))
+        }
+        Ok(None) => Ok(
+            HttpResponse::NotFound().json(Response::<Vec<jaeger::Trace>>::error(
+                404,
+                "trace not found".to_string(),
+            )),
+        ),
+        Err(e) => Ok(error::<Vec<jaeger::Trace>>(e)),
+    }
+}
+
+fn bad_request(msg: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(Response::<Vec<jaeger::Trace>>::error(400, msg))
+}
+
+fn error<T: serde::Serialize + Default>(err: errors::Error) -> HttpResponse {
+    log::error!("jaeger query error: {:?}", err);
+    HttpResponse::InternalServerError().json(Response::<T>::error(500, err.to_string()))
+}
@@ -12,10 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use actix_web::{http, post, web, HttpRequest, HttpResponse};
+use actix_web::{get, http, post, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
 use std::io::Error;
 
-use crate::{meta, service::traces::otlp_http};
+use crate::infra::{config::CONFIG, errors};
+use crate::meta::{
+    self,
+    traces::{OperationList, ServiceList, TraceList, TraceSearch},
+};
+use crate::service::traces::{otlp_http, query, span_metrics};
+
+pub mod jaeger;
 
 pub const CONTENT_TYPE_JSON: &str = "application/json";
 pub const CONTENT_TYPE_PROTO: &str = "application/x-protobuf";
@@ -25,6 +33,10 @@ pub const CONTENT_TYPE_PROTO: &str = "application/x-protobuf";
     context_path = "/api",
     tag = "Traces",
     operation_id = "PostTraces",
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream-name" = Option<String>, Header, description = "Traces stream name, by default the trace routes of the organization choose the stream"),
+    ),
     request_body(content = String, description = "ExportTraceServiceRequest", content_type = "application/x-protobuf"),
     responses(
         (status = 200, description="Success", content_type = "application/json", body = IngestionResponse, example = json!({"code": 200})),
@@ -39,11 +51,81 @@ pub async fn traces_write(
     body: actix_web::web::Bytes,
 ) -> Result<HttpResponse, Error> {
     let org_id = org_id.into_inner();
+    let stream_name = req
+        .headers()
+        .get(&CONFIG.grpc.stream_header_key)
+        .and_then(|v| v.to_str().ok());
+    write(&org_id, stream_name, thread_id, &req, body).await
+}
+
+/** OTLP traces ingestion API */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "TracesIngestionOtlp",
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream-name" = Option<String>, Header, description = "Traces stream name, by default the trace routes of the organization choose the stream"),
+    ),
+    request_body(content = String, description = "ExportTraceServiceRequest", content_type = "application/x-protobuf"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = IngestionResponse, example = json!({"code": 200})),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/v1/traces")]
+pub async fn otlp_traces_write(
+    org_id: web::Path<String>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    body: actix_web::web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let org_id = org_id.into_inner();
+    let stream_name = req
+        .headers()
+        .get(&CONFIG.grpc.stream_header_key)
+        .and_then(|v| v.to_str().ok());
+    write(&org_id, stream_name, thread_id, &req, body).await
+}
+
+/** TracesIngestToStream */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "PostTracesToStream",
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Traces stream name"),
+    ),
+    request_body(content = String, description = "ExportTraceServiceRequest", content_type = "application/x-protobuf"),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = IngestionResponse, example = json!({"code": 200})),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[post("/{org_id}/{stream_name}/traces")]
+pub async fn traces_write_stream(
+    path: web::Path<(String, String)>,
+    thread_id: web::Data<usize>,
+    req: HttpRequest,
+    body: actix_web::web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    write(&org_id, Some(&stream_name), thread_id, &req, body).await
+}
+
+async fn write(
+    org_id: &str,
+    stream_name: Option<&str>,
+    thread_id: web::Data<usize>,
+    req: &HttpRequest,
+    body: actix_web::web::Bytes,
+) -> Result<HttpResponse, Error> {
     let content_type = req.headers().get("Content-Type").unwrap().to_str().unwrap();
     if content_type.eq(CONTENT_TYPE_PROTO) {
-        otlp_http::traces_proto(&org_id, thread_id.into_inner(), body).await
+        otlp_http::traces_proto(org_id, stream_name, thread_id.into_inner(), body).await
     } else if content_type.eq(CONTENT_TYPE_JSON) {
-        otlp_http::traces_json(&org_id, thread_id.into_inner(), body).await
+        otlp_http::traces_json(org_id, stream_name, thread_id.into_inner(), body).await
     } else {
         Ok(
             HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
@@ -53,3 +135,264 @@ pub async fn traces_write(
         )
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct TimeRange {
+    #[serde(default)]
+    pub start_time: i64,
+    #[serde(default)]
+    pub end_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    pub service: Option<String>,
+    pub operation: Option<String>,
+    pub min_duration: Option<i64>,
+    pub max_duration: Option<i64>,
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub start_time: i64,
+    #[serde(default)]
+    pub end_time: i64,
+    #[serde(default = "default_size")]
+    pub size: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OperationParams {
+    pub service: Option<String>,
+    #[serde(default)]
+    pub start_time: i64,
+    #[serde(default)]
+    pub end_time: i64,
+}
+
+fn default_size() -> usize {
+    20
+}
+
+/** SearchTraces */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "SearchTraces",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Traces stream name"),
+        ("service" = Option<String>, Query, description = "Service name of a span"),
+        ("operation" = Option<String>, Query, description = "Operation name of a span"),
+        ("min_duration" = Option<i64>, Query, description = "Minimum duration of a span, in microseconds"),
+        ("max_duration" = Option<i64>, Query, description = "Maximum duration of a span, in microseconds"),
+        ("tags" = Option<String>, Query, description = "Attributes of a span, a JSON object or space separated key=value pairs"),
+        ("start_time" = Option<i64>, Query, description = "start time, default is a day ago"),
+        ("end_time" = Option<i64>, Query, description = "end time"),
+        ("size" = Option<usize>, Query, description = "Maximum number of traces, the latest ones, default 20, capped by ZO_QUERY_TRACE_MAX_SIZE"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = TraceList),
+        (status = 400, description="Failure", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/traces/_search")]
+pub async fn search_traces(
+    path: web::Path<(String, String)>,
+    params: web::Query<SearchParams>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    let params = params.into_inner();
+    let tags = match query::parse_tags(params.tags.as_deref().unwrap_or_default()) {
+        Ok(tags) => tags,
+        Err(e) => return Ok(bad_request(e)),
+    };
+    let search = TraceSearch {
+        service: params.service,
+        operation: params.operation,
+        min_duration: params.min_duration,
+        max_duration: params.max_duration,
+        tags,
+        start_time: params.start_time,
+        end_time: params.end_time,
+        size: params.size,
+    };
+    match query::search_traces(&org_id, &stream_name, &search).await {
+        Ok(traces) => Ok(HttpResponse::Ok().json(TraceList {
+            list: traces
+                .iter()
+                .map(|(trace_id, spans)| query::summary(trace_id, spans))
+                .collect(),
+        })),
+        Err(e) => Ok(search_error(e)),
+    }
+}
+
+/** ListTraceServices */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "ListTraceServices",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Traces stream name"),
+        ("start_time" = Option<i64>, Query, description = "start time, default is a day ago"),
+        ("end_time" = Option<i64>, Query, description = "end time"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = ServiceList),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/traces/_services")]
+pub async fn list_services(
+    path: web::Path<(String, String)>,
+    params: web::Query<TimeRange>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    match query::services(&org_id, &stream_name, params.start_time, params.end_time).await {
+        Ok(list) => Ok(HttpResponse::Ok().json(ServiceList { list })),
+        Err(e) => Ok(search_error(e)),
+    }
+}
+
+/** ListTraceOperations */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "ListTraceOperations",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Traces stream name"),
+        ("service" = Option<String>, Query, description = "Service name, default is all the services"),
+        ("start_time" = Option<i64>, Query, description = "start time, default is a day ago"),
+        ("end_time" = Option<i64>, Query, description = "end time"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = OperationList),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/traces/_operations")]
+pub async fn list_operations(
+    path: web::Path<(String, String)>,
+    params: web::Query<OperationParams>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name) = path.into_inner();
+    match query::operations(
+        &org_id,
+        &stream_name,
+        params.service.as_deref(),
+        params.start_time,
+        params.end_time,
+    )
+    .await
+    {
+        Ok(list) => Ok(HttpResponse::Ok().json(OperationList { list })),
+        Err(e) => Ok(search_error(e)),
+    }
+}
+
+/** GetTrace */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetTrace",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("stream_name" = String, Path, description = "Traces stream name"),
+        ("trace_id" = String, Path, description = "Trace id"),
+        ("start_time" = Option<i64>, Query, description = "start time, default is a day ago"),
+        ("end_time" = Option<i64>, Query, description = "end time"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = Trace),
+        (status = 404, description="NotFound", content_type = "application/json", body = HttpResponse),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/{stream_name}/traces/{trace_id}")]
+pub async fn get_trace(
+    path: web::Path<(String, String, String)>,
+    params: web::Query<TimeRange>,
+) -> Result<HttpResponse, Error> {
+    let (org_id, stream_name, trace_id) = path.into_inner();
+    match query::get_trace(
+        &org_id,
+        &stream_name,
+        &trace_id,
+        params.start_time,
+        params.end_time,
+    )
+    .await
+    {
+        Ok(Some(spans)) => Ok(HttpResponse::Ok().json(query::build_trace(&trace_id, spans))),
+        Ok(None) => Ok(
+            HttpResponse::NotFound().json(meta::http::HttpResponse::error(
+                http::StatusCode::NOT_FOUND.into(),
+                "Trace not found".to_string(),
+            )),
+        ),
+        Err(e) => Ok(search_error(e)),
+    }
+}
+
+/** GetServiceGraph */
+#[utoipa::path(
+    context_path = "/api",
+    tag = "Traces",
+    operation_id = "GetServiceGraph",
+    security(
+        ("Authorization"= [])
+    ),
+    params(
+        ("org_id" = String, Path, description = "Organization name"),
+        ("start_time" = Option<i64>, Query, description = "start time, default is an hour ago"),
+        ("end_time" = Option<i64>, Query, description = "end time"),
+    ),
+    responses(
+        (status = 200, description="Success", content_type = "application/json", body = ServiceGraph),
+        (status = 500, description="Failure", content_type = "application/json", body = HttpResponse),
+    )
+)]
+#[get("/{org_id}/traces/_service_graph")]
+pub async fn get_service_graph(
+    org_id: web::Path<String>,
+    params: web::Query<TimeRange>,
+) -> Result<HttpResponse, Error> {
+    match span_metrics::service_graph(&org_id, params.start_time, params.end_time).await {
+        Ok(graph) => Ok(HttpResponse::Ok().json(graph)),
+        Err(e) => Ok(search_error(e)),
+    }
+}
+
+fn bad_request(msg: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
+        http::StatusCode::BAD_REQUEST.into(),
+        msg,
+    ))
+}
+
+fn search_error(err: errors::Error) -> HttpResponse {
+    log::error!("trace query error: {:?}", err);
+    match err {
+        errors::Error::ErrorCode(code) => {
+            HttpResponse::InternalServerError().json(meta::http::HttpResponse::error_code(code))
+        }
+        _ => HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+            http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+            err.to_string(),
+        )),
+    }
+}
@@ -28,11 +28,12 @@ use super::request::kv;
 use super::request::logs;
 use super::request::organization;
 use super::request::prom;
+use super::request::saved_searches;
 use super::request::search;
 use super::request::status;
 use super::request::stream;
 use super::request::syslog;
-use super::request::traces::*;
+use super::request::traces::{self, *};
 use super::request::users;
 use super::request::{alerts::*, lookup_table};
 use crate::infra::config::CONFIG;
@@ -122,7 +123,18 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(logs::ingest::bulk)
             .service(logs::ingest::multi)
             .service(logs::ingest::json)
+            .service(logs::ingest::otlp_logs_write)
             .service(search::search)
+            .service(search::search_cursor)
+            .service(search::export)
+            .service(search::close_cursor)
+            .service(search::search_cost)
+            .service(search::job::submit_job)
+            .service(search::job::list_jobs)
+            .service(search::job::get_job)
+            .service(search::job::get_job_result)
+            .service(search::job::cancel_job)
+            .service(search::job::delete_job)
             .service(search::around)
             .service(search::values)
             .service(stream::schema)
@@ -141,6 +153,8 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(users::delete)
             .service(users::add_user_to_org)
             .service(prom::remote_write)
+            .service(prom::otlp_metrics_write)
+            .service(prom::remote_read)
             .service(prom::query_get)
             .service(prom::query_post)
             .service(prom::query_range_get)
@@ -151,12 +165,42 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(prom::labels_get)
             .service(prom::labels_post)
             .service(prom::label_values)
+            .service(prom::buildinfo)
+            .service(prom::list_rules)
+            .service(prom::list_alerts)
+            .service(prom::query_exemplars_get)
+            .service(prom::query_exemplars_post)
+            .service(prom::format_query_get)
+            .service(prom::format_query_post)
+            .service(prom::rules::save_rule_group)
+            .service(prom::rules::list_rule_groups)
+            .service(prom::rules::get_rule_group)
+            .service(prom::rules::delete_rule_group)
             .service(create_dashboard)
             .service(update_dashboard)
             .service(list_dashboards)
             .service(get_dashboard)
             .service(delete_dashboard)
+            .service(saved_searches::save_search)
+            .service(saved_searches::list_saved_searches)
+            .service(saved_searches::get_saved_search)
+            .service(saved_searches::delete_saved_search)
+            .service(saved_searches::run_saved_search)
+            .service(saved_searches::get_report)
             .service(traces_write)
+            // before `traces_write_stream`, which would take `v1` as a stream
+            .service(traces::otlp_traces_write)
+            .service(traces::traces_write_stream)
+            .service(traces::search_traces)
+            .service(traces::list_services)
+            .service(traces::list_operations)
+            .service(traces::get_trace)
+            .service(traces::get_service_graph)
+            .service(traces::jaeger::services)
+            .service(traces::jaeger::service_operations)
+            .service(traces::jaeger::operations)
+            .service(traces::jaeger::search_traces)
+            .service(traces::jaeger::get_trace)
             .service(save_alert)
             .service(get_alert)
             .service(list_alerts)
@@ -167,6 +211,12 @@ pub fn get_service_routes(cfg: &mut web::ServiceConfig) {
             .service(organization::org_summary)
             .service(organization::get_user_passcode)
             .service(organization::update_user_passcode)
+            .service(organization::get_search_limits)
+            .service(organization::set_search_limits)
+            .service(organization::delete_search_limits)
+            .service(organization::get_trace_routes)
+            .service(organization::set_trace_routes)
+            .service(organization::delete_trace_routes)
             .service(organization::es::org_index)
             .service(organization::es::org_license)
             .service(organization::es::org_xpack)
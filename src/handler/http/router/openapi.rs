@@ -29,13 +29,30 @@ use crate::meta;
         request::logs::ingest::handle_kinesis_request,
         request::logs::ingest::multi,
         request::logs::ingest::json,
+        request::logs::ingest::otlp_logs_write,
         request::dashboards::create_dashboard,
         request::dashboards::update_dashboard,
         request::dashboards::list_dashboards,
         request::dashboards::get_dashboard,
         request::dashboards::delete_dashboard,
         request::search::search,
+        request::search::search_cursor,
+        request::search::export,
+        request::search::close_cursor,
+        request::search::search_cost,
+        request::search::job::submit_job,
+        request::search::job::list_jobs,
+        request::search::job::get_job,
+        request::search::job::get_job_result,
+        request::search::job::cancel_job,
+        request::search::job::delete_job,
         request::search::around,
+        request::saved_searches::save_search,
+        request::saved_searches::list_saved_searches,
+        request::saved_searches::get_saved_search,
+        request::saved_searches::delete_saved_search,
+        request::saved_searches::run_saved_search,
+        request::saved_searches::get_report,
         request::search::values,
         request::functions::list_functions,
         request::functions::update_function,
@@ -68,19 +85,43 @@ use crate::meta;
         request::organization::org_summary,
         request::organization::get_user_passcode,
         request::organization::update_user_passcode,
+        request::organization::get_search_limits,
+        request::organization::set_search_limits,
+        request::organization::delete_search_limits,
+        request::organization::get_trace_routes,
+        request::organization::set_trace_routes,
+        request::organization::delete_trace_routes,
         request::kv::get,
         request::kv::set,
         request::kv::delete,
         request::kv::list,
         request::status::healthz,
         request::prom::remote_write,
+        request::prom::otlp_metrics_write,
+        request::prom::remote_read,
         request::prom::query_get,
         request::prom::query_range_get,
         request::prom::metadata,
         request::prom::series_get,
         request::prom::labels_get,
         request::prom::label_values,
+        request::prom::buildinfo,
+        request::prom::list_rules,
+        request::prom::list_alerts,
+        request::prom::query_exemplars_get,
+        request::prom::format_query_get,
+        request::prom::rules::save_rule_group,
+        request::prom::rules::list_rule_groups,
+        request::prom::rules::get_rule_group,
+        request::prom::rules::delete_rule_group,
         request::traces::traces_write,
+        request::traces::otlp_traces_write,
+        request::traces::traces_write_stream,
+        request::traces::search_traces,
+        request::traces::list_services,
+        request::traces::list_operations,
+        request::traces::get_trace,
+        request::traces::get_service_graph,
         request::syslog::create_route,
         request::syslog::update_route,
         request::syslog::list_routes,
@@ -115,16 +156,32 @@ use crate::meta;
             meta::search::Request,
             meta::search::RequestEncoding,
             meta::search::Response,
+            meta::search::CursorRequest,
+            meta::search::Cost,
+            meta::search::Job,
+            meta::search::JobList,
+            meta::search::JobStatus,
             meta::search::ResponseTook,
+            meta::saved_searches::SavedSearch,
+            meta::saved_searches::SavedSearchList,
+            meta::saved_searches::ReportSchedule,
+            meta::saved_searches::ReportFormat,
+            meta::search::Profile,
+            meta::search::NodeProfile,
+            meta::search::Plan,
             meta::alert::Alert,
             meta::alert::AlertList,
             meta::alert::Condition,
             meta::alert::AllOperator,
+            meta::alert::AlertKind,
             meta::alert::AlertDestination,
             meta::alert::AlertDestinationResponse,
             meta::alert::AlertDestType,
             meta::alert::AlertHTTPType,
             meta::alert::DestinationTemplate,
+            meta::prom::RuleGroup,
+            meta::prom::RecordingRule,
+            meta::prom::RuleGroupList,
             meta::functions::Transform,
             meta::functions::FunctionList,
             meta::functions::StreamFunctionsList,
@@ -145,6 +202,9 @@ use crate::meta;
             meta::organization::OrgUser,
             meta::organization::IngestionPasscode,
             meta::organization::PasscodeResponse,
+            meta::organization::OrgSearchLimits,
+            meta::traces::TraceRoutes,
+            meta::traces::TraceRoute,
             request::status::HealthzResponse,
             meta::ingestion::BulkResponse,
             meta::ingestion::BulkResponseItem,
@@ -152,6 +212,14 @@ use crate::meta;
             meta::ingestion::BulkResponseError,
             meta::syslog::SyslogRoute,
             meta::syslog::SyslogRoutes,
+            meta::traces::Trace,
+            meta::traces::TraceSummary,
+            meta::traces::TraceList,
+            meta::traces::ServiceList,
+            meta::traces::Operation,
+            meta::traces::OperationList,
+            meta::traces::ServiceGraph,
+            meta::traces::ServiceGraphEdge,
          ),
     ),
     modifiers(&SecurityAddon),
@@ -168,7 +236,7 @@ use crate::meta;
         (name = "Users", description = "Users retrieval & management operations"),
         (name = "KV", description = "Key Value retrieval & management operations"),
         (name = "Metrics", description = "Metrics data ingestion operations"),
-        (name = "Traces", description = "Traces data ingestion operations"),
+        (name = "Traces", description = "Traces data ingestion & query operations"),
         (name = "Syslog Routes", description = "Syslog Routes retrieval & management operations"),
     ),
     info(
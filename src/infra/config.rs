@@ -26,8 +26,11 @@ use vector_enrichment::TableRegistry;
 use crate::common::file::get_file_meta;
 use crate::meta::alert::{AlertDestination, AlertList, DestinationTemplate, Trigger, TriggerTimer};
 use crate::meta::functions::{StreamFunctionsList, Transform};
-use crate::meta::prom::ClusterLeader;
+use crate::meta::organization::OrgSearchLimits;
+use crate::meta::prom::{ClusterLeader, RuleGroup};
+use crate::meta::saved_searches::SavedSearch;
 use crate::meta::syslog::SyslogRoute;
+use crate::meta::traces::TraceRoutes;
 use crate::meta::user::User;
 use crate::service::enrichment::StreamTable;
 
@@ -68,7 +71,11 @@ pub static TRIGGERS: Lazy<DashMap<String, Trigger>> = Lazy::new(DashMap::new);
 pub static TRIGGERS_IN_PROCESS: Lazy<DashMap<String, TriggerTimer>> = Lazy::new(DashMap::new);
 pub static ALERTS_TEMPLATES: Lazy<DashMap<String, DestinationTemplate>> = Lazy::new(DashMap::new);
 pub static ALERTS_DESTINATIONS: Lazy<DashMap<String, AlertDestination>> = Lazy::new(DashMap::new);
+pub static PROM_RULE_GROUPS: Lazy<DashMap<String, RuleGroup>> = Lazy::new(DashMap::new);
+pub static SAVED_SEARCHES: Lazy<DashMap<String, SavedSearch>> = Lazy::new(DashMap::new);
 pub static SYSLOG_ROUTES: Lazy<DashMap<String, SyslogRoute>> = Lazy::new(DashMap::new);
+pub static ORG_SEARCH_LIMITS: Lazy<DashMap<String, OrgSearchLimits>> = Lazy::new(DashMap::new);
+pub static ORG_TRACE_ROUTES: Lazy<DashMap<String, TraceRoutes>> = Lazy::new(DashMap::new);
 pub static SYSLOG_ENABLED: Lazy<Arc<RwLock<bool>>> = Lazy::new(|| Arc::new(RwLock::new(false)));
 pub static LOOKUP_TABLES: Lazy<DashMap<String, StreamTable>> = Lazy::new(DashMap::new);
 pub static LOOKUP_REGISTRY: Lazy<Arc<TableRegistry>> =
@@ -114,6 +121,9 @@ pub struct Grpc {
     pub timeout: u64,
     #[env_config(name = "ZO_GRPC_ORG_HEADER_KEY", default = "zinc-org-id")]
     pub org_header_key: String,
+    // stream of the OTLP logs and traces, also read from the headers of HTTP requests
+    #[env_config(name = "ZO_GRPC_STREAM_HEADER_KEY", default = "stream-name")]
+    pub stream_header_key: String,
     #[env_config(name = "ZO_INTERNAL_GRPC_TOKEN", default = "")]
     pub internal_grpc_token: String,
 }
@@ -153,6 +163,9 @@ pub struct Common {
     pub data_stream_dir: String,
     #[env_config(name = "ZO_BASE_URI", default = "")]
     pub base_uri: String,
+    // the address users reach the server at, used for the links in reports
+    #[env_config(name = "ZO_WEB_URL", default = "")]
+    pub web_url: String,
     #[env_config(name = "ZO_WAL_MEMORY_MODE_ENABLED", default = false)]
     pub wal_memory_mode_enabled: bool,
     #[env_config(name = "ZO_FILE_EXT_JSON", default = ".json")]
@@ -169,6 +182,10 @@ pub struct Common {
     pub feature_per_thread_lock: bool,
     #[env_config(name = "ZO_FEATURE_FULLTEXT_ON_ALL_FIELDS", default = false)]
     pub feature_fulltext_on_all_fields: bool,
+    #[env_config(name = "ZO_FEATURE_FULLTEXT_INDEX", default = true)]
+    pub feature_fulltext_index: bool,
+    #[env_config(name = "ZO_RESULT_CACHE_ENABLED", default = true)]
+    pub result_cache_enabled: bool,
     #[env_config(name = "ZO_UI_ENABLED", default = true)]
     pub ui_enabled: bool,
     #[env_config(name = "ZO_UI_SQL_BASE64_ENABLED", default = false)]
@@ -177,6 +194,10 @@ pub struct Common {
     pub metrics_dedup_enabled: bool,
     #[env_config(name = "ZO_TRACING_ENABLED", default = false)]
     pub tracing_enabled: bool,
+    // request, error and duration metrics and the service graph, generated
+    // from the ingested spans
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_ENABLED", default = false)]
+    pub traces_span_metrics_enabled: bool,
     #[env_config(
         name = "OTEL_OTLP_HTTP_ENDPOINT",
         default = "http://127.0.0.1:5080/api/nexus/traces"
@@ -215,12 +236,67 @@ pub struct Limit {
     pub file_move_thread_num: usize,
     #[env_config(name = "ZO_QUERY_THREAD_NUM", default = 0)]
     pub query_thread_num: usize,
+    #[env_config(name = "ZO_SEARCH_CURSOR_TTL", default = 300)] // seconds
+    pub search_cursor_ttl: u64,
+    #[env_config(name = "ZO_SEARCH_CURSOR_MAX_TTL", default = 3600)] // seconds
+    pub search_cursor_max_ttl: u64,
+    // records an export searches at a time
+    #[env_config(name = "ZO_EXPORT_PAGE_SIZE", default = 10000)]
+    pub export_page_size: usize,
+    #[env_config(name = "ZO_SEARCH_JOB_CHUNK_FILES", default = 500)]
+    pub search_job_chunk_files: usize,
+    #[env_config(name = "ZO_RESULT_CACHE_BUCKET", default = 300)] // seconds
+    pub result_cache_bucket: u64,
+    #[env_config(name = "ZO_RESULT_CACHE_MAX_SIZE", default = 256)] // MB
+    pub result_cache_max_size: usize,
+    #[env_config(name = "ZO_QUERY_MAX_SCAN_SIZE", default = 0)] // MB, 0 is unlimited
+    pub query_max_scan_size: u64,
+    #[env_config(name = "ZO_QUERY_MAX_TIME_RANGE", default = 0)] // hours, 0 is unlimited
+    pub query_max_time_range: i64,
+    // records searched from every stream of a JOIN or UNION query
+    #[env_config(name = "ZO_QUERY_JOIN_MAX_ROWS", default = 100000)]
+    pub query_join_max_rows: usize,
+    // traces returned by a trace search, a larger size or limit is capped
+    #[env_config(name = "ZO_QUERY_TRACE_MAX_SIZE", default = 100)]
+    pub query_trace_max_size: usize,
+    // spans returned by a trace query, in total for all the traces of a search
+    #[env_config(name = "ZO_QUERY_TRACE_MAX_SPANS", default = 10000)]
+    pub query_trace_max_spans: usize,
+    // searches a node runs at the same time, default is half of the CPU cores
+    #[env_config(name = "ZO_QUERY_MAX_CONCURRENT", default = 0)]
+    pub query_max_concurrent: usize,
+    // searches of one org a node runs at the same time, 0 is no limit
+    #[env_config(name = "ZO_QUERY_MAX_CONCURRENT_PER_ORG", default = 0)]
+    pub query_max_concurrent_per_org: usize,
+    // time range of a query of a Prometheus remote read, 0 is unlimited
+    #[env_config(name = "ZO_METRICS_REMOTE_READ_MAX_RANGE", default = 0)] // hours
+    pub metrics_remote_read_max_range: i64,
+    // series returned for a query of a Prometheus remote read, 0 is unlimited
+    #[env_config(name = "ZO_METRICS_REMOTE_READ_MAX_SERIES", default = 10000)]
+    pub metrics_remote_read_max_series: usize,
+    // the running total of a delta series without points for this long is
+    // dropped, and restarts from zero if it has points again
+    #[env_config(name = "ZO_METRICS_DELTA_IDLE", default = 3600)] // seconds
+    pub metrics_delta_idle: i64,
     #[env_config(name = "ZO_INGEST_ALLOWED_UPTO", default = 5)] // in hours - in past
     pub ingest_allowed_upto: i64,
     #[env_config(name = "ZO_METRICS_LEADER_PUSH_INTERVAL", default = 15)]
     pub metrics_leader_push_interval: u64,
     #[env_config(name = "ZO_METRICS_LEADER_ELECTION_INTERVAL", default = 30)]
     pub metrics_leader_election_interval: i64,
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_INTERVAL", default = 15)] // seconds
+    pub traces_span_metrics_interval: u64,
+    // how long a span waits for its client or server span to join an edge
+    // of the service graph
+    #[env_config(name = "ZO_TRACES_SERVICE_GRAPH_WAIT", default = 10)] // seconds
+    pub traces_service_graph_wait: i64,
+    // the counters of the series without spans for this long are dropped
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_IDLE", default = 3600)] // seconds
+    pub traces_span_metrics_idle: i64,
+    // span metrics series of an organization, the operations of the new series
+    // beyond it are counted as the `__overflow__` operation, 0 is unlimited
+    #[env_config(name = "ZO_TRACES_SPAN_METRICS_MAX_SERIES", default = 10000)]
+    pub traces_span_metrics_max_series: usize,
     #[env_config(name = "ZO_HEARTBEAT_INTERVAL", default = 30)] // in minutes
     pub hb_interval: i64,
     // no need set by environment
@@ -363,6 +439,10 @@ pub fn init() -> Config {
     if cfg.limit.file_move_thread_num == 0 {
         cfg.limit.file_move_thread_num = cpu_num;
     }
+    // HACK for query_max_concurrent equal to half of CPU core
+    if cfg.limit.query_max_concurrent == 0 {
+        cfg.limit.query_max_concurrent = std::cmp::max(cpu_num / 2, 1);
+    }
 
     // check common config
     if let Err(e) = check_common_config(&mut cfg) {
@@ -400,6 +480,18 @@ fn check_common_config(cfg: &mut Config) -> Result<(), anyhow::Error> {
     if cfg.limit.req_cols_per_record_limit == 0 {
         cfg.limit.req_cols_per_record_limit = 1000;
     }
+    if cfg.limit.traces_span_metrics_interval == 0 {
+        cfg.limit.traces_span_metrics_interval = 15;
+    }
+    if cfg.limit.traces_span_metrics_idle <= 0 {
+        cfg.limit.traces_span_metrics_idle = 3600;
+    }
+    if cfg.limit.query_trace_max_size == 0 {
+        cfg.limit.query_trace_max_size = 100;
+    }
+    if cfg.limit.metrics_delta_idle <= 0 {
+        cfg.limit.metrics_delta_idle = 3600;
+    }
 
     // HACK instance_name
     if cfg.common.instance_name.is_empty() {
@@ -460,6 +552,9 @@ fn check_path_config(cfg: &mut Config) -> Result<(), anyhow::Error> {
     if cfg.common.base_uri.ends_with('/') {
         cfg.common.base_uri = cfg.common.base_uri.trim_end_matches('/').to_string();
     }
+    if cfg.common.web_url.ends_with('/') {
+        cfg.common.web_url = cfg.common.web_url.trim_end_matches('/').to_string();
+    }
     if cfg.sled.data_dir.is_empty() {
         cfg.sled.data_dir = format!("{}db/", cfg.common.data_dir);
     }
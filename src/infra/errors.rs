@@ -63,6 +63,11 @@ pub enum ErrorCodes {
     SearchParquetFileNotFound,
     SearchFieldHasNoCompatibleDataType(String),
     SearchSQLExecuteError(String),
+    SearchCursorNotFound(String),
+    SearchCancelled(String),
+    SearchScanSizeExceeded(String),
+    SearchTimeRangeExceeded(String),
+    SearchJoinRowsExceeded(String),
 }
 
 impl std::fmt::Display for ErrorCodes {
@@ -88,6 +93,11 @@ impl ErrorCodes {
             ErrorCodes::SearchParquetFileNotFound => 20006,
             ErrorCodes::SearchFieldHasNoCompatibleDataType(_) => 20007,
             ErrorCodes::SearchSQLExecuteError(_) => 20008,
+            ErrorCodes::SearchCursorNotFound(_) => 20009,
+            ErrorCodes::SearchCancelled(_) => 20010,
+            ErrorCodes::SearchScanSizeExceeded(_) => 20011,
+            ErrorCodes::SearchTimeRangeExceeded(_) => 20012,
+            ErrorCodes::SearchJoinRowsExceeded(_) => 20013,
         }
     }
 
@@ -110,6 +120,19 @@ impl ErrorCodes {
                 format!("Search field has no compatible data type: {field}")
             }
             ErrorCodes::SearchSQLExecuteError(_) => "Search SQL execute error".to_string(),
+            ErrorCodes::SearchCursorNotFound(id) => {
+                format!("Search cursor not found or expired: {id}")
+            }
+            ErrorCodes::SearchCancelled(_) => "Search cancelled".to_string(),
+            ErrorCodes::SearchScanSizeExceeded(msg) => {
+                format!("Search scan size exceeds the limit: {msg}")
+            }
+            ErrorCodes::SearchTimeRangeExceeded(msg) => {
+                format!("Search time range exceeds the limit: {msg}")
+            }
+            ErrorCodes::SearchJoinRowsExceeded(msg) => {
+                format!("Search rows of a joined stream exceed the limit: {msg}")
+            }
         }
     }
 
@@ -124,6 +147,11 @@ impl ErrorCodes {
             ErrorCodes::SearchParquetFileNotFound => "".to_string(),
             ErrorCodes::SearchFieldHasNoCompatibleDataType(field) => field.to_owned(),
             ErrorCodes::SearchSQLExecuteError(msg) => msg.to_owned(),
+            ErrorCodes::SearchCursorNotFound(id) => id.to_owned(),
+            ErrorCodes::SearchCancelled(job) => job.to_owned(),
+            ErrorCodes::SearchScanSizeExceeded(msg) => msg.to_owned(),
+            ErrorCodes::SearchTimeRangeExceeded(msg) => msg.to_owned(),
+            ErrorCodes::SearchJoinRowsExceeded(msg) => msg.to_owned(),
         }
     }
 
@@ -138,6 +166,11 @@ impl ErrorCodes {
             ErrorCodes::SearchParquetFileNotFound => "".to_string(),
             ErrorCodes::SearchFieldHasNoCompatibleDataType(_) => "".to_string(),
             ErrorCodes::SearchSQLExecuteError(msg) => msg.to_owned(),
+            ErrorCodes::SearchCursorNotFound(_) => "".to_string(),
+            ErrorCodes::SearchCancelled(_) => "".to_string(),
+            ErrorCodes::SearchScanSizeExceeded(_) => "".to_string(),
+            ErrorCodes::SearchTimeRangeExceeded(_) => "".to_string(),
+            ErrorCodes::SearchJoinRowsExceeded(_) => "".to_string(),
         }
     }
 
@@ -185,6 +218,11 @@ impl ErrorCodes {
             20006 => Ok(ErrorCodes::SearchParquetFileNotFound),
             20007 => Ok(ErrorCodes::SearchFieldHasNoCompatibleDataType(message)),
             20008 => Ok(ErrorCodes::SearchSQLExecuteError(message)),
+            20009 => Ok(ErrorCodes::SearchCursorNotFound(message)),
+            20010 => Ok(ErrorCodes::SearchCancelled(message)),
+            20011 => Ok(ErrorCodes::SearchScanSizeExceeded(message)),
+            20012 => Ok(ErrorCodes::SearchTimeRangeExceeded(message)),
+            20013 => Ok(ErrorCodes::SearchJoinRowsExceeded(message)),
             _ => Ok(ErrorCodes::ServerInternalError(json.to_string())),
         }
     }
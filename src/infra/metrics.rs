@@ -157,6 +157,33 @@ pub static QUERY_CACHE_RECORDS: Lazy<IntGaugeVec> = Lazy::new(|| {
     )
     .expect("Metric created")
 });
+pub static QUERY_QUEUE_TIME: Lazy<HistogramVec> = Lazy::new(|| {
+    HistogramVec::new(
+        HistogramOpts::new("query_queue_time", "Querier search queue time")
+            .namespace(NAMESPACE)
+            .const_labels(create_const_labels()),
+        &["organization", "priority"],
+    )
+    .expect("Metric created")
+});
+pub static QUERY_QUEUE_PENDING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    IntGaugeVec::new(
+        Opts::new("query_queue_pending", "Querier queued searches")
+            .namespace(NAMESPACE)
+            .const_labels(create_const_labels()),
+        &["organization", "priority"],
+    )
+    .expect("Metric created")
+});
+pub static QUERY_RUNNING: Lazy<IntGaugeVec> = Lazy::new(|| {
+    IntGaugeVec::new(
+        Opts::new("query_running", "Querier running searches")
+            .namespace(NAMESPACE)
+            .const_labels(create_const_labels()),
+        &["organization"],
+    )
+    .expect("Metric created")
+});
 
 // compactor stats
 pub static COMPACT_USED_TIME: Lazy<CounterVec> = Lazy::new(|| {
@@ -401,6 +428,15 @@ fn register_metrics(registry: &Registry) {
     registry
         .register(Box::new(QUERY_CACHE_RECORDS.clone()))
         .expect("Metric registered");
+    registry
+        .register(Box::new(QUERY_QUEUE_TIME.clone()))
+        .expect("Metric registered");
+    registry
+        .register(Box::new(QUERY_QUEUE_PENDING.clone()))
+        .expect("Metric registered");
+    registry
+        .register(Box::new(QUERY_RUNNING.clone()))
+        .expect("Metric registered");
 
     // compactor stats
     registry
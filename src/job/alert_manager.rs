@@ -21,6 +21,9 @@ pub async fn run() -> Result<(), anyhow::Error> {
     if !is_alert_manager(&super::cluster::LOCAL_NODE_ROLE) {
         return Ok(());
     }
+    tokio::task::spawn(async move { run_rules().await });
+    tokio::task::spawn(async move { run_reports().await });
+
     // should run it every 10 seconds
     let mut interval = time::interval(time::Duration::from_secs(30));
     interval.tick().await; // trigger the first run
@@ -32,3 +35,27 @@ pub async fn run() -> Result<(), anyhow::Error> {
         }
     }
 }
+
+async fn run_rules() {
+    // check which rule groups are due every 10 seconds
+    let mut interval = time::interval(time::Duration::from_secs(10));
+    interval.tick().await; // trigger the first run
+    loop {
+        interval.tick().await;
+        if let Err(e) = service::metrics::rules::run().await {
+            log::error!("[RECORDING RULES] run error: {}", e);
+        }
+    }
+}
+
+async fn run_reports() {
+    // check which scheduled saved searches are due every 10 seconds
+    let mut interval = time::interval(time::Duration::from_secs(10));
+    interval.tick().await; // trigger the first run
+    loop {
+        interval.tick().await;
+        if let Err(e) = service::saved_searches::run_reports().await {
+            log::error!("[SAVED SEARCH] run reports error: {}", e);
+        }
+    }
+}
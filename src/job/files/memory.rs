@@ -20,7 +20,11 @@ use tokio::{sync::Semaphore, task, time};
 use crate::common::{json, utils::populate_file_meta};
 use crate::infra::{cluster, config::CONFIG, file_lock, metrics, storage};
 use crate::meta::{common::FileMeta, StreamType};
-use crate::service::{db, schema::schema_evolution, search::datafusion::new_writer};
+use crate::service::{
+    db,
+    schema::schema_evolution,
+    search::{self, datafusion::new_writer},
+};
 
 /// TaskResult (local file path, remote file key, file meta, stream type)
 type TaskResult = (String, String, FileMeta, StreamType);
@@ -245,6 +249,8 @@ async fn upload_file(
         compressed_size: buf_parquet.len() as u64,
     };
 
+    let fulltext_index =
+        search::index::build(org_id, stream_name, stream_type, &arrow_schema, &meta_batch).await;
     populate_file_meta(arrow_schema.clone(), vec![meta_batch], &mut file_meta).await?;
 
     schema_evolution(
@@ -273,6 +279,10 @@ async fn upload_file(
     match storage::put(&new_file_key, bytes::Bytes::from(buf_parquet)).await {
         Ok(_output) => {
             log::info!("[JOB] memory file upload succeeded: {}", new_file_key);
+            // the index is only useful once the file exists
+            if let Some(index) = fulltext_index {
+                search::index::upload(&new_file_key, index).await;
+            }
             Ok((new_file_key, file_meta, stream_type))
         }
         Err(err) => {
@@ -16,7 +16,7 @@ use crate::infra::config::{CONFIG, INSTANCE_ID, SYSLOG_ENABLED};
 use crate::infra::{cluster, ider};
 use crate::meta::organization::DEFAULT_ORG;
 use crate::meta::user::UserRequest;
-use crate::service::{db, users};
+use crate::service::{db, search, users};
 use regex::Regex;
 
 mod alert_manager;
@@ -91,8 +91,12 @@ pub async fn init() -> Result<(), anyhow::Error> {
     tokio::task::spawn(async move { db::triggers::watch().await });
     tokio::task::spawn(async move { db::alerts::templates::watch().await });
     tokio::task::spawn(async move { db::alerts::destinations::watch().await });
+    tokio::task::spawn(async move { db::prom_rules::watch().await });
+    tokio::task::spawn(async move { db::saved_searches::watch().await });
     tokio::task::spawn(async move { db::syslog::watch().await });
     tokio::task::spawn(async move { db::syslog::watch_syslog_settings().await });
+    tokio::task::spawn(async move { db::organization::watch().await });
+    tokio::task::spawn(async move { db::organization::watch_trace_routes().await });
     tokio::task::yield_now().await; // yield let other tasks run
 
     db::functions::cache()
@@ -114,6 +118,18 @@ pub async fn init() -> Result<(), anyhow::Error> {
     db::alerts::destinations::cache()
         .await
         .expect("alerts destinations cache failed");
+    db::prom_rules::cache()
+        .await
+        .expect("prometheus rule groups cache failed");
+    db::saved_searches::cache()
+        .await
+        .expect("saved searches cache failed");
+    db::organization::cache()
+        .await
+        .expect("organization search limits cache failed");
+    db::organization::cache_trace_routes()
+        .await
+        .expect("organization trace routes cache failed");
     db::syslog::cache().await.expect("syslog cache failed");
     db::syslog::cache_syslog_settings()
         .await
@@ -142,6 +158,11 @@ pub async fn init() -> Result<(), anyhow::Error> {
     tokio::task::spawn(async move { file_list::run().await });
     tokio::task::spawn(async move { prom::run().await });
     tokio::task::spawn(async move { metrics::run().await });
+    tokio::task::spawn(async move { crate::service::traces::span_metrics::run().await });
+
+    // querier run
+    tokio::task::spawn(async move { search::cursor::run().await });
+    tokio::task::spawn(async move { search::job::run_sweeper().await });
 
     // Syslog server start
     let start_syslog = *SYSLOG_ENABLED.read();
@@ -20,6 +20,8 @@ use opentelemetry::{
     KeyValue,
 };
 use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_server::LogsServiceServer;
+use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_server::MetricsServiceServer;
 use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::TraceServiceServer;
 use std::{
     collections::HashMap,
@@ -44,7 +46,10 @@ use openobserve::{
                 event_server::EventServer, metrics_server::MetricsServer,
                 search_server::SearchServer,
             },
-            request::{event::Eventer, metrics::Querier, search::Searcher, traces::TraceServer},
+            request::{
+                event::Eventer, logs::LogsServer, metrics::Querier,
+                metrics_ingest::MetricsIngester, search::Searcher, traces::TraceServer,
+            },
         },
         http::router::*,
     },
@@ -210,6 +215,8 @@ fn init_grpc_server() -> Result<(), anyhow::Error> {
         .accept_compressed(CompressionEncoding::Gzip);
     let tracer = TraceServer::default();
     let trace_svc = TraceServiceServer::new(tracer);
+    let logs_svc = LogsServiceServer::new(LogsServer::default());
+    let metrics_ingest_svc = MetricsServiceServer::new(MetricsIngester::default());
 
     tokio::task::spawn(async move {
         log::info!("starting gRPC server at {}", gaddr);
@@ -219,6 +226,8 @@ fn init_grpc_server() -> Result<(), anyhow::Error> {
             .add_service(search_svc)
             .add_service(metrics_svc)
             .add_service(trace_svc)
+            .add_service(logs_svc)
+            .add_service(metrics_ingest_svc)
             .serve(gaddr)
             .await
             .expect("gRPC server init failed");
@@ -14,7 +14,11 @@
 
 use ahash::HashMap;
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+use strum::Display;
 use utoipa::ToSchema;
 
 use super::{search::Query, StreamType};
@@ -28,6 +32,7 @@ pub struct Alert {
     pub stream: String,
     #[schema(value_type = Option<SearchQuery>)]
     pub query: Option<Query>,
+    #[serde(default)]
     pub condition: Condition,
     pub duration: i64,
     pub frequency: i64,
@@ -40,6 +45,28 @@ pub struct Alert {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream_type: Option<StreamType>,
+    #[serde(default)]
+    pub kind: AlertKind,
+    /// PromQL expression of a `promql` alert. Every series it returns is an
+    /// alert of its own.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub promql: Option<String>,
+    /// How long, in seconds, a series has to be returned by `promql` before
+    /// the alert fires. Until then it is pending.
+    #[serde(default)]
+    #[serde(rename = "for")]
+    pub for_duration: i64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    /// The first row returned by `query` is checked against `condition`.
+    #[default]
+    Sql,
+    /// Fires for every series returned by the `promql` expression.
+    Promql,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
@@ -159,6 +186,10 @@ pub struct Trigger {
     pub is_ingest_time: bool,
     #[serde(default)]
     pub stream_type: StreamType,
+    /// State of every series of a PromQL alert, keyed by its labels.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub series: BTreeMap<String, SeriesState>,
 }
 
 impl Default for Trigger {
@@ -173,10 +204,139 @@ impl Default for Trigger {
             count: 0,
             stream_type: StreamType::Logs,
             is_ingest_time: false,
+            series: BTreeMap::new(),
+        }
+    }
+}
+
+/// How long resolved series of a PromQL alert are kept, microseconds.
+const RESOLVED_RETENTION: i64 = 15 * 60 * 1_000_000;
+
+impl Trigger {
+    /// Updates the state of the series of a PromQL alert with the series
+    /// `active` at `now` and returns the keys of the series to notify about.
+    ///
+    /// Series become pending when they appear and firing once they have been
+    /// active for `for_duration`. Firing series which disappear are resolved;
+    /// pending ones are dropped. Notifications are sent when a series starts
+    /// firing, when it is resolved, and again every `repeat_interval` while
+    /// it keeps firing. All durations are in microseconds.
+    ///
+    /// A notification is returned again on the next update until it is
+    /// marked as sent with [`Trigger::mark_sent`].
+    pub fn update_series(
+        &mut self,
+        active: Vec<(BTreeMap<String, String>, f64)>,
+        now: i64,
+        for_duration: i64,
+        repeat_interval: i64,
+    ) -> Vec<String> {
+        let mut active_keys = HashSet::with_capacity(active.len());
+        for (labels, value) in active {
+            let key = series_key(&labels);
+            let series = self
+                .series
+                .entry(key.clone())
+                .or_insert_with(|| SeriesState {
+                    labels,
+                    state: AlertState::Pending,
+                    value,
+                    active_at: now,
+                    fired_at: 0,
+                    resolved_at: 0,
+                    last_sent_at: 0,
+                });
+            if series.state == AlertState::Resolved {
+                // the series is active again
+                series.state = AlertState::Pending;
+                series.active_at = now;
+                series.fired_at = 0;
+                series.resolved_at = 0;
+                series.last_sent_at = 0;
+            }
+            series.value = value;
+            if series.state == AlertState::Pending && now - series.active_at >= for_duration {
+                series.state = AlertState::Firing;
+                series.fired_at = now;
+            }
+            active_keys.insert(key);
+        }
+
+        let mut notify = Vec::new();
+        self.series.retain(|key, series| {
+            if active_keys.contains(key) {
+                if series.state == AlertState::Firing
+                    && (series.last_sent_at == 0 || now - series.last_sent_at > repeat_interval)
+                {
+                    notify.push(key.clone());
+                }
+                return true;
+            }
+            match series.state {
+                AlertState::Pending => false,
+                AlertState::Firing => {
+                    series.state = AlertState::Resolved;
+                    series.resolved_at = now;
+                    notify.push(key.clone());
+                    true
+                }
+                AlertState::Resolved => {
+                    if now - series.resolved_at >= RESOLVED_RETENTION {
+                        return false;
+                    }
+                    if series.last_sent_at < series.resolved_at {
+                        // the resolved notification wasn't sent yet
+                        notify.push(key.clone());
+                    }
+                    true
+                }
+            }
+        });
+        notify
+    }
+
+    /// Records that the notification about a series was sent at `now`.
+    pub fn mark_sent(&mut self, key: &str, now: i64) {
+        if let Some(series) = self.series.get_mut(key) {
+            series.last_sent_at = now;
         }
     }
 }
 
+/// Formats labels the way PromQL does, e.g. `{instance="a",job="b"}`.
+pub fn series_key(labels: &BTreeMap<String, String>) -> String {
+    let labels = labels
+        .iter()
+        .map(|(name, value)| format!("{name}={value:?}"))
+        .collect::<Vec<_>>();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SeriesState {
+    pub labels: BTreeMap<String, String>,
+    pub state: AlertState,
+    /// The last value of the series.
+    pub value: f64,
+    /// When the series became pending, microseconds.
+    pub active_at: i64,
+    #[serde(default)]
+    pub fired_at: i64,
+    #[serde(default)]
+    pub resolved_at: i64,
+    #[serde(default)]
+    pub last_sent_at: i64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum AlertState {
+    Pending,
+    Firing,
+    Resolved,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TriggerTimer {
     #[serde(default)]
@@ -185,7 +345,7 @@ pub struct TriggerTimer {
     pub expires_at: i64,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Condition {
     pub column: String,
@@ -333,4 +493,87 @@ mod tests {
         let row = json!({"Country":"USA","occurance": 10});
         condition.evaluate(row.as_object().unwrap().clone());
     }
+
+    #[test]
+    fn test_update_series() {
+        let labels = BTreeMap::from([("job".to_string(), "api".to_string())]);
+        let key = series_key(&labels);
+        assert_eq!(key, r#"{job="api"}"#);
+
+        let mut trigger = Trigger::default();
+        let for_duration = 60;
+        let repeat_interval = 300;
+
+        // pending
+        let notify = trigger.update_series(
+            vec![(labels.clone(), 1.0)],
+            0,
+            for_duration,
+            repeat_interval,
+        );
+        assert!(notify.is_empty());
+        assert_eq!(trigger.series[&key].state, AlertState::Pending);
+
+        // firing after `for`
+        let notify = trigger.update_series(
+            vec![(labels.clone(), 2.0)],
+            60,
+            for_duration,
+            repeat_interval,
+        );
+        assert_eq!(notify, vec![key.clone()]);
+        assert_eq!(trigger.series[&key].state, AlertState::Firing);
+        assert_eq!(trigger.series[&key].value, 2.0);
+
+        // notified again until the notification is sent
+        let notify = trigger.update_series(
+            vec![(labels.clone(), 2.0)],
+            90,
+            for_duration,
+            repeat_interval,
+        );
+        assert_eq!(notify, vec![key.clone()]);
+        trigger.mark_sent(&key, 90);
+
+        // no repeated notification within the repeat interval
+        let notify = trigger.update_series(
+            vec![(labels.clone(), 2.0)],
+            120,
+            for_duration,
+            repeat_interval,
+        );
+        assert!(notify.is_empty());
+        let notify = trigger.update_series(
+            vec![(labels.clone(), 2.0)],
+            400,
+            for_duration,
+            repeat_interval,
+        );
+        assert_eq!(notify, vec![key.clone()]);
+        trigger.mark_sent(&key, 400);
+
+        // resolved, notified again until the notification is sent
+        let notify = trigger.update_series(vec![], 460, for_duration, repeat_interval);
+        assert_eq!(notify, vec![key.clone()]);
+        assert_eq!(trigger.series[&key].state, AlertState::Resolved);
+        let notify = trigger.update_series(vec![], 480, for_duration, repeat_interval);
+        assert_eq!(notify, vec![key.clone()]);
+        assert_eq!(trigger.series[&key].resolved_at, 460);
+        trigger.mark_sent(&key, 480);
+        let notify = trigger.update_series(vec![], 500, for_duration, repeat_interval);
+        assert!(notify.is_empty());
+
+        // pending again, then dropped when it disappears
+        trigger.update_series(
+            vec![(labels.clone(), 1.0)],
+            520,
+            for_duration,
+            repeat_interval,
+        );
+        assert_eq!(trigger.series[&key].state, AlertState::Pending);
+        assert_eq!(trigger.series[&key].active_at, 520);
+        let notify = trigger.update_series(vec![], 540, for_duration, repeat_interval);
+        assert!(notify.is_empty());
+        assert!(trigger.series.is_empty());
+    }
 }
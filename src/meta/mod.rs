@@ -23,6 +23,7 @@ pub mod http;
 pub mod ingestion;
 pub mod organization;
 pub mod prom;
+pub mod saved_searches;
 pub mod search;
 pub mod service;
 pub mod sql;
@@ -70,3 +70,15 @@ pub struct IngestionPasscode {
 pub struct PasscodeResponse {
     pub data: IngestionPasscode,
 }
+
+/// The limits of the searches of an organization, which override
+/// `ZO_QUERY_MAX_SCAN_SIZE` and `ZO_QUERY_MAX_TIME_RANGE`. Zero means no limit.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct OrgSearchLimits {
+    /// The maximum size of the data a search scans, in MB.
+    #[serde(default)]
+    pub max_scan_size: Option<u64>,
+    /// The maximum time range of a search, in hours.
+    #[serde(default)]
+    pub max_time_range: Option<i64>,
+}
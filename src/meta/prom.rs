@@ -16,6 +16,7 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 use strum::Display;
+use utoipa::ToSchema;
 
 use crate::service::metrics::prom::prometheus as proto;
 
@@ -193,6 +194,151 @@ pub struct RequestLabelValues {
     pub end: Option<String>,
 }
 
+/// A group of recording rules, evaluated in order at a regular interval.
+///
+/// See <https://prometheus.io/docs/prometheus/latest/configuration/recording_rules/>
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct RuleGroup {
+    #[serde(default)]
+    pub name: String,
+    /// How often the rules of the group are evaluated, seconds.
+    #[serde(default = "default_rule_group_interval")]
+    pub interval: i64,
+    pub rules: Vec<RecordingRule>,
+}
+
+fn default_rule_group_interval() -> i64 {
+    60
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct RecordingRule {
+    /// The name of the metric the result is written to.
+    pub record: String,
+    /// PromQL expression, evaluated as an instant query.
+    pub expr: String,
+    /// Labels to add or overwrite before storing the result.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct RuleGroupList {
+    pub list: Vec<RuleGroup>,
+}
+
+/// Build information, as returned by `/api/v1/status/buildinfo`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    /// Prometheus version whose API we are compatible with. Grafana uses it
+    /// to decide which features of the data source to enable.
+    pub version: String,
+    pub revision: String,
+    pub branch: String,
+    pub build_user: String,
+    pub build_date: String,
+    pub go_version: String,
+}
+
+/// Request a list of alerting and recording rules.
+#[derive(Debug, Deserialize)]
+pub struct RequestRules {
+    /// Return only the alerting rules (`alert`) or only the recording rules
+    /// (`record`). Both are returned if left empty.
+    #[serde(rename = "type")]
+    pub typ: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuleDiscovery {
+    pub groups: Vec<RuleGroupResponse>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleGroupResponse {
+    pub name: String,
+    pub file: String,
+    pub rules: Vec<RuleResponse>,
+    /// Evaluation interval, seconds.
+    pub interval: f64,
+    /// Duration of the last evaluation, seconds.
+    pub evaluation_time: f64,
+    /// RFC 3339 timestamp of the last evaluation.
+    pub last_evaluation: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum RuleResponse {
+    #[serde(rename_all = "camelCase")]
+    Alerting {
+        name: String,
+        query: String,
+        /// `for` duration, seconds.
+        duration: f64,
+        labels: HashMap<String, String>,
+        annotations: HashMap<String, String>,
+        alerts: Vec<AlertResponse>,
+        /// `ok`, `err` or `unknown`.
+        health: String,
+        /// `firing`, `pending` or `inactive`.
+        state: String,
+        evaluation_time: f64,
+        last_evaluation: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    Recording {
+        name: String,
+        query: String,
+        labels: HashMap<String, String>,
+        health: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_error: Option<String>,
+        evaluation_time: f64,
+        last_evaluation: String,
+    },
+}
+
+/// An active (pending or firing) alert.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertResponse {
+    pub labels: HashMap<String, String>,
+    pub annotations: HashMap<String, String>,
+    /// `pending` or `firing`.
+    pub state: String,
+    /// RFC 3339 timestamp of the moment the alert became active.
+    pub active_at: String,
+    /// The value of the series at the last evaluation, formatted as a string.
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AlertDiscovery {
+    pub alerts: Vec<AlertResponse>,
+}
+
+/// Request exemplars for a query over a time range.
+#[derive(Debug, Deserialize)]
+pub struct RequestQueryExemplars {
+    /// Prometheus expression query string.
+    pub query: Option<String>,
+    /// Start timestamp.
+    pub start: Option<String>,
+    /// End timestamp.
+    pub end: Option<String>,
+}
+
+/// Request a PromQL expression to be formatted.
+#[derive(Debug, Deserialize)]
+pub struct RequestFormatQuery {
+    /// Prometheus expression query string.
+    pub query: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -209,4 +355,21 @@ mod tests {
         assert_eq!(format!("{}", MetricType::Unknown), "unknown");
         assert_eq!(MetricType::Unknown.to_string(), "unknown");
     }
+
+    #[test]
+    fn test_rule_response_serialize() {
+        let rule = RuleResponse::Recording {
+            name: "job:up:sum".to_string(),
+            query: "sum by (job) (up)".to_string(),
+            labels: HashMap::new(),
+            health: "ok".to_string(),
+            last_error: None,
+            evaluation_time: 0.0,
+            last_evaluation: "2023-01-01T00:00:00Z".to_string(),
+        };
+        let json = serde_json::to_value(&rule).unwrap();
+        assert_eq!(json["type"], "recording");
+        assert_eq!(json["lastEvaluation"], "2023-01-01T00:00:00Z");
+        assert!(json.get("lastError").is_none());
+    }
 }
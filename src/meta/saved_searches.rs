@@ -0,0 +1,86 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::StreamType;
+
+/// A named search of an organization, which can be run on demand or on a
+/// schedule.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SavedSearch {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// The user who saved the search.
+    #[serde(default)]
+    pub owner: String,
+    #[serde(default)]
+    pub stream_type: StreamType,
+    pub sql: String,
+    /// VRL function applied to the hits, base64 encoded as in `/_search`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_fn: Option<String>,
+    /// The search covers the last `duration` minutes before it runs.
+    pub duration: i64,
+    /// Maximum number of records returned or reported.
+    #[serde(default = "default_size")]
+    pub size: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<ReportSchedule>,
+    /// Microseconds.
+    #[serde(default)]
+    pub created_at: i64,
+    /// Microseconds.
+    #[serde(default)]
+    pub updated_at: i64,
+}
+
+fn default_size() -> usize {
+    100
+}
+
+/// Runs a saved search at a regular interval on the alert manager, and sends
+/// the result to an alert destination.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReportSchedule {
+    /// Minutes between two reports.
+    pub frequency: i64,
+    /// Name of the alert destination the report is sent to.
+    pub destination: String,
+    #[serde(default)]
+    pub format: ReportFormat,
+    /// Microseconds, the last time the report was sent.
+    #[serde(default)]
+    pub last_sent_at: i64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// The records are sent inline as a table, in `{report_table}`.
+    #[default]
+    Table,
+    /// The records are stored as a CSV file, its link is in `{report_link}`.
+    Csv,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct SavedSearchList {
+    pub list: Vec<SavedSearch>,
+}
@@ -17,6 +17,7 @@ use std::collections::HashMap;
 use utoipa::ToSchema;
 
 use crate::common::{self, json};
+use crate::meta::StreamType;
 use crate::service::search::datafusion::storage::StorageType;
 
 #[derive(Clone, Debug)]
@@ -87,6 +88,10 @@ pub struct Query {
     pub uses_zo_fn: bool,
     #[serde(default)]
     pub query_fn: Option<String>,
+    /// Returns the physical plans and the timings of every phase of the
+    /// search on every node, see `Profile`.
+    #[serde(default)]
+    pub profile: bool,
 }
 
 fn default_size() -> usize {
@@ -107,6 +112,7 @@ impl Default for Query {
             query_context: None,
             uses_zo_fn: false,
             query_fn: None,
+            profile: false,
         }
     }
 }
@@ -157,6 +163,103 @@ pub struct Response {
     pub scan_size: usize,
     #[serde(skip_serializing_if = "String::is_empty")]
     pub response_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<SearchProfile>)]
+    pub profile: Option<Profile>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[schema(as = SearchCursorRequest)]
+pub struct CursorRequest {
+    pub cursor_id: String,
+    /// Page size, defaults to the size of the first page.
+    #[serde(default)]
+    pub size: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    #[default]
+    Pending,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_done(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Finished | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// A search which runs in the background, its response is stored in object
+/// storage.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[schema(as = SearchJob)]
+pub struct Job {
+    pub id: String,
+    pub stream_type: StreamType,
+    #[schema(value_type = SearchRequest)]
+    pub request: Request,
+    pub status: JobStatus,
+    /// The node which runs the job.
+    pub node: String,
+    pub created_at: i64,
+    #[serde(default)]
+    pub started_at: i64,
+    #[serde(default)]
+    pub finished_at: i64,
+    #[serde(default)]
+    pub total_files: usize,
+    #[serde(default)]
+    pub scanned_files: usize,
+    /// Scanned bytes, in MB.
+    #[serde(default)]
+    pub scan_size: usize,
+    /// Number of hits of the response.
+    #[serde(default)]
+    pub total: usize,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+#[schema(as = SearchJobList)]
+pub struct JobList {
+    pub list: Vec<Job>,
+}
+
+/// The estimated cost of a search, from the metadata of the files it scans.
+#[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
+#[schema(as = SearchCost)]
+pub struct Cost {
+    pub start_time: i64,
+    pub end_time: i64,
+    /// Number of files to scan, not counting the data which is not yet in
+    /// object storage.
+    pub files: usize,
+    pub records: u64,
+    /// Uncompressed bytes to scan.
+    pub original_size: u64,
+    /// Compressed bytes to scan.
+    pub compressed_size: u64,
+    /// The maximum scan size of the organization, in MB, 0 is unlimited.
+    pub max_scan_size: u64,
+    /// The maximum time range of the organization, in hours, 0 is unlimited.
+    pub max_time_range: i64,
+    /// Whether the search is within the limits of the organization.
+    pub allowed: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
@@ -167,6 +270,55 @@ pub struct ResponseTook {
     pub cluster_wait_queue: usize,
 }
 
+/// Where the time of a search went, returned when the query sets `profile`.
+/// All timings are in milliseconds.
+#[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
+#[schema(as = SearchProfile)]
+pub struct Profile {
+    /// Time to list the files on the leader.
+    pub file_list_took: usize,
+    /// Files left after pruning by time range and partition keys.
+    pub files: usize,
+    /// Time to merge the results of the nodes on the leader.
+    pub merge_took: usize,
+    #[schema(value_type = Vec<SearchNodeProfile>)]
+    pub nodes: Vec<NodeProfile>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
+#[schema(as = SearchNodeProfile)]
+pub struct NodeProfile {
+    pub node: String,
+    pub took: usize,
+    /// Time to list the files and to prune them by the fulltext index.
+    pub file_list_took: usize,
+    /// Time to download the files into the memory cache.
+    pub cache_took: usize,
+    /// Time to execute the queries on the files in object storage.
+    pub scan_took: usize,
+    /// Time to search the WAL files of an ingester.
+    pub wal_took: usize,
+    /// Time to merge the results of the node.
+    pub merge_took: usize,
+    pub files: usize,
+    pub files_pruned: usize,
+    pub files_scanned: usize,
+    pub wal_files: usize,
+    #[schema(value_type = Vec<SearchPlan>)]
+    pub plans: Vec<Plan>,
+}
+
+/// The physical plan of a query, annotated with the metrics of its execution.
+#[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
+#[schema(as = SearchPlan)]
+pub struct Plan {
+    /// `wal` or `storage`.
+    pub source: String,
+    /// `query` or `agg_{name}`.
+    pub name: String,
+    pub plan: String,
+}
+
 impl Response {
     pub fn new(from: usize, size: usize) -> Self {
         Response {
@@ -180,6 +332,8 @@ impl Response {
             hits: Vec::new(),
             aggs: HashMap::new(),
             response_type: "".to_string(),
+            cursor_id: None,
+            profile: None,
         }
     }
 
@@ -240,6 +394,15 @@ mod test {
         assert_eq!(res.total, 11);
     }
 
+    #[test]
+    fn test_cursor_request() {
+        let req: CursorRequest = json::from_value(json::json!({"cursor_id": "abc"})).unwrap();
+        assert_eq!(req.cursor_id, "abc");
+        assert_eq!(req.size, None);
+        let res = json::to_value(Response::new(0, 10)).unwrap();
+        assert!(res.get("cursor_id").is_none());
+    }
+
     #[test]
     fn test_request_encoding() {
         let req = json::json!(
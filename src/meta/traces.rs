@@ -15,6 +15,7 @@
 use ahash::AHashMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use utoipa::ToSchema;
 
 use crate::common::json;
 
@@ -24,6 +25,8 @@ pub struct Span {
     pub span_id: String,
     pub flags: u8,
     pub span_kind: String,
+    /// `UNSET`, `OK` or `ERROR`.
+    pub span_status: String,
     pub operation_name: String,
     pub start_time: u64,
     pub end_time: u64,
@@ -58,6 +61,129 @@ pub struct Event {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub attributes: AHashMap<String, json::Value>,
 }
+/// A trace, with its spans as a tree built from their parent span ids.
+/// Times are in microseconds.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct Trace {
+    pub trace_id: String,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub duration: i64,
+    /// The root spans.
+    #[schema(value_type = Vec<Object>)]
+    pub spans: Vec<SpanNode>,
+}
+
+/// A span as stored in the stream, with its children ordered by start time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpanNode {
+    #[serde(flatten)]
+    pub span: json::Map<String, json::Value>,
+    pub children: Vec<SpanNode>,
+}
+
+/// The filters of a trace search, a trace matches if one of its spans
+/// matches all of them.
+#[derive(Clone, Debug, Default)]
+pub struct TraceSearch {
+    pub service: Option<String>,
+    pub operation: Option<String>,
+    /// Microseconds.
+    pub min_duration: Option<i64>,
+    /// Microseconds.
+    pub max_duration: Option<i64>,
+    /// Span attributes, by attribute name.
+    pub tags: HashMap<String, String>,
+    pub start_time: i64,
+    pub end_time: i64,
+    /// Maximum number of traces, the latest ones.
+    pub size: usize,
+}
+
+/// A trace found by a search. Times are in microseconds.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TraceSummary {
+    pub trace_id: String,
+    /// Service of the root span.
+    pub service_name: String,
+    /// Operation of the root span.
+    pub operation_name: String,
+    pub start_time: i64,
+    pub duration: i64,
+    pub spans: usize,
+    pub services: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct TraceList {
+    pub list: Vec<TraceSummary>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct ServiceList {
+    pub list: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Operation {
+    pub name: String,
+    pub span_kind: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct OperationList {
+    pub list: Vec<Operation>,
+}
+
+/// The calls between services, from the pairs of client and server spans.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct ServiceGraph {
+    pub edges: Vec<ServiceGraphEdge>,
+}
+
+/// The calls of a client service to a server service in a time range.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ServiceGraphEdge {
+    pub client: String,
+    pub server: String,
+    pub requests: f64,
+    /// Requests whose client or server span has the `ERROR` status.
+    pub failed: f64,
+}
+
+/// Routing of the spans of an organization to traces streams by the
+/// attributes of their resource, used when a request doesn't name a stream.
+/// The first matching route wins, spans matching no route go to the
+/// `default` stream.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TraceRoutes {
+    #[serde(default)]
+    pub routes: Vec<TraceRoute>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TraceRoute {
+    pub stream_name: String,
+    /// Resource attributes, e.g. `service.name`, and the values they must
+    /// have. A value ending with `*` matches as a prefix, no attributes
+    /// match all the spans.
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+}
+
+impl TraceRoute {
+    pub fn matches(&self, attributes: &AHashMap<String, String>) -> bool {
+        self.attributes.iter().all(|(key, pattern)| {
+            attributes
+                .get(key)
+                .map_or(false, |value| match pattern.strip_suffix('*') {
+                    Some(prefix) => value.starts_with(prefix),
+                    None => value == pattern,
+                })
+        })
+    }
+}
+
 /* #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct SpanReference {
     pub parent_trace_id: String,
@@ -73,3 +199,29 @@ pub struct Service {
     #[serde(skip_serializing_if = "HashMap::is_empty")]
     pub attributes: AHashMap<String, Value>,
 } */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_route_matches() {
+        let route = TraceRoute {
+            stream_name: "payments".to_string(),
+            attributes: HashMap::from([
+                ("service.name".to_string(), "payment-*".to_string()),
+                ("deployment.environment".to_string(), "prod".to_string()),
+            ]),
+        };
+        let mut attributes = AHashMap::from([
+            ("service.name".to_string(), "payment-api".to_string()),
+            ("deployment.environment".to_string(), "prod".to_string()),
+        ]);
+        assert!(route.matches(&attributes));
+        attributes.insert("deployment.environment".to_string(), "dev".to_string());
+        assert!(!route.matches(&attributes));
+        attributes.remove("deployment.environment");
+        assert!(!route.matches(&attributes));
+        assert!(TraceRoute::default().matches(&attributes));
+    }
+}
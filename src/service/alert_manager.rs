@@ -16,11 +16,13 @@ use chrono::Utc;
 use std::collections::HashMap;
 use tokio::time;
 
-use crate::common::notification::send_notification;
+use crate::common::notification::{send_notification, send_series_notification};
 use crate::infra::config::{TRIGGERS, TRIGGERS_IN_PROCESS};
 use crate::meta;
-use crate::meta::alert::{Evaluate, Trigger, TriggerTimer};
+use crate::meta::alert::{Alert, AlertKind, Evaluate, Trigger, TriggerTimer};
+use crate::meta::prom::NAME_LABEL;
 use crate::meta::search::Request;
+use crate::service::promql::{self, value::Value};
 use crate::service::search as SearchService;
 use crate::service::triggers;
 
@@ -98,6 +100,9 @@ async fn handle_trigger(alert_name: &str, frequency: i64) {
             .await;
 
             match alert_resp.unwrap_or(None) {
+                Some(alert) if alert.kind == AlertKind::Promql => {
+                    handle_promql_alert(&alert, trigger.clone()).await;
+                }
                 Some(alert) => {
                     let mut query = alert.query.clone().unwrap();
                     let curr_ts = Utc::now().timestamp_micros();
@@ -109,8 +114,13 @@ async fn handle_trigger(alert_name: &str, frequency: i64) {
                         encoding: meta::search::RequestEncoding::Empty,
                     };
                     // do search
-                    match SearchService::search(&trigger.org, alert.stream_type.unwrap(), &req)
-                        .await
+                    match SearchService::search_with_priority(
+                        &trigger.org,
+                        alert.stream_type.unwrap(),
+                        &req,
+                        SearchService::Priority::Alert,
+                    )
+                    .await
                     {
                         Ok(res) => {
                             if !res.hits.is_empty() {
@@ -149,6 +159,74 @@ async fn handle_trigger(alert_name: &str, frequency: i64) {
     }
 }
 
+/// Evaluates a PromQL alert, updates the state of its series and notifies
+/// about the series which started firing or were resolved.
+#[cfg_attr(coverage_nightly, no_coverage)]
+async fn handle_promql_alert(alert: &Alert, mut trigger: Trigger) {
+    let curr_ts = Utc::now().timestamp_micros();
+    let req = promql::MetricsQueryRequest {
+        query: alert.promql.clone().unwrap_or_default(),
+        start: curr_ts,
+        end: curr_ts,
+        step: 0,
+    };
+    let active = match promql::search::search(&trigger.org, &req).await {
+        Ok(Value::Vector(v)) => v
+            .into_iter()
+            .map(|v| {
+                let labels = v
+                    .labels
+                    .iter()
+                    .filter(|l| l.name != NAME_LABEL)
+                    .map(|l| (l.name.clone(), l.value.clone()))
+                    .collect();
+                (labels, v.sample.value)
+            })
+            .collect(),
+        Ok(Value::None) => vec![],
+        Ok(v) => {
+            log::error!(
+                "alert_manager promql alert {} must return an instant vector, got {}",
+                alert.name,
+                v.get_type()
+            );
+            return;
+        }
+        Err(err) => {
+            log::error!("alert_manager promql search error: {:?}", err);
+            return;
+        }
+    };
+
+    let notify = trigger.update_series(
+        active,
+        curr_ts,
+        alert.for_duration * 1_000_000,
+        get_micros_from_min(alert.time_between_alerts),
+    );
+    let mut sent = false;
+    for key in notify.iter() {
+        let series = trigger.series.get(key).unwrap();
+        match send_series_notification(alert, &trigger, series).await {
+            Ok(_) => {
+                trigger.mark_sent(key, curr_ts);
+                sent = true;
+            }
+            Err(err) => log::error!(
+                "alert_manager promql alert {} send notification for {} error: {:?}",
+                alert.name,
+                key,
+                err
+            ),
+        }
+    }
+    if sent {
+        trigger.last_sent_at = curr_ts;
+        trigger.count += 1;
+    }
+    let _ = triggers::save_trigger(alert.name.clone(), trigger).await;
+}
+
 fn get_micros_from_min(min: i64) -> i64 {
     min * 60 * 1000000
 }
@@ -22,7 +22,7 @@ use super::search::sql::Sql;
 use super::{db, triggers};
 use crate::common::notification::send_notification;
 use crate::handler::grpc::cluster_rpc;
-use crate::meta::alert::{Alert, AlertList, Trigger};
+use crate::meta::alert::{Alert, AlertKind, AlertList, Trigger};
 use crate::meta::http::HttpResponse as MetaHttpResponse;
 use crate::meta::search::Query;
 use crate::meta::{self, StreamType};
@@ -67,7 +67,23 @@ pub async fn save_alert(
         )));
     }
 
-    if alert.query.is_none() {
+    if alert.kind == AlertKind::Promql {
+        let expr = alert.promql.clone().unwrap_or_default();
+        if let Err(e) = promql_parser::parser::parse(&expr) {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!("Invalid PromQL query : {e}"),
+            )));
+        }
+        if alert.for_duration < 0 {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!("Invalid for duration : {}", alert.for_duration),
+            )));
+        }
+        // PromQL alerts are always evaluated by the alert manager
+        alert.is_real_time = false;
+    } else if alert.query.is_none() {
         alert.query = Some(Query {
             sql: format!("select * from {stream_name}"),
             start_time: 0,
@@ -80,6 +96,7 @@ pub async fn save_alert(
             query_context: None,
             uses_zo_fn: false,
             query_fn: None,
+            profile: false,
         });
         alert.is_real_time = true;
 
@@ -137,6 +154,7 @@ pub async fn save_alert(
             last_sent_at: 0,
             count: 0,
             is_ingest_time: false,
+            series: Default::default(),
         };
         let _ = triggers::save_trigger(trigger.alert_name.clone(), trigger.clone()).await;
     }
@@ -235,6 +253,7 @@ pub async fn trigger_alert(
                 count: 0,
                 is_ingest_time: alert.is_real_time,
                 stream_type,
+                series: Default::default(),
             };
             let _ = send_notification(&alert, &trigger).await;
             Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
@@ -272,6 +291,7 @@ mod test {
                 query_context: None,
                 uses_zo_fn: false,
                 query_fn: None,
+                profile: false,
             }),
             condition: Condition {
                 column: "occurance".to_owned(),
@@ -286,6 +306,9 @@ mod test {
             destination: "test".to_string(),
             is_real_time: false,
             context_attributes: None,
+            kind: AlertKind::Sql,
+            promql: None,
+            for_duration: 0,
         }
     }
 
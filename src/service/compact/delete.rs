@@ -23,7 +23,7 @@ use crate::infra::config::CONFIG;
 use crate::infra::{cache, ider, storage};
 use crate::meta::common::{FileKey, FileMeta};
 use crate::meta::StreamType;
-use crate::service::{db, file_list};
+use crate::service::{db, file_list, search};
 
 pub async fn delete_all(
     org_id: &str,
@@ -70,6 +70,7 @@ pub async fn delete_all(
 
     // delete from file list
     delete_from_file_list(org_id, stream_name, stream_type, (0, 0)).await?;
+    search::cache::invalidate(org_id, stream_type, stream_name, None);
 
     // mark delete done
     db::compact::delete::delete_stream_done(org_id, stream_name, stream_type, None).await
@@ -154,6 +155,7 @@ pub async fn delete_by_date(
 
     // delete from file list
     delete_from_file_list(org_id, stream_name, stream_type, time_range).await?;
+    search::cache::invalidate(org_id, stream_type, stream_name, Some(time_range));
 
     // mark delete done
     db::compact::delete::delete_stream_done(org_id, stream_name, stream_type, Some(date_range))
@@ -24,7 +24,10 @@ use crate::meta::{
     common::{FileKey, FileMeta},
     StreamType,
 };
-use crate::service::{db, file_list, search::datafusion};
+use crate::service::{
+    db, file_list,
+    search::{datafusion, index},
+};
 
 /// compactor run steps on a stream:
 /// 3. get a cluster lock for compactor stream
@@ -262,6 +265,18 @@ pub async fn merge_by_stream(
                     log::error!("[COMPACT] delete file failed: {}", e);
                 }
             }
+            // delete the fulltext indexes of small files, they may not exist
+            if CONFIG.common.feature_fulltext_index {
+                let index_list = new_file_list
+                    .iter()
+                    .map(|v| index::index_key(v))
+                    .collect::<Vec<_>>();
+                if let Err(e) =
+                    storage::del(&index_list.iter().map(|v| v.as_str()).collect::<Vec<_>>()).await
+                {
+                    log::error!("[COMPACT] delete fulltext index failed: {}", e);
+                }
+            }
             // delete files from file list
             files_with_size.retain(|value| !&new_file_list.contains(&value.0));
         }
@@ -432,10 +447,25 @@ async fn merge_files(
     );
 
     // upload file
-    match storage::put(&new_file_key, buf.into()).await {
-        Ok(_) => Ok((new_file_key, new_file_meta, new_file_list)),
-        Err(e) => Err(e),
+    let buf = bytes::Bytes::from(buf);
+    let fulltext_index =
+        match index::build_from_parquet(org_id, stream_name, stream_type, buf.clone()).await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!(
+                    "[COMPACT] build fulltext index {} error: {}",
+                    new_file_key,
+                    e
+                );
+                None
+            }
+        };
+    storage::put(&new_file_key, buf).await?;
+    // the index is only useful once the file exists
+    if let Some(fulltext_index) = fulltext_index {
+        index::upload(&new_file_key, fulltext_index).await;
     }
+    Ok((new_file_key, new_file_meta, new_file_list))
 }
 
 #[cfg(test)]
@@ -117,10 +117,12 @@ pub async fn watch() -> Result<(), anyhow::Error> {
         match ev {
             Event::Put(ev) => {
                 let item_key = ev.key.strip_prefix(key).unwrap();
+                invalidate_search_cache(item_key);
                 CACHE.insert(item_key.to_string());
             }
             Event::Delete(ev) => {
                 let item_key = ev.key.strip_prefix(key).unwrap();
+                invalidate_search_cache(item_key);
                 CACHE.remove(item_key);
             }
         }
@@ -128,6 +130,21 @@ pub async fn watch() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+// the data of a stream is deleted on the compactor, drop the cached search
+// results of the stream on this node
+fn invalidate_search_cache(item_key: &str) {
+    let columns = item_key.split('/').collect::<Vec<_>>();
+    if columns.len() < 3 {
+        return;
+    }
+    crate::service::search::cache::invalidate(
+        columns[0],
+        StreamType::from(columns[1]),
+        columns[2],
+        None,
+    );
+}
+
 pub async fn cache() -> Result<(), anyhow::Error> {
     let db = &crate::infra::db::DEFAULT;
     let key = "/compact/delete/";
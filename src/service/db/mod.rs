@@ -22,7 +22,12 @@ pub mod functions;
 pub mod kv;
 pub mod lookup_table;
 pub mod metrics;
+pub mod organization;
+pub mod prom_rules;
+pub mod saved_searches;
 pub mod schema;
+pub mod search_cursors;
+pub mod search_jobs;
 pub mod syslog;
 pub mod triggers;
 pub mod user;
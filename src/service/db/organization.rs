@@ -0,0 +1,158 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::{
+    common::json,
+    infra::{
+        config::{ORG_SEARCH_LIMITS, ORG_TRACE_ROUTES},
+        db::{self, Event},
+    },
+    meta::{organization::OrgSearchLimits, traces::TraceRoutes},
+};
+
+const SEARCH_LIMITS_KEY: &str = "/organization/search_limits/";
+const TRACE_ROUTES_KEY: &str = "/organization/trace_routes/";
+
+pub fn get_search_limits(org_id: &str) -> Option<OrgSearchLimits> {
+    ORG_SEARCH_LIMITS.get(org_id).map(|v| v.value().clone())
+}
+
+#[tracing::instrument(skip(limits))]
+pub async fn set_search_limits(
+    org_id: &str,
+    limits: &OrgSearchLimits,
+) -> Result<(), anyhow::Error> {
+    db::DEFAULT
+        .put(
+            &format!("{SEARCH_LIMITS_KEY}{org_id}"),
+            json::to_vec(limits).unwrap().into(),
+        )
+        .await?;
+    ORG_SEARCH_LIMITS.insert(org_id.to_string(), limits.clone());
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn delete_search_limits(org_id: &str) -> Result<(), anyhow::Error> {
+    ORG_SEARCH_LIMITS.remove(org_id);
+    Ok(db::DEFAULT
+        .delete_if_exists(&format!("{SEARCH_LIMITS_KEY}{org_id}"), false)
+        .await?)
+}
+
+pub fn get_trace_routes(org_id: &str) -> Option<TraceRoutes> {
+    ORG_TRACE_ROUTES.get(org_id).map(|v| v.value().clone())
+}
+
+#[tracing::instrument(skip(routes))]
+pub async fn set_trace_routes(org_id: &str, routes: &TraceRoutes) -> Result<(), anyhow::Error> {
+    db::DEFAULT
+        .put(
+            &format!("{TRACE_ROUTES_KEY}{org_id}"),
+            json::to_vec(routes).unwrap().into(),
+        )
+        .await?;
+    ORG_TRACE_ROUTES.insert(org_id.to_string(), routes.clone());
+    Ok(())
+}
+
+#[tracing::instrument]
+pub async fn delete_trace_routes(org_id: &str) -> Result<(), anyhow::Error> {
+    ORG_TRACE_ROUTES.remove(org_id);
+    Ok(db::DEFAULT
+        .delete_if_exists(&format!("{TRACE_ROUTES_KEY}{org_id}"), false)
+        .await?)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let key = SEARCH_LIMITS_KEY;
+    let mut events = db::DEFAULT.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching organization search limits");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_search_limits: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: OrgSearchLimits = json::from_slice(&ev.value.unwrap()).unwrap();
+                ORG_SEARCH_LIMITS.insert(item_key.to_owned(), item_value);
+            }
+            Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                ORG_SEARCH_LIMITS.remove(item_key);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let key = SEARCH_LIMITS_KEY;
+    let ret = db::DEFAULT.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: OrgSearchLimits = json::from_slice(&item_value).unwrap();
+        ORG_SEARCH_LIMITS.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Organization search limits Cached");
+    Ok(())
+}
+
+pub async fn watch_trace_routes() -> Result<(), anyhow::Error> {
+    let key = TRACE_ROUTES_KEY;
+    let mut events = db::DEFAULT.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching organization trace routes");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_trace_routes: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: TraceRoutes = json::from_slice(&ev.value.unwrap()).unwrap();
+                ORG_TRACE_ROUTES.insert(item_key.to_owned(), item_value);
+            }
+            Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                ORG_TRACE_ROUTES.remove(item_key);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache_trace_routes() -> Result<(), anyhow::Error> {
+    let key = TRACE_ROUTES_KEY;
+    let ret = db::DEFAULT.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: TraceRoutes = json::from_slice(&item_value).unwrap();
+        ORG_TRACE_ROUTES.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Organization trace routes Cached");
+    Ok(())
+}
@@ -0,0 +1,122 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::common::json;
+use crate::infra::config::PROM_RULE_GROUPS;
+use crate::infra::db::Event;
+use crate::meta::prom::RuleGroup;
+
+pub async fn get(org_id: &str, name: &str) -> Result<Option<RuleGroup>, anyhow::Error> {
+    let map_key = format!("{org_id}/{name}");
+    if let Some(group) = PROM_RULE_GROUPS.get(&map_key) {
+        return Ok(Some(group.value().clone()));
+    }
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/prom_rules/{org_id}/{name}");
+    Ok(match db.get(&key).await {
+        Ok(val) => json::from_slice(&val)?,
+        Err(_) => None,
+    })
+}
+
+pub async fn set(org_id: &str, name: &str, group: &RuleGroup) -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/prom_rules/{org_id}/{name}");
+    Ok(db.put(&key, json::to_vec(group)?.into()).await?)
+}
+
+pub async fn delete(org_id: &str, name: &str) -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/prom_rules/{org_id}/{name}");
+    db.delete(&key, false).await?;
+    let key = format!("/prom_rules_eval/{org_id}/{name}");
+    Ok(db.delete_if_exists(&key, false).await?)
+}
+
+/// Returns the timestamp of the last evaluation of a rule group on any node,
+/// microseconds.
+pub async fn get_evaluated_at(org_id: &str, name: &str) -> Result<i64, anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/prom_rules_eval/{org_id}/{name}");
+    let value = match db.get(&key).await {
+        Ok(ret) => String::from_utf8_lossy(&ret).to_string(),
+        Err(_) => String::from("0"),
+    };
+    Ok(value.parse()?)
+}
+
+pub async fn set_evaluated_at(org_id: &str, name: &str, ts: i64) -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/prom_rules_eval/{org_id}/{name}");
+    Ok(db.put(&key, ts.to_string().into()).await?)
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<RuleGroup>, anyhow::Error> {
+    let prefix = format!("{org_id}/");
+    let mut groups: Vec<RuleGroup> = PROM_RULE_GROUPS
+        .iter()
+        .filter_map(|group| {
+            group
+                .key()
+                .starts_with(&prefix)
+                .then(|| group.value().clone())
+        })
+        .collect();
+    groups.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(groups)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = "/prom_rules/";
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching prometheus rule groups");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_prom_rules: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: RuleGroup = json::from_slice(&ev.value.unwrap()).unwrap();
+                PROM_RULE_GROUPS.insert(item_key.to_owned(), item_value);
+            }
+            Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                PROM_RULE_GROUPS.remove(item_key);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = "/prom_rules/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: RuleGroup = json::from_slice(&item_value).unwrap();
+        PROM_RULE_GROUPS.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Prometheus rule groups Cached");
+    Ok(())
+}
@@ -0,0 +1,122 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use crate::common::json;
+use crate::infra::config::SAVED_SEARCHES;
+use crate::infra::db::Event;
+use crate::meta::saved_searches::SavedSearch;
+
+pub async fn get(org_id: &str, name: &str) -> Result<Option<SavedSearch>, anyhow::Error> {
+    let map_key = format!("{org_id}/{name}");
+    if let Some(search) = SAVED_SEARCHES.get(&map_key) {
+        return Ok(Some(search.value().clone()));
+    }
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/saved_search/{org_id}/{name}");
+    Ok(match db.get(&key).await {
+        Ok(val) => json::from_slice(&val)?,
+        Err(_) => None,
+    })
+}
+
+pub async fn set(org_id: &str, search: &SavedSearch) -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/saved_search/{org_id}/{}", search.name);
+    Ok(db.put(&key, json::to_vec(search)?.into()).await?)
+}
+
+pub async fn delete(org_id: &str, name: &str) -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/saved_search_report/{org_id}/{name}");
+    db.delete_if_exists(&key, false).await?;
+    let key = format!("/saved_search/{org_id}/{name}");
+    Ok(db.delete(&key, false).await?)
+}
+
+/// Returns the timestamp of the last report started on any node,
+/// microseconds.
+pub async fn get_report_claimed_at(org_id: &str, name: &str) -> Result<i64, anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/saved_search_report/{org_id}/{name}");
+    let value = match db.get(&key).await {
+        Ok(ret) => String::from_utf8_lossy(&ret).to_string(),
+        Err(_) => String::from("0"),
+    };
+    Ok(value.parse()?)
+}
+
+pub async fn set_report_claimed_at(org_id: &str, name: &str, ts: i64) -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = format!("/saved_search_report/{org_id}/{name}");
+    Ok(db.put(&key, ts.to_string().into()).await?)
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<SavedSearch>, anyhow::Error> {
+    let prefix = format!("{org_id}/");
+    let mut searches: Vec<SavedSearch> = SAVED_SEARCHES
+        .iter()
+        .filter_map(|search| {
+            search
+                .key()
+                .starts_with(&prefix)
+                .then(|| search.value().clone())
+        })
+        .collect();
+    searches.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(searches)
+}
+
+pub async fn watch() -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = "/saved_search/";
+    let mut events = db.watch(key).await?;
+    let events = Arc::get_mut(&mut events).unwrap();
+    log::info!("Start watching saved searches");
+    loop {
+        let ev = match events.recv().await {
+            Some(ev) => ev,
+            None => {
+                log::error!("watch_saved_searches: event channel closed");
+                break;
+            }
+        };
+        match ev {
+            Event::Put(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                let item_value: SavedSearch = json::from_slice(&ev.value.unwrap()).unwrap();
+                SAVED_SEARCHES.insert(item_key.to_owned(), item_value);
+            }
+            Event::Delete(ev) => {
+                let item_key = ev.key.strip_prefix(key).unwrap();
+                SAVED_SEARCHES.remove(item_key);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn cache() -> Result<(), anyhow::Error> {
+    let db = &crate::infra::db::DEFAULT;
+    let key = "/saved_search/";
+    let ret = db.list(key).await?;
+    for (item_key, item_value) in ret {
+        let item_key = item_key.strip_prefix(key).unwrap();
+        let json_val: SavedSearch = json::from_slice(&item_value).unwrap();
+        SAVED_SEARCHES.insert(item_key.to_owned(), json_val);
+    }
+    log::info!("Saved searches Cached");
+    Ok(())
+}
@@ -0,0 +1,51 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::json;
+use crate::infra::db;
+use crate::service::search::cursor::Cursor;
+
+pub async fn get(org_id: &str, id: &str) -> Result<Option<Cursor>, anyhow::Error> {
+    let key = format!("/search_cursors/{org_id}/{id}");
+    Ok(match db::DEFAULT.get(&key).await {
+        Ok(val) => json::from_slice(&val)?,
+        Err(_) => None,
+    })
+}
+
+pub async fn set(org_id: &str, cursor: &Cursor) -> Result<(), anyhow::Error> {
+    let key = format!("/search_cursors/{org_id}/{}", cursor.id);
+    Ok(db::DEFAULT.put(&key, json::to_vec(cursor)?.into()).await?)
+}
+
+/// Fails if the cursor doesn't exist, e.g. it was taken by another request.
+pub async fn delete(org_id: &str, id: &str) -> Result<(), anyhow::Error> {
+    let key = format!("/search_cursors/{org_id}/{id}");
+    Ok(db::DEFAULT.delete(&key, false).await?)
+}
+
+pub async fn delete_if_exists(org_id: &str, id: &str) -> Result<(), anyhow::Error> {
+    let key = format!("/search_cursors/{org_id}/{id}");
+    Ok(db::DEFAULT.delete_if_exists(&key, false).await?)
+}
+
+/// Lists the cursors of all the organizations.
+pub async fn list() -> Result<Vec<Cursor>, anyhow::Error> {
+    Ok(db::DEFAULT
+        .list_values("/search_cursors/")
+        .await?
+        .iter()
+        .map(|val| json::from_slice(val))
+        .collect::<Result<Vec<Cursor>, _>>()?)
+}
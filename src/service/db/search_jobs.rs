@@ -0,0 +1,61 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::json;
+use crate::infra::db;
+use crate::meta::search::Job;
+
+pub async fn get(org_id: &str, id: &str) -> Result<Option<Job>, anyhow::Error> {
+    let key = format!("/search_jobs/{org_id}/{id}");
+    Ok(match db::DEFAULT.get(&key).await {
+        Ok(val) => json::from_slice(&val)?,
+        Err(_) => None,
+    })
+}
+
+pub async fn set(org_id: &str, job: &Job) -> Result<(), anyhow::Error> {
+    let key = format!("/search_jobs/{org_id}/{}", job.id);
+    Ok(db::DEFAULT.put(&key, json::to_vec(job)?.into()).await?)
+}
+
+pub async fn delete(org_id: &str, id: &str) -> Result<(), anyhow::Error> {
+    let key = format!("/search_jobs/{org_id}/{id}");
+    Ok(db::DEFAULT.delete(&key, false).await?)
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<Job>, anyhow::Error> {
+    let key = format!("/search_jobs/{org_id}/");
+    let mut jobs = db::DEFAULT
+        .list_values(&key)
+        .await?
+        .iter()
+        .map(|val| json::from_slice(val))
+        .collect::<Result<Vec<Job>, _>>()?;
+    jobs.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(jobs)
+}
+
+/// Lists the jobs of all the organizations, with their organization.
+pub async fn list_all() -> Result<Vec<(String, Job)>, anyhow::Error> {
+    let key = "/search_jobs/";
+    let mut jobs = Vec::new();
+    for (item_key, item_value) in db::DEFAULT.list(key).await? {
+        let org_id = item_key
+            .strip_prefix(key)
+            .and_then(|v| v.split('/').next())
+            .unwrap_or_default();
+        jobs.push((org_id.to_string(), json::from_slice(&item_value)?));
+    }
+    Ok(jobs)
+}
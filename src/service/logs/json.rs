@@ -38,27 +38,43 @@ pub async fn ingest(
     body: actix_web::web::Bytes,
     thread_id: web::Data<usize>,
 ) -> Result<HttpResponse, Error> {
+    let reader: Vec<json::Value> = json::from_slice(&body)?;
+    //JSON Flattening
+    let records = reader.iter().map(json::flatten_json_and_format_field);
+    match ingest_records(org_id, in_stream_name, records, thread_id, "/_json").await {
+        Ok(stream_status) => Ok(HttpResponse::Ok().json(IngestionResponse::new(
+            http::StatusCode::OK.into(),
+            vec![stream_status],
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+/// Ingests flattened records into a log stream. `path` is the API the records
+/// came from, for the metrics.
+pub(crate) async fn ingest_records(
+    org_id: &str,
+    in_stream_name: &str,
+    records: impl IntoIterator<Item = json::Value>,
+    thread_id: web::Data<usize>,
+    path: &str,
+) -> Result<StreamStatus, anyhow::Error> {
     let start = Instant::now();
 
     let stream_name = &crate::service::ingestion::format_stream_name(in_stream_name);
 
     if !cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
-        return Ok(
-            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
-                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
-                "not an ingester".to_string(),
-            )),
-        );
+        return Err(anyhow::anyhow!("not an ingester"));
     }
 
     // check if we are allowed to ingest
     if db::compact::delete::is_deleting_stream(org_id, stream_name, StreamType::Logs, None) {
-        return Ok(
-            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
-                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
-                format!("stream [{stream_name}] is being deleted"),
-            )),
-        );
+        return Err(anyhow::anyhow!("stream [{stream_name}] is being deleted"));
     }
 
     let mut min_ts =
@@ -109,13 +125,7 @@ pub async fn ingest(
     // End get stream alert
 
     let mut buf: AHashMap<String, Vec<String>> = AHashMap::new();
-    let body_vec = body.to_vec();
-    let reader: Vec<json::Value> = json::from_slice(&body_vec)?;
-    for item in reader.iter() {
-        let mut value = item.to_owned();
-        //JSON Flattening
-        value = json::flatten_json_and_format_field(&value);
-
+    for value in records {
         #[cfg(feature = "zo_functions")]
         let mut value = crate::service::ingestion::apply_stream_transform(
             &local_tans,
@@ -124,6 +134,8 @@ pub async fn ingest(
             stream_name,
             &mut runtime,
         );
+        #[cfg(not(feature = "zo_functions"))]
+        let mut value = value;
         #[cfg(feature = "zo_functions")]
         if value.is_null() || !value.is_object() {
             stream_status.status.failed += 1; // transform failed or dropped
@@ -189,7 +201,7 @@ pub async fn ingest(
     let time = start.elapsed().as_secs_f64();
     metrics::HTTP_RESPONSE_TIME
         .with_label_values(&[
-            "/_json",
+            path,
             "200",
             org_id,
             stream_name,
@@ -198,7 +210,7 @@ pub async fn ingest(
         .observe(time);
     metrics::HTTP_INCOMING_REQUESTS
         .with_label_values(&[
-            "/_json",
+            path,
             "200",
             org_id,
             stream_name,
@@ -206,8 +218,5 @@ pub async fn ingest(
         ])
         .inc();
 
-    Ok(HttpResponse::Ok().json(IngestionResponse::new(
-        http::StatusCode::OK.into(),
-        vec![stream_status],
-    )))
+    Ok(stream_status)
 }
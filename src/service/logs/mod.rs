@@ -28,6 +28,7 @@ pub mod bulk;
 pub mod json;
 pub mod kinesis_firehose;
 pub mod multi;
+pub mod otlp;
 pub mod syslog;
 
 static BULK_OPERATORS: [&str; 3] = ["create", "index", "update"];
@@ -274,6 +275,7 @@ async fn add_valid_record(
                                     last_sent_at: 0,
                                     count: 0,
                                     is_ingest_time: true,
+                                    series: Default::default(),
                                 });
                             }
                         }
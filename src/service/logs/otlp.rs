@@ -0,0 +1,504 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OTLP logs ingestion, `ExportLogsServiceRequest` over gRPC and over HTTP as
+//! protobuf or JSON.
+//!
+//! Every log record is flattened with the attributes of its resource and
+//! scope, the resource attributes are prefixed with `service_` like the ones
+//! of the spans, and ingested into a log stream like the records of `_json`.
+
+use actix_web::{http, web, HttpResponse};
+use bytes::BytesMut;
+use chrono::Utc;
+use opentelemetry::trace::{SpanId, TraceId};
+use opentelemetry_proto::tonic::{
+    collector::logs::v1::{ExportLogsServiceRequest, ExportLogsServiceResponse},
+    common::v1::KeyValue,
+};
+use prost::Message;
+use std::io::Error;
+
+use crate::common::json::{self, Map, Value};
+use crate::infra::config::CONFIG;
+use crate::meta::http::HttpResponse as MetaHttpResponse;
+use crate::meta::ingestion::{IngestionResponse, StreamStatus};
+use crate::service::traces::get_val;
+
+const DEFAULT_STREAM: &str = "default";
+const SERVICE_NAME: &str = "service.name";
+const SERVICE: &str = "service";
+
+pub async fn logs_proto(
+    org_id: &str,
+    in_stream_name: Option<&str>,
+    body: web::Bytes,
+    thread_id: web::Data<usize>,
+) -> Result<HttpResponse, Error> {
+    let request = match ExportLogsServiceRequest::decode(body) {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!("Invalid protobuf: {e}"),
+            )))
+        }
+    };
+    match handle_logs_request(org_id, in_stream_name, request, thread_id).await {
+        Ok(_) => {
+            let res = ExportLogsServiceResponse {};
+            let mut out = BytesMut::with_capacity(res.encoded_len());
+            res.encode(&mut out).expect("Out of memory");
+            Ok(HttpResponse::Ok()
+                .content_type("application/x-protobuf")
+                .body(out))
+        }
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+pub async fn logs_json(
+    org_id: &str,
+    in_stream_name: Option<&str>,
+    body: web::Bytes,
+    thread_id: web::Data<usize>,
+) -> Result<HttpResponse, Error> {
+    let request: Value = match json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!("Invalid json: {e}"),
+            )))
+        }
+    };
+    match ingest(org_id, in_stream_name, json_records(&request), thread_id).await {
+        Ok(stream_status) => Ok(HttpResponse::Ok().json(IngestionResponse::new(
+            http::StatusCode::OK.into(),
+            vec![stream_status],
+        ))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+/// Ingests the records of an OTLP logs request into a log stream, the
+/// `default` stream if none is given.
+pub async fn handle_logs_request(
+    org_id: &str,
+    in_stream_name: Option<&str>,
+    request: ExportLogsServiceRequest,
+    thread_id: web::Data<usize>,
+) -> Result<StreamStatus, anyhow::Error> {
+    ingest(org_id, in_stream_name, proto_records(request), thread_id).await
+}
+
+async fn ingest(
+    org_id: &str,
+    in_stream_name: Option<&str>,
+    records: Vec<Value>,
+    thread_id: web::Data<usize>,
+) -> Result<StreamStatus, anyhow::Error> {
+    let stream_name = in_stream_name
+        .filter(|name| !name.is_empty())
+        .unwrap_or(DEFAULT_STREAM);
+    super::json::ingest_records(org_id, stream_name, records, thread_id, "/v1/logs").await
+}
+
+/// Returns the fields of the resource attributes, `service.name` is stored
+/// as `service_name` and the others are prefixed with `service.`.
+fn resource_fields(attributes: Vec<(String, Value)>) -> Map<String, Value> {
+    let mut fields = Map::new();
+    for (key, value) in attributes {
+        if value.is_null() {
+            continue;
+        }
+        if key.eq(SERVICE_NAME) {
+            fields.insert("service_name".to_string(), value);
+        } else {
+            fields.insert(format!("{SERVICE}.{key}"), value);
+        }
+    }
+    fields
+}
+
+/// The fields of a log record, before they are flattened.
+struct LogFields {
+    /// Microseconds.
+    timestamp: i64,
+    severity_text: String,
+    severity_number: i64,
+    body: Value,
+    attributes: Vec<(String, Value)>,
+    trace_id: String,
+    span_id: String,
+    flags: i64,
+    dropped_attributes_count: i64,
+}
+
+fn log_record(resource: &Map<String, Value>, log: LogFields) -> Value {
+    let mut record = resource.clone();
+    let timestamp = if log.timestamp > 0 {
+        log.timestamp
+    } else {
+        Utc::now().timestamp_micros()
+    };
+    record.insert(
+        CONFIG.common.column_timestamp.clone(),
+        Value::Number(timestamp.into()),
+    );
+    if !log.severity_text.is_empty() {
+        record.insert("severity".to_string(), Value::String(log.severity_text));
+    }
+    if log.severity_number > 0 {
+        record.insert("severity_number".to_string(), log.severity_number.into());
+    }
+    if !log.body.is_null() {
+        record.insert("body".to_string(), log.body);
+    }
+    if !log.attributes.is_empty() {
+        record.insert(
+            "attributes".to_string(),
+            Value::Object(log.attributes.into_iter().collect()),
+        );
+    }
+    if !log.trace_id.is_empty() {
+        record.insert("trace_id".to_string(), Value::String(log.trace_id));
+    }
+    if !log.span_id.is_empty() {
+        record.insert("span_id".to_string(), Value::String(log.span_id));
+    }
+    record.insert("flags".to_string(), log.flags.into());
+    if log.dropped_attributes_count > 0 {
+        record.insert(
+            "dropped_attributes_count".to_string(),
+            log.dropped_attributes_count.into(),
+        );
+    }
+    json::flatten_json_and_format_field(&Value::Object(record))
+}
+
+fn key_values(attributes: Vec<KeyValue>) -> Vec<(String, Value)> {
+    attributes
+        .into_iter()
+        .map(|attr| (attr.key, get_val(attr.value)))
+        .collect()
+}
+
+fn proto_records(request: ExportLogsServiceRequest) -> Vec<Value> {
+    let mut records = Vec::new();
+    for res_logs in request.resource_logs {
+        let resource = resource_fields(
+            res_logs
+                .resource
+                .map(|resource| key_values(resource.attributes))
+                .unwrap_or_default(),
+        );
+        for inst_logs in res_logs.instrumentation_library_logs {
+            let mut resource = resource.clone();
+            if let Some(library) = inst_logs.instrumentation_library {
+                scope_fields(&mut resource, library.name, library.version);
+            }
+            for log in inst_logs.log_records {
+                let trace_id = <[u8; 16]>::try_from(log.trace_id.as_slice())
+                    .map(|id| TraceId::from_bytes(id).to_string())
+                    .unwrap_or_default();
+                let span_id = <[u8; 8]>::try_from(log.span_id.as_slice())
+                    .map(|id| SpanId::from_bytes(id).to_string())
+                    .unwrap_or_default();
+                records.push(log_record(
+                    &resource,
+                    LogFields {
+                        timestamp: ([log.time_unix_nano, log.observed_time_unix_nano]
+                            .into_iter()
+                            .find(|ts| *ts > 0)
+                            .unwrap_or_default()
+                            / 1000) as i64,
+                        severity_text: log.severity_text,
+                        severity_number: log.severity_number as i64,
+                        body: get_val(log.body),
+                        attributes: key_values(log.attributes),
+                        trace_id,
+                        span_id,
+                        flags: log.flags as i64,
+                        dropped_attributes_count: log.dropped_attributes_count as i64,
+                    },
+                ));
+            }
+        }
+    }
+    records
+}
+
+fn scope_fields(fields: &mut Map<String, Value>, name: String, version: String) {
+    if !name.is_empty() {
+        fields.insert("scope_name".to_string(), Value::String(name));
+    }
+    if !version.is_empty() {
+        fields.insert("scope_version".to_string(), Value::String(version));
+    }
+}
+
+/// Returns the value of an OTLP/JSON `AnyValue`, like `{"stringValue": "a"}`.
+fn json_any_value(value: &Value) -> Value {
+    let Some(value) = value.as_object() else {
+        return Value::Null;
+    };
+    if let Some(v) = value.get("stringValue") {
+        v.clone()
+    } else if let Some(v) = value.get("boolValue") {
+        v.clone()
+    } else if let Some(v) = value.get("intValue") {
+        // 64 bit integers are encoded as strings
+        json_i64(v).map_or(Value::Null, Value::from)
+    } else if let Some(v) = value.get("doubleValue") {
+        v.clone()
+    } else if let Some(v) = value.get("arrayValue") {
+        Value::Array(json_array(v, "values").iter().map(json_any_value).collect())
+    } else if let Some(v) = value.get("kvlistValue") {
+        Value::Object(json_key_values(v, "values").into_iter().collect())
+    } else if let Some(v) = value.get("bytesValue") {
+        v.clone()
+    } else {
+        Value::Null
+    }
+}
+
+pub(crate) fn json_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::String(v) => v.parse().ok(),
+        Value::Number(v) => v.as_i64().or_else(|| v.as_u64().map(|v| v as i64)),
+        _ => None,
+    }
+}
+
+pub(crate) fn json_str(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub(crate) fn json_array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or_default()
+}
+
+fn json_key_values(value: &Value, key: &str) -> Vec<(String, Value)> {
+    json_array(value, key)
+        .iter()
+        .map(|attr| {
+            (
+                json_str(attr, "key"),
+                attr.get("value").map_or(Value::Null, json_any_value),
+            )
+        })
+        .collect()
+}
+
+fn json_records(request: &Value) -> Vec<Value> {
+    let mut records = Vec::new();
+    for res_logs in json_array(request, "resourceLogs") {
+        let resource = resource_fields(
+            res_logs
+                .get("resource")
+                .map(|resource| json_key_values(resource, "attributes"))
+                .unwrap_or_default(),
+        );
+        // `instrumentationLibraryLogs` is the name before OTLP 0.15
+        let scope_logs = json_array(res_logs, "scopeLogs")
+            .iter()
+            .map(|logs| (logs, logs.get("scope")))
+            .chain(
+                json_array(res_logs, "instrumentationLibraryLogs")
+                    .iter()
+                    .map(|logs| (logs, logs.get("instrumentationLibrary"))),
+            );
+        for (scope_log, scope) in scope_logs {
+            let mut resource = resource.clone();
+            if let Some(scope) = scope {
+                scope_fields(
+                    &mut resource,
+                    json_str(scope, "name"),
+                    json_str(scope, "version"),
+                );
+            }
+            for log in json_array(scope_log, "logRecords") {
+                let timestamp = ["timeUnixNano", "observedTimeUnixNano"]
+                    .iter()
+                    .filter_map(|key| log.get(*key).and_then(json_i64))
+                    .find(|ts| *ts > 0)
+                    .unwrap_or_default();
+                records.push(log_record(
+                    &resource,
+                    LogFields {
+                        timestamp: timestamp / 1000,
+                        severity_text: json_str(log, "severityText"),
+                        severity_number: log
+                            .get("severityNumber")
+                            .and_then(json_i64)
+                            .unwrap_or_default(),
+                        body: log.get("body").map_or(Value::Null, json_any_value),
+                        attributes: json_key_values(log, "attributes"),
+                        // the ids are hex encoded in OTLP/JSON
+                        trace_id: json_str(log, "traceId").to_lowercase(),
+                        span_id: json_str(log, "spanId").to_lowercase(),
+                        flags: log.get("flags").and_then(json_i64).unwrap_or_default(),
+                        dropped_attributes_count: log
+                            .get("droppedAttributesCount")
+                            .and_then(json_i64)
+                            .unwrap_or_default(),
+                    },
+                ));
+            }
+        }
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_proto::tonic::{
+        common::v1::{any_value, AnyValue, InstrumentationLibrary},
+        logs::v1::{InstrumentationLibraryLogs, LogRecord, ResourceLogs},
+        resource::v1::Resource,
+    };
+
+    fn string_value(value: &str) -> Option<AnyValue> {
+        Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        })
+    }
+
+    #[test]
+    fn test_proto_records() {
+        let request = ExportLogsServiceRequest {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(Resource {
+                    attributes: vec![
+                        KeyValue {
+                            key: "service.name".to_string(),
+                            value: string_value("checkout"),
+                        },
+                        KeyValue {
+                            key: "host.name".to_string(),
+                            value: string_value("node-1"),
+                        },
+                    ],
+                    ..Default::default()
+                }),
+                instrumentation_library_logs: vec![InstrumentationLibraryLogs {
+                    instrumentation_library: Some(InstrumentationLibrary {
+                        name: "logback".to_string(),
+                        version: "1.4".to_string(),
+                    }),
+                    log_records: vec![
+                        LogRecord {
+                            time_unix_nano: 1_681_000_000_000_000_123,
+                            severity_number: 17,
+                            severity_text: "ERROR".to_string(),
+                            body: string_value("payment failed"),
+                            attributes: vec![KeyValue {
+                                key: "http.status_code".to_string(),
+                                value: Some(AnyValue {
+                                    value: Some(any_value::Value::IntValue(502)),
+                                }),
+                            }],
+                            span_id: vec![1, 2, 3, 4, 5, 6, 7, 8],
+                            ..Default::default()
+                        },
+                        LogRecord {
+                            observed_time_unix_nano: 1_681_000_000_000_000_000,
+                            body: string_value("retry"),
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+        let records = proto_records(request);
+        assert_eq!(records.len(), 2);
+        let record = records[0].as_object().unwrap();
+        assert_eq!(record["_timestamp"], 1_681_000_000_000_000_i64);
+        assert_eq!(record["service_name"], "checkout");
+        assert_eq!(record["service_host_name"], "node-1");
+        assert_eq!(record["scope_name"], "logback");
+        assert_eq!(record["severity"], "ERROR");
+        assert_eq!(record["severity_number"], 17);
+        assert_eq!(record["body"], "payment failed");
+        assert_eq!(record["attributes_http_status_code"], 502);
+        assert_eq!(record["span_id"], "0102030405060708");
+        assert!(!record.contains_key("trace_id"));
+        let record = records[1].as_object().unwrap();
+        assert_eq!(record["_timestamp"], 1_681_000_000_000_000_i64);
+        assert_eq!(record["body"], "retry");
+    }
+
+    #[test]
+    fn test_json_records() {
+        let request = json::json!({
+            "resourceLogs": [{
+                "resource": {
+                    "attributes": [
+                        {"key": "service.name", "value": {"stringValue": "checkout"}}
+                    ]
+                },
+                "scopeLogs": [{
+                    "scope": {"name": "logback"},
+                    "logRecords": [{
+                        "timeUnixNano": "1681000000000000123",
+                        "severityText": "INFO",
+                        "body": {"kvlistValue": {"values": [
+                            {"key": "msg", "value": {"stringValue": "paid"}},
+                            {"key": "amount", "value": {"intValue": "42"}}
+                        ]}},
+                        "traceId": "5B8EFFF798038103D269B633813FC60C"
+                    }, {
+                        "observedTimeUnixNano": 1681000000000000000_i64,
+                        "body": {"stringValue": "retry"}
+                    }]
+                }]
+            }]
+        });
+        let records = json_records(&request);
+        assert_eq!(records.len(), 2);
+        let record = records[0].as_object().unwrap();
+        assert_eq!(record["_timestamp"], 1_681_000_000_000_000_i64);
+        assert_eq!(record["service_name"], "checkout");
+        assert_eq!(record["scope_name"], "logback");
+        assert_eq!(record["severity"], "INFO");
+        assert_eq!(record["body_msg"], "paid");
+        assert_eq!(record["body_amount"], 42);
+        assert_eq!(record["trace_id"], "5b8efff798038103d269b633813fc60c");
+        let record = records[1].as_object().unwrap();
+        assert_eq!(record["_timestamp"], 1_681_000_000_000_000_i64);
+        assert_eq!(record["body"], "retry");
+    }
+}
@@ -17,7 +17,10 @@ use datafusion::arrow::datatypes::Schema;
 use crate::common;
 use crate::meta::prom::{Metadata, METADATA_LABEL};
 
+pub mod otlp;
 pub mod prom;
+pub mod remote_read;
+pub mod rules;
 
 pub fn get_prom_metadata_from_schema(schema: &Schema) -> Option<Metadata> {
     let metadata = schema.metadata.get(METADATA_LABEL)?;
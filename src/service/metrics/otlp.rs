@@ -0,0 +1,1269 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OTLP metrics ingestion, `ExportMetricsServiceRequest` over gRPC and over
+//! HTTP as protobuf or JSON.
+//!
+//! The data points are converted to the samples of a Prometheus remote write:
+//! a stream per metric name, whose records have the labels, the `value` and
+//! the `__hash__` of the labels. Histograms are expanded into `_bucket`,
+//! `_sum` and `_count` series, and summaries into `quantile`, `_sum` and
+//! `_count` series, as the Prometheus clients expose them, so that PromQL
+//! queries work unchanged. The `service.name` and `service.instance.id`
+//! resource attributes are the `job` and `instance` labels.
+//!
+//! The points of delta sums and histograms are added to the running totals of
+//! their series, kept in memory by the ingester which receives them, as
+//! Prometheus only has cumulative counters. The accumulated series have the
+//! name of the ingester as the `ingester` label, so the totals of the points
+//! sent to different ingesters are different series instead of one counter
+//! going back and forth. A restart of the ingester resets the totals, which
+//! `rate()` and `increase()` handle as a counter reset, and the total of a
+//! series without points for `ZO_METRICS_DELTA_IDLE` seconds is dropped.
+
+use actix_web::{http, web, HttpResponse};
+use ahash::AHashMap;
+use bytes::BytesMut;
+use chrono::Utc;
+use dashmap::DashMap;
+use datafusion::arrow::datatypes::Schema;
+use once_cell::sync::Lazy;
+use opentelemetry_proto::tonic::{
+    collector::metrics::v1::{ExportMetricsServiceRequest, ExportMetricsServiceResponse},
+    common::v1::{any_value, AnyValue, KeyValue},
+    metrics::v1::{
+        exponential_histogram_data_point::Buckets, metric::Data, number_data_point,
+        summary_data_point::ValueAtQuantile, AggregationTemporality, ExponentialHistogram,
+        ExponentialHistogramDataPoint, Gauge, Histogram, HistogramDataPoint,
+        InstrumentationLibraryMetrics, Metric, NumberDataPoint, ResourceMetrics, Sum, Summary,
+        SummaryDataPoint,
+    },
+    resource::v1::Resource,
+};
+use prost::Message;
+use std::{
+    io,
+    sync::atomic::{AtomicI64, Ordering},
+    time::Instant,
+};
+
+use crate::common::json;
+use crate::infra::{cluster, config::CONFIG, metrics};
+use crate::meta::{
+    self,
+    alert::{Alert, Trigger},
+    prom::{
+        self, FxIndexMap, HASH_LABEL, LE_LABEL, METADATA_LABEL, NAME_LABEL, QUANTILE_LABEL,
+        VALUE_LABEL,
+    },
+    StreamType,
+};
+use crate::service::{
+    db,
+    ingestion::{chk_schema_by_record, write_file},
+    logs::otlp::{json_array, json_i64, json_str},
+    schema::{set_schema_metadata, stream_schema_exists},
+    traces::get_val,
+};
+
+const SERVICE_NAME: &str = "service.name";
+const SERVICE_NAMESPACE: &str = "service.namespace";
+const SERVICE_INSTANCE_ID: &str = "service.instance.id";
+
+const INGESTER_LABEL: &str = "ingester";
+
+/// How often the idle running totals are dropped, in microseconds.
+const DELTA_EVICT_INTERVAL: i64 = 60_000_000;
+
+/// The running totals of the delta series, by org and labels, with the time of
+/// their last point in microseconds.
+static DELTA_TOTALS: Lazy<DashMap<String, (f64, i64)>> = Lazy::new(DashMap::new);
+
+/// When the idle running totals were last dropped, in microseconds.
+static DELTA_EVICTED_AT: AtomicI64 = AtomicI64::new(0);
+
+pub async fn metrics_proto(
+    org_id: &str,
+    thread_id: web::Data<usize>,
+    body: web::Bytes,
+) -> Result<HttpResponse, io::Error> {
+    let request = match ExportMetricsServiceRequest::decode(body) {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(
+                HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
+                    http::StatusCode::BAD_REQUEST.into(),
+                    format!("Invalid protobuf: {e}"),
+                )),
+            )
+        }
+    };
+    match handle_metrics_request(org_id, thread_id, request).await {
+        Ok(()) => {
+            let res = ExportMetricsServiceResponse {};
+            let mut out = BytesMut::with_capacity(res.encoded_len());
+            res.encode(&mut out).expect("Out of memory");
+            Ok(HttpResponse::Ok()
+                .content_type("application/x-protobuf")
+                .body(out))
+        }
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+pub async fn metrics_json(
+    org_id: &str,
+    thread_id: web::Data<usize>,
+    body: web::Bytes,
+) -> Result<HttpResponse, io::Error> {
+    let request: json::Value = match json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return Ok(
+                HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
+                    http::StatusCode::BAD_REQUEST.into(),
+                    format!("Invalid json: {e}"),
+                )),
+            )
+        }
+    };
+    match handle_metrics_request(org_id, thread_id, json_request(&request)).await {
+        // the JSON encoding of an empty `ExportMetricsServiceResponse`
+        Ok(()) => Ok(HttpResponse::Ok().json(json::json!({}))),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+pub async fn handle_metrics_request(
+    org_id: &str,
+    thread_id: web::Data<usize>,
+    request: ExportMetricsServiceRequest,
+) -> Result<(), anyhow::Error> {
+    let start = Instant::now();
+    if !cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
+        return Err(anyhow::anyhow!("not an ingester"));
+    }
+
+    ingest(org_id, thread_id, request).await?;
+
+    let time = start.elapsed().as_secs_f64();
+    metrics::HTTP_RESPONSE_TIME
+        .with_label_values(&[
+            "/v1/metrics",
+            "200",
+            org_id,
+            "",
+            &StreamType::Metrics.to_string(),
+        ])
+        .observe(time);
+    metrics::HTTP_INCOMING_REQUESTS
+        .with_label_values(&[
+            "/v1/metrics",
+            "200",
+            org_id,
+            "",
+            &StreamType::Metrics.to_string(),
+        ])
+        .inc();
+
+    Ok(())
+}
+
+/// Writes the data points of a request to the metrics streams, also used for
+/// the metrics the server generates, e.g. from the spans.
+pub(crate) async fn ingest(
+    org_id: &str,
+    thread_id: web::Data<usize>,
+    request: ExportMetricsServiceRequest,
+) -> Result<(), anyhow::Error> {
+    let Samples {
+        metadata,
+        mut samples,
+    } = Samples::from(request);
+    accumulate(org_id, &mut samples, Utc::now().timestamp_micros());
+    for metadata in metadata {
+        let mut extra_metadata: AHashMap<String, String> = AHashMap::new();
+        extra_metadata.insert(
+            METADATA_LABEL.to_string(),
+            json::to_string(&metadata).unwrap(),
+        );
+        set_schema_metadata(
+            org_id,
+            &metadata.metric_family_name,
+            StreamType::Metrics,
+            extra_metadata,
+        )
+        .await?;
+    }
+
+    let mut metric_samples: AHashMap<String, Vec<Sample>> = AHashMap::new();
+    for sample in samples {
+        metric_samples
+            .entry(sample.metric_name.clone())
+            .or_default()
+            .push(sample);
+    }
+
+    let mut metric_schema_map: AHashMap<String, Schema> = AHashMap::new();
+    let mut stream_alerts_map: AHashMap<String, Vec<Alert>> = AHashMap::new();
+    let mut stream_trigger_map: AHashMap<String, Trigger> = AHashMap::new();
+    for (metric_name, samples) in metric_samples {
+        // check if we are allowed to ingest
+        if db::compact::delete::is_deleting_stream(org_id, &metric_name, StreamType::Metrics, None)
+        {
+            return Err(anyhow::anyhow!("stream [{metric_name}] is being deleted"));
+        }
+
+        // get partition keys
+        let stream_schema = stream_schema_exists(
+            org_id,
+            &metric_name,
+            StreamType::Metrics,
+            &mut metric_schema_map,
+        )
+        .await;
+        let mut partition_keys: Vec<String> = vec![];
+        if stream_schema.has_partition_keys {
+            partition_keys = crate::service::ingestion::get_stream_partition_keys(
+                &metric_name,
+                &metric_schema_map,
+            )
+            .await;
+        }
+
+        // Start get stream alerts
+        let key = format!("{}/{}/{}", &org_id, StreamType::Metrics, &metric_name);
+        crate::service::ingestion::get_stream_alerts(key.clone(), &mut stream_alerts_map).await;
+        // End get stream alert
+
+        #[cfg(feature = "zo_functions")]
+        let mut runtime = crate::service::ingestion::init_functions_runtime();
+
+        // Start Register Transforms for stream
+        #[cfg(feature = "zo_functions")]
+        let (local_tans, stream_vrl_map) = crate::service::ingestion::register_stream_transforms(
+            org_id,
+            StreamType::Metrics,
+            &metric_name,
+        );
+        // End Register Transforms for stream
+
+        let mut buf: AHashMap<String, Vec<String>> = AHashMap::new();
+        for sample in samples {
+            let value: json::Value = json::to_value(&sample.metric).unwrap();
+
+            // Start row based transform
+            #[cfg(feature = "zo_functions")]
+            let value = crate::service::ingestion::apply_stream_transform(
+                &local_tans,
+                &value,
+                &stream_vrl_map,
+                &metric_name,
+                &mut runtime,
+            );
+            // End row based transform
+            let mut value = value;
+
+            // get json object
+            let Some(val_map) = value.as_object_mut() else {
+                continue; // dropped by the transform
+            };
+            let hash = super::signature_without_labels(val_map, &[VALUE_LABEL]);
+            val_map.insert(HASH_LABEL.to_string(), json::Value::String(hash.into()));
+            val_map.insert(
+                CONFIG.common.column_timestamp.clone(),
+                json::Value::Number(sample.timestamp.into()),
+            );
+            let value_str = json::to_string(&val_map).unwrap();
+            chk_schema_by_record(
+                &mut metric_schema_map,
+                org_id,
+                StreamType::Metrics,
+                &metric_name,
+                sample.timestamp,
+                &value_str,
+            )
+            .await;
+
+            // get hour key
+            let hour_key = crate::service::ingestion::get_hour_key(
+                sample.timestamp,
+                partition_keys.clone(),
+                value.as_object().unwrap().clone(),
+            );
+            buf.entry(hour_key).or_default().push(value_str);
+
+            // real time alert
+            if let Some(alerts) = stream_alerts_map.get(&key) {
+                for alert in alerts.iter().filter(|alert| alert.is_real_time) {
+                    if meta::alert::Evaluate::evaluate(
+                        &alert.condition,
+                        value.as_object().unwrap().clone(),
+                    ) {
+                        stream_trigger_map.insert(
+                            metric_name.clone(),
+                            Trigger {
+                                timestamp: sample.timestamp,
+                                is_valid: true,
+                                alert_name: alert.name.clone(),
+                                stream: metric_name.clone(),
+                                org: org_id.to_string(),
+                                stream_type: StreamType::Metrics,
+                                last_sent_at: 0,
+                                count: 0,
+                                is_ingest_time: true,
+                                series: Default::default(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        // write to file
+        write_file(
+            buf,
+            thread_id.clone(),
+            org_id,
+            &metric_name,
+            StreamType::Metrics,
+        );
+    }
+
+    // only one trigger per stream, as it updates etcd
+    for entry in stream_trigger_map.values() {
+        let mut alerts = stream_alerts_map
+            .get(&format!(
+                "{}/{}/{}",
+                entry.org,
+                StreamType::Metrics,
+                entry.stream
+            ))
+            .unwrap()
+            .clone();
+
+        alerts.retain(|alert| alert.name.eq(&entry.alert_name));
+        if !alerts.is_empty() {
+            crate::service::ingestion::send_ingest_notification(
+                entry.clone(),
+                alerts.first().unwrap().clone(),
+            )
+            .await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the values of the delta samples by the running totals of their
+/// series, labelled with the ingester, and drops the totals of the series
+/// without points for `ZO_METRICS_DELTA_IDLE` seconds.
+fn accumulate(org_id: &str, samples: &mut [Sample], now: i64) {
+    let evicted_at = DELTA_EVICTED_AT.load(Ordering::Relaxed);
+    if now - evicted_at >= DELTA_EVICT_INTERVAL
+        && DELTA_EVICTED_AT
+            .compare_exchange(evicted_at, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    {
+        let idle_before = now - CONFIG.limit.metrics_delta_idle * 1_000_000;
+        DELTA_TOTALS.retain(|_, (_, updated_at)| *updated_at >= idle_before);
+    }
+
+    for sample in samples.iter_mut().filter(|sample| sample.delta) {
+        sample.metric.labels.insert(
+            INGESTER_LABEL.to_string(),
+            CONFIG.common.instance_name.clone(),
+        );
+        let mut labels = sample
+            .metric
+            .labels
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>();
+        labels.sort();
+        let key = format!("{org_id}/{}", labels.join(","));
+        let mut entry = DELTA_TOTALS.entry(key).or_default();
+        let (total, updated_at) = entry.value_mut();
+        *total += sample.metric.value;
+        *updated_at = now;
+        sample.metric.value = *total;
+    }
+}
+
+/// A sample of a series, as in a Prometheus remote write.
+#[derive(Debug)]
+struct Sample {
+    metric_name: String,
+    metric: prom::Metric,
+    /// Microseconds.
+    timestamp: i64,
+    /// The value is the change since the previous sample of the series.
+    delta: bool,
+}
+
+/// The samples of a request, with the metadata of their metric families.
+#[derive(Debug, Default)]
+struct Samples {
+    metadata: Vec<prom::Metadata>,
+    samples: Vec<Sample>,
+    /// The points being added are delta points.
+    delta: bool,
+}
+
+impl From<ExportMetricsServiceRequest> for Samples {
+    fn from(request: ExportMetricsServiceRequest) -> Self {
+        let mut samples = Samples::default();
+        for res_metrics in request.resource_metrics {
+            let resource_labels = resource_labels(
+                res_metrics
+                    .resource
+                    .map(|resource| resource.attributes)
+                    .unwrap_or_default(),
+            );
+            for inst_metrics in res_metrics.instrumentation_library_metrics {
+                for metric in inst_metrics.metrics {
+                    samples.add_metric(&resource_labels, metric);
+                }
+            }
+        }
+        samples
+    }
+}
+
+impl Samples {
+    fn add_metric(&mut self, resource_labels: &[(String, String)], metric: Metric) {
+        let name = sanitize(&metric.name);
+        let Some(data) = metric.data else {
+            return;
+        };
+        let delta = AggregationTemporality::Delta as i32;
+        self.delta = match &data {
+            Data::Sum(sum) => sum.aggregation_temporality == delta,
+            Data::Histogram(histogram) => histogram.aggregation_temporality == delta,
+            Data::ExponentialHistogram(histogram) => histogram.aggregation_temporality == delta,
+            Data::Gauge(_) | Data::Summary(_) => false,
+        };
+        let metric_type = match &data {
+            Data::Gauge(_) => prom::MetricType::Gauge,
+            Data::Sum(sum) if sum.is_monotonic => prom::MetricType::Counter,
+            Data::Sum(_) => prom::MetricType::Gauge,
+            Data::Histogram(_) | Data::ExponentialHistogram(_) => prom::MetricType::Histogram,
+            Data::Summary(_) => prom::MetricType::Summary,
+        };
+        self.metadata.push(prom::Metadata {
+            metric_type,
+            metric_family_name: name.clone(),
+            help: metric.description,
+            unit: metric.unit,
+        });
+        match data {
+            Data::Gauge(gauge) => self.add_numbers(&name, resource_labels, gauge.data_points),
+            Data::Sum(sum) => self.add_numbers(&name, resource_labels, sum.data_points),
+            Data::Histogram(histogram) => {
+                for point in histogram.data_points {
+                    self.add_histogram(&name, resource_labels, point);
+                }
+            }
+            Data::ExponentialHistogram(histogram) => {
+                for point in histogram.data_points {
+                    self.add_exponential_histogram(&name, resource_labels, point);
+                }
+            }
+            Data::Summary(summary) => {
+                for point in summary.data_points {
+                    self.add_summary(&name, resource_labels, point);
+                }
+            }
+        }
+    }
+
+    fn add_numbers(
+        &mut self,
+        name: &str,
+        resource_labels: &[(String, String)],
+        points: Vec<NumberDataPoint>,
+    ) {
+        for point in points {
+            let value = match point.value {
+                Some(number_data_point::Value::AsDouble(v)) => v,
+                Some(number_data_point::Value::AsInt(v)) => v as f64,
+                None => continue,
+            };
+            let labels = point_labels(resource_labels, point.attributes);
+            self.add(name, &labels, None, value, point.time_unix_nano);
+        }
+    }
+
+    fn add_histogram(
+        &mut self,
+        name: &str,
+        resource_labels: &[(String, String)],
+        point: HistogramDataPoint,
+    ) {
+        let labels = point_labels(resource_labels, point.attributes);
+        let bucket_name = format!("{name}_bucket");
+        let mut count = 0;
+        for (i, bound) in point.explicit_bounds.iter().enumerate() {
+            count += point.bucket_counts.get(i).copied().unwrap_or_default();
+            self.add(
+                &bucket_name,
+                &labels,
+                Some((LE_LABEL, format_bound(*bound))),
+                count as f64,
+                point.time_unix_nano,
+            );
+        }
+        self.add_totals(name, &labels, point.count, point.sum, point.time_unix_nano);
+    }
+
+    /// The buckets of an exponential histogram are converted to the buckets
+    /// of their upper bounds.
+    fn add_exponential_histogram(
+        &mut self,
+        name: &str,
+        resource_labels: &[(String, String)],
+        point: ExponentialHistogramDataPoint,
+    ) {
+        let labels = point_labels(resource_labels, point.attributes);
+        let bucket_name = format!("{name}_bucket");
+        let base = 2f64.powf(2f64.powi(-point.scale));
+        let mut count = 0;
+        // the negative buckets are ordered by their absolute value
+        if let Some(Buckets {
+            offset,
+            bucket_counts,
+        }) = point.negative
+        {
+            for (i, bucket_count) in bucket_counts.iter().enumerate().rev() {
+                count += bucket_count;
+                let bound = -base.powi(offset + i as i32);
+                self.add(
+                    &bucket_name,
+                    &labels,
+                    Some((LE_LABEL, format_bound(bound))),
+                    count as f64,
+                    point.time_unix_nano,
+                );
+            }
+        }
+        count += point.zero_count;
+        self.add(
+            &bucket_name,
+            &labels,
+            Some((LE_LABEL, format_bound(0.0))),
+            count as f64,
+            point.time_unix_nano,
+        );
+        if let Some(Buckets {
+            offset,
+            bucket_counts,
+        }) = point.positive
+        {
+            for (i, bucket_count) in bucket_counts.iter().enumerate() {
+                count += bucket_count;
+                let bound = base.powi(offset + i as i32 + 1);
+                self.add(
+                    &bucket_name,
+                    &labels,
+                    Some((LE_LABEL, format_bound(bound))),
+                    count as f64,
+                    point.time_unix_nano,
+                );
+            }
+        }
+        self.add_totals(name, &labels, point.count, point.sum, point.time_unix_nano);
+    }
+
+    fn add_summary(
+        &mut self,
+        name: &str,
+        resource_labels: &[(String, String)],
+        point: SummaryDataPoint,
+    ) {
+        let labels = point_labels(resource_labels, point.attributes);
+        for quantile in point.quantile_values {
+            self.add(
+                name,
+                &labels,
+                Some((QUANTILE_LABEL, format_bound(quantile.quantile))),
+                quantile.value,
+                point.time_unix_nano,
+            );
+        }
+        self.add(
+            &format!("{name}_sum"),
+            &labels,
+            None,
+            point.sum,
+            point.time_unix_nano,
+        );
+        self.add(
+            &format!("{name}_count"),
+            &labels,
+            None,
+            point.count as f64,
+            point.time_unix_nano,
+        );
+    }
+
+    /// Adds the `+Inf` bucket, the `_sum` and the `_count` of a histogram.
+    fn add_totals(
+        &mut self,
+        name: &str,
+        labels: &[(String, String)],
+        count: u64,
+        sum: impl Into<Option<f64>>,
+        time_unix_nano: u64,
+    ) {
+        self.add(
+            &format!("{name}_bucket"),
+            labels,
+            Some((LE_LABEL, format_bound(f64::INFINITY))),
+            count as f64,
+            time_unix_nano,
+        );
+        if let Some(sum) = sum.into() {
+            self.add(&format!("{name}_sum"), labels, None, sum, time_unix_nano);
+        }
+        self.add(
+            &format!("{name}_count"),
+            labels,
+            None,
+            count as f64,
+            time_unix_nano,
+        );
+    }
+
+    fn add(
+        &mut self,
+        name: &str,
+        labels: &[(String, String)],
+        extra_label: Option<(&str, String)>,
+        value: f64,
+        time_unix_nano: u64,
+    ) {
+        // revisit in future, as in the remote write
+        let value = if value.is_nan() {
+            return;
+        } else if value == f64::INFINITY {
+            f64::MAX
+        } else if value == f64::NEG_INFINITY {
+            f64::MIN
+        } else {
+            value
+        };
+        let mut metric_labels: FxIndexMap<String, String> = FxIndexMap::default();
+        metric_labels.insert(NAME_LABEL.to_string(), name.to_string());
+        for (key, val) in labels {
+            metric_labels.insert(key.clone(), val.clone());
+        }
+        if let Some((key, val)) = extra_label {
+            metric_labels.insert(key.to_string(), val);
+        }
+        let timestamp = match (time_unix_nano / 1000) as i64 {
+            0 => Utc::now().timestamp_micros(),
+            ts => ts,
+        };
+        self.samples.push(Sample {
+            metric_name: name.to_string(),
+            metric: prom::Metric {
+                labels: metric_labels,
+                value,
+            },
+            timestamp,
+            delta: self.delta,
+        });
+    }
+}
+
+/// Converts an OTLP/JSON request, whose 64 bit integers may be strings and
+/// whose enums may be names, to its protobuf message.
+fn json_request(request: &json::Value) -> ExportMetricsServiceRequest {
+    let resource_metrics = json_array(request, "resourceMetrics")
+        .iter()
+        .map(|res_metrics| {
+            // `instrumentationLibraryMetrics` is the name before OTLP 0.15
+            let metrics = json_array(res_metrics, "scopeMetrics")
+                .iter()
+                .chain(json_array(res_metrics, "instrumentationLibraryMetrics"))
+                .flat_map(|scope_metrics| json_array(scope_metrics, "metrics"))
+                .map(json_metric)
+                .collect();
+            ResourceMetrics {
+                resource: res_metrics.get("resource").map(|resource| Resource {
+                    attributes: json_attributes(resource),
+                    ..Default::default()
+                }),
+                instrumentation_library_metrics: vec![InstrumentationLibraryMetrics {
+                    metrics,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }
+        })
+        .collect();
+    ExportMetricsServiceRequest { resource_metrics }
+}
+
+fn json_metric(metric: &json::Value) -> Metric {
+    let data = if let Some(gauge) = metric.get("gauge") {
+        Some(Data::Gauge(Gauge {
+            data_points: json_points(gauge, json_number_point),
+        }))
+    } else if let Some(sum) = metric.get("sum") {
+        Some(Data::Sum(Sum {
+            data_points: json_points(sum, json_number_point),
+            aggregation_temporality: json_temporality(sum),
+            is_monotonic: sum
+                .get("isMonotonic")
+                .and_then(|v| v.as_bool())
+                .unwrap_or_default(),
+        }))
+    } else if let Some(histogram) = metric.get("histogram") {
+        Some(Data::Histogram(Histogram {
+            data_points: json_points(histogram, json_histogram_point),
+            aggregation_temporality: json_temporality(histogram),
+        }))
+    } else if let Some(histogram) = metric.get("exponentialHistogram") {
+        Some(Data::ExponentialHistogram(ExponentialHistogram {
+            data_points: json_points(histogram, json_exponential_histogram_point),
+            aggregation_temporality: json_temporality(histogram),
+        }))
+    } else {
+        metric.get("summary").map(|summary| {
+            Data::Summary(Summary {
+                data_points: json_points(summary, json_summary_point),
+            })
+        })
+    };
+    Metric {
+        name: json_str(metric, "name"),
+        description: json_str(metric, "description"),
+        unit: json_str(metric, "unit"),
+        data,
+    }
+}
+
+fn json_points<T>(data: &json::Value, point: fn(&json::Value) -> T) -> Vec<T> {
+    json_array(data, "dataPoints").iter().map(point).collect()
+}
+
+fn json_number_point(point: &json::Value) -> NumberDataPoint {
+    let value = if let Some(v) = point.get("asDouble") {
+        Some(number_data_point::Value::AsDouble(
+            json_f64(v).unwrap_or_default(),
+        ))
+    } else {
+        point
+            .get("asInt")
+            .and_then(json_i64)
+            .map(number_data_point::Value::AsInt)
+    };
+    NumberDataPoint {
+        attributes: json_attributes(point),
+        time_unix_nano: json_u64(point, "timeUnixNano"),
+        value,
+        ..Default::default()
+    }
+}
+
+fn json_histogram_point(point: &json::Value) -> HistogramDataPoint {
+    HistogramDataPoint {
+        attributes: json_attributes(point),
+        time_unix_nano: json_u64(point, "timeUnixNano"),
+        count: json_u64(point, "count"),
+        sum: point
+            .get("sum")
+            .and_then(json_f64)
+            .unwrap_or_default()
+            .into(),
+        bucket_counts: json_array(point, "bucketCounts")
+            .iter()
+            .map(|v| json_i64(v).unwrap_or_default() as u64)
+            .collect(),
+        explicit_bounds: json_array(point, "explicitBounds")
+            .iter()
+            .map(|v| json_f64(v).unwrap_or_default())
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn json_exponential_histogram_point(point: &json::Value) -> ExponentialHistogramDataPoint {
+    let buckets = |key: &str| {
+        point.get(key).map(|buckets| Buckets {
+            offset: buckets.get("offset").and_then(json_i64).unwrap_or_default() as i32,
+            bucket_counts: json_array(buckets, "bucketCounts")
+                .iter()
+                .map(|v| json_i64(v).unwrap_or_default() as u64)
+                .collect(),
+        })
+    };
+    ExponentialHistogramDataPoint {
+        attributes: json_attributes(point),
+        time_unix_nano: json_u64(point, "timeUnixNano"),
+        count: json_u64(point, "count"),
+        sum: point
+            .get("sum")
+            .and_then(json_f64)
+            .unwrap_or_default()
+            .into(),
+        scale: point.get("scale").and_then(json_i64).unwrap_or_default() as i32,
+        zero_count: json_u64(point, "zeroCount"),
+        positive: buckets("positive"),
+        negative: buckets("negative"),
+        ..Default::default()
+    }
+}
+
+fn json_summary_point(point: &json::Value) -> SummaryDataPoint {
+    SummaryDataPoint {
+        attributes: json_attributes(point),
+        time_unix_nano: json_u64(point, "timeUnixNano"),
+        count: json_u64(point, "count"),
+        sum: point.get("sum").and_then(json_f64).unwrap_or_default(),
+        quantile_values: json_array(point, "quantileValues")
+            .iter()
+            .map(|v| ValueAtQuantile {
+                quantile: v.get("quantile").and_then(json_f64).unwrap_or_default(),
+                value: v.get("value").and_then(json_f64).unwrap_or_default(),
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn json_temporality(data: &json::Value) -> i32 {
+    match data.get("aggregationTemporality") {
+        Some(json::Value::String(v)) if v == "AGGREGATION_TEMPORALITY_DELTA" => {
+            AggregationTemporality::Delta as i32
+        }
+        Some(json::Value::String(v)) if v == "AGGREGATION_TEMPORALITY_CUMULATIVE" => {
+            AggregationTemporality::Cumulative as i32
+        }
+        Some(v) => json_i64(v).unwrap_or_default() as i32,
+        None => AggregationTemporality::Unspecified as i32,
+    }
+}
+
+/// The attributes are only used as labels, so the values which aren't
+/// scalars are kept as their JSON.
+fn json_attributes(value: &json::Value) -> Vec<KeyValue> {
+    json_array(value, "attributes")
+        .iter()
+        .map(|attr| {
+            let value = attr.get("value").and_then(|v| v.as_object());
+            let value = match value.and_then(|v| v.iter().next()) {
+                Some((key, v)) if key == "stringValue" => {
+                    any_value::Value::StringValue(v.as_str().unwrap_or_default().to_string())
+                }
+                Some((key, v)) if key == "boolValue" => {
+                    any_value::Value::BoolValue(v.as_bool().unwrap_or_default())
+                }
+                Some((key, v)) if key == "intValue" => {
+                    any_value::Value::IntValue(json_i64(v).unwrap_or_default())
+                }
+                Some((key, v)) if key == "doubleValue" => {
+                    any_value::Value::DoubleValue(json_f64(v).unwrap_or_default())
+                }
+                Some((_, v)) => any_value::Value::StringValue(v.to_string()),
+                None => any_value::Value::StringValue(String::new()),
+            };
+            KeyValue {
+                key: json_str(attr, "key"),
+                value: Some(AnyValue { value: Some(value) }),
+            }
+        })
+        .collect()
+}
+
+fn json_u64(value: &json::Value, key: &str) -> u64 {
+    value.get(key).and_then(json_i64).unwrap_or_default() as u64
+}
+
+/// Doubles may also be strings, like `"NaN"` or `"Infinity"`.
+fn json_f64(value: &json::Value) -> Option<f64> {
+    match value {
+        json::Value::Number(v) => v.as_f64(),
+        json::Value::String(v) if v == "Infinity" => Some(f64::INFINITY),
+        json::Value::String(v) if v == "-Infinity" => Some(f64::NEG_INFINITY),
+        json::Value::String(v) => v.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Returns the `job` and `instance` labels of a resource.
+fn resource_labels(attributes: Vec<KeyValue>) -> Vec<(String, String)> {
+    let mut namespace = String::new();
+    let mut job = String::new();
+    let mut instance = String::new();
+    for attr in attributes {
+        match attr.key.as_str() {
+            SERVICE_NAMESPACE => namespace = label_value(get_val(attr.value)),
+            SERVICE_NAME => job = label_value(get_val(attr.value)),
+            SERVICE_INSTANCE_ID => instance = label_value(get_val(attr.value)),
+            _ => {}
+        }
+    }
+    if !namespace.is_empty() && !job.is_empty() {
+        job = format!("{namespace}/{job}");
+    }
+    let mut labels = Vec::new();
+    if !job.is_empty() {
+        labels.push(("job".to_string(), job));
+    }
+    if !instance.is_empty() {
+        labels.push(("instance".to_string(), instance));
+    }
+    labels
+}
+
+fn point_labels(
+    resource_labels: &[(String, String)],
+    attributes: Vec<KeyValue>,
+) -> Vec<(String, String)> {
+    let mut labels = resource_labels.to_vec();
+    for attr in attributes {
+        labels.push((sanitize(&attr.key), label_value(get_val(attr.value))));
+    }
+    labels
+}
+
+fn label_value(value: json::Value) -> String {
+    match value {
+        json::Value::String(v) => v,
+        json::Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+/// Replaces the characters which aren't valid in Prometheus names by `_`.
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{name}")
+    } else {
+        name
+    }
+}
+
+fn format_bound(bound: f64) -> String {
+    if bound == f64::INFINITY {
+        "+Inf".to_string()
+    } else if bound == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        bound.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME: u64 = 1_681_000_000_000_000_000;
+
+    fn attribute(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(any_value::Value::StringValue(value.to_string())),
+            }),
+        }
+    }
+
+    fn request(metric: Metric) -> ExportMetricsServiceRequest {
+        ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(Resource {
+                    attributes: vec![
+                        attribute("service.name", "checkout"),
+                        attribute("service.instance.id", "pod-1"),
+                        attribute("host.name", "node-1"),
+                    ],
+                    ..Default::default()
+                }),
+                instrumentation_library_metrics: vec![InstrumentationLibraryMetrics {
+                    metrics: vec![metric],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn series(samples: &Samples) -> Vec<(String, f64)> {
+        samples
+            .samples
+            .iter()
+            .map(|sample| {
+                let labels = sample
+                    .metric
+                    .labels
+                    .iter()
+                    .map(|(k, v)| format!("{k}={v}"))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                (labels, sample.metric.value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sum() {
+        let samples = Samples::from(request(Metric {
+            name: "http.requests".to_string(),
+            description: "Requests".to_string(),
+            data: Some(Data::Sum(Sum {
+                data_points: vec![NumberDataPoint {
+                    attributes: vec![attribute("http.method", "GET")],
+                    time_unix_nano: TIME,
+                    value: Some(number_data_point::Value::AsInt(42)),
+                    ..Default::default()
+                }],
+                is_monotonic: true,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }));
+        assert_eq!(samples.metadata.len(), 1);
+        assert_eq!(samples.metadata[0].metric_family_name, "http_requests");
+        assert_eq!(samples.metadata[0].metric_type, prom::MetricType::Counter);
+        assert_eq!(samples.samples[0].metric_name, "http_requests");
+        assert_eq!(samples.samples[0].timestamp, 1_681_000_000_000_000);
+        assert_eq!(
+            series(&samples),
+            vec![(
+                "__name__=http_requests,job=checkout,instance=pod-1,http_method=GET".to_string(),
+                42.0
+            )]
+        );
+    }
+
+    #[test]
+    fn test_histogram() {
+        let samples = Samples::from(request(Metric {
+            name: "latency".to_string(),
+            data: Some(Data::Histogram(Histogram {
+                data_points: vec![HistogramDataPoint {
+                    time_unix_nano: TIME,
+                    count: 6,
+                    sum: 12.5.into(),
+                    bucket_counts: vec![1, 2, 3],
+                    explicit_bounds: vec![0.5, 1.0],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }));
+        assert_eq!(samples.metadata[0].metric_type, prom::MetricType::Histogram);
+        assert_eq!(
+            series(&samples),
+            vec![
+                (
+                    "__name__=latency_bucket,job=checkout,instance=pod-1,le=0.5".to_string(),
+                    1.0
+                ),
+                (
+                    "__name__=latency_bucket,job=checkout,instance=pod-1,le=1".to_string(),
+                    3.0
+                ),
+                (
+                    "__name__=latency_bucket,job=checkout,instance=pod-1,le=+Inf".to_string(),
+                    6.0
+                ),
+                (
+                    "__name__=latency_sum,job=checkout,instance=pod-1".to_string(),
+                    12.5
+                ),
+                (
+                    "__name__=latency_count,job=checkout,instance=pod-1".to_string(),
+                    6.0
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exponential_histogram() {
+        let samples = Samples::from(request(Metric {
+            name: "latency".to_string(),
+            data: Some(Data::ExponentialHistogram(ExponentialHistogram {
+                data_points: vec![ExponentialHistogramDataPoint {
+                    time_unix_nano: TIME,
+                    count: 7,
+                    scale: 0,
+                    zero_count: 1,
+                    positive: Some(Buckets {
+                        offset: 0,
+                        bucket_counts: vec![2, 3],
+                    }),
+                    negative: Some(Buckets {
+                        offset: 1,
+                        bucket_counts: vec![1],
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })),
+            ..Default::default()
+        }));
+        let buckets: Vec<(String, f64)> = samples
+            .samples
+            .iter()
+            .filter(|sample| sample.metric_name == "latency_bucket")
+            .map(|sample| (sample.metric.labels[LE_LABEL].clone(), sample.metric.value))
+            .collect();
+        assert_eq!(
+            buckets,
+            vec![
+                ("-2".to_string(), 1.0),
+                ("0".to_string(), 2.0),
+                ("2".to_string(), 4.0),
+                ("4".to_string(), 7.0),
+                ("+Inf".to_string(), 7.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let samples = Samples::from(request(Metric {
+            name: "rpc_duration".to_string(),
+            data: Some(Data::Summary(Summary {
+                data_points: vec![SummaryDataPoint {
+                    time_unix_nano: TIME,
+                    count: 10,
+                    sum: 3.0,
+                    quantile_values: vec![ValueAtQuantile {
+                        quantile: 0.99,
+                        value: 0.8,
+                    }],
+                    ..Default::default()
+                }],
+            })),
+            ..Default::default()
+        }));
+        assert_eq!(
+            series(&samples),
+            vec![
+                (
+                    "__name__=rpc_duration,job=checkout,instance=pod-1,quantile=0.99".to_string(),
+                    0.8
+                ),
+                (
+                    "__name__=rpc_duration_sum,job=checkout,instance=pod-1".to_string(),
+                    3.0
+                ),
+                (
+                    "__name__=rpc_duration_count,job=checkout,instance=pod-1".to_string(),
+                    10.0
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delta_sum() {
+        let delta = |value, now| {
+            let mut samples = Samples::from(request(Metric {
+                name: "delta_requests".to_string(),
+                data: Some(Data::Sum(Sum {
+                    data_points: vec![NumberDataPoint {
+                        time_unix_nano: TIME,
+                        value: Some(number_data_point::Value::AsInt(value)),
+                        ..Default::default()
+                    }],
+                    aggregation_temporality: AggregationTemporality::Delta as i32,
+                    is_monotonic: true,
+                })),
+                ..Default::default()
+            }))
+            .samples;
+            accumulate("test_delta_sum", &mut samples, now);
+            assert_eq!(
+                samples[0].metric.labels.get(INGESTER_LABEL),
+                Some(&CONFIG.common.instance_name)
+            );
+            samples[0].metric.value
+        };
+        let now = Utc::now().timestamp_micros();
+        assert_eq!(delta(5, now), 5.0);
+        assert_eq!(delta(3, now), 8.0);
+        // the total restarts once the series has been idle
+        let later = now + CONFIG.limit.metrics_delta_idle * 1_000_000 + DELTA_EVICT_INTERVAL + 1;
+        assert_eq!(delta(2, later), 2.0);
+    }
+
+    #[test]
+    fn test_json_request() {
+        let request = json::json!({
+            "resourceMetrics": [{
+                "resource": {
+                    "attributes": [{"key": "service.name", "value": {"stringValue": "checkout"}}]
+                },
+                "scopeMetrics": [{
+                    "metrics": [{
+                        "name": "http.requests",
+                        "sum": {
+                            "dataPoints": [{
+                                "attributes": [{"key": "code", "value": {"intValue": "200"}}],
+                                "timeUnixNano": "1681000000000000000",
+                                "asInt": "42"
+                            }],
+                            "aggregationTemporality": 2,
+                            "isMonotonic": true
+                        }
+                    }]
+                }]
+            }]
+        });
+        let samples = Samples::from(json_request(&request));
+        assert_eq!(samples.metadata[0].metric_type, prom::MetricType::Counter);
+        assert_eq!(samples.samples[0].timestamp, 1_681_000_000_000_000);
+        assert_eq!(
+            series(&samples),
+            vec![(
+                "__name__=http_requests,job=checkout,code=200".to_string(),
+                42.0
+            )]
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("http.server.duration"), "http_server_duration");
+        assert_eq!(sanitize("1xx-count"), "_1xx_count");
+    }
+}
@@ -306,6 +306,7 @@ pub async fn remote_write(
                                         last_sent_at: 0,
                                         count: 0,
                                         is_ingest_time: true,
+                                        series: Default::default(),
                                     },
                                 );
                             }
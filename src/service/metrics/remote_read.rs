@@ -0,0 +1,723 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prometheus remote-read API.
+//!
+//! Every query of a `ReadRequest` is turned into a range vector selector and
+//! evaluated at its end timestamp, so it goes through the same label matching
+//! and cluster fan-out as PromQL queries do. The time range and the number of
+//! series of a query can be limited by `ZO_METRICS_REMOTE_READ_MAX_RANGE` and
+//! `ZO_METRICS_REMOTE_READ_MAX_SERIES`, as the series of a query are loaded
+//! before they are sent. The time range is unlimited by default, so that
+//! Prometheus can read the long-term history.
+//!
+//! All the queries are checked before the response starts, and the first
+//! query is evaluated before it too. An error in a later query of a streamed
+//! response can only end the stream early.
+//!
+//! See <https://prometheus.io/docs/prometheus/latest/querying/remote_read_api/>
+
+use actix_web::{http, web, HttpResponse};
+use futures::{stream, StreamExt, TryStreamExt};
+use prost::Message;
+use std::io::{Error, ErrorKind};
+
+use super::prom::prometheus::{
+    self, chunk, label_matcher, read_request::ResponseType, ChunkedReadResponse, ChunkedSeries,
+    QueryResult, ReadRequest, ReadResponse,
+};
+use crate::infra::config::CONFIG;
+use crate::meta::{http::HttpResponse as MetaHttpResponse, prom::NAME_LABEL};
+use crate::service::promql::{
+    self,
+    value::{RangeValue, Value},
+};
+
+/// Maximum number of samples in a chunk, as in Prometheus TSDB.
+const SAMPLES_PER_CHUNK: usize = 120;
+
+/// The `func` read hint of the queries which only need the labels of the
+/// series.
+const SERIES_FUNC: &str = "series";
+
+/// A remote-read query, checked and ready to be evaluated.
+#[derive(Debug, PartialEq)]
+struct ReadQuery {
+    /// Range vector selector of the query, see [`to_promql`].
+    promql: String,
+    /// Time range of the query, microseconds.
+    start: i64,
+    /// Evaluation timestamp, microseconds.
+    end: i64,
+    /// Step of the query which reads the series, microseconds.
+    step: i64,
+    /// Whether only the labels of the series are returned.
+    series_only: bool,
+}
+
+#[tracing::instrument(skip(body))]
+pub async fn remote_read(org_id: &str, body: web::Bytes) -> Result<HttpResponse, Error> {
+    let request = match snap::raw::Decoder::new()
+        .decompress_vec(&body)
+        .map_err(|e| e.to_string())
+        .and_then(|v| ReadRequest::decode(v.as_slice()).map_err(|e| e.to_string()))
+    {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!("Invalid remote read request: {e}"),
+            )))
+        }
+    };
+    let queries = match request
+        .queries
+        .iter()
+        .map(prepare)
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+                http::StatusCode::BAD_REQUEST.into(),
+                format!("Invalid remote read request: {e}"),
+            )))
+        }
+    };
+    // the first accepted response type is the preferred one
+    let streamed =
+        request.accepted_response_types.first() == Some(&(ResponseType::StreamedXorChunks as i32));
+
+    let mut queries = queries.into_iter();
+    let first = match queries.next() {
+        Some(query) => match read_query(org_id, &query).await {
+            Ok(v) => Some(v),
+            Err(e) => {
+                log::error!("remote read error: {e}");
+                return Ok(
+                    HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                        http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                        e.to_string(),
+                    )),
+                );
+            }
+        },
+        None => None,
+    };
+
+    if streamed {
+        // the other queries are evaluated one by one while the response is
+        // being sent
+        let org_id = org_id.to_string();
+        let first = stream::iter(first.map(|matrix| Ok::<_, Error>((0, matrix))));
+        let rest = stream::iter(queries.enumerate()).then(move |(index, query)| {
+            let org_id = org_id.clone();
+            async move {
+                let matrix = read_query(&org_id, &query).await.map_err(|e| {
+                    log::error!("remote read error: {e}");
+                    Error::new(ErrorKind::Other, e.to_string())
+                })?;
+                Ok::<_, Error>((index + 1, matrix))
+            }
+        });
+        let frames = first
+            .chain(rest)
+            .map_ok(|(index, matrix)| {
+                stream::iter(matrix.into_iter().map(move |series| {
+                    let resp = ChunkedReadResponse {
+                        chunked_series: vec![to_chunked_series(series)],
+                        query_index: index as i64,
+                    };
+                    Ok::<_, Error>(web::Bytes::from(encode_frame(&resp.encode_to_vec())))
+                }))
+            })
+            .try_flatten();
+        return Ok(HttpResponse::Ok()
+            .content_type("application/x-streamed-protobuf; proto=prometheus.ChunkedReadResponse")
+            .streaming(frames));
+    }
+
+    let mut results = Vec::with_capacity(request.queries.len());
+    if let Some(matrix) = first {
+        results.push(QueryResult {
+            timeseries: matrix.into_iter().map(to_timeseries).collect(),
+        });
+    }
+    for query in queries {
+        let matrix = match read_query(org_id, &query).await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("remote read error: {e}");
+                return Ok(
+                    HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                        http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                        e.to_string(),
+                    )),
+                );
+            }
+        };
+        results.push(QueryResult {
+            timeseries: matrix.into_iter().map(to_timeseries).collect(),
+        });
+    }
+    let resp = ReadResponse { results };
+    let body = match snap::raw::Encoder::new().compress_vec(&resp.encode_to_vec()) {
+        Ok(v) => v,
+        Err(e) => {
+            return Ok(
+                HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                    http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                    e.to_string(),
+                )),
+            )
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-protobuf")
+        .insert_header((http::header::CONTENT_ENCODING, "snappy"))
+        .body(body))
+}
+
+/// Checks a remote-read query and narrows it to its read hints.
+fn prepare(query: &prometheus::Query) -> Result<ReadQuery, anyhow::Error> {
+    let mut query = query.clone();
+    let mut step = 0;
+    let mut series_only = false;
+    if let Some(hints) = query.hints.take() {
+        if hints.start_ms > 0 {
+            query.start_timestamp_ms = query.start_timestamp_ms.max(hints.start_ms);
+        }
+        if hints.end_ms > 0 {
+            query.end_timestamp_ms = query.end_timestamp_ms.min(hints.end_ms);
+        }
+        step = hints.step_ms.max(0) * 1000;
+        series_only = hints.func == SERIES_FUNC;
+    }
+    let max_range = CONFIG.limit.metrics_remote_read_max_range * 3_600_000;
+    if max_range > 0 && query.end_timestamp_ms - query.start_timestamp_ms > max_range {
+        return Err(anyhow::anyhow!(
+            "the time range of a query exceeds {} hours",
+            CONFIG.limit.metrics_remote_read_max_range
+        ));
+    }
+    Ok(ReadQuery {
+        promql: to_promql(&query)?,
+        start: query.start_timestamp_ms * 1000,
+        end: query.end_timestamp_ms * 1000,
+        step,
+        series_only,
+    })
+}
+
+/// Loads the raw samples of the series matching a remote-read query.
+async fn read_query(org_id: &str, query: &ReadQuery) -> Result<Vec<RangeValue>, anyhow::Error> {
+    if query.start > query.end {
+        return Ok(vec![]);
+    }
+    let req = promql::MetricsQueryRequest {
+        query: query.promql.clone(),
+        start: query.end,
+        end: query.end,
+        step: query.step,
+    };
+    let mut matrix = match promql::search::search(org_id, &req).await? {
+        Value::Matrix(v) => v,
+        Value::None => return Ok(vec![]),
+        v => {
+            return Err(anyhow::anyhow!(
+                "remote read: unexpected result type {}",
+                v.get_type()
+            ))
+        }
+    };
+    // series without samples in the time range aren't returned by Prometheus
+    matrix.retain(|series| !series.samples.is_empty());
+    let max_series = CONFIG.limit.metrics_remote_read_max_series;
+    if max_series > 0 && matrix.len() > max_series {
+        return Err(anyhow::anyhow!(
+            "remote read: the query {} matches more than {max_series} series",
+            query.promql
+        ));
+    }
+    if query.series_only {
+        matrix.iter_mut().for_each(|series| series.samples.clear());
+    }
+    Ok(matrix)
+}
+
+/// Converts a remote-read query into a range vector selector, e.g.
+/// `up{job="api"}[3600001ms]`. The range selector excludes its start, hence
+/// one extra millisecond so that samples at `start_timestamp_ms` are returned.
+///
+/// Metrics are stored in a stream per metric name, so an equality matcher on
+/// `__name__` is required.
+fn to_promql(query: &prometheus::Query) -> Result<String, anyhow::Error> {
+    let mut name = None;
+    let mut matchers = Vec::with_capacity(query.matchers.len());
+    for mat in query.matchers.iter() {
+        let op = match mat.r#type() {
+            label_matcher::Type::Eq => "=",
+            label_matcher::Type::Neq => "!=",
+            label_matcher::Type::Re => "=~",
+            label_matcher::Type::Nre => "!~",
+        };
+        if mat.name == NAME_LABEL && op == "=" && name.is_none() {
+            name = Some(mat.value.as_str());
+        } else {
+            matchers.push(format!("{}{}{:?}", mat.name, op, mat.value));
+        }
+    }
+    let Some(name) = name else {
+        return Err(anyhow::anyhow!(
+            "remote read requires an equality matcher on {NAME_LABEL}"
+        ));
+    };
+    let range = query.end_timestamp_ms - query.start_timestamp_ms + 1;
+    Ok(format!("{name}{{{}}}[{range}ms]", matchers.join(",")))
+}
+
+fn to_labels(series: &RangeValue) -> Vec<prometheus::Label> {
+    let mut labels = series
+        .labels
+        .iter()
+        .map(|l| prometheus::Label {
+            name: l.name.clone(),
+            value: l.value.clone(),
+        })
+        .collect::<Vec<_>>();
+    labels.sort_by(|a, b| a.name.cmp(&b.name));
+    labels
+}
+
+fn to_timeseries(series: RangeValue) -> prometheus::TimeSeries {
+    prometheus::TimeSeries {
+        labels: to_labels(&series),
+        samples: series
+            .samples
+            .iter()
+            .map(|s| prometheus::Sample {
+                value: s.value,
+                timestamp: s.timestamp / 1000,
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn to_chunked_series(series: RangeValue) -> ChunkedSeries {
+    let chunks = series
+        .samples
+        .chunks(SAMPLES_PER_CHUNK)
+        .map(|samples| {
+            let mut chunk = XorChunk::new();
+            for s in samples {
+                chunk.append(s.timestamp / 1000, s.value);
+            }
+            prometheus::Chunk {
+                min_time_ms: samples[0].timestamp / 1000,
+                max_time_ms: samples[samples.len() - 1].timestamp / 1000,
+                r#type: chunk::Encoding::Xor as i32,
+                data: chunk.into_bytes(),
+            }
+        })
+        .collect();
+    ChunkedSeries {
+        labels: to_labels(&series),
+        chunks,
+    }
+}
+
+/// Frames a message of a streamed response: the message size as uvarint,
+/// the CRC-32C of the message as big-endian u32, then the message itself.
+fn encode_frame(msg: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(msg.len() + 14);
+    let mut size = msg.len() as u64;
+    while size >= 0x80 {
+        buf.push(size as u8 | 0x80);
+        size >>= 7;
+    }
+    buf.push(size as u8);
+    buf.extend_from_slice(&crc32c(msg).to_be_bytes());
+    buf.extend_from_slice(msg);
+    buf
+}
+
+/// CRC-32 with the Castagnoli polynomial, as used by Prometheus for framing.
+fn crc32c(data: &[u8]) -> u32 {
+    const POLY: u32 = 0x82f6_3b78; // reversed 0x1edc6f41
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Writes bits most significant first.
+#[derive(Default)]
+struct BitWriter {
+    buf: Vec<u8>,
+    /// Number of bits still free in the last byte.
+    free: u8,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.free == 0 {
+            self.buf.push(0);
+            self.free = 8;
+        }
+        self.free -= 1;
+        if bit {
+            *self.buf.last_mut().unwrap() |= 1 << self.free;
+        }
+    }
+
+    /// Writes the `nbits` least significant bits of `v`.
+    fn write_bits(&mut self, v: u64, nbits: u8) {
+        for i in (0..nbits).rev() {
+            self.write_bit((v >> i) & 1 == 1);
+        }
+    }
+
+    fn write_uvarint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.write_bits((v & 0x7f) | 0x80, 8);
+            v >>= 7;
+        }
+        self.write_bits(v, 8);
+    }
+
+    fn write_varint(&mut self, v: i64) {
+        self.write_uvarint(((v << 1) ^ (v >> 63)) as u64);
+    }
+}
+
+/// Gorilla-style chunk of float samples, binary compatible with the XOR
+/// chunks of Prometheus TSDB: a big-endian u16 sample count followed by
+/// delta-of-delta encoded timestamps and XOR encoded values.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/tsdb/chunkenc/xor.go
+struct XorChunk {
+    b: BitWriter,
+    num: u16,
+    t: i64,
+    v: f64,
+    t_delta: i64,
+    leading: u8,
+    trailing: u8,
+}
+
+impl XorChunk {
+    fn new() -> Self {
+        Self {
+            b: BitWriter {
+                buf: vec![0, 0],
+                free: 0,
+            },
+            num: 0,
+            t: 0,
+            v: 0.0,
+            t_delta: 0,
+            leading: 0xff,
+            trailing: 0,
+        }
+    }
+
+    fn append(&mut self, t: i64, v: f64) {
+        match self.num {
+            0 => {
+                self.b.write_varint(t);
+                self.b.write_bits(v.to_bits(), 64);
+            }
+            1 => {
+                self.t_delta = t - self.t;
+                self.b.write_uvarint(self.t_delta as u64);
+                self.write_value(v);
+            }
+            _ => {
+                let t_delta = t - self.t;
+                let dod = t_delta - self.t_delta;
+                if dod == 0 {
+                    self.b.write_bit(false);
+                } else if bit_range(dod, 14) {
+                    self.b.write_bits(0b10, 2);
+                    self.b.write_bits(dod as u64, 14);
+                } else if bit_range(dod, 17) {
+                    self.b.write_bits(0b110, 3);
+                    self.b.write_bits(dod as u64, 17);
+                } else if bit_range(dod, 20) {
+                    self.b.write_bits(0b1110, 4);
+                    self.b.write_bits(dod as u64, 20);
+                } else {
+                    self.b.write_bits(0b1111, 4);
+                    self.b.write_bits(dod as u64, 64);
+                }
+                self.t_delta = t_delta;
+                self.write_value(v);
+            }
+        }
+        self.t = t;
+        self.v = v;
+        self.num += 1;
+    }
+
+    fn write_value(&mut self, v: f64) {
+        let delta = v.to_bits() ^ self.v.to_bits();
+        if delta == 0 {
+            self.b.write_bit(false);
+            return;
+        }
+        self.b.write_bit(true);
+
+        // the number of leading zeros is stored in 5 bits
+        let leading = (delta.leading_zeros() as u8).min(31);
+        let trailing = delta.trailing_zeros() as u8;
+        if self.leading != 0xff && leading >= self.leading && trailing >= self.trailing {
+            // the meaningful bits fit into the previous window
+            self.b.write_bit(false);
+            self.b
+                .write_bits(delta >> self.trailing, 64 - self.leading - self.trailing);
+            return;
+        }
+        self.leading = leading;
+        self.trailing = trailing;
+        self.b.write_bit(true);
+        self.b.write_bits(leading as u64, 5);
+        // 64 significant bits overflow to 0, which can't be a valid value
+        let sigbits = 64 - leading - trailing;
+        self.b.write_bits(sigbits as u64, 6);
+        self.b.write_bits(delta >> trailing, sigbits);
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.b.buf[..2].copy_from_slice(&self.num.to_be_bytes());
+        self.b.buf
+    }
+}
+
+/// Whether `x` can be represented in `nbits` bits.
+fn bit_range(x: i64, nbits: u8) -> bool {
+    -((1 << (nbits - 1)) - 1) <= x && x <= 1 << (nbits - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads back what [`BitWriter`] wrote.
+    struct BitReader<'a> {
+        buf: &'a [u8],
+        pos: usize,
+    }
+
+    impl BitReader<'_> {
+        fn read_bit(&mut self) -> bool {
+            let bit = (self.buf[self.pos / 8] >> (7 - self.pos % 8)) & 1 == 1;
+            self.pos += 1;
+            bit
+        }
+
+        fn read_bits(&mut self, nbits: u8) -> u64 {
+            (0..nbits).fold(0, |v, _| (v << 1) | self.read_bit() as u64)
+        }
+
+        fn read_uvarint(&mut self) -> u64 {
+            let mut v = 0;
+            for shift in (0..).step_by(7) {
+                let b = self.read_bits(8);
+                v |= (b & 0x7f) << shift;
+                if b < 0x80 {
+                    break;
+                }
+            }
+            v
+        }
+
+        fn read_varint(&mut self) -> i64 {
+            let v = self.read_uvarint();
+            (v >> 1) as i64 ^ -((v & 1) as i64)
+        }
+    }
+
+    fn decode(data: &[u8]) -> Vec<(i64, f64)> {
+        let num = u16::from_be_bytes([data[0], data[1]]);
+        let mut r = BitReader {
+            buf: &data[2..],
+            pos: 0,
+        };
+        let mut samples = Vec::new();
+        let (mut t, mut v, mut t_delta) = (0i64, 0f64, 0i64);
+        let (mut leading, mut trailing) = (0u8, 0u8);
+        for i in 0..num {
+            match i {
+                0 => {
+                    t = r.read_varint();
+                    v = f64::from_bits(r.read_bits(64));
+                    samples.push((t, v));
+                    continue;
+                }
+                1 => t_delta = r.read_uvarint() as i64,
+                _ => {
+                    let (prefix, nbits) = if !r.read_bit() {
+                        (0, 0)
+                    } else if !r.read_bit() {
+                        (2, 14)
+                    } else if !r.read_bit() {
+                        (3, 17)
+                    } else if !r.read_bit() {
+                        (4, 20)
+                    } else {
+                        (4, 64)
+                    };
+                    if prefix > 0 {
+                        let bits = r.read_bits(nbits);
+                        // sign extension
+                        let dod = if nbits < 64 && bits > 1 << (nbits - 1) {
+                            bits as i64 - (1 << nbits)
+                        } else {
+                            bits as i64
+                        };
+                        t_delta += dod;
+                    }
+                }
+            }
+            t += t_delta;
+            if r.read_bit() {
+                if r.read_bit() {
+                    leading = r.read_bits(5) as u8;
+                    let sigbits = match r.read_bits(6) as u8 {
+                        0 => 64,
+                        n => n,
+                    };
+                    trailing = 64 - leading - sigbits;
+                }
+                let delta = r.read_bits(64 - leading - trailing) << trailing;
+                v = f64::from_bits(v.to_bits() ^ delta);
+            }
+            samples.push((t, v));
+        }
+        samples
+    }
+
+    #[test]
+    fn test_xor_chunk() {
+        let samples = vec![
+            (1_672_531_200_000, 1.0),
+            (1_672_531_215_000, 1.0),
+            (1_672_531_230_000, 2.5),
+            (1_672_531_245_001, 2.75),
+            (1_672_531_320_000, -3.0),
+            (1_672_531_335_000, f64::MAX),
+            (1_672_531_350_000, 0.0),
+            (1_672_541_350_000, 0.1),
+            (1_672_641_350_000, 1e-300),
+        ];
+        let mut chunk = XorChunk::new();
+        for (t, v) in samples.iter() {
+            chunk.append(*t, *v);
+        }
+        assert_eq!(decode(&chunk.into_bytes()), samples);
+    }
+
+    #[test]
+    fn test_encode_frame() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        let msg = vec![1u8; 200];
+        let frame = encode_frame(&msg);
+        assert_eq!(&frame[..2], &[0xc8, 0x01]);
+        assert_eq!(&frame[2..6], &crc32c(&msg).to_be_bytes());
+        assert_eq!(&frame[6..], msg.as_slice());
+    }
+
+    #[test]
+    fn test_to_promql() {
+        let matcher =
+            |typ: label_matcher::Type, name: &str, value: &str| prometheus::LabelMatcher {
+                r#type: typ as i32,
+                name: name.to_string(),
+                value: value.to_string(),
+            };
+        let mut query = prometheus::Query {
+            start_timestamp_ms: 1_000,
+            end_timestamp_ms: 61_000,
+            matchers: vec![
+                matcher(label_matcher::Type::Eq, NAME_LABEL, "up"),
+                matcher(label_matcher::Type::Re, "job", "api|web"),
+                matcher(label_matcher::Type::Neq, "env", "dev"),
+            ],
+            hints: None,
+        };
+        assert_eq!(
+            to_promql(&query).unwrap(),
+            r#"up{job=~"api|web",env!="dev"}[60001ms]"#
+        );
+
+        query.matchers[0] = matcher(label_matcher::Type::Re, NAME_LABEL, "up|down");
+        assert!(to_promql(&query).is_err());
+    }
+
+    #[test]
+    fn test_prepare() {
+        let mut query = prometheus::Query {
+            start_timestamp_ms: 1_000,
+            end_timestamp_ms: 61_000,
+            matchers: vec![prometheus::LabelMatcher {
+                r#type: label_matcher::Type::Eq as i32,
+                name: NAME_LABEL.to_string(),
+                value: "up".to_string(),
+            }],
+            hints: None,
+        };
+        assert_eq!(
+            prepare(&query).unwrap(),
+            ReadQuery {
+                promql: "up{}[60001ms]".to_string(),
+                start: 1_000_000,
+                end: 61_000_000,
+                step: 0,
+                series_only: false,
+            }
+        );
+
+        query.hints = Some(prometheus::ReadHints {
+            step_ms: 15_000,
+            func: SERIES_FUNC.to_string(),
+            start_ms: 31_000,
+            end_ms: 91_000,
+            ..Default::default()
+        });
+        assert_eq!(
+            prepare(&query).unwrap(),
+            ReadQuery {
+                promql: "up{}[30001ms]".to_string(),
+                start: 31_000_000,
+                end: 61_000_000,
+                step: 15_000_000,
+                series_only: true,
+            }
+        );
+
+        // the time range is unlimited by default
+        query.hints = None;
+        query.end_timestamp_ms = query.start_timestamp_ms + 365 * 24 * 3_600_000;
+        assert!(prepare(&query).is_ok());
+    }
+}
@@ -0,0 +1,681 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use actix_web::{http, web, HttpResponse};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use prost::Message;
+use rand::seq::SliceRandom;
+use std::{collections::HashMap, io::Error};
+use tonic::{metadata::MetadataValue, transport::Channel, Request};
+
+use super::prom::{self, prometheus};
+use crate::handler::grpc::cluster_rpc::{metrics_client::MetricsClient, MetricsWriteRequest};
+use crate::infra::{
+    cluster,
+    config::{CONFIG, PROM_RULE_GROUPS, TRIGGERS},
+    db::etcd,
+};
+use crate::meta::{
+    alert::{Alert, AlertKind, AlertState, Trigger},
+    http::HttpResponse as MetaHttpResponse,
+    prom::{
+        AlertDiscovery, AlertResponse, FxIndexMap, RecordingRule, RuleDiscovery, RuleGroup,
+        RuleGroupList, RuleGroupResponse, RuleResponse, NAME_LABEL,
+    },
+};
+use crate::service::{
+    db,
+    promql::{self, value::Value},
+};
+
+/// key — `{org_id}/{group_name}`; value — the last evaluation timestamp of
+/// the group, microseconds.
+static LAST_EVALUATED: Lazy<DashMap<String, i64>> = Lazy::new(DashMap::new);
+
+/// key — `{org_id}/{group_name}`; value — the outcome of the last completed
+/// evaluation of the group.
+static EVALUATIONS: Lazy<DashMap<String, GroupEvaluation>> = Lazy::new(DashMap::new);
+
+/// key — gRPC address of an ingester; value — the channel rule results are
+/// forwarded over, reused across evaluations.
+static CHANNELS: Lazy<DashMap<String, Channel>> = Lazy::new(DashMap::new);
+
+struct GroupEvaluation {
+    /// Evaluation timestamp, microseconds.
+    timestamp: i64,
+    /// How long the evaluation took, seconds.
+    duration: f64,
+    /// key — rule name; value — error message.
+    errors: HashMap<String, String>,
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn save_rule_group(
+    org_id: String,
+    name: String,
+    mut group: RuleGroup,
+) -> Result<HttpResponse, Error> {
+    group.name = name.clone();
+    if let Err(e) = validate(&group) {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e,
+        )));
+    }
+    if let Err(e) = db::prom_rules::set(&org_id, &name, &group).await {
+        return Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        );
+    }
+    Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+        http::StatusCode::OK.into(),
+        "Rule group saved".to_string(),
+    )))
+}
+
+#[tracing::instrument]
+pub async fn list_rule_groups(org_id: String) -> Result<HttpResponse, Error> {
+    match db::prom_rules::list(&org_id).await {
+        Ok(list) => Ok(HttpResponse::Ok().json(RuleGroupList { list })),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn get_rule_group(org_id: String, name: String) -> Result<HttpResponse, Error> {
+    match db::prom_rules::get(&org_id, &name).await {
+        Ok(Some(group)) => Ok(HttpResponse::Ok().json(group)),
+        _ => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            "Rule group not found".to_string(),
+        ))),
+    }
+}
+
+#[tracing::instrument]
+pub async fn delete_rule_group(org_id: String, name: String) -> Result<HttpResponse, Error> {
+    match db::prom_rules::delete(&org_id, &name).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+            http::StatusCode::OK.into(),
+            "Rule group deleted".to_string(),
+        ))),
+        Err(e) => Ok(HttpResponse::NotFound().json(MetaHttpResponse::error(
+            http::StatusCode::NOT_FOUND.into(),
+            e.to_string(),
+        ))),
+    }
+}
+
+/// Lists the recording rule groups and the PromQL alerts of the organization
+/// in the format of the Prometheus `/api/v1/rules` API. Every PromQL alert is
+/// reported as a group of its own.
+///
+/// The health of recording rules is only known on the node which evaluates
+/// them; other nodes report `unknown`.
+#[tracing::instrument]
+pub async fn get_rules(
+    org_id: &str,
+    with_alerts: bool,
+    with_records: bool,
+) -> Result<RuleDiscovery, anyhow::Error> {
+    let mut groups = Vec::new();
+    if with_records {
+        for group in db::prom_rules::list(org_id).await? {
+            groups.push(recording_group_response(org_id, group));
+        }
+    }
+    if with_alerts {
+        for alert in promql_alerts(org_id).await? {
+            groups.push(alerting_group_response(alert));
+        }
+    }
+    Ok(RuleDiscovery { groups })
+}
+
+/// Lists the pending and firing series of the PromQL alerts of the
+/// organization in the format of the Prometheus `/api/v1/alerts` API.
+#[tracing::instrument]
+pub async fn get_alerts(org_id: &str) -> Result<AlertDiscovery, anyhow::Error> {
+    let alerts = promql_alerts(org_id)
+        .await?
+        .iter()
+        .flat_map(|alert| active_alerts(alert, TRIGGERS.get(&alert.name).as_deref()))
+        .collect();
+    Ok(AlertDiscovery { alerts })
+}
+
+async fn promql_alerts(org_id: &str) -> Result<Vec<Alert>, anyhow::Error> {
+    Ok(db::alerts::list(org_id, None, None)
+        .await?
+        .into_iter()
+        .filter(|alert| alert.kind == AlertKind::Promql)
+        .collect())
+}
+
+fn recording_group_response(org_id: &str, group: RuleGroup) -> RuleGroupResponse {
+    let evaluation = EVALUATIONS.get(&format!("{org_id}/{}", group.name));
+    let last_evaluation = evaluation
+        .as_ref()
+        .map(|ev| rfc3339(ev.timestamp))
+        .unwrap_or_else(|| rfc3339(0));
+    let rules = group
+        .rules
+        .into_iter()
+        .map(|rule| {
+            let last_error = evaluation
+                .as_ref()
+                .and_then(|ev| ev.errors.get(&rule.record).cloned());
+            let health = match (&evaluation, &last_error) {
+                (None, _) => "unknown",
+                (Some(_), None) => "ok",
+                (Some(_), Some(_)) => "err",
+            };
+            RuleResponse::Recording {
+                name: rule.record,
+                query: rule.expr,
+                labels: rule.labels,
+                health: health.to_string(),
+                last_error,
+                evaluation_time: 0.0,
+                last_evaluation: last_evaluation.clone(),
+            }
+        })
+        .collect();
+    RuleGroupResponse {
+        name: group.name,
+        file: String::new(),
+        rules,
+        interval: group.interval as f64,
+        evaluation_time: evaluation.as_ref().map_or(0.0, |ev| ev.duration),
+        last_evaluation,
+    }
+}
+
+fn alerting_group_response(alert: Alert) -> RuleGroupResponse {
+    let trigger = TRIGGERS.get(&alert.name);
+    let alerts = active_alerts(&alert, trigger.as_deref());
+    let state = if alerts
+        .iter()
+        .any(|a| a.state == AlertState::Firing.to_string())
+    {
+        AlertState::Firing.to_string()
+    } else if !alerts.is_empty() {
+        AlertState::Pending.to_string()
+    } else {
+        "inactive".to_string()
+    };
+    let rule = RuleResponse::Alerting {
+        name: alert.name.clone(),
+        query: alert.promql.clone().unwrap_or_default(),
+        duration: alert.for_duration as f64,
+        labels: HashMap::new(),
+        annotations: alert.context_attributes.clone().unwrap_or_default(),
+        alerts,
+        health: if trigger.is_some() { "ok" } else { "unknown" }.to_string(),
+        state,
+        evaluation_time: 0.0,
+        last_evaluation: rfc3339(0),
+    };
+    RuleGroupResponse {
+        name: alert.name,
+        file: String::new(),
+        rules: vec![rule],
+        interval: (alert.frequency * 60) as f64,
+        evaluation_time: 0.0,
+        last_evaluation: rfc3339(0),
+    }
+}
+
+/// Returns the pending and firing series of an alert.
+fn active_alerts(alert: &Alert, trigger: Option<&Trigger>) -> Vec<AlertResponse> {
+    let Some(trigger) = trigger else {
+        return vec![];
+    };
+    trigger
+        .series
+        .values()
+        .filter(|series| series.state != AlertState::Resolved)
+        .map(|series| AlertResponse {
+            labels: series
+                .labels
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            annotations: alert.context_attributes.clone().unwrap_or_default(),
+            state: series.state.to_string(),
+            active_at: rfc3339(series.active_at),
+            value: series.value.to_string(),
+        })
+        .collect()
+}
+
+/// Formats a timestamp in microseconds as RFC 3339. Zero stands for "never"
+/// and is formatted like the zero time of Go, as Prometheus does.
+fn rfc3339(ts: i64) -> String {
+    if ts == 0 {
+        return "0001-01-01T00:00:00Z".to_string();
+    }
+    Utc.timestamp_nanos(ts * 1000)
+        .to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn validate(group: &RuleGroup) -> Result<(), String> {
+    if group.interval <= 0 {
+        return Err(format!(
+            "Rule group interval must be positive, got {}",
+            group.interval
+        ));
+    }
+    if group.rules.is_empty() {
+        return Err("Rule group must contain at least one rule".to_string());
+    }
+    for rule in &group.rules {
+        if !is_valid_metric_name(&rule.record) {
+            return Err(format!("Invalid recording rule name: {:?}", rule.record));
+        }
+        if let Err(e) = promql_parser::parser::parse(&rule.expr) {
+            return Err(format!("Invalid expression of rule {}: {e}", rule.record));
+        }
+        if let Some(name) = rule
+            .labels
+            .keys()
+            .find(|name| !promql::value::is_valid_label_name(name))
+        {
+            return Err(format!(
+                "Invalid label name of rule {}: {name:?}",
+                rule.record
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Metric names must match `[a-zA-Z_:][a-zA-Z0-9_:]*`.
+fn is_valid_metric_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == ':' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Evaluates the rule groups which are due. Called periodically by the alert
+/// manager.
+///
+/// Every alert manager node runs this, a group which is due is evaluated by
+/// the node which claims it first, see [`claim_evaluation`].
+#[cfg_attr(coverage_nightly, no_coverage)]
+pub async fn run() -> Result<(), anyhow::Error> {
+    // forget the groups which were deleted
+    LAST_EVALUATED.retain(|key, _| PROM_RULE_GROUPS.contains_key(key));
+    EVALUATIONS.retain(|key, _| PROM_RULE_GROUPS.contains_key(key));
+
+    let now = Utc::now().timestamp_micros();
+    for group in PROM_RULE_GROUPS.iter() {
+        let key = group.key().to_string();
+        let last_evaluated = LAST_EVALUATED.get(&key).map(|v| *v).unwrap_or_default();
+        if now - last_evaluated < group.interval * 1_000_000 {
+            continue;
+        }
+        LAST_EVALUATED.insert(key.clone(), now);
+
+        let org_id = key.split('/').next().unwrap().to_string();
+        let group = group.value().clone();
+        tokio::task::spawn(async move {
+            match claim_evaluation(&org_id, &group, now).await {
+                Ok(true) => evaluate_group(&org_id, &group, now).await,
+                Ok(false) => {}
+                Err(e) => log::error!(
+                    "[RECORDING RULES] claim group {}/{} error: {}",
+                    org_id,
+                    group.name,
+                    e
+                ),
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Claims the evaluation of a group at `eval_ts` for this node. The last
+/// evaluation timestamp is kept in the meta store and checked and updated
+/// under a cluster lock, so a group is evaluated by one node per interval.
+#[cfg_attr(coverage_nightly, no_coverage)]
+async fn claim_evaluation(
+    org_id: &str,
+    group: &RuleGroup,
+    eval_ts: i64,
+) -> Result<bool, anyhow::Error> {
+    let mut locker = None;
+    if !CONFIG.common.local_mode {
+        // get a cluster lock for the rule group
+        let lock_key = format!("prom_rules/{org_id}/{}", group.name);
+        let mut lock = etcd::Locker::new(&lock_key);
+        if lock.lock(CONFIG.etcd.command_timeout).await.is_err() {
+            return Ok(false); // lock failed, another node is evaluating it
+        }
+        locker = Some(lock);
+    }
+
+    let ret = match db::prom_rules::get_evaluated_at(org_id, &group.name).await {
+        Ok(evaluated_at) if eval_ts - evaluated_at < group.interval * 1_000_000 => {
+            // evaluated by another node, wait for the next interval from there
+            LAST_EVALUATED.insert(format!("{org_id}/{}", group.name), evaluated_at);
+            Ok(false)
+        }
+        Ok(_) => db::prom_rules::set_evaluated_at(org_id, &group.name, eval_ts)
+            .await
+            .map(|_| true),
+        Err(e) => Err(e),
+    };
+
+    if let Some(mut lock) = locker {
+        // release cluster lock
+        lock.unlock().await?;
+    }
+    ret
+}
+
+/// Evaluates the rules of a group in order. Every rule is written before the
+/// next one is evaluated, so rules can depend on the results of preceding
+/// rules of the same group. A failing rule doesn't stop the evaluation of the
+/// remaining ones.
+#[cfg_attr(coverage_nightly, no_coverage)]
+async fn evaluate_group(org_id: &str, group: &RuleGroup, eval_ts: i64) {
+    let start = std::time::Instant::now();
+    let mut errors = HashMap::new();
+    for rule in &group.rules {
+        if let Err(e) = evaluate_rule(org_id, rule, eval_ts).await {
+            log::error!(
+                "[RECORDING RULES] evaluate rule {}/{}/{} error: {}",
+                org_id,
+                group.name,
+                rule.record,
+                e
+            );
+            errors.insert(rule.record.clone(), e.to_string());
+        }
+    }
+    EVALUATIONS.insert(
+        format!("{org_id}/{}", group.name),
+        GroupEvaluation {
+            timestamp: eval_ts,
+            duration: start.elapsed().as_secs_f64(),
+            errors,
+        },
+    );
+}
+
+#[cfg_attr(coverage_nightly, no_coverage)]
+async fn evaluate_rule(
+    org_id: &str,
+    rule: &RecordingRule,
+    eval_ts: i64,
+) -> Result<(), anyhow::Error> {
+    let req = promql::MetricsQueryRequest {
+        query: rule.expr.clone(),
+        start: eval_ts,
+        end: eval_ts,
+        step: 300_000_000, // 5m
+    };
+    let value = promql::search::search(org_id, &req).await?;
+    let timeseries = to_timeseries(rule, value, eval_ts / 1000)?;
+    if timeseries.is_empty() {
+        return Ok(());
+    }
+
+    let request = prometheus::WriteRequest {
+        timeseries,
+        metadata: vec![],
+    };
+    let body = snap::raw::Encoder::new().compress_vec(&request.encode_to_vec())?;
+    write(org_id, body)
+        .await
+        .map_err(|e| anyhow::anyhow!("write rule {} error: {}", rule.record, e))
+}
+
+/// Ingests a snappy compressed remote write request through
+/// [`prom::remote_write`], on this node if it is an ingester, otherwise on one
+/// of the online ingesters.
+#[cfg_attr(coverage_nightly, no_coverage)]
+async fn write(org_id: &str, body: Vec<u8>) -> Result<(), anyhow::Error> {
+    if cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
+        let resp = prom::remote_write(org_id, web::Data::new(0), body.into()).await?;
+        if !resp.status().is_success() {
+            return Err(anyhow::anyhow!("remote write error: {}", resp.status()));
+        }
+        return Ok(());
+    }
+
+    let nodes = cluster::get_cached_online_ingester_nodes().unwrap_or_default();
+    let node_addr = match nodes.choose(&mut rand::thread_rng()) {
+        Some(node) => node.grpc_addr.clone(),
+        None => return Err(anyhow::anyhow!("no online ingester")),
+    };
+    let channel = match CHANNELS.get(&node_addr) {
+        Some(channel) => channel.clone(),
+        None => {
+            let channel = Channel::from_shared(node_addr.clone())?.connect_lazy();
+            CHANNELS.insert(node_addr, channel.clone());
+            channel
+        }
+    };
+    let token: MetadataValue<_> = cluster::get_internal_grpc_token().parse()?;
+    let org_header: MetadataValue<_> = org_id.parse()?;
+    let mut client = MetricsClient::with_interceptor(channel, move |mut req: Request<()>| {
+        req.metadata_mut().insert("authorization", token.clone());
+        req.metadata_mut()
+            .insert(CONFIG.grpc.org_header_key.as_str(), org_header.clone());
+        Ok(req)
+    });
+    client
+        .write(MetricsWriteRequest {
+            org_id: org_id.to_string(),
+            data: body,
+        })
+        .await?;
+    Ok(())
+}
+
+/// Converts the result of a recording rule into the time series to write,
+/// named after the rule and with the labels of the rule applied.
+fn to_timeseries(
+    rule: &RecordingRule,
+    value: Value,
+    timestamp: i64,
+) -> Result<Vec<prometheus::TimeSeries>, anyhow::Error> {
+    let samples: Vec<(FxIndexMap<String, String>, f64)> = match value {
+        Value::Vector(v) => v
+            .into_iter()
+            .map(|v| {
+                let labels = v
+                    .labels
+                    .iter()
+                    .map(|l| (l.name.clone(), l.value.clone()))
+                    .collect();
+                (labels, v.sample.value)
+            })
+            .collect(),
+        Value::Sample(v) => vec![(FxIndexMap::default(), v.value)],
+        Value::Float(v) => vec![(FxIndexMap::default(), v)],
+        Value::None => vec![],
+        v => {
+            return Err(anyhow::anyhow!(
+                "rule {} must evaluate to an instant vector or scalar, got {}",
+                rule.record,
+                v.get_type()
+            ))
+        }
+    };
+
+    Ok(samples
+        .into_iter()
+        .map(|(mut labels, value)| {
+            labels.insert(NAME_LABEL.to_string(), rule.record.clone());
+            for (name, value) in &rule.labels {
+                labels.insert(name.clone(), value.clone());
+            }
+            prometheus::TimeSeries {
+                labels: labels
+                    .into_iter()
+                    .map(|(name, value)| prometheus::Label { name, value })
+                    .collect(),
+                samples: vec![prometheus::Sample { value, timestamp }],
+                ..Default::default()
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::json;
+    use crate::meta::alert::{series_key, SeriesState};
+    use crate::service::promql::value::{InstantValue, Label, Sample};
+    use std::{collections::BTreeMap, sync::Arc};
+
+    fn rule(record: &str, expr: &str) -> RecordingRule {
+        RecordingRule {
+            record: record.to_string(),
+            expr: expr.to_string(),
+            labels: HashMap::from([("team".to_string(), "infra".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut group = RuleGroup {
+            name: "test".to_string(),
+            interval: 60,
+            rules: vec![rule("job:up:sum", "sum by (job) (up)")],
+        };
+        assert!(validate(&group).is_ok());
+
+        group.rules.push(rule("1invalid", "up"));
+        assert!(validate(&group).is_err());
+
+        group.rules[1] = rule("job:up:count", "count(up");
+        assert!(validate(&group).is_err());
+
+        group.rules.pop();
+        group.interval = 0;
+        assert!(validate(&group).is_err());
+    }
+
+    #[test]
+    fn test_to_timeseries() {
+        let value = Value::Vector(vec![InstantValue {
+            labels: vec![
+                Arc::new(Label {
+                    name: NAME_LABEL.to_string(),
+                    value: "up".to_string(),
+                }),
+                Arc::new(Label {
+                    name: "job".to_string(),
+                    value: "api".to_string(),
+                }),
+            ],
+            sample: Sample::new(1_000_000, 1.0),
+        }]);
+        let series = to_timeseries(&rule("job:up", "up"), value, 1_000).unwrap();
+        assert_eq!(series.len(), 1);
+        let labels = series[0]
+            .labels
+            .iter()
+            .map(|l| (l.name.as_str(), l.value.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            vec![(NAME_LABEL, "job:up"), ("job", "api"), ("team", "infra")]
+        );
+        assert_eq!(series[0].samples[0].timestamp, 1_000);
+        assert_eq!(series[0].samples[0].value, 1.0);
+
+        let series = to_timeseries(&rule("one", "1"), Value::Float(1.0), 1_000).unwrap();
+        assert_eq!(series[0].labels.len(), 2);
+
+        assert!(to_timeseries(&rule("none", "up"), Value::None, 1_000)
+            .unwrap()
+            .is_empty());
+        assert!(to_timeseries(&rule("s", "\"s\""), Value::String("s".into()), 1_000).is_err());
+    }
+
+    #[test]
+    fn test_active_alerts() {
+        let alert: Alert = json::from_str(
+            r#"{
+                "name": "high_load",
+                "kind": "promql",
+                "promql": "load1 > 4",
+                "duration": 0,
+                "frequency": 1,
+                "time_between_alerts": 5,
+                "destination": "slack",
+                "context_attributes": {"summary": "high load"}
+            }"#,
+        )
+        .unwrap();
+        let mut trigger = Trigger::default();
+        for (host, state) in [
+            ("a", AlertState::Pending),
+            ("b", AlertState::Firing),
+            ("c", AlertState::Resolved),
+        ] {
+            let labels = BTreeMap::from([("host".to_string(), host.to_string())]);
+            trigger.series.insert(
+                series_key(&labels),
+                SeriesState {
+                    labels,
+                    state,
+                    value: 5.0,
+                    active_at: 1_672_531_200_000_000,
+                    fired_at: 0,
+                    resolved_at: 0,
+                    last_sent_at: 0,
+                },
+            );
+        }
+
+        assert!(active_alerts(&alert, None).is_empty());
+        let alerts = active_alerts(&alert, Some(&trigger));
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].state, "pending");
+        assert_eq!(alerts[1].state, "firing");
+        assert_eq!(alerts[1].labels["host"], "b");
+        assert_eq!(alerts[1].annotations["summary"], "high load");
+        assert_eq!(alerts[1].active_at, "2023-01-01T00:00:00.000000Z");
+        assert_eq!(alerts[1].value, "5");
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(0), "0001-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(1_672_531_200_123_456),
+            "2023-01-01T00:00:00.123456Z"
+        );
+    }
+}
@@ -28,6 +28,7 @@ pub mod metrics;
 pub mod organization;
 pub mod promql;
 pub mod router;
+pub mod saved_searches;
 pub mod schema;
 pub mod search;
 pub mod stream;
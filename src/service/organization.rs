@@ -16,7 +16,8 @@ use rand::distributions::{Alphanumeric, DistString};
 
 use super::stream::get_streams;
 use crate::common::auth::is_root_user;
-use crate::meta::organization::{IngestionPasscode, OrgSummary};
+use crate::meta::organization::{IngestionPasscode, OrgSearchLimits, OrgSummary};
+use crate::meta::traces::TraceRoutes;
 use crate::meta::user::UserOrg;
 use crate::service::db;
 
@@ -76,6 +77,53 @@ pub async fn update_passcode(org_id: Option<&str>, user_id: &str) -> IngestionPa
     }
 }
 
+#[tracing::instrument]
+pub fn get_search_limits(org_id: &str) -> OrgSearchLimits {
+    db::organization::get_search_limits(org_id).unwrap_or_default()
+}
+
+#[tracing::instrument]
+pub async fn set_search_limits(
+    org_id: &str,
+    limits: &OrgSearchLimits,
+) -> Result<(), anyhow::Error> {
+    if limits.max_time_range.map_or(false, |v| v < 0) {
+        return Err(anyhow::anyhow!("max_time_range should not be negative"));
+    }
+    db::organization::set_search_limits(org_id, limits).await
+}
+
+#[tracing::instrument]
+pub async fn delete_search_limits(org_id: &str) -> Result<(), anyhow::Error> {
+    db::organization::delete_search_limits(org_id).await
+}
+
+#[tracing::instrument]
+pub fn get_trace_routes(org_id: &str) -> TraceRoutes {
+    db::organization::get_trace_routes(org_id).unwrap_or_default()
+}
+
+#[tracing::instrument]
+pub async fn set_trace_routes(org_id: &str, routes: &TraceRoutes) -> Result<(), anyhow::Error> {
+    for route in &routes.routes {
+        if route.stream_name.is_empty() {
+            return Err(anyhow::anyhow!("stream_name of a trace route is empty"));
+        }
+        if route.stream_name.contains(|c| c == '/' || c == '=') {
+            return Err(anyhow::anyhow!(
+                "stream_name of a trace route should not contain '/' or '=': {}",
+                route.stream_name
+            ));
+        }
+    }
+    db::organization::set_trace_routes(org_id, routes).await
+}
+
+#[tracing::instrument]
+pub async fn delete_trace_routes(org_id: &str) -> Result<(), anyhow::Error> {
+    db::organization::delete_trace_routes(org_id).await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
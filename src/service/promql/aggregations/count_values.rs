@@ -0,0 +1,137 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::{DataFusionError, Result};
+use promql_parser::parser::LabelModifier;
+use rustc_hash::FxHashMap;
+use std::sync::Arc;
+
+use crate::service::promql::value::{
+    is_valid_label_name, signature, InstantValue, Label, Sample, Value,
+};
+
+/// Counts the number of elements with the same value. The value is written
+/// into the `label_name` label of each output series.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2506
+pub fn count_values(
+    timestamp: i64,
+    label_name: &str,
+    param: &Option<LabelModifier>,
+    data: &Value,
+) -> Result<Value> {
+    if !is_valid_label_name(label_name) {
+        return Err(DataFusionError::Plan(format!(
+            "[count_values] invalid label name {label_name:?}"
+        )));
+    }
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::Plan(
+                "[count_values] function only accept vector values".to_string(),
+            ))
+        }
+    };
+
+    let mut score_values = FxHashMap::default();
+    for item in data.iter() {
+        let mut labels = super::grouping_labels(&item.labels, param);
+        labels.retain(|l| l.name != label_name);
+        labels.push(Arc::new(Label {
+            name: label_name.to_string(),
+            value: format_value(item.sample.value),
+        }));
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+        let entry =
+            score_values
+                .entry(signature(&labels))
+                .or_insert_with(|| super::ArithmeticItem {
+                    labels,
+                    value: 0.0,
+                    num: 0,
+                });
+        entry.num += 1;
+    }
+    let values = score_values
+        .into_values()
+        .map(|it| InstantValue {
+            labels: it.labels,
+            sample: Sample::new(timestamp, it.num as _),
+        })
+        .collect();
+    Ok(Value::Vector(values))
+}
+
+/// Formats a sample value as Prometheus does for label values, with Go's
+/// `strconv.FormatFloat(v, 'f', -1, 64)`: the fewest digits which parse back
+/// to the value, in decimal notation without an exponent.
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    } else if value == f64::INFINITY {
+        return "+Inf".to_string();
+    } else if value == f64::NEG_INFINITY {
+        return "-Inf".to_string();
+    }
+    // `{:e}` has the fewest digits, e.g. `-1.5e-7`
+    let formatted = format!("{value:e}");
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let digits = mantissa.replace('.', "");
+    // the number of digits before the decimal point
+    let point = exponent + 1;
+    if point <= 0 {
+        format!("{sign}0.{}{digits}", "0".repeat(-point as usize))
+    } else if point as usize >= digits.len() {
+        format!(
+            "{sign}{digits}{}",
+            "0".repeat(point as usize - digits.len())
+        )
+    } else {
+        let (int, frac) = digits.split_at(point as usize);
+        format!("{sign}{int}.{frac}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_value() {
+        // the outputs of strconv.FormatFloat(v, 'f', -1, 64)
+        let cases = [
+            (0.0, "0"),
+            (-0.0, "-0"),
+            (100.0, "100"),
+            (0.1, "0.1"),
+            (-2.5, "-2.5"),
+            (123456789.125, "123456789.125"),
+            (1.5e-7, "0.00000015"),
+            (1e21, "1000000000000000000000"),
+            (1.2345e25, "12345000000000000000000000"),
+            (f64::NAN, "NaN"),
+            (f64::INFINITY, "+Inf"),
+            (f64::NEG_INFINITY, "-Inf"),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(format_value(value), expected);
+        }
+    }
+}
@@ -23,16 +23,26 @@ use crate::service::promql::value::{signature, Labels, Signature, Value};
 mod avg;
 mod bottomk;
 mod count;
+mod count_values;
+mod group;
 mod max;
 mod min;
+mod quantile;
+mod stddev;
+mod stdvar;
 mod sum;
 mod topk;
 
 pub(crate) use avg::avg;
 pub(crate) use bottomk::bottomk;
 pub(crate) use count::count;
+pub(crate) use count_values::count_values;
+pub(crate) use group::group;
 pub(crate) use max::max;
 pub(crate) use min::min;
+pub(crate) use quantile::quantile;
+pub(crate) use stddev::stddev;
+pub(crate) use stdvar::stdvar;
 pub(crate) use sum::sum;
 pub(crate) use topk::topk;
 
@@ -42,6 +52,11 @@ pub(crate) struct ArithmeticItem {
     pub(crate) num: usize,
 }
 
+pub(crate) struct StatisticItem {
+    pub(crate) labels: Labels,
+    pub(crate) values: Vec<f64>,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct TopItem {
     pub(crate) index: usize,
@@ -65,62 +80,77 @@ pub(crate) fn eval_arithmetic(
     };
 
     let mut score_values = FxHashMap::default();
-    match param {
-        Some(v) => match v {
-            LabelModifier::Include(labels) => {
-                for item in data.iter() {
-                    let mut sum_labels = Labels::default();
-                    for label in item.labels.iter() {
-                        if labels.contains(&label.name) {
-                            sum_labels.push(label.clone());
-                        }
-                    }
-                    let sum_hash = signature(&sum_labels);
-                    let entry = score_values.entry(sum_hash).or_insert(ArithmeticItem {
-                        labels: sum_labels,
-                        value: 0.0,
-                        num: 0,
-                    });
-                    entry.value = f_handler(entry.value, item.sample.value);
-                    entry.num += 1;
-                }
-            }
-            LabelModifier::Exclude(labels) => {
-                for item in data.iter() {
-                    let mut sum_labels = Labels::default();
-                    for label in item.labels.iter() {
-                        if !labels.contains(&label.name) {
-                            sum_labels.push(label.clone());
-                        }
-                    }
-                    let sum_hash = signature(&sum_labels);
-                    let entry = score_values.entry(sum_hash).or_insert(ArithmeticItem {
-                        labels: sum_labels,
-                        value: 0.0,
-                        num: 0,
-                    });
-                    entry.value = f_handler(entry.value, item.sample.value);
-                    entry.num += 1;
-                }
-            }
-        },
-        None => {
-            for item in data.iter() {
-                let entry = score_values
-                    .entry(Signature::default())
-                    .or_insert(ArithmeticItem {
-                        labels: Labels::default(),
-                        value: 0.0,
-                        num: 0,
-                    });
-                entry.value = f_handler(entry.value, item.sample.value);
-                entry.num += 1;
-            }
+    for item in data.iter() {
+        let sum_labels = grouping_labels(&item.labels, param);
+        let sum_hash = signature(&sum_labels);
+        let entry = score_values.entry(sum_hash).or_insert(ArithmeticItem {
+            labels: sum_labels,
+            value: 0.0,
+            num: 0,
+        });
+        entry.value = f_handler(entry.value, item.sample.value);
+        entry.num += 1;
+    }
+    Ok(Some(score_values))
+}
+
+/// Collects the values of every group defined by the `by`/`without` label
+/// modifier, for the aggregations which need all of them at once.
+pub(crate) fn eval_statistics(
+    param: &Option<LabelModifier>,
+    data: &Value,
+    f_name: &str,
+) -> Result<Option<FxHashMap<Signature, StatisticItem>>> {
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(None),
+        _ => {
+            return Err(DataFusionError::Plan(format!(
+                "[{f_name}] function only accept vector values"
+            )))
         }
+    };
+
+    let mut score_values = FxHashMap::default();
+    for item in data.iter() {
+        let group_labels = grouping_labels(&item.labels, param);
+        let group_hash = signature(&group_labels);
+        let entry = score_values
+            .entry(group_hash)
+            .or_insert_with(|| StatisticItem {
+                labels: group_labels,
+                values: Vec::new(),
+            });
+        entry.values.push(item.sample.value);
     }
     Ok(Some(score_values))
 }
 
+/// Returns the labels of the group the series with `labels` is aggregated
+/// into, according to the `by`/`without` label modifier.
+pub(crate) fn grouping_labels(labels: &Labels, param: &Option<LabelModifier>) -> Labels {
+    match param {
+        Some(LabelModifier::Include(names)) => labels
+            .iter()
+            .filter(|l| names.contains(&l.name))
+            .cloned()
+            .collect(),
+        Some(LabelModifier::Exclude(names)) => labels
+            .iter()
+            .filter(|l| !names.contains(&l.name))
+            .cloned()
+            .collect(),
+        None => Labels::default(),
+    }
+}
+
+/// Population variance of `values`.
+pub(crate) fn variance(values: &[f64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n
+}
+
 pub async fn eval_top(
     ctx: &mut Engine,
     param: Box<PromExpr>,
@@ -172,3 +202,115 @@ pub async fn eval_top(
         .collect();
     Ok(Value::Vector(values))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{InstantValue, Label, Sample};
+    use std::sync::Arc;
+
+    fn data() -> Value {
+        let values = [
+            ("a", "x", 2.0),
+            ("b", "x", 4.0),
+            ("c", "y", 4.0),
+            ("d", "y", 6.0),
+        ];
+        Value::Vector(
+            values
+                .iter()
+                .map(|(instance, job, value)| InstantValue {
+                    labels: vec![
+                        Arc::new(Label {
+                            name: "instance".to_string(),
+                            value: instance.to_string(),
+                        }),
+                        Arc::new(Label {
+                            name: "job".to_string(),
+                            value: job.to_string(),
+                        }),
+                    ],
+                    sample: Sample::new(1_000_000, *value),
+                })
+                .collect(),
+        )
+    }
+
+    fn sorted_values(value: Value) -> Vec<(String, f64)> {
+        let mut values = match value {
+            Value::Vector(v) => v
+                .into_iter()
+                .map(|v| {
+                    let labels = v
+                        .labels
+                        .iter()
+                        .map(|l| format!("{}={}", l.name, l.value))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    (labels, v.sample.value)
+                })
+                .collect::<Vec<_>>(),
+            v => panic!("not a vector: {v:?}"),
+        };
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        values
+    }
+
+    #[test]
+    fn test_variance() {
+        assert_eq!(variance(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]), 4.0);
+        assert_eq!(variance(&[1.0]), 0.0);
+    }
+
+    #[test]
+    fn test_statistics_aggregations() {
+        let by_job = Some(LabelModifier::Include(
+            ["job".to_string()].into_iter().collect(),
+        ));
+
+        let res = sorted_values(stdvar(1_000_000, &None, &data()).unwrap());
+        assert_eq!(res, [(String::new(), 2.0)]);
+
+        let res = sorted_values(stddev(1_000_000, &by_job, &data()).unwrap());
+        assert_eq!(
+            res,
+            [("job=x".to_string(), 1.0), ("job=y".to_string(), 1.0)]
+        );
+
+        let res = sorted_values(group(1_000_000, &by_job, &data()).unwrap());
+        assert_eq!(
+            res,
+            [("job=x".to_string(), 1.0), ("job=y".to_string(), 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_count_values() {
+        let res = sorted_values(count_values(1_000_000, "value", &None, &data()).unwrap());
+        assert_eq!(
+            res,
+            [
+                ("value=2".to_string(), 1.0),
+                ("value=4".to_string(), 2.0),
+                ("value=6".to_string(), 1.0)
+            ]
+        );
+
+        let without_instance = Some(LabelModifier::Exclude(
+            ["instance".to_string()].into_iter().collect(),
+        ));
+        let res =
+            sorted_values(count_values(1_000_000, "value", &without_instance, &data()).unwrap());
+        assert_eq!(
+            res,
+            [
+                ("job=x,value=2".to_string(), 1.0),
+                ("job=x,value=4".to_string(), 1.0),
+                ("job=y,value=4".to_string(), 1.0),
+                ("job=y,value=6".to_string(), 1.0)
+            ]
+        );
+
+        assert!(count_values(1_000_000, "1abc", &None, &data()).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::{DataFusionError, Result};
+use promql_parser::parser::{Expr as PromExpr, LabelModifier};
+
+use super::Engine;
+use crate::service::promql::value::{self, InstantValue, Sample, Value};
+
+pub async fn quantile(
+    ctx: &mut Engine,
+    timestamp: i64,
+    param: Box<PromExpr>,
+    modifier: &Option<LabelModifier>,
+    data: &Value,
+) -> Result<Value> {
+    let param = ctx.exec_expr(&param).await?;
+    let phi = match param {
+        Value::Float(v) => v,
+        _ => {
+            return Err(DataFusionError::Plan(
+                "[quantile] param must be NumberLiteral".to_string(),
+            ))
+        }
+    };
+
+    let score_values = super::eval_statistics(modifier, data, "quantile")?;
+    if score_values.is_none() {
+        return Ok(Value::None);
+    }
+    let values = score_values
+        .unwrap()
+        .into_values()
+        .map(|mut it| InstantValue {
+            labels: it.labels,
+            sample: Sample::new(timestamp, value::quantile(&mut it.values, phi)),
+        })
+        .collect();
+    Ok(Value::Vector(values))
+}
@@ -0,0 +1,33 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::Result;
+use promql_parser::parser::LabelModifier;
+
+use crate::service::promql::value::{InstantValue, Sample, Value};
+
+pub fn stddev(timestamp: i64, param: &Option<LabelModifier>, data: &Value) -> Result<Value> {
+    let score_values = super::eval_statistics(param, data, "stddev")?;
+    if score_values.is_none() {
+        return Ok(Value::None);
+    }
+    let values = score_values
+        .unwrap()
+        .values()
+        .map(|it| InstantValue {
+            labels: it.labels.clone(),
+            sample: Sample::new(timestamp, super::variance(&it.values).sqrt()),
+        })
+        .collect();
+    Ok(Value::Vector(values))
+}
@@ -1,5 +1,336 @@
-mod add;
-mod sub;
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
 
-pub(crate) use add::add;
-pub(crate) use sub::sub;
+use datafusion::error::{DataFusionError, Result};
+use promql_parser::parser::{token, token::TokenId, BinaryExpr, LabelModifier};
+
+use crate::meta::prom::NAME_LABEL;
+use crate::service::promql::value::{signature, signature_without_labels, Labels, Signature};
+
+mod scalar;
+mod set;
+mod vector;
+
+pub(crate) use scalar::{scalar_binary_operations, vector_scalar_bin_op};
+pub(crate) use set::{vector_and, vector_or, vector_unless};
+pub(crate) use vector::vector_bin_op;
+
+/// Returns true if `op` is one of `==`, `!=`, `>`, `<`, `>=`, `<=`.
+pub(crate) fn is_comparison_operator(op: TokenId) -> bool {
+    matches!(
+        op,
+        token::T_EQLC | token::T_NEQ | token::T_GTR | token::T_LSS | token::T_GTE | token::T_LTE
+    )
+}
+
+/// Returns true if `op` is one of the set operators `and`, `or`, `unless`.
+pub(crate) fn is_set_operator(op: TokenId) -> bool {
+    matches!(op, token::T_LAND | token::T_LOR | token::T_LUNLESS)
+}
+
+/// Arithmetic operators drop the metric name from the resulting series.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2394
+fn should_drop_metric_name(op: TokenId) -> bool {
+    matches!(
+        op,
+        token::T_ADD
+            | token::T_SUB
+            | token::T_MUL
+            | token::T_DIV
+            | token::T_POW
+            | token::T_MOD
+            | token::T_ATAN2
+    )
+}
+
+/// Whether the comparison result should be returned as 0/1 instead of
+/// filtering the samples, i.e. `a > bool b`.
+fn return_bool(expr: &BinaryExpr) -> bool {
+    expr.modifier.as_ref().map_or(false, |m| m.return_bool)
+}
+
+/// Applies `op` to a pair of float values. Returns the resulting value and
+/// whether the sample should be kept. Comparison operators return the
+/// left-hand side value unchanged.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2343
+fn vector_elem_binop(op: TokenId, lhs: f64, rhs: f64) -> Result<(f64, bool)> {
+    Ok(match op {
+        token::T_ADD => (lhs + rhs, true),
+        token::T_SUB => (lhs - rhs, true),
+        token::T_MUL => (lhs * rhs, true),
+        token::T_DIV => (lhs / rhs, true),
+        token::T_POW => (lhs.powf(rhs), true),
+        token::T_MOD => (lhs % rhs, true),
+        token::T_ATAN2 => (lhs.atan2(rhs), true),
+        token::T_EQLC => (lhs, lhs == rhs),
+        token::T_NEQ => (lhs, lhs != rhs),
+        token::T_GTR => (lhs, lhs > rhs),
+        token::T_LSS => (lhs, lhs < rhs),
+        token::T_GTE => (lhs, lhs >= rhs),
+        token::T_LTE => (lhs, lhs <= rhs),
+        _ => {
+            return Err(DataFusionError::NotImplemented(format!(
+                "Unsupported binary operator: {op}"
+            )))
+        }
+    })
+}
+
+/// Computes the signature used to match series of both sides of a binary
+/// operation. With `on(...)` only the listed labels are taken into account,
+/// otherwise every label except the `ignoring(...)` ones and the metric name.
+fn matching_signature(labels: &Labels, matching: Option<&LabelModifier>) -> Signature {
+    match matching {
+        Some(LabelModifier::Include(names)) => {
+            let on_labels: Labels = labels
+                .iter()
+                .filter(|l| names.contains(&l.name))
+                .cloned()
+                .collect();
+            signature(&on_labels)
+        }
+        Some(LabelModifier::Exclude(names)) => {
+            let mut exclude_names: Vec<&str> = names.iter().map(|s| s.as_str()).collect();
+            exclude_names.push(NAME_LABEL);
+            signature_without_labels(labels, &exclude_names)
+        }
+        None => signature_without_labels(labels, &[NAME_LABEL]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{InstantValue, Label, Sample, Value};
+    use std::sync::Arc;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                Arc::new(Label {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_vector_elem_binop() {
+        assert_eq!(
+            vector_elem_binop(token::T_ADD, 1.0, 2.0).unwrap(),
+            (3.0, true)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_SUB, 1.0, 2.0).unwrap(),
+            (-1.0, true)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_MUL, 3.0, 2.0).unwrap(),
+            (6.0, true)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_DIV, 3.0, 2.0).unwrap(),
+            (1.5, true)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_POW, 3.0, 2.0).unwrap(),
+            (9.0, true)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_MOD, -7.0, 3.0).unwrap(),
+            (-1.0, true)
+        );
+        assert!(vector_elem_binop(token::T_DIV, 1.0, 0.0)
+            .unwrap()
+            .0
+            .is_infinite());
+
+        assert_eq!(
+            vector_elem_binop(token::T_GTR, 3.0, 2.0).unwrap(),
+            (3.0, true)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_LSS, 3.0, 2.0).unwrap(),
+            (3.0, false)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_EQLC, 2.0, 2.0).unwrap(),
+            (2.0, true)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_NEQ, 2.0, 2.0).unwrap(),
+            (2.0, false)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_GTE, 2.0, 2.0).unwrap(),
+            (2.0, true)
+        );
+        assert_eq!(
+            vector_elem_binop(token::T_LTE, 3.0, 2.0).unwrap(),
+            (3.0, false)
+        );
+    }
+
+    fn binary_expr(query: &str) -> BinaryExpr {
+        match promql_parser::parser::parse(query).unwrap() {
+            promql_parser::parser::Expr::Binary(expr) => expr,
+            expr => panic!("not a binary expression: {expr:?}"),
+        }
+    }
+
+    fn instant(pairs: &[(&str, &str)], value: f64) -> InstantValue {
+        InstantValue {
+            labels: labels(pairs),
+            sample: Sample::new(1_000_000, value),
+        }
+    }
+
+    fn get_vector(value: Value) -> Vec<InstantValue> {
+        match value {
+            Value::Vector(v) => v,
+            v => panic!("not a vector: {v:?}"),
+        }
+    }
+
+    #[test]
+    fn test_vector_scalar_bin_op() {
+        let data = [
+            instant(&[("__name__", "up"), ("instance", "a")], 1.0),
+            instant(&[("__name__", "up"), ("instance", "b")], 0.0),
+        ];
+
+        let res = get_vector(
+            vector_scalar_bin_op(&binary_expr("up * 100"), &data, 100.0, false).unwrap(),
+        );
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].sample.value, 100.0);
+        assert!(res[0].labels.iter().all(|l| l.name != NAME_LABEL));
+
+        let res =
+            get_vector(vector_scalar_bin_op(&binary_expr("up > 0.5"), &data, 0.5, false).unwrap());
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].sample.value, 1.0);
+        assert!(res[0].labels.iter().any(|l| l.name == NAME_LABEL));
+
+        let res = get_vector(
+            vector_scalar_bin_op(&binary_expr("0.5 < bool up"), &data, 0.5, true).unwrap(),
+        );
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].sample.value, 1.0);
+        assert_eq!(res[1].sample.value, 0.0);
+    }
+
+    #[test]
+    fn test_vector_bin_op() {
+        let errors = [
+            instant(
+                &[("__name__", "errors"), ("instance", "a"), ("code", "500")],
+                5.0,
+            ),
+            instant(
+                &[("__name__", "errors"), ("instance", "a"), ("code", "503")],
+                15.0,
+            ),
+            instant(
+                &[("__name__", "errors"), ("instance", "b"), ("code", "500")],
+                1.0,
+            ),
+        ];
+        let total = [
+            instant(
+                &[("__name__", "total"), ("instance", "a"), ("job", "api")],
+                100.0,
+            ),
+            instant(
+                &[("__name__", "total"), ("instance", "b"), ("job", "api")],
+                10.0,
+            ),
+        ];
+
+        // one-to-one matching needs unique series on the left side
+        let expr = binary_expr("errors / on(instance) total");
+        assert!(vector_bin_op(&expr, &errors, &total).is_err());
+
+        let expr = binary_expr("errors / on(instance) group_left(job) total");
+        let mut res = get_vector(vector_bin_op(&expr, &errors, &total).unwrap());
+        res.sort_by(|a, b| a.sample.value.partial_cmp(&b.sample.value).unwrap());
+        assert_eq!(res.len(), 3);
+        assert_eq!(res[0].sample.value, 0.05);
+        assert_eq!(res[2].sample.value, 0.15);
+        let names: Vec<_> = res[0].labels.iter().map(|l| l.name.as_str()).collect();
+        assert_eq!(names, ["code", "instance", "job"]);
+
+        // comparisons keep the value of the left-hand side and the labels
+        // of the "many" side
+        let expr = binary_expr("total > on(instance) group_right errors");
+        let mut res = get_vector(vector_bin_op(&expr, &total, &errors).unwrap());
+        res.sort_by(|a, b| a.sample.value.partial_cmp(&b.sample.value).unwrap());
+        assert_eq!(
+            res.iter().map(|v| v.sample.value).collect::<Vec<_>>(),
+            [10.0, 100.0, 100.0]
+        );
+        assert!(res[0].labels.iter().any(|l| l.value == "errors"));
+    }
+
+    #[test]
+    fn test_set_operators() {
+        let lhs = [
+            instant(&[("__name__", "foo"), ("instance", "a")], 1.0),
+            instant(&[("__name__", "foo"), ("instance", "b")], 2.0),
+        ];
+        let rhs = [
+            instant(&[("__name__", "bar"), ("instance", "b")], 3.0),
+            instant(&[("__name__", "bar"), ("instance", "c")], 4.0),
+        ];
+
+        let res = get_vector(vector_bin_op(&binary_expr("foo and bar"), &lhs, &rhs).unwrap());
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].sample.value, 2.0);
+
+        let res = get_vector(vector_bin_op(&binary_expr("foo or bar"), &lhs, &rhs).unwrap());
+        assert_eq!(
+            res.iter().map(|v| v.sample.value).collect::<Vec<_>>(),
+            [1.0, 2.0, 4.0]
+        );
+
+        let res = get_vector(vector_bin_op(&binary_expr("foo unless bar"), &lhs, &rhs).unwrap());
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].sample.value, 1.0);
+    }
+
+    #[test]
+    fn test_matching_signature() {
+        let a = labels(&[("__name__", "foo"), ("instance", "a"), ("job", "x")]);
+        let b = labels(&[("__name__", "bar"), ("instance", "a"), ("job", "y")]);
+
+        // the metric name never takes part in matching
+        assert_ne!(matching_signature(&a, None), matching_signature(&b, None));
+
+        let on = LabelModifier::Include(["instance".to_string()].into_iter().collect());
+        assert_eq!(
+            matching_signature(&a, Some(&on)),
+            matching_signature(&b, Some(&on))
+        );
+
+        let ignoring = LabelModifier::Exclude(["job".to_string()].into_iter().collect());
+        assert_eq!(
+            matching_signature(&a, Some(&ignoring)),
+            matching_signature(&b, Some(&ignoring))
+        );
+    }
+}
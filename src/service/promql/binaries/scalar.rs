@@ -0,0 +1,77 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use datafusion::error::Result;
+use promql_parser::parser::{token::TokenId, BinaryExpr};
+
+use crate::meta::prom::NAME_LABEL;
+use crate::service::promql::value::{InstantValue, Sample, Value};
+
+/// Binary operation between two scalars. Comparisons between scalars always
+/// require the `bool` modifier, so they evaluate to `0` or `1`.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2310
+pub(crate) fn scalar_binary_operations(op: TokenId, lhs: f64, rhs: f64) -> Result<f64> {
+    let (value, keep) = super::vector_elem_binop(op, lhs, rhs)?;
+    if super::is_comparison_operator(op) {
+        return Ok(if keep { 1.0 } else { 0.0 });
+    }
+    Ok(value)
+}
+
+/// Binary operation between an instant vector and a scalar. `swap` is set
+/// when the scalar is the left-hand side operand, e.g. `2 * foo`.
+///
+/// Comparison operators filter the vector (or return `0`/`1` with `bool`),
+/// always keeping the value of the vector sample.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2272
+pub(crate) fn vector_scalar_bin_op(
+    expr: &BinaryExpr,
+    data: &[InstantValue],
+    scalar: f64,
+    swap: bool,
+) -> Result<Value> {
+    let op = expr.op.id();
+    let return_bool = super::return_bool(expr);
+    let is_comparison = super::is_comparison_operator(op);
+    let drop_name = super::should_drop_metric_name(op) || return_bool;
+
+    let mut values = Vec::with_capacity(data.len());
+    for item in data {
+        let (lhs, rhs) = if swap {
+            (scalar, item.sample.value)
+        } else {
+            (item.sample.value, scalar)
+        };
+        let (mut value, keep) = super::vector_elem_binop(op, lhs, rhs)?;
+        if is_comparison && swap {
+            value = rhs;
+        }
+        if return_bool {
+            value = if keep { 1.0 } else { 0.0 };
+        } else if !keep {
+            continue;
+        }
+        let mut labels = item.labels.clone();
+        if drop_name {
+            labels.retain(|l| l.name != NAME_LABEL);
+        }
+        values.push(InstantValue {
+            labels,
+            sample: Sample::new(item.sample.timestamp, value),
+        });
+    }
+    Ok(Value::Vector(values))
+}
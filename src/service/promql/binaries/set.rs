@@ -0,0 +1,89 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use promql_parser::parser::LabelModifier;
+use rustc_hash::FxHashSet;
+
+use crate::service::promql::value::{InstantValue, Signature, Value};
+
+fn signatures(data: &[InstantValue], matching: Option<&LabelModifier>) -> FxHashSet<Signature> {
+    data.iter()
+        .map(|item| super::matching_signature(&item.labels, matching))
+        .collect()
+}
+
+/// `lhs and rhs` --- the elements of `lhs` which have a match in `rhs`.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2029
+pub(crate) fn vector_and(
+    lhs: &[InstantValue],
+    rhs: &[InstantValue],
+    matching: Option<&LabelModifier>,
+) -> Value {
+    if lhs.is_empty() || rhs.is_empty() {
+        return Value::Vector(vec![]);
+    }
+    let rhs_sigs = signatures(rhs, matching);
+    let values = lhs
+        .iter()
+        .filter(|item| rhs_sigs.contains(&super::matching_signature(&item.labels, matching)))
+        .cloned()
+        .collect();
+    Value::Vector(values)
+}
+
+/// `lhs or rhs` --- all the elements of `lhs` plus the elements of `rhs`
+/// which have no match in `lhs`.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2051
+pub(crate) fn vector_or(
+    lhs: &[InstantValue],
+    rhs: &[InstantValue],
+    matching: Option<&LabelModifier>,
+) -> Value {
+    if lhs.is_empty() {
+        return Value::Vector(rhs.to_vec());
+    }
+    if rhs.is_empty() {
+        return Value::Vector(lhs.to_vec());
+    }
+    let lhs_sigs = signatures(lhs, matching);
+    let mut values = lhs.to_vec();
+    values.extend(
+        rhs.iter()
+            .filter(|item| !lhs_sigs.contains(&super::matching_signature(&item.labels, matching)))
+            .cloned(),
+    );
+    Value::Vector(values)
+}
+
+/// `lhs unless rhs` --- the elements of `lhs` which have no match in `rhs`.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2074
+pub(crate) fn vector_unless(
+    lhs: &[InstantValue],
+    rhs: &[InstantValue],
+    matching: Option<&LabelModifier>,
+) -> Value {
+    if lhs.is_empty() || rhs.is_empty() {
+        return Value::Vector(lhs.to_vec());
+    }
+    let rhs_sigs = signatures(rhs, matching);
+    let values = lhs
+        .iter()
+        .filter(|item| !rhs_sigs.contains(&super::matching_signature(&item.labels, matching)))
+        .cloned()
+        .collect();
+    Value::Vector(values)
+}
@@ -0,0 +1,167 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use datafusion::error::{DataFusionError, Result};
+use promql_parser::parser::{
+    token, token::TokenId, BinaryExpr, LabelModifier, VectorMatchCardinality,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::meta::prom::NAME_LABEL;
+use crate::service::promql::value::{signature, InstantValue, Labels, Sample, Signature, Value};
+
+/// Binary operation between two instant vectors.
+///
+/// Arithmetic and comparison operators use one-to-one matching by default;
+/// `group_left`/`group_right` switch to many-to-one/one-to-many matching.
+/// Set operators (`and`, `or`, `unless`) always match many-to-many.
+///
+/// See <https://prometheus.io/docs/prometheus/latest/querying/operators/#vector-matching>
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2098
+pub(crate) fn vector_bin_op(
+    expr: &BinaryExpr,
+    lhs: &[InstantValue],
+    rhs: &[InstantValue],
+) -> Result<Value> {
+    let op = expr.op.id();
+    let (card, matching) = match &expr.modifier {
+        Some(modifier) => (modifier.card.clone(), modifier.matching.as_ref()),
+        None => (VectorMatchCardinality::OneToOne, None),
+    };
+
+    match op {
+        token::T_LAND => return Ok(super::vector_and(lhs, rhs, matching)),
+        token::T_LOR => return Ok(super::vector_or(lhs, rhs, matching)),
+        token::T_LUNLESS => return Ok(super::vector_unless(lhs, rhs, matching)),
+        _ => {}
+    }
+
+    // The "many" side provides the labels of the result, the "one" side may
+    // contribute the extra labels listed in `group_left(...)`/`group_right(...)`.
+    let (many, one, include, swap) = match &card {
+        VectorMatchCardinality::OneToOne => (lhs, rhs, vec![], false),
+        VectorMatchCardinality::ManyToOne(labels) => {
+            (lhs, rhs, labels.iter().map(|s| s.as_str()).collect(), false)
+        }
+        VectorMatchCardinality::OneToMany(labels) => {
+            (rhs, lhs, labels.iter().map(|s| s.as_str()).collect(), true)
+        }
+        VectorMatchCardinality::ManyToMany => {
+            return Err(DataFusionError::Plan(format!(
+                "many-to-many matching is only allowed for set operators, got: {:?}",
+                expr.op
+            )))
+        }
+    };
+    let one_to_one = matches!(card, VectorMatchCardinality::OneToOne);
+    let return_bool = super::return_bool(expr);
+
+    let mut one_sigs: FxHashMap<Signature, &InstantValue> = FxHashMap::default();
+    for item in one {
+        let sig = super::matching_signature(&item.labels, matching);
+        if one_sigs.insert(sig, item).is_some() {
+            return Err(DataFusionError::Plan(format!(
+                "many-to-many matching not allowed: found duplicate series on the {} side of the operation",
+                if swap { "left" } else { "right" }
+            )));
+        }
+    }
+
+    let mut matched_sigs: FxHashSet<Signature> = FxHashSet::default();
+    let mut values = Vec::with_capacity(many.len());
+    for item in many {
+        let sig = super::matching_signature(&item.labels, matching);
+        let other = match one_sigs.get(&sig) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let (lv, rv) = if swap {
+            (other.sample.value, item.sample.value)
+        } else {
+            (item.sample.value, other.sample.value)
+        };
+        let (mut value, keep) = super::vector_elem_binop(op, lv, rv)?;
+        if return_bool {
+            value = if keep { 1.0 } else { 0.0 };
+        } else if !keep {
+            continue;
+        }
+
+        let labels = result_labels(
+            &item.labels,
+            &other.labels,
+            op,
+            one_to_one,
+            matching,
+            &include,
+            return_bool,
+        );
+        let inserted = if one_to_one {
+            matched_sigs.insert(sig)
+        } else {
+            matched_sigs.insert(signature(&labels))
+        };
+        if !inserted {
+            return Err(DataFusionError::Plan(if one_to_one {
+                "multiple matches for labels: many-to-one matching must be explicit (group_left/group_right)".to_string()
+            } else {
+                "multiple matches for labels: grouping labels must ensure unique matches"
+                    .to_string()
+            }));
+        }
+
+        values.push(InstantValue {
+            labels,
+            sample: Sample::new(item.sample.timestamp, value),
+        });
+    }
+    Ok(Value::Vector(values))
+}
+
+/// Builds the label set of a vector-matching result element.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L2227
+fn result_labels(
+    many: &Labels,
+    one: &Labels,
+    op: TokenId,
+    one_to_one: bool,
+    matching: Option<&LabelModifier>,
+    include: &[&str],
+    return_bool: bool,
+) -> Labels {
+    let mut labels = many.clone();
+    if super::should_drop_metric_name(op) || return_bool {
+        labels.retain(|l| l.name != NAME_LABEL);
+    }
+    if one_to_one {
+        match matching {
+            Some(LabelModifier::Include(names)) => labels.retain(|l| names.contains(&l.name)),
+            Some(LabelModifier::Exclude(names)) => labels.retain(|l| !names.contains(&l.name)),
+            None => {}
+        }
+    }
+    if !include.is_empty() {
+        for name in include {
+            labels.retain(|l| l.name != *name);
+            if let Some(label) = one.iter().find(|l| l.name == *name) {
+                labels.push(label.clone());
+            }
+        }
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    labels
+}
@@ -25,16 +25,20 @@ use futures::future::try_join_all;
 use promql_parser::{
     label::MatchOp,
     parser::{
-        token, AggregateExpr, Call, Expr as PromExpr, Function, FunctionArgs, LabelModifier,
-        MatrixSelector, NumberLiteral, ParenExpr, TokenType, UnaryExpr, VectorSelector,
+        token, AggregateExpr, AtModifier, Call, Expr as PromExpr, Function, FunctionArgs,
+        LabelModifier, MatrixSelector, NumberLiteral, Offset, ParenExpr, StringLiteral,
+        SubqueryExpr, TokenType, UnaryExpr, VectorSelector,
     },
 };
 use rustc_hash::FxHashMap;
 use std::{str::FromStr, sync::Arc, time::Duration};
+use tokio::sync::RwLock;
 
 use crate::infra::config::CONFIG;
 use crate::meta::prom::{HASH_LABEL, VALUE_LABEL};
-use crate::service::promql::{aggregations, binaries, functions, micros, value::*};
+use crate::service::promql::{
+    aggregations, binaries, functions, micros, micros_since_epoch, value::*,
+};
 
 pub struct Engine {
     ctx: Arc<super::exec::Query>,
@@ -75,36 +79,54 @@ impl Engine {
             PromExpr::Binary(expr) => {
                 let lhs = self.exec_expr(&expr.lhs).await?;
                 let rhs = self.exec_expr(&expr.rhs).await?;
-                match expr.op.id() {
-                    token::T_ADD => binaries::add(&lhs, &rhs)?,
-                    token::T_SUB => binaries::sub(&lhs, &rhs)?,
-                    _ => {
-                        return Err(DataFusionError::NotImplemented(format!(
-                            "Unsupported Binary: {:?}",
-                            expr
+                let value = match (lhs, rhs) {
+                    (Value::Float(left), Value::Float(right)) => Value::Float(
+                        binaries::scalar_binary_operations(expr.op.id(), left, right)?,
+                    ),
+                    (Value::Vector(left), Value::Vector(right)) => {
+                        binaries::vector_bin_op(expr, &left, &right)?
+                    }
+                    (Value::Vector(left), Value::Float(right)) => {
+                        binaries::vector_scalar_bin_op(expr, &left, right, false)?
+                    }
+                    (Value::Float(left), Value::Vector(right)) => {
+                        binaries::vector_scalar_bin_op(expr, &right, left, true)?
+                    }
+                    // `Value::None` is an empty instant vector, it still
+                    // matters for set operators like `a or b`.
+                    (Value::Vector(left), Value::None) => {
+                        binaries::vector_bin_op(expr, &left, &[])?
+                    }
+                    (Value::None, Value::Vector(right)) => {
+                        binaries::vector_bin_op(expr, &[], &right)?
+                    }
+                    (Value::None, Value::None | Value::Float(_))
+                    | (Value::Float(_), Value::None) => Value::None,
+                    (left, right) => {
+                        return Err(DataFusionError::Plan(format!(
+                            "binary expression must contain only scalar and instant vector types, got: {} {:?} {}",
+                            left.get_type(),
+                            expr.op,
+                            right.get_type()
                         )))
                     }
+                };
+                match value {
+                    Value::Vector(v) if v.is_empty() => Value::None,
+                    v => v,
                 }
             }
-            PromExpr::Paren(ParenExpr { expr }) => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Paren: {:?}",
-                    expr
-                )));
-            }
+            PromExpr::Paren(ParenExpr { expr }) => self.exec_expr(expr).await?,
             PromExpr::Subquery(expr) => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Subquery: {:?}",
-                    expr
-                )));
+                let data = self.eval_subquery(expr).await?;
+                if data.is_empty() {
+                    Value::None
+                } else {
+                    Value::Matrix(data)
+                }
             }
             PromExpr::NumberLiteral(NumberLiteral { val }) => Value::Float(*val),
-            PromExpr::StringLiteral(expr) => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported StringLiteral: {:?}",
-                    expr
-                )));
-            }
+            PromExpr::StringLiteral(StringLiteral { val }) => Value::String(val.clone()),
             PromExpr::VectorSelector(v) => {
                 let data = self.eval_vector_selector(v).await?;
                 if data.is_empty() {
@@ -140,17 +162,28 @@ impl Engine {
     async fn eval_vector_selector(
         &mut self,
         selector: &VectorSelector,
+    ) -> Result<Vec<InstantValue>> {
+        let eval_ts = self.time;
+        let mut values = self.eval_vector_selector_samples(selector).await?;
+        for value in values.iter_mut() {
+            // See https://promlabs.com/blog/2020/06/18/the-anatomy-of-a-promql-query/#instant-queries
+            value.sample.timestamp = eval_ts;
+        }
+        Ok(values)
+    }
+
+    /// Same as [`Self::eval_vector_selector`], but keeps the original
+    /// timestamps of the selected samples, which `timestamp()` returns.
+    async fn eval_vector_selector_samples(
+        &mut self,
+        selector: &VectorSelector,
     ) -> Result<Vec<InstantValue>> {
         if self.result_type.is_none() {
             self.result_type = Some("vector".to_string());
         }
-        let metrics_name = selector.name.as_ref().unwrap();
-        let cache_exists = { self.ctx.data_cache.read().await.contains_key(metrics_name) };
-        if !cache_exists {
-            self.selector_load_data(selector, None).await?;
-        }
+        let cache_key = self.selector_load_data(selector, None).await?;
         let metrics_cache = self.ctx.data_cache.read().await;
-        let metrics_cache = match metrics_cache.get(metrics_name) {
+        let metrics_cache = match metrics_cache.get(&cache_key) {
             Some(v) => match v.get_ref_matrix_values() {
                 Some(v) => v,
                 None => return Ok(vec![]),
@@ -158,25 +191,22 @@ impl Engine {
             None => return Ok(vec![]),
         };
 
-        // Evaluation timestamp.
-        let eval_ts = self.time;
+        // Evaluation timestamp, shifted by the `offset` and `@` modifiers.
+        let eval_ts = self.selector_eval_ts(&selector.offset, &selector.at);
         let start = eval_ts - self.ctx.lookback_delta;
 
         let mut values = vec![];
         for metric in metrics_cache {
-            if let Some(last_value) = metric
+            if let Some(last_sample) = metric
                 .samples
                 .iter()
-                .filter_map(|s| (start < s.timestamp && s.timestamp <= eval_ts).then_some(s.value))
+                .filter(|s| start < s.timestamp && s.timestamp <= eval_ts)
                 .last()
             {
-                values.push(
-                    // See https://promlabs.com/blog/2020/06/18/the-anatomy-of-a-promql-query/#instant-queries
-                    InstantValue {
-                        labels: metric.labels.clone(),
-                        sample: Sample::new(eval_ts, last_value),
-                    },
-                );
+                values.push(InstantValue {
+                    labels: metric.labels.clone(),
+                    sample: *last_sample,
+                });
             }
         }
         Ok(values)
@@ -195,13 +225,9 @@ impl Engine {
         if self.result_type.is_none() {
             self.result_type = Some("matrix".to_string());
         }
-        let metrics_name = selector.name.as_ref().unwrap();
-        let cache_exists = { self.ctx.data_cache.read().await.contains_key(metrics_name) };
-        if !cache_exists {
-            self.selector_load_data(selector, None).await?;
-        }
+        let cache_key = self.selector_load_data(selector, Some(range)).await?;
         let metrics_cache = self.ctx.data_cache.read().await;
-        let metrics_cache = match metrics_cache.get(metrics_name) {
+        let metrics_cache = match metrics_cache.get(&cache_key) {
             Some(v) => match v.get_ref_matrix_values() {
                 Some(v) => v,
                 None => return Ok(vec![]),
@@ -209,40 +235,197 @@ impl Engine {
             None => return Ok(vec![]),
         };
 
-        // Evaluation timestamp --- end of the time window.
-        let eval_ts = self.time;
+        // End of the time window, shifted by the `offset` and `@` modifiers.
+        let end = self.selector_eval_ts(&selector.offset, &selector.at);
         // Start of the time window.
-        let start = eval_ts - micros(range); // e.g. [5m]
+        let start = end - micros(range); // e.g. [5m]
+        let time_window = TimeWindow {
+            offset: self.time - end,
+            ..TimeWindow::new(self.time, range)
+        };
 
         let mut values = Vec::with_capacity(metrics_cache.len());
         for metric in metrics_cache {
             let samples = metric
                 .samples
                 .iter()
-                .filter(|v| start < v.timestamp && v.timestamp <= eval_ts)
+                .filter(|v| start < v.timestamp && v.timestamp <= end)
+                .cloned()
+                .collect();
+            values.push(RangeValue {
+                labels: metric.labels.clone(),
+                samples,
+                time_window: Some(time_window.clone()),
+            });
+        }
+        Ok(values)
+    }
+
+    /// Subquery --- evaluate the inner instant expression at each sub-step of
+    /// the range and return the result as a range vector.
+    ///
+    /// See <https://prometheus.io/blog/2019/01/28/subquery-support/>
+    async fn eval_subquery(&mut self, expr: &SubqueryExpr) -> Result<Vec<RangeValue>> {
+        if self.result_type.is_none() {
+            self.result_type = Some("matrix".to_string());
+        }
+        let range = micros(expr.range);
+        let cache_key = format!("{:?}", expr);
+        let cache_exists = { self.ctx.data_cache.read().await.contains_key(&cache_key) };
+        if !cache_exists {
+            self.subquery_load_data(expr, &cache_key).await?;
+        }
+        let data_cache = self.ctx.data_cache.read().await;
+        let data_cache = match data_cache.get(&cache_key) {
+            Some(v) => match v.get_ref_matrix_values() {
+                Some(v) => v,
+                None => return Ok(vec![]),
+            },
+            None => return Ok(vec![]),
+        };
+
+        // End of the time window, shifted by the `offset` and `@` modifiers.
+        let end = self.selector_eval_ts(&expr.offset, &expr.at);
+        // Start of the time window.
+        let start = end - range; // e.g. [1h:1m]
+        let time_window = TimeWindow {
+            offset: self.time - end,
+            ..TimeWindow::new(self.time, expr.range)
+        };
+
+        let mut values = Vec::with_capacity(data_cache.len());
+        for metric in data_cache {
+            let samples: Vec<Sample> = metric
+                .samples
+                .iter()
+                .filter(|v| start < v.timestamp && v.timestamp <= end)
                 .cloned()
                 .collect();
+            if samples.is_empty() {
+                continue;
+            }
             values.push(RangeValue {
                 labels: metric.labels.clone(),
                 samples,
-                time_window: Some(TimeWindow::new(eval_ts, range)),
+                time_window: Some(time_window.clone()),
             });
         }
         Ok(values)
     }
 
+    /// Evaluates the inner expression of a subquery over the whole query
+    /// range at once and caches the resulting matrix.
+    ///
+    /// The sub-steps are aligned to multiples of the subquery step (not to
+    /// the evaluation timestamp), so every evaluation step of the outer
+    /// query can take its samples from the same result.
+    //
+    // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/engine.go#L1460-L1474
+    #[tracing::instrument(name = "promql:engine:subquery", skip_all)]
+    async fn subquery_load_data(&mut self, expr: &SubqueryExpr, cache_key: &str) -> Result<()> {
+        let range = micros(expr.range);
+        let step = match expr.step {
+            Some(step) if step > Duration::ZERO => micros(step),
+            _ => self.ctx.interval,
+        };
+        let (start, end) = self.shifted_time_range(&expr.offset, &expr.at);
+
+        let mut sub_start = step * ((start - range) / step);
+        if sub_start < start - range {
+            sub_start += step;
+        }
+        let mut query = (*self.ctx).clone();
+        query.start = sub_start;
+        query.end = end.max(sub_start);
+        query.interval = step;
+        query.data_cache = Arc::new(RwLock::new(FxHashMap::default()));
+
+        let (value, _) = query.eval(*expr.expr.clone()).await?;
+        let values = match value {
+            Value::Matrix(v) => v,
+            Value::Vector(v) => v
+                .into_iter()
+                .map(|v| RangeValue::new(v.labels, [v.sample]))
+                .collect(),
+            Value::Instant(v) => vec![RangeValue::new(v.labels, [v.sample])],
+            Value::Range(v) => vec![v],
+            Value::Sample(v) => vec![RangeValue::new(Labels::default(), [v])],
+            Value::Float(v) => vec![RangeValue::new(
+                Labels::default(),
+                [Sample::new(query.end, v)],
+            )],
+            Value::String(_) | Value::StringSample(_) => {
+                return Err(DataFusionError::Plan(
+                    "subquery is only allowed on instant vector, got string".to_string(),
+                ))
+            }
+            Value::None => vec![],
+        };
+        let values = if values.is_empty() {
+            Value::None
+        } else {
+            Value::Matrix(values)
+        };
+        self.ctx
+            .data_cache
+            .write()
+            .await
+            .insert(cache_key.to_string(), values);
+        Ok(())
+    }
+
+    /// Returns the evaluation timestamp of a selector or subquery with the
+    /// given `offset` and `@` modifiers.
+    ///
+    /// See <https://prometheus.io/docs/prometheus/latest/querying/basics/#modifier>
+    fn selector_eval_ts(&self, offset: &Option<Offset>, at: &Option<AtModifier>) -> i64 {
+        self.at_timestamp(at).unwrap_or(self.time) - offset_micros(offset)
+    }
+
+    /// Returns the `[start, end]` evaluation timestamps of the whole query
+    /// for a selector or subquery with the given `offset` and `@` modifiers.
+    fn shifted_time_range(&self, offset: &Option<Offset>, at: &Option<AtModifier>) -> (i64, i64) {
+        let offset = offset_micros(offset);
+        match self.at_timestamp(at) {
+            // `@` pins all evaluation steps to the same timestamp.
+            Some(at) => (at - offset, at - offset),
+            None => (self.ctx.start - offset, self.ctx.end - offset),
+        }
+    }
+
+    fn at_timestamp(&self, at: &Option<AtModifier>) -> Option<i64> {
+        at.as_ref().map(|at| match at {
+            AtModifier::Start => self.ctx.query_start,
+            AtModifier::End => self.ctx.query_end,
+            AtModifier::At(t) => micros_since_epoch(*t),
+        })
+    }
+
+    /// Loads the data of `selector` needed by the whole query, unless it is
+    /// cached already, and returns its key in the data cache.
+    ///
+    /// The key contains the time range of the data, so the same metric
+    /// selected with different `offset` or `@` modifiers is cached separately.
     #[tracing::instrument(name = "promql:engine:load_data", skip_all)]
     async fn selector_load_data(
         &mut self,
         selector: &VectorSelector,
         range: Option<Duration>,
-    ) -> Result<()> {
+    ) -> Result<String> {
         // https://promlabs.com/blog/2020/07/02/selecting-data-in-promql/#lookback-delta
-        let start = self.ctx.start - range.map_or(self.ctx.lookback_delta, micros);
-        let end = self.ctx.end; // 30 minutes + 5m = 35m
+        let (start, end) = self.shifted_time_range(&selector.offset, &selector.at);
+        let start = start - range.map_or(self.ctx.lookback_delta, micros);
 
-        // 1. Group by metrics (sets of label name-value pairs)
         let table_name = selector.name.as_ref().unwrap();
+        let cache_key = format!(
+            "{}{:?}[{},{}]",
+            table_name, selector.matchers.matchers, start, end
+        );
+        if self.ctx.data_cache.read().await.contains_key(&cache_key) {
+            return Ok(cache_key);
+        }
+
+        // 1. Group by metrics (sets of label name-value pairs)
         let filters: Vec<(&str, &str)> = selector
             .matchers
             .matchers
@@ -290,8 +473,8 @@ impl Engine {
                 .data_cache
                 .write()
                 .await
-                .insert(table_name.to_string(), Value::None);
-            return Ok(());
+                .insert(cache_key.clone(), Value::None);
+            return Ok(cache_key);
         }
 
         // cache data
@@ -308,8 +491,8 @@ impl Engine {
             .data_cache
             .write()
             .await
-            .insert(table_name.to_string(), values);
-        Ok(())
+            .insert(cache_key.clone(), values);
+        Ok(cache_key)
     }
 
     async fn aggregate_exprs(
@@ -328,13 +511,30 @@ impl Engine {
             token::T_COUNT => aggregations::count(sample_time, modifier, &input)?,
             token::T_MIN => aggregations::min(sample_time, modifier, &input)?,
             token::T_MAX => aggregations::max(sample_time, modifier, &input)?,
-            token::T_GROUP => Value::None,
-            token::T_STDDEV => Value::None,
-            token::T_STDVAR => Value::None,
+            token::T_GROUP => aggregations::group(sample_time, modifier, &input)?,
+            token::T_STDDEV => aggregations::stddev(sample_time, modifier, &input)?,
+            token::T_STDVAR => aggregations::stdvar(sample_time, modifier, &input)?,
             token::T_TOPK => aggregations::topk(self, param.clone().unwrap(), &input).await?,
             token::T_BOTTOMK => aggregations::bottomk(self, param.clone().unwrap(), &input).await?,
-            token::T_COUNT_VALUES => Value::None,
-            token::T_QUANTILE => Value::None,
+            token::T_COUNT_VALUES => {
+                let param = match param {
+                    Some(param) => self.exec_expr(param).await?,
+                    None => Value::None,
+                };
+                let label_name = match param {
+                    Value::String(v) => v,
+                    _ => {
+                        return Err(DataFusionError::Plan(
+                            "[count_values] param must be a string".to_string(),
+                        ))
+                    }
+                };
+                aggregations::count_values(sample_time, &label_name, modifier, &input)?
+            }
+            token::T_QUANTILE => {
+                aggregations::quantile(self, sample_time, param.clone().unwrap(), modifier, &input)
+                    .await?
+            }
             _ => {
                 return Err(DataFusionError::NotImplemented(format!(
                     "Unsupported Aggregate: {:?}",
@@ -350,282 +550,215 @@ impl Engine {
         let func_name = Func::from_str(func.name).map_err(|_| {
             DataFusionError::NotImplemented(format!("Unsupported function: {}", func.name))
         })?;
+        let eval_ts = self.time;
 
-        let last_arg = args
-            .last()
-            .expect("BUG: promql-parser should have validated function arguments");
-        let input = self.exec_expr(&last_arg).await?;
+        let input = match func_name {
+            // The time series are the last argument of these functions.
+            Func::HistogramFraction | Func::HistogramQuantile | Func::QuantileOverTime => {
+                let last_arg = args
+                    .last()
+                    .expect("BUG: promql-parser should have validated function arguments");
+                self.exec_expr(&last_arg).await?
+            }
+            // `timestamp()` needs the original timestamps of the samples.
+            Func::Timestamp => match args.args.first().map(|arg| arg.as_ref()) {
+                Some(PromExpr::VectorSelector(selector)) => {
+                    let data = self.eval_vector_selector_samples(selector).await?;
+                    if data.is_empty() {
+                        Value::None
+                    } else {
+                        Value::Vector(data)
+                    }
+                }
+                Some(arg) => self.exec_expr(arg).await?,
+                None => Value::None,
+            },
+            _ => match args.args.first() {
+                Some(arg) => self.exec_expr(arg).await?,
+                // The date functions default to `vector(time())`.
+                None => Value::Vector(vec![InstantValue {
+                    labels: Labels::default(),
+                    sample: Sample::new(eval_ts, eval_ts as f64 / 1_000_000.0),
+                }]),
+            },
+        };
 
         Ok(match func_name {
-            Func::Abs => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Absent => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::AbsentOverTime => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::Abs => functions::abs(&input)?,
+            Func::Absent => functions::absent(eval_ts, &input, &args.args[0])?,
+            Func::AbsentOverTime => functions::absent_over_time(eval_ts, &input, &args.args[0])?,
             Func::AvgOverTime => functions::avg_over_time(&input)?,
-            Func::Ceil => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Changes => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::Ceil => functions::ceil(&input)?,
+            Func::Changes => functions::changes(&input)?,
             Func::Clamp => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let min = self.eval_float_arg(func, args, 1).await?;
+                let max = self.eval_float_arg(func, args, 2).await?;
+                functions::clamp(&input, min, max)?
             }
             Func::ClampMax => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let max = self.eval_float_arg(func, args, 1).await?;
+                functions::clamp_max(&input, max)?
             }
             Func::ClampMin => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let min = self.eval_float_arg(func, args, 1).await?;
+                functions::clamp_min(&input, min)?
             }
             Func::CountOverTime => functions::count_over_time(&input)?,
-            Func::DayOfMonth => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::DayOfWeek => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::DayOfYear => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::DaysInMonth => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::DayOfMonth => functions::day_of_month(&input)?,
+            Func::DayOfWeek => functions::day_of_week(&input)?,
+            Func::DayOfYear => functions::day_of_year(&input)?,
+            Func::DaysInMonth => functions::days_in_month(&input)?,
             Func::Delta => functions::delta(&input)?,
-            Func::Deriv => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Exp => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Floor => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::HistogramCount => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::Deriv => functions::deriv(&input)?,
+            Func::Exp => functions::exp(&input)?,
+            Func::Floor => functions::floor(&input)?,
+            Func::HistogramCount => functions::histogram_count(eval_ts, input)?,
             Func::HistogramFraction => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let lower = self.eval_float_arg(func, args, 0).await?;
+                let upper = self.eval_float_arg(func, args, 1).await?;
+                functions::histogram_fraction(eval_ts, lower, upper, input)?
             }
             Func::HistogramQuantile => {
-                let args = &args.args;
-                if args.len() != 2 {
+                let phi = self.eval_float_arg(func, args, 0).await?;
+                functions::histogram_quantile(eval_ts, phi, input)?
+            }
+            Func::HistogramSum => functions::histogram_sum(input)?,
+            Func::HoltWinters => {
+                let sf = self.eval_float_arg(func, args, 1).await?;
+                let tf = self.eval_float_arg(func, args, 2).await?;
+                if sf <= 0.0 || sf >= 1.0 {
                     return Err(DataFusionError::Plan(format!(
-                        "{}: expected 2 arguments, got {}",
-                        func.name,
-                        args.len()
+                        "invalid smoothing factor. Expected: 0 < sf < 1, got: {sf}"
                     )));
                 }
-                let phi = {
-                    match *args[0] {
-                        PromExpr::NumberLiteral(ref num) => num.val,
-                        _ => {
-                            return Err(DataFusionError::Plan(format!(
-                                "{}: the first argument must be a number",
-                                func.name
-                            )))
-                        }
-                    }
-                };
-                let sample_time = self.time;
-                functions::histogram_quantile(sample_time, phi, input)?
-            }
-            Func::HistogramSum => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::HoltWinters => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Hour => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                if tf <= 0.0 || tf >= 1.0 {
+                    return Err(DataFusionError::Plan(format!(
+                        "invalid trend factor. Expected: 0 < tf < 1, got: {tf}"
+                    )));
+                }
+                functions::holt_winters(&input, sf, tf)?
             }
+            Func::Hour => functions::hour(&input)?,
             Func::Idelta => functions::idelta(&input)?,
             Func::Increase => functions::increase(&input)?,
             Func::Irate => functions::irate(&input)?,
             Func::LabelJoin => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let dst_label = self.eval_string_arg(func, args, 1).await?;
+                let separator = self.eval_string_arg(func, args, 2).await?;
+                let mut src_labels = Vec::with_capacity(args.args.len());
+                for i in 3..args.args.len() {
+                    src_labels.push(self.eval_string_arg(func, args, i).await?);
+                }
+                functions::label_join(&input, &dst_label, &separator, &src_labels)?
             }
             Func::LabelReplace => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Ln => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Log10 => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Log2 => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+                let dst_label = self.eval_string_arg(func, args, 1).await?;
+                let replacement = self.eval_string_arg(func, args, 2).await?;
+                let src_label = self.eval_string_arg(func, args, 3).await?;
+                let regex = self.eval_string_arg(func, args, 4).await?;
+                functions::label_replace(&input, &dst_label, &replacement, &src_label, &regex)?
+            }
+            Func::Ln => functions::ln(&input)?,
+            Func::Log10 => functions::log10(&input)?,
+            Func::Log2 => functions::log2(&input)?,
             Func::MaxOverTime => functions::max_over_time(&input)?,
             Func::MinOverTime => functions::min_over_time(&input)?,
-            Func::Minute => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Month => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::Minute => functions::minute(&input)?,
+            Func::Month => functions::month(&input)?,
             Func::PredictLinear => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let duration = self.eval_float_arg(func, args, 1).await?;
+                functions::predict_linear(&input, duration)?
             }
             Func::QuantileOverTime => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let phi = self.eval_float_arg(func, args, 0).await?;
+                functions::quantile_over_time(phi, &input)?
             }
             Func::Rate => functions::rate(&input)?,
-            Func::Resets => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::Resets => functions::resets(&input)?,
             Func::Round => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Scalar => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Sgn => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Sort => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::SortDesc => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
+                let to_nearest = if args.args.len() > 1 {
+                    self.eval_float_arg(func, args, 1).await?
+                } else {
+                    1.0
+                };
+                functions::round(&input, to_nearest)?
             }
+            Func::Scalar => functions::scalar(&input)?,
+            Func::Sgn => functions::sgn(&input)?,
+            Func::Sort => functions::sort(input)?,
+            Func::SortDesc => functions::sort_desc(input)?,
             Func::SumOverTime => functions::sum_over_time(&input)?,
-            Func::Time => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Timestamp => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Vector => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
-            Func::Year => {
-                return Err(DataFusionError::NotImplemented(format!(
-                    "Unsupported Function: {:?}",
-                    func_name
-                )));
-            }
+            Func::Time => functions::time(eval_ts),
+            Func::Timestamp => functions::timestamp(eval_ts, &input)?,
+            Func::Vector => functions::vector(eval_ts, &input)?,
+            Func::Year => functions::year(&input)?,
         })
     }
+
+    /// Evaluates the `index`-th argument of a function call, which must be
+    /// a scalar.
+    async fn eval_float_arg(
+        &mut self,
+        func: &Function,
+        args: &FunctionArgs,
+        index: usize,
+    ) -> Result<f64> {
+        match self.eval_arg(func, args, index).await? {
+            Value::Float(v) => Ok(v),
+            v => Err(DataFusionError::Plan(format!(
+                "{}: argument {} must be a scalar, got {}",
+                func.name,
+                index + 1,
+                v.get_type()
+            ))),
+        }
+    }
+
+    /// Evaluates the `index`-th argument of a function call, which must be
+    /// a string.
+    async fn eval_string_arg(
+        &mut self,
+        func: &Function,
+        args: &FunctionArgs,
+        index: usize,
+    ) -> Result<String> {
+        match self.eval_arg(func, args, index).await? {
+            Value::String(v) => Ok(v),
+            v => Err(DataFusionError::Plan(format!(
+                "{}: argument {} must be a string, got {}",
+                func.name,
+                index + 1,
+                v.get_type()
+            ))),
+        }
+    }
+
+    async fn eval_arg(
+        &mut self,
+        func: &Function,
+        args: &FunctionArgs,
+        index: usize,
+    ) -> Result<Value> {
+        let arg = args.args.get(index).ok_or_else(|| {
+            DataFusionError::Plan(format!(
+                "{}: expected at least {} arguments, got {}",
+                func.name,
+                index + 1,
+                args.args.len()
+            ))
+        })?;
+        self.exec_expr(arg).await
+    }
+}
+
+/// Returns how far an `offset` modifier shifts the evaluation back in time,
+/// microseconds. Negative offsets shift it into the future.
+fn offset_micros(offset: &Option<Offset>) -> i64 {
+    match offset {
+        Some(Offset::Pos(d)) => micros(*d),
+        Some(Offset::Neg(d)) => -micros(*d),
+        None => 0,
+    }
 }
 
 async fn selector_load_data_from_datafusion(
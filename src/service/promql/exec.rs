@@ -12,8 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use datafusion::error::Result;
-use promql_parser::parser::EvalStmt;
+use datafusion::error::{DataFusionError, Result};
+use promql_parser::parser::{Call, EvalStmt, Expr as PromExpr};
 use rustc_hash::FxHashMap;
 use std::{
     sync::Arc,
@@ -33,11 +33,16 @@ pub struct Query {
     /// is evaluated.
     pub start: i64,
     pub end: i64,
+    /// The time boundaries of the top-level query, which `@ start()` and
+    /// `@ end()` refer to, also inside of subqueries.
+    pub query_start: i64,
+    pub query_end: i64,
     /// Time between two evaluated instants for the range [start:end].
     pub interval: i64,
     /// Default look back from sample search.
     pub lookback_delta: i64,
-    /// key — metric name; value — time series data
+    /// key — selector with the time range of its data, or subquery
+    /// expression; value — time series data
     pub data_cache: Arc<RwLock<FxHashMap<String, Value>>>,
 }
 
@@ -53,6 +58,8 @@ impl Query {
             table_provider: Arc::new(Box::new(provider)),
             start: now,
             end: now,
+            query_start: now,
+            query_end: now,
             interval: five_min,
             lookback_delta: five_min,
             data_cache: Arc::new(RwLock::new(FxHashMap::default())),
@@ -63,15 +70,21 @@ impl Query {
     pub async fn exec(&mut self, stmt: EvalStmt) -> Result<(Value, Option<String>)> {
         self.start = micros_since_epoch(stmt.start);
         self.end = micros_since_epoch(stmt.end);
+        self.query_start = self.start;
+        self.query_end = self.end;
         if stmt.interval > Duration::ZERO {
             self.interval = micros(stmt.interval);
         }
         if stmt.lookback_delta > Duration::ZERO {
             self.lookback_delta = micros(stmt.lookback_delta);
         }
+        self.eval(stmt.expr).await
+    }
 
+    /// Evaluates `expr` over the `[start, end]` range of this query.
+    pub(crate) async fn eval(&self, expr: PromExpr) -> Result<(Value, Option<String>)> {
         let ctx = Arc::new(self.clone());
-        let expr = Arc::new(stmt.expr);
+        let expr = Arc::new(expr);
         let mut result_type: Option<String> = None;
 
         // range query always be matrix result type.
@@ -81,12 +94,24 @@ impl Query {
             // Instant query
             let mut engine = super::Engine::new(ctx.clone(), self.start);
             let expr = expr.clone();
-            let (mut value, result_type_exec) = engine.exec(&expr).await?;
-            if let Value::Float(val) = value {
-                value = Value::Sample(Sample::new(self.end, val));
+            let (value, result_type_exec) = engine.exec(&expr).await?;
+            let mut value = match value {
+                Value::Float(val) => Value::Sample(Sample::new(self.end, val)),
+                Value::String(val) => Value::StringSample(StringSample::new(self.end, val)),
+                value => value,
+            };
+            // `sort()` and `sort_desc()` define the order of the result.
+            let is_sorted = matches!(
+                expr.as_ref(),
+                PromExpr::Call(Call { func, .. }) if func.name == "sort" || func.name == "sort_desc"
+            );
+            if !is_sorted {
+                value.sort();
             }
-            value.sort();
-            if result_type_exec.is_some() {
+            if let Value::Sample(_) = value {
+                // e.g. `scalar(foo)` selects a vector, but returns a scalar
+                result_type = Some("scalar".to_string());
+            } else if result_type_exec.is_some() {
                 result_type = result_type_exec;
             }
             return Ok((value, result_type));
@@ -125,6 +150,11 @@ impl Query {
                 Value::Sample(s) => instant_vectors.push(RangeValue::new(Labels::default(), [s])),
                 Value::Float(val) => instant_vectors
                     .push(RangeValue::new(Labels::default(), [Sample::new(time, val)])),
+                Value::String(_) | Value::StringSample(_) => {
+                    return Err(DataFusionError::Plan(
+                        "invalid expression type \"string\" for range query, must be Scalar or instant Vector".to_string(),
+                    ))
+                }
                 Value::None => continue,
             };
         }
@@ -0,0 +1,390 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use promql_parser::{
+    label::MatchOp,
+    parser::{
+        token, AggregateExpr, AtModifier, BinaryExpr, Call, Expr as PromExpr, LabelModifier,
+        MatrixSelector, NumberLiteral, Offset, ParenExpr, StringLiteral, SubqueryExpr, TokenType,
+        UnaryExpr, VectorMatchCardinality, VectorSelector,
+    },
+};
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::meta::prom::NAME_LABEL;
+
+/// Formats a parsed PromQL expression the way Prometheus prints it, e.g.
+/// `sum by (job) (rate(foo{bar="baz"}[5m]))`.
+///
+/// Parentheses are kept as written, so the result parses to the same
+/// expression.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/parser/printer.go
+pub fn format_expr(expr: &PromExpr) -> String {
+    match expr {
+        PromExpr::Aggregate(AggregateExpr {
+            op,
+            expr,
+            param,
+            modifier,
+        }) => {
+            let mut s = token_str(op).to_string();
+            match modifier {
+                Some(LabelModifier::Include(labels)) => {
+                    s.push_str(&format!(" by ({}) ", join_labels(labels.iter())));
+                }
+                Some(LabelModifier::Exclude(labels)) => {
+                    s.push_str(&format!(" without ({}) ", join_labels(labels.iter())));
+                }
+                None => {}
+            }
+            match param {
+                Some(param) => {
+                    s.push_str(&format!("({}, {})", format_expr(param), format_expr(expr)))
+                }
+                None => s.push_str(&format!("({})", format_expr(expr))),
+            }
+            s
+        }
+        PromExpr::Unary(UnaryExpr { expr }) => format!("-{}", format_expr(expr)),
+        PromExpr::Binary(BinaryExpr {
+            op,
+            lhs,
+            rhs,
+            modifier,
+        }) => {
+            let mut op = token_str(op).to_string();
+            if let Some(modifier) = modifier {
+                if modifier.return_bool {
+                    op.push_str(" bool");
+                }
+                match &modifier.matching {
+                    Some(LabelModifier::Include(labels)) => {
+                        op.push_str(&format!(" on ({})", join_labels(labels.iter())));
+                    }
+                    Some(LabelModifier::Exclude(labels)) => {
+                        op.push_str(&format!(" ignoring ({})", join_labels(labels.iter())));
+                    }
+                    None => {}
+                }
+                match &modifier.card {
+                    VectorMatchCardinality::ManyToOne(labels) => {
+                        op.push_str(" group_left");
+                        if !labels.is_empty() {
+                            op.push_str(&format!(" ({})", join_labels(labels.iter())));
+                        }
+                    }
+                    VectorMatchCardinality::OneToMany(labels) => {
+                        op.push_str(" group_right");
+                        if !labels.is_empty() {
+                            op.push_str(&format!(" ({})", join_labels(labels.iter())));
+                        }
+                    }
+                    VectorMatchCardinality::OneToOne | VectorMatchCardinality::ManyToMany => {}
+                }
+            }
+            format!("{} {} {}", format_expr(lhs), op, format_expr(rhs))
+        }
+        PromExpr::Paren(ParenExpr { expr }) => format!("({})", format_expr(expr)),
+        PromExpr::Subquery(SubqueryExpr {
+            expr,
+            offset,
+            at,
+            range,
+            step,
+        }) => {
+            let step = step.map(format_duration).unwrap_or_default();
+            format!(
+                "{}[{}:{}]{}",
+                format_expr(expr),
+                format_duration(*range),
+                step,
+                format_modifiers(offset, at)
+            )
+        }
+        PromExpr::NumberLiteral(NumberLiteral { val }) => format_number(*val),
+        PromExpr::StringLiteral(StringLiteral { val }) => quote(val),
+        PromExpr::VectorSelector(selector) => format!(
+            "{}{}",
+            format_selector(selector),
+            format_modifiers(&selector.offset, &selector.at)
+        ),
+        PromExpr::MatrixSelector(MatrixSelector {
+            vector_selector,
+            range,
+        }) => format!(
+            "{}[{}]{}",
+            format_selector(vector_selector),
+            format_duration(*range),
+            format_modifiers(&vector_selector.offset, &vector_selector.at)
+        ),
+        PromExpr::Call(Call { func, args }) => {
+            let args = args
+                .args
+                .iter()
+                .map(|arg| format_expr(arg))
+                .collect::<Vec<_>>();
+            format!("{}({})", func.name, args.join(", "))
+        }
+        PromExpr::Extension(expr) => format!("{expr:?}"),
+    }
+}
+
+/// Formats the metric name and the label matchers of a selector.
+fn format_selector(selector: &VectorSelector) -> String {
+    let matchers = selector
+        .matchers
+        .matchers
+        .iter()
+        .filter(|mat| {
+            // the metric name is printed in front of the matchers
+            !(mat.name == NAME_LABEL
+                && mat.op == MatchOp::Equal
+                && selector.name.as_deref() == Some(mat.value.as_str()))
+        })
+        .map(|mat| {
+            let op = match mat.op {
+                MatchOp::Equal => "=",
+                MatchOp::NotEqual => "!=",
+                MatchOp::Re(_) => "=~",
+                MatchOp::NotRe(_) => "!~",
+            };
+            format!("{}{}{}", mat.name, op, quote(&mat.value))
+        })
+        .collect::<Vec<_>>();
+    let name = selector.name.as_deref().unwrap_or_default();
+    if matchers.is_empty() && !name.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}{{{}}}", name, matchers.join(", "))
+    }
+}
+
+fn format_modifiers(offset: &Option<Offset>, at: &Option<AtModifier>) -> String {
+    let mut s = String::new();
+    match at {
+        Some(AtModifier::Start) => s.push_str(" @ start()"),
+        Some(AtModifier::End) => s.push_str(" @ end()"),
+        Some(AtModifier::At(t)) => {
+            let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+            s.push_str(&format!(" @ {:.3}", secs.as_secs_f64()));
+        }
+        None => {}
+    }
+    match offset {
+        Some(Offset::Pos(d)) => s.push_str(&format!(" offset {}", format_duration(*d))),
+        Some(Offset::Neg(d)) => s.push_str(&format!(" offset -{}", format_duration(*d))),
+        None => {}
+    }
+    s
+}
+
+fn format_number(val: f64) -> String {
+    if val.is_nan() {
+        "NaN".to_string()
+    } else if val == f64::INFINITY {
+        "+Inf".to_string()
+    } else if val == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        val.to_string()
+    }
+}
+
+/// Formats a duration like Prometheus does, e.g. `1h30m`. Years and weeks are
+/// only used if the duration is a multiple of them.
+fn format_duration(d: Duration) -> String {
+    const UNITS: [(&str, u128, bool); 7] = [
+        ("y", 365 * 24 * 60 * 60 * 1000, true),
+        ("w", 7 * 24 * 60 * 60 * 1000, true),
+        ("d", 24 * 60 * 60 * 1000, false),
+        ("h", 60 * 60 * 1000, false),
+        ("m", 60 * 1000, false),
+        ("s", 1000, false),
+        ("ms", 1, false),
+    ];
+    let mut ms = d.as_millis();
+    if ms == 0 {
+        return "0s".to_string();
+    }
+    let mut s = String::new();
+    for (unit, mult, exact) in UNITS {
+        if ms >= mult && (!exact || ms % mult == 0) {
+            s.push_str(&format!("{}{}", ms / mult, unit));
+            ms %= mult;
+        }
+    }
+    s
+}
+
+/// Quotes a string like Prometheus does with Go's `strconv.Quote`: printable
+/// characters, non-ASCII ones included, are kept as they are, the rest are
+/// escaped with `\n`, `\xNN`, `\uNNNN` etc.
+fn quote(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\u{07}' => out.push_str("\\a"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{0b}' => out.push_str("\\v"),
+            c if c < ' ' || c == '\u{7f}' => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c if is_print(c) => out.push(c),
+            c if (c as u32) < 0x10000 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push_str(&format!("\\U{:08x}", c as u32)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Approximates Go's `unicode.IsPrint`: control, format and separator
+/// characters other than the ASCII space are not printable.
+fn is_print(c: char) -> bool {
+    c == ' '
+        || !(c.is_control()
+            || c.is_whitespace()
+            || matches!(
+                c,
+                '\u{ad}'
+                    | '\u{600}'..='\u{605}'
+                    | '\u{61c}'
+                    | '\u{6dd}'
+                    | '\u{70f}'
+                    | '\u{180e}'
+                    | '\u{200b}'..='\u{200f}'
+                    | '\u{202a}'..='\u{202e}'
+                    | '\u{2060}'..='\u{2064}'
+                    | '\u{2066}'..='\u{206f}'
+                    | '\u{feff}'
+                    | '\u{fff9}'..='\u{fffb}'
+                    | '\u{e000}'..='\u{f8ff}'
+                    | '\u{f0000}'..='\u{10ffff}'
+            ))
+}
+
+fn join_labels<'a>(labels: impl Iterator<Item = &'a String>) -> String {
+    labels.map(|l| l.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+fn token_str(op: &TokenType) -> &'static str {
+    match op.id() {
+        token::T_ADD => "+",
+        token::T_SUB => "-",
+        token::T_MUL => "*",
+        token::T_DIV => "/",
+        token::T_MOD => "%",
+        token::T_POW => "^",
+        token::T_EQLC => "==",
+        token::T_NEQ => "!=",
+        token::T_GTR => ">",
+        token::T_LSS => "<",
+        token::T_GTE => ">=",
+        token::T_LTE => "<=",
+        token::T_LAND => "and",
+        token::T_LOR => "or",
+        token::T_LUNLESS => "unless",
+        token::T_ATAN2 => "atan2",
+        token::T_SUM => "sum",
+        token::T_AVG => "avg",
+        token::T_COUNT => "count",
+        token::T_MIN => "min",
+        token::T_MAX => "max",
+        token::T_GROUP => "group",
+        token::T_STDDEV => "stddev",
+        token::T_STDVAR => "stdvar",
+        token::T_TOPK => "topk",
+        token::T_BOTTOMK => "bottomk",
+        token::T_COUNT_VALUES => "count_values",
+        token::T_QUANTILE => "quantile",
+        _ => "<unknown>",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use promql_parser::parser::parse;
+
+    fn format(query: &str) -> String {
+        format_expr(&parse(query).unwrap())
+    }
+
+    #[test]
+    fn test_format_expr() {
+        let tests = [
+            ("up", "up"),
+            (
+                r#"sum  by(job)(rate(http_requests_total{code=~"5.."}[5m]))"#,
+                r#"sum by (job) (rate(http_requests_total{code=~"5.."}[5m]))"#,
+            ),
+            ("topk(3,up)", "topk(3, up)"),
+            (
+                "a/on(instance)group_left(job)b",
+                "a / on (instance) group_left (job) b",
+            ),
+            ("up>bool 1", "up > bool 1"),
+            ("(a+b)*2", "(a + b) * 2"),
+            ("up offset 1h30m", "up offset 1h30m"),
+            ("up offset 1w", "up offset 1w"),
+            ("up @ end()", "up @ end()"),
+            (
+                "max_over_time(rate(x[1m])[1h:5m])",
+                "max_over_time(rate(x[1m])[1h:5m])",
+            ),
+            ("rate(x[5m] offset 1d)", "rate(x[5m] offset 1d)"),
+            (
+                r#"label_join(up,"a",",","b")"#,
+                r#"label_join(up, "a", ",", "b")"#,
+            ),
+            ("-up", "-up"),
+        ];
+        for (query, expected) in tests {
+            assert_eq!(format(query), expected, "query: {query}");
+        }
+    }
+
+    #[test]
+    fn test_quote() {
+        // cf. the output of Go's strconv.Quote
+        let tests = [
+            ("abc", r#""abc""#),
+            (r#"a"b\c"#, r#""a\"b\\c""#),
+            ("a\nb\tc", r#""a\nb\tc""#),
+            ("\u{1b}[0m\u{7f}", r#""\x1b[0m\x7f""#),
+            ("héllo 世界", r#""héllo 世界""#),
+            ("\u{a0}\u{200b}\u{2028}", r#""\u00a0\u200b\u2028""#),
+            ("\u{f0000}", r#""\U000f0000""#),
+        ];
+        for (s, expected) in tests {
+            assert_eq!(quote(s), expected, "string: {s:?}");
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1s500ms");
+        assert_eq!(format_duration(Duration::from_secs(8 * 24 * 3600)), "8d");
+        assert_eq!(format_duration(Duration::from_secs(14 * 24 * 3600)), "2w");
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::{DataFusionError, Result};
+use promql_parser::{
+    label::MatchOp,
+    parser::{Expr as PromExpr, MatrixSelector},
+};
+use rustc_hash::FxHashSet;
+use std::sync::Arc;
+
+use crate::meta::prom::NAME_LABEL;
+use crate::service::promql::value::{InstantValue, Label, Labels, Sample, Value};
+
+/// Returns a 1-element vector with the value 1 if the vector passed to it
+/// has no elements, and an empty vector otherwise.
+///
+/// If the argument is a vector selector, the labels of the result are taken
+/// from its equality matchers, e.g. `absent(nonexistent{job="myjob"})`
+/// returns `{job="myjob"} 1`.
+pub(crate) fn absent(eval_ts: i64, data: &Value, arg: &PromExpr) -> Result<Value> {
+    let is_absent = match data {
+        Value::Vector(v) => v.is_empty(),
+        Value::None => true,
+        _ => {
+            return Err(DataFusionError::Plan(
+                "absent: vector argument expected".to_string(),
+            ))
+        }
+    };
+    Ok(absent_value(eval_ts, is_absent, arg))
+}
+
+/// Same as [`absent`], but for range vectors: returns a 1-element vector if
+/// none of the series has samples in the range.
+pub(crate) fn absent_over_time(eval_ts: i64, data: &Value, arg: &PromExpr) -> Result<Value> {
+    let is_absent = match data {
+        Value::Matrix(v) => v.iter().all(|series| series.samples.is_empty()),
+        Value::None => true,
+        _ => {
+            return Err(DataFusionError::Plan(
+                "absent_over_time: matrix argument expected".to_string(),
+            ))
+        }
+    };
+    Ok(absent_value(eval_ts, is_absent, arg))
+}
+
+fn absent_value(eval_ts: i64, is_absent: bool, arg: &PromExpr) -> Value {
+    if !is_absent {
+        return Value::None;
+    }
+    Value::Vector(vec![InstantValue {
+        labels: absent_labels(arg),
+        sample: Sample::new(eval_ts, 1.0),
+    }])
+}
+
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/functions.go#L1295
+fn absent_labels(arg: &PromExpr) -> Labels {
+    let selector = match arg {
+        PromExpr::VectorSelector(v) => v,
+        PromExpr::MatrixSelector(MatrixSelector {
+            vector_selector, ..
+        }) => vector_selector,
+        _ => return Labels::default(),
+    };
+
+    let mut labels = Labels::default();
+    let mut seen = FxHashSet::default();
+    for mat in selector.matchers.matchers.iter() {
+        if mat.name == NAME_LABEL {
+            continue;
+        }
+        // A label matched more than once can't be reproduced unambiguously.
+        labels.retain(|l| l.name != mat.name);
+        if mat.op == MatchOp::Equal && seen.insert(mat.name.as_str()) {
+            labels.push(Arc::new(Label {
+                name: mat.name.clone(),
+                value: mat.value.clone(),
+            }));
+        }
+    }
+    labels.sort_by(|a, b| a.name.cmp(&b.name));
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use promql_parser::parser;
+
+    fn labels(query: &str) -> Vec<(String, String)> {
+        let expr = parser::parse(query).unwrap();
+        let PromExpr::Call(call) = expr else {
+            panic!("function call expected");
+        };
+        let Value::Vector(v) = absent(1_000_000, &Value::None, &call.args.args[0]).unwrap() else {
+            panic!("vector expected");
+        };
+        assert_eq!(v[0].sample.value, 1.0);
+        v[0].labels
+            .iter()
+            .map(|l| (l.name.clone(), l.value.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_absent() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test
+        assert_eq!(
+            labels(r#"absent(nonexistent{job="testjob", instance="testinstance", method=~".x"})"#),
+            vec![
+                ("instance".to_string(), "testinstance".to_string()),
+                ("job".to_string(), "testjob".to_string()),
+            ]
+        );
+        assert_eq!(
+            labels(r#"absent(nonexistent{job="testjob", job="testjob2", foo="bar"})"#),
+            vec![("foo".to_string(), "bar".to_string())]
+        );
+        assert_eq!(labels("absent(sum(nonexistent))"), vec![]);
+    }
+}
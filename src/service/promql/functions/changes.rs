@@ -0,0 +1,69 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::Result;
+
+use crate::service::promql::value::{RangeValue, Value};
+
+pub(crate) fn changes(data: &Value) -> Result<Value> {
+    super::eval_idelta(data, "changes", exec)
+}
+
+fn exec(data: &RangeValue) -> Option<f64> {
+    if data.samples.is_empty() {
+        return None;
+    }
+    let changes = data
+        .samples
+        .windows(2)
+        .filter(|w| {
+            let (prev, curr) = (w[0].value, w[1].value);
+            !(curr == prev || (curr.is_nan() && prev.is_nan()))
+        })
+        .count();
+    Some(changes as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Labels, Sample};
+
+    fn series(values: &[f64]) -> RangeValue {
+        RangeValue {
+            labels: Labels::default(),
+            samples: values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| Sample::new(i as i64 * 300_000_000, *v))
+                .collect(),
+            time_window: None,
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test
+        // `changes(http_requests[50m])` at 50m over the samples every 5m
+        let cases = [
+            (vec![1.0, 2.0, 3.0, 0.0, 1.0, 0.0, 0.0, 1.0, 2.0, 0.0], 8.0),
+            (vec![1.0, 2.0, 3.0, 4.0, 5.0, 1.0, 2.0, 3.0, 4.0, 5.0], 9.0),
+            (vec![0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0], 1.0),
+        ];
+        for (values, expected) in cases {
+            assert_eq!(exec(&series(&values)), Some(expected));
+        }
+        assert_eq!(exec(&series(&[f64::NAN, f64::NAN])), Some(0.0));
+        assert_eq!(exec(&series(&[])), None);
+    }
+}
@@ -0,0 +1,65 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::Result;
+
+use crate::service::promql::value::{RangeValue, Value};
+
+pub(crate) fn deriv(data: &Value) -> Result<Value> {
+    super::eval_idelta(data, "deriv", exec)
+}
+
+fn exec(data: &RangeValue) -> Option<f64> {
+    if data.samples.len() < 2 {
+        return None;
+    }
+    // We pass in an arbitrary timestamp that is near the values in use
+    // to avoid floating point accuracy issues.
+    let (slope, _) = super::linear_regression(&data.samples, data.samples[0].timestamp);
+    Some(slope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Labels, Sample};
+    use float_cmp::approx_eq;
+
+    fn series(values: &[f64]) -> RangeValue {
+        RangeValue {
+            labels: Labels::default(),
+            samples: values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| Sample::new(i as i64 * 300_000_000, *v))
+                .collect(),
+            time_window: None,
+        }
+    }
+
+    #[test]
+    fn test_deriv() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test
+        // `0+80x10`, deriv is the same as rate in simple cases
+        let values = (0..=10).map(|i| i as f64 * 80.0).collect::<Vec<_>>();
+        let v = exec(&series(&values)).unwrap();
+        assert!(approx_eq!(f64, v, 0.26666666666666666, epsilon = 1e-12));
+        // `testcounter_reset_middle`, `0+10x4 0+10x5`
+        let values = [
+            0.0, 10.0, 20.0, 30.0, 40.0, 0.0, 10.0, 20.0, 30.0, 40.0, 50.0,
+        ];
+        let v = exec(&series(&values)).unwrap();
+        assert!(approx_eq!(f64, v, 0.010606060606060607, epsilon = 1e-12));
+        assert_eq!(exec(&series(&[1.0])), None);
+    }
+}
@@ -40,13 +40,64 @@ struct MetricWithBuckets {
 ///
 /// [`histogramQuantile`]: https://github.com/prometheus/prometheus/blob/f7c6130ff27a2a12412c02cce223f7a8abc59e49/promql/quantile.go#L146
 pub(crate) fn histogram_quantile(sample_time: i64, phi: f64, data: Value) -> Result<Value> {
+    eval_buckets(sample_time, data, "histogram_quantile", |buckets| {
+        bucket_quantile(phi, buckets)
+    })
+}
+
+/// Returns the count of observations of conventional histograms, i.e. the
+/// value of their `+Inf` bucket.
+///
+/// Native histograms are not stored, so unlike Prometheus the function
+/// works on the `le`-labelled bucket series instead.
+pub(crate) fn histogram_count(sample_time: i64, data: Value) -> Result<Value> {
+    eval_buckets(sample_time, data, "histogram_count", bucket_count)
+}
+
+/// Returns the estimated fraction of observations between `lower` and
+/// `upper` of conventional histograms, interpolating linearly within the
+/// buckets (as `histogram_quantile` does).
+pub(crate) fn histogram_fraction(
+    sample_time: i64,
+    lower: f64,
+    upper: f64,
+    data: Value,
+) -> Result<Value> {
+    eval_buckets(sample_time, data, "histogram_fraction", |buckets| {
+        bucket_fraction(lower, upper, buckets)
+    })
+}
+
+/// The sum of observations is only kept by native histograms, which are not
+/// stored. Conventional histograms store it in a separate `<basename>_sum`
+/// series, which is queried directly instead.
+pub(crate) fn histogram_sum(data: Value) -> Result<Value> {
+    match data {
+        Value::Vector(_) | Value::None => Err(DataFusionError::NotImplemented(
+            "histogram_sum: native histograms are not supported, query the <basename>_sum series instead"
+                .to_owned(),
+        )),
+        _ => Err(DataFusionError::Plan(
+            "histogram_sum: vector argument expected".to_owned(),
+        )),
+    }
+}
+
+/// Groups the bucket series of `data` by histogram and evaluates
+/// `fn_handler` over the buckets of each histogram.
+fn eval_buckets(
+    sample_time: i64,
+    data: Value,
+    fn_name: &str,
+    fn_handler: impl Fn(Vec<Bucket>) -> f64,
+) -> Result<Value> {
     let in_vec = match data {
         Value::Vector(v) => v,
         Value::None => return Ok(Value::None),
         _ => {
-            return Err(DataFusionError::Plan(
-                "histogram_quantile: vector argument expected".to_owned(),
-            ))
+            return Err(DataFusionError::Plan(format!(
+                "{fn_name}: vector argument expected"
+            )))
         }
     };
 
@@ -86,7 +137,7 @@ pub(crate) fn histogram_quantile(sample_time: i64, phi: f64, data: Value) -> Res
         .into_values()
         .map(|mb| InstantValue {
             labels: mb.labels,
-            sample: Sample::new(sample_time, bucket_quantile(phi, mb.buckets)),
+            sample: Sample::new(sample_time, fn_handler(mb.buckets)),
         })
         .collect();
 
@@ -146,6 +197,77 @@ fn bucket_quantile(phi: f64, mut buckets: Vec<Bucket>) -> f64 {
     bucket_start + (bucket_end - bucket_start) * rank / count
 }
 
+/// Sorts, merges and validates the buckets of a histogram. Returns `None`
+/// if the highest bucket is not `+Inf` or there are fewer than two buckets.
+fn prepare_buckets(mut buckets: Vec<Bucket>) -> Option<Vec<Bucket>> {
+    buckets.sort_by(|a, b| a.upper_bound.partial_cmp(&b.upper_bound).unwrap());
+    let highest_bucket = buckets.last()?;
+    if !(highest_bucket.upper_bound.is_infinite() && highest_bucket.upper_bound.is_sign_positive())
+    {
+        return None;
+    }
+    let mut buckets = coalesce_buckets(buckets);
+    ensure_monotonic(&mut buckets);
+    if buckets.len() < 2 {
+        return None;
+    }
+    Some(buckets)
+}
+
+fn bucket_count(buckets: Vec<Bucket>) -> f64 {
+    match prepare_buckets(buckets) {
+        Some(buckets) => buckets[buckets.len() - 1].count,
+        None => f64::NAN,
+    }
+}
+
+fn bucket_fraction(lower: f64, upper: f64, buckets: Vec<Bucket>) -> f64 {
+    if lower.is_nan() || upper.is_nan() {
+        return f64::NAN;
+    }
+    let buckets = match prepare_buckets(buckets) {
+        Some(buckets) => buckets,
+        None => return f64::NAN,
+    };
+    let observations = buckets[buckets.len() - 1].count;
+    if observations == 0.0 {
+        return f64::NAN;
+    }
+    if upper <= lower {
+        return 0.0;
+    }
+    (observations_below(upper, &buckets) - observations_below(lower, &buckets)) / observations
+}
+
+/// Estimates the number of observations less than or equal to `value`.
+/// The buckets must be prepared by [`prepare_buckets`].
+fn observations_below(value: f64, buckets: &[Bucket]) -> f64 {
+    // The lowest bucket is assumed to start at zero, unless its upper bound
+    // is negative (same as `bucket_quantile`).
+    let mut lower_bound = if buckets[0].upper_bound <= 0.0 {
+        f64::NEG_INFINITY
+    } else {
+        0.0
+    };
+    let mut lower_count = 0.0;
+    for bucket in buckets {
+        if value < bucket.upper_bound {
+            if value <= lower_bound || lower_bound.is_infinite() {
+                return lower_count;
+            }
+            if bucket.upper_bound.is_infinite() {
+                // Observations of the `+Inf` bucket can't be located.
+                return lower_count;
+            }
+            let fraction = (value - lower_bound) / (bucket.upper_bound - lower_bound);
+            return lower_count + (bucket.count - lower_count) * fraction;
+        }
+        lower_bound = bucket.upper_bound;
+        lower_count = bucket.count;
+    }
+    lower_count
+}
+
 /// `coalesce_buckets` merges buckets with the same upper bound.
 /// The input buckets must be sorted.
 fn coalesce_buckets(buckets: Vec<Bucket>) -> Vec<Bucket> {
@@ -239,6 +361,41 @@ mod tests {
         .assert_debug_eq(&coalesce_buckets(buckets));
     }
 
+    #[test]
+    fn test_bucket_fraction() {
+        let buckets = vec![
+            Bucket {
+                upper_bound: 1.0,
+                count: 10.0,
+            },
+            Bucket {
+                upper_bound: 2.0,
+                count: 20.0,
+            },
+            Bucket {
+                upper_bound: 4.0,
+                count: 30.0,
+            },
+            Bucket {
+                upper_bound: f64::INFINITY,
+                count: 40.0,
+            },
+        ];
+        assert_eq!(bucket_count(buckets.clone()), 40.0);
+        assert_eq!(bucket_fraction(0.0, 1.0, buckets.clone()), 0.25);
+        assert_eq!(bucket_fraction(0.5, 3.0, buckets.clone()), 0.5);
+        assert_eq!(
+            bucket_fraction(f64::NEG_INFINITY, 4.0, buckets.clone()),
+            0.75
+        );
+        assert_eq!(bucket_fraction(0.0, f64::INFINITY, buckets.clone()), 1.0);
+        assert_eq!(bucket_fraction(2.0, 1.0, buckets.clone()), 0.0);
+
+        // no `+Inf` bucket
+        assert!(bucket_count(buckets[..3].to_vec()).is_nan());
+        assert!(bucket_fraction(0.0, 1.0, buckets[..3].to_vec()).is_nan());
+    }
+
     #[test]
     fn test_ensure_monotonic() {
         let mut buckets = vec![
@@ -290,4 +447,10 @@ mod tests {
         "#]]
         .assert_debug_eq(&buckets);
     }
+
+    #[test]
+    fn test_histogram_sum_unsupported() {
+        let err = histogram_sum(Value::None).unwrap_err();
+        assert!(err.to_string().contains("not supported"));
+    }
 }
@@ -0,0 +1,80 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::Result;
+
+use crate::service::promql::value::{RangeValue, Value};
+
+/// Produces a smoothed value for the time series based on the range. The
+/// lower the smoothing factor `sf`, the more importance is given to old
+/// data. The higher the trend factor `tf`, the more trends in the data are
+/// considered. Both `sf` and `tf` must be between 0 and 1.
+pub(crate) fn holt_winters(data: &Value, sf: f64, tf: f64) -> Result<Value> {
+    super::eval_idelta(data, "holt_winters", |series| exec(series, sf, tf))
+}
+
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/functions.go#L265
+fn exec(series: &RangeValue, sf: f64, tf: f64) -> Option<f64> {
+    let samples = &series.samples;
+    // Can't do the smoothing operation with less than two points.
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let mut s0 = 0.0;
+    let mut s1 = samples[0].value;
+    let mut b = samples[1].value - samples[0].value;
+    for (i, sample) in samples.iter().enumerate().skip(1) {
+        // Scale the raw value against the smoothing factor.
+        let x = sf * sample.value;
+        // Scale the last smoothed value with the trend at this point.
+        b = calc_trend_value(i - 1, tf, s0, s1, b);
+        let y = (1.0 - sf) * (s1 + b);
+        s0 = s1;
+        s1 = x + y;
+    }
+    Some(s1)
+}
+
+/// Calculates the trend value at the given index `i` in raw data `d`.
+/// This is somewhat analogous to the slope of the trend at the given index.
+fn calc_trend_value(i: usize, tf: f64, s0: f64, s1: f64, b: f64) -> f64 {
+    if i == 0 {
+        return b;
+    }
+    let x = tf * (s1 - s0);
+    let y = (1.0 - tf) * b;
+    x + y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Labels, Sample};
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn test_holt_winters() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test#L238-L246
+        let samples = (7950..=8000)
+            .step_by(10)
+            .map(|t| Sample::new(t * 1_000_000, t as f64))
+            .collect::<Vec<_>>();
+        let series = RangeValue::new(Labels::default(), samples);
+        let v = exec(&series, 0.01, 0.1).unwrap();
+        assert!(approx_eq!(f64, v, 8000.0, epsilon = 1e-9));
+
+        let series = RangeValue::new(Labels::default(), [Sample::new(1_000_000, 1.0)]);
+        assert!(exec(&series, 0.5, 0.5).is_none());
+    }
+}
@@ -0,0 +1,253 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::{DataFusionError, Result};
+use regex::Regex;
+use rustc_hash::FxHashSet;
+use std::sync::Arc;
+
+use crate::service::promql::value::{
+    is_valid_label_name, labels_value, signature, InstantValue, Label, Labels, Value,
+};
+
+/// For each timeseries in `data`, matches the regular expression `regex`
+/// against the value of the label `src_label`. If it matches, the value of
+/// the label `dst_label` in the returned timeseries will be the expansion
+/// of `replacement`, together with the original labels in the input.
+/// Capturing groups in the regular expression can be referenced with `$1`,
+/// `$2`, etc. If the regular expression doesn't match then the timeseries
+/// is returned unchanged.
+///
+/// See <https://prometheus.io/docs/prometheus/latest/querying/functions/#label_replace>
+pub(crate) fn label_replace(
+    data: &Value,
+    dst_label: &str,
+    replacement: &str,
+    src_label: &str,
+    regex: &str,
+) -> Result<Value> {
+    let re = Regex::new(&format!("^(?:{regex})$")).map_err(|_| {
+        DataFusionError::Plan(format!(
+            "invalid regular expression in label_replace(): {regex}"
+        ))
+    })?;
+    if !is_valid_label_name(dst_label) {
+        return Err(DataFusionError::Plan(format!(
+            "invalid destination label name in label_replace(): {dst_label}"
+        )));
+    }
+
+    eval_labels(data, "label_replace", |labels| {
+        let src_value = labels_value(labels, src_label).unwrap_or_default();
+        if let Some(caps) = re.captures(&src_value) {
+            let mut dst_value = String::new();
+            caps.expand(replacement, &mut dst_value);
+            set_label(labels, dst_label, dst_value);
+        }
+    })
+}
+
+/// For each timeseries in `data`, joins all the values of all the
+/// `src_labels` using `separator` and returns the timeseries with the label
+/// `dst_label` containing the joined value.
+///
+/// See <https://prometheus.io/docs/prometheus/latest/querying/functions/#label_join>
+pub(crate) fn label_join(
+    data: &Value,
+    dst_label: &str,
+    separator: &str,
+    src_labels: &[String],
+) -> Result<Value> {
+    if !is_valid_label_name(dst_label) {
+        return Err(DataFusionError::Plan(format!(
+            "invalid destination label name in label_join(): {dst_label}"
+        )));
+    }
+
+    eval_labels(data, "label_join", |labels| {
+        let dst_value = src_labels
+            .iter()
+            .map(|name| labels_value(labels, name).unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join(separator);
+        set_label(labels, dst_label, dst_value);
+    })
+}
+
+/// Rewrites the labels of every element of an instant vector with
+/// `fn_handler`. The result must not contain duplicate label sets.
+fn eval_labels(data: &Value, fn_name: &str, fn_handler: impl Fn(&mut Labels)) -> Result<Value> {
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::Plan(format!(
+                "{fn_name}: vector argument expected"
+            )))
+        }
+    };
+
+    let mut seen = FxHashSet::default();
+    let mut values = Vec::with_capacity(data.len());
+    for item in data {
+        let mut labels = item.labels.clone();
+        fn_handler(&mut labels);
+        if !seen.insert(signature(&labels)) {
+            return Err(DataFusionError::Plan(format!(
+                "{fn_name}: vector cannot contain metrics with the same labelset"
+            )));
+        }
+        values.push(InstantValue {
+            labels,
+            sample: item.sample,
+        });
+    }
+    Ok(Value::Vector(values))
+}
+
+/// Sets the label `name` to `value`, keeping the labels sorted by name.
+/// An empty value removes the label.
+fn set_label(labels: &mut Labels, name: &str, value: String) {
+    labels.retain(|l| l.name != name);
+    if !value.is_empty() {
+        labels.push(Arc::new(Label {
+            name: name.to_string(),
+            value,
+        }));
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::Sample;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                Arc::new(Label {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    fn data() -> Value {
+        Value::Vector(vec![InstantValue {
+            labels: labels(&[
+                ("__name__", "testmetric"),
+                ("dst", "original-destination-value"),
+                ("src", "source-value-10"),
+            ]),
+            sample: Sample::new(1_000_000, 0.0),
+        }])
+    }
+
+    fn get_labels(value: Value) -> Vec<(String, String)> {
+        match value {
+            Value::Vector(v) => v[0]
+                .labels
+                .iter()
+                .map(|l| (l.name.clone(), l.value.clone()))
+                .collect(),
+            v => panic!("not a vector: {v:?}"),
+        }
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_label_replace() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test#L290-L334
+        let res = label_replace(
+            &data(),
+            "dst",
+            "destination-value-$1",
+            "src",
+            "source-value-(.*)",
+        )
+        .unwrap();
+        assert_eq!(
+            get_labels(res),
+            pairs(&[
+                ("__name__", "testmetric"),
+                ("dst", "destination-value-10"),
+                ("src", "source-value-10"),
+            ])
+        );
+
+        // Label replacement not matching.
+        let res = label_replace(
+            &data(),
+            "dst",
+            "destination-value-$1",
+            "src",
+            "non-matching-regex",
+        )
+        .unwrap();
+        assert_eq!(get_labels(res), get_labels(data()));
+
+        // The regex is anchored.
+        let res = label_replace(&data(), "dst", "value-$1", "src", "value-(.*)").unwrap();
+        assert_eq!(get_labels(res), get_labels(data()));
+
+        // Empty replacement deletes the destination label.
+        let res = label_replace(&data(), "dst", "", "dst", ".*").unwrap();
+        assert_eq!(
+            get_labels(res),
+            pairs(&[("__name__", "testmetric"), ("src", "source-value-10")])
+        );
+
+        // A new label is created.
+        let res = label_replace(&data(), "new", "$1", "src", "source-(value)-.*").unwrap();
+        assert_eq!(
+            get_labels(res),
+            pairs(&[
+                ("__name__", "testmetric"),
+                ("dst", "original-destination-value"),
+                ("new", "value"),
+                ("src", "source-value-10"),
+            ])
+        );
+
+        assert!(label_replace(&data(), "1invalid", "", "src", ".*").is_err());
+        assert!(label_replace(&data(), "dst", "", "src", "(.*").is_err());
+    }
+
+    #[test]
+    fn test_label_join() {
+        let data = Value::Vector(vec![InstantValue {
+            labels: labels(&[("src1", "a"), ("src2", "b"), ("src3", "c")]),
+            sample: Sample::new(1_000_000, 0.0),
+        }]);
+        let src_labels = ["src1", "src2", "src3"].map(String::from);
+        let res = label_join(&data, "dst", "-", &src_labels).unwrap();
+        assert_eq!(
+            get_labels(res),
+            pairs(&[
+                ("dst", "a-b-c"),
+                ("src1", "a"),
+                ("src2", "b"),
+                ("src3", "c")
+            ])
+        );
+    }
+}
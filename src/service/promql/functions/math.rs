@@ -0,0 +1,170 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::Result;
+
+use crate::service::promql::value::Value;
+
+pub(crate) fn abs(data: &Value) -> Result<Value> {
+    super::eval_instant(data, "abs", f64::abs)
+}
+
+pub(crate) fn ceil(data: &Value) -> Result<Value> {
+    super::eval_instant(data, "ceil", f64::ceil)
+}
+
+pub(crate) fn floor(data: &Value) -> Result<Value> {
+    super::eval_instant(data, "floor", f64::floor)
+}
+
+pub(crate) fn exp(data: &Value) -> Result<Value> {
+    super::eval_instant(data, "exp", f64::exp)
+}
+
+pub(crate) fn ln(data: &Value) -> Result<Value> {
+    super::eval_instant(data, "ln", f64::ln)
+}
+
+pub(crate) fn log2(data: &Value) -> Result<Value> {
+    super::eval_instant(data, "log2", f64::log2)
+}
+
+pub(crate) fn log10(data: &Value) -> Result<Value> {
+    super::eval_instant(data, "log10", f64::log10)
+}
+
+/// Returns 1 for positive values, -1 for negative values and keeps zero
+/// (and NaN) values as is.
+pub(crate) fn sgn(data: &Value) -> Result<Value> {
+    super::eval_instant(data, "sgn", |v| {
+        if v < 0.0 {
+            -1.0
+        } else if v > 0.0 {
+            1.0
+        } else {
+            v
+        }
+    })
+}
+
+/// Rounds the values to the nearest multiple of `to_nearest`. Ties are
+/// resolved by rounding up.
+pub(crate) fn round(data: &Value, to_nearest: f64) -> Result<Value> {
+    // Dividing by the inverse avoids floating point artifacts such as
+    // `round(0.3, 0.1) == 0.30000000000000004`.
+    let to_nearest_inverse = 1.0 / to_nearest;
+    super::eval_instant(data, "round", |v| {
+        (v * to_nearest_inverse + 0.5).floor() / to_nearest_inverse
+    })
+}
+
+/// Clamps the values to have a lower limit of `min` and an upper limit of
+/// `max`. Returns an empty vector if `min` is greater than `max`.
+pub(crate) fn clamp(data: &Value, min: f64, max: f64) -> Result<Value> {
+    if max < min {
+        return Ok(Value::None);
+    }
+    super::eval_instant(data, "clamp", |v| go_max(min, go_min(max, v)))
+}
+
+pub(crate) fn clamp_max(data: &Value, max: f64) -> Result<Value> {
+    super::eval_instant(data, "clamp_max", |v| go_min(max, v))
+}
+
+pub(crate) fn clamp_min(data: &Value, min: f64) -> Result<Value> {
+    super::eval_instant(data, "clamp_min", |v| go_max(min, v))
+}
+
+/// Unlike [`f64::max`], NaN is propagated (as Go's `math.Max` does).
+fn go_max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.max(b)
+    }
+}
+
+/// Unlike [`f64::min`], NaN is propagated (as Go's `math.Min` does).
+fn go_min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.min(b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{InstantValue, Labels, Sample};
+
+    fn values(data: &[f64]) -> Value {
+        Value::Vector(
+            data.iter()
+                .map(|v| InstantValue {
+                    labels: Labels::default(),
+                    sample: Sample::new(1_000_000, *v),
+                })
+                .collect(),
+        )
+    }
+
+    fn get_values(value: Value) -> Vec<f64> {
+        match value {
+            Value::Vector(v) => v.into_iter().map(|v| v.sample.value).collect(),
+            Value::None => vec![],
+            v => panic!("not a vector: {v:?}"),
+        }
+    }
+
+    #[test]
+    fn test_round() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test#L500-L534
+        let data = values(&[-1.5, -0.5, 0.5, 1.5, 2.5, 0.3, 0.33]);
+        assert_eq!(
+            get_values(round(&data, 1.0).unwrap()),
+            [-1.0, 0.0, 1.0, 2.0, 3.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            get_values(round(&data, 0.1).unwrap()),
+            [-1.5, -0.5, 0.5, 1.5, 2.5, 0.3, 0.3]
+        );
+        assert_eq!(
+            get_values(round(&values(&[11.0, 14.0, 15.0]), 10.0).unwrap()),
+            [10.0, 10.0, 20.0]
+        );
+    }
+
+    #[test]
+    fn test_clamp() {
+        let data = values(&[-50.0, 0.0, 100.0, f64::NAN]);
+        let res = get_values(clamp(&data, -25.0, 75.0).unwrap());
+        assert_eq!(res[..3], [-25.0, 0.0, 75.0]);
+        assert!(res[3].is_nan());
+        assert!(get_values(clamp(&data, 5.0, -5.0).unwrap()).is_empty());
+        assert_eq!(
+            get_values(clamp_max(&data, 42.0).unwrap())[..3],
+            [-50.0, 0.0, 42.0]
+        );
+        assert_eq!(
+            get_values(clamp_min(&data, -42.0).unwrap())[..3],
+            [-42.0, 0.0, 100.0]
+        );
+    }
+
+    #[test]
+    fn test_sgn() {
+        let res = get_values(sgn(&values(&[-3.0, 0.0, 2.0])).unwrap());
+        assert_eq!(res, [-1.0, 0.0, 1.0]);
+    }
+}
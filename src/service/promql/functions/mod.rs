@@ -17,29 +17,62 @@ use datafusion::error::{DataFusionError, Result};
 use crate::meta::prom::NAME_LABEL;
 use crate::service::promql::value::{InstantValue, RangeValue, Sample, Value};
 
+mod absent;
 mod avg_over_time;
+mod changes;
 mod count_over_time;
 mod delta;
+mod deriv;
 mod histogram;
+mod holt_winters;
 mod idelta;
 mod increase;
 mod irate;
+mod label;
+mod math;
 mod max_over_time;
 mod min_over_time;
+mod predict_linear;
+mod quantile_over_time;
 mod rate;
+mod resets;
+mod scalar;
+mod sort;
 mod sum_over_time;
+mod time;
+mod vector;
 
+pub(crate) use absent::{absent, absent_over_time};
 pub(crate) use avg_over_time::avg_over_time;
+pub(crate) use changes::changes;
 pub(crate) use count_over_time::count_over_time;
 pub(crate) use delta::delta;
-pub(crate) use histogram::histogram_quantile;
+pub(crate) use deriv::deriv;
+pub(crate) use histogram::{
+    histogram_count, histogram_fraction, histogram_quantile, histogram_sum,
+};
+pub(crate) use holt_winters::holt_winters;
 pub(crate) use idelta::idelta;
 pub(crate) use increase::increase;
 pub(crate) use irate::irate;
+pub(crate) use label::{label_join, label_replace};
+pub(crate) use math::{
+    abs, ceil, clamp, clamp_max, clamp_min, exp, floor, ln, log10, log2, round, sgn,
+};
 pub(crate) use max_over_time::max_over_time;
 pub(crate) use min_over_time::min_over_time;
+pub(crate) use predict_linear::predict_linear;
+pub(crate) use quantile_over_time::quantile_over_time;
 pub(crate) use rate::rate;
+pub(crate) use resets::resets;
+pub(crate) use scalar::scalar;
+pub(crate) use sort::{sort, sort_desc};
 pub(crate) use sum_over_time::sum_over_time;
+pub(crate) use time::{
+    day_of_month, day_of_week, day_of_year, days_in_month, hour, minute, month, time, timestamp,
+    year,
+};
+pub(crate) use vector::vector;
 
 use strum::EnumString;
 
@@ -102,7 +135,7 @@ pub(crate) enum Func {
 pub(crate) fn eval_idelta(
     data: &Value,
     fn_name: &str,
-    fn_handler: fn(&RangeValue) -> Option<f64>,
+    fn_handler: impl Fn(&RangeValue) -> Option<f64>,
 ) -> Result<Value> {
     let data = match data {
         Value::Matrix(v) => v,
@@ -128,3 +161,68 @@ pub(crate) fn eval_idelta(
     }
     Ok(Value::Vector(rate_values))
 }
+
+/// Applies `fn_handler` to the value of every element of an instant vector.
+/// The metric name is dropped from the result.
+pub(crate) fn eval_instant(
+    data: &Value,
+    fn_name: &str,
+    fn_handler: impl Fn(f64) -> f64,
+) -> Result<Value> {
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::Plan(format!(
+                "{fn_name}: vector argument expected"
+            )))
+        }
+    };
+
+    let values = data
+        .iter()
+        .map(|item| {
+            let mut labels = item.labels.clone();
+            labels.retain(|l| l.name != NAME_LABEL);
+            InstantValue {
+                labels,
+                sample: Sample::new(item.sample.timestamp, fn_handler(item.sample.value)),
+            }
+        })
+        .collect();
+    Ok(Value::Vector(values))
+}
+
+/// `linear_regression` is a utility function for deriv/predict_linear.
+///
+/// Returns the slope (per second) and the intercept at `intercept_time` of
+/// the simple linear regression of `samples`.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/functions.go#L865
+pub(crate) fn linear_regression(samples: &[Sample], intercept_time: i64) -> (f64, f64) {
+    let init_y = samples[0].value;
+    let mut const_y = true;
+    let (mut n, mut sum_x, mut sum_y, mut sum_xy, mut sum_x2) = (0.0, 0.0, 0.0, 0.0, 0.0);
+    for (i, sample) in samples.iter().enumerate() {
+        if const_y && i > 0 && sample.value != init_y {
+            const_y = false;
+        }
+        n += 1.0;
+        let x = (sample.timestamp - intercept_time) as f64 / 1_000_000.0;
+        sum_x += x;
+        sum_y += sample.value;
+        sum_xy += x * sample.value;
+        sum_x2 += x * x;
+    }
+    if const_y {
+        if init_y.is_infinite() {
+            return (f64::NAN, f64::NAN);
+        }
+        return (0.0, init_y);
+    }
+    let cov_xy = sum_xy - sum_x * sum_y / n;
+    let var_x = sum_x2 - sum_x * sum_x / n;
+    let slope = cov_xy / var_x;
+    let intercept = sum_y / n - slope * sum_x / n;
+    (slope, intercept)
+}
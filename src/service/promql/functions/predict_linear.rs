@@ -0,0 +1,68 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::Result;
+
+use crate::service::promql::value::{RangeValue, Value};
+
+/// Predicts the value of the time series `duration` seconds from the
+/// evaluation time, using simple linear regression.
+pub(crate) fn predict_linear(data: &Value, duration: f64) -> Result<Value> {
+    super::eval_idelta(data, "predict_linear", |series| exec(series, duration))
+}
+
+fn exec(series: &RangeValue, duration: f64) -> Option<f64> {
+    if series.samples.len() < 2 {
+        return None;
+    }
+    let tw = series
+        .time_window
+        .as_ref()
+        .expect("BUG: `predict_linear` function requires time window");
+    let (slope, intercept) = super::linear_regression(&series.samples, tw.eval_ts);
+    Some(slope * duration + intercept)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Labels, Sample, TimeWindow};
+    use float_cmp::approx_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn test_predict_linear() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test#L197-L212
+        //
+        // X/s = [  0, 300, 600, 900,1200,1500,1800,2100,2400,2700,3000]
+        // Y   = [  0,  10,  20,  30,  40,   0,  10,  20,  30,  40,  50]
+        let values = [
+            0.0, 10.0, 20.0, 30.0, 40.0, 0.0, 10.0, 20.0, 30.0, 40.0, 50.0,
+        ];
+        let samples = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| Sample::new(i as i64 * 300_000_000, *v))
+            .collect::<Vec<_>>();
+        let series = RangeValue {
+            labels: Labels::default(),
+            samples,
+            time_window: Some(TimeWindow::new(3_000_000_000, Duration::from_secs(3000))),
+        };
+
+        let v = exec(&series, 0.0).unwrap();
+        assert!(approx_eq!(f64, v, 32.5, epsilon = 1e-9));
+        let v = exec(&series, 3600.0).unwrap();
+        assert!(approx_eq!(f64, v, 70.68181818181819, epsilon = 1e-9));
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::Result;
+
+use crate::service::promql::value::{self, RangeValue, Value};
+
+pub(crate) fn quantile_over_time(phi: f64, data: &Value) -> Result<Value> {
+    super::eval_idelta(data, "quantile_over_time", |series| exec(series, phi))
+}
+
+fn exec(series: &RangeValue, phi: f64) -> Option<f64> {
+    if series.samples.is_empty() {
+        return None;
+    }
+    let mut values = series.samples.iter().map(|s| s.value).collect::<Vec<_>>();
+    Some(value::quantile(&mut values, phi))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Labels, Sample};
+    use float_cmp::approx_eq;
+
+    #[test]
+    fn test_quantile_over_time() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test#L606-L645
+        let series = |values: &[f64]| {
+            RangeValue::new(
+                Labels::default(),
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| Sample::new((i as i64 + 1) * 10_000_000, *v)),
+            )
+        };
+        let two_samples = series(&[0.0, 1.0]);
+        let three_samples = series(&[0.0, 1.0, 2.0]);
+        let uneven_samples = series(&[0.0, 1.0, 4.0]);
+
+        for (phi, expected) in [
+            (0.0, [0.0, 0.0, 0.0]),
+            (0.5, [0.5, 1.0, 1.0]),
+            (0.75, [0.75, 1.5, 2.5]),
+            (0.8, [0.8, 1.6, 2.8]),
+            (1.0, [1.0, 2.0, 4.0]),
+        ] {
+            for (series, expected) in [&two_samples, &three_samples, &uneven_samples]
+                .into_iter()
+                .zip(expected)
+            {
+                let v = exec(series, phi).unwrap();
+                assert!(approx_eq!(f64, v, expected, epsilon = 1e-9), "{phi}: {v}");
+            }
+        }
+        assert_eq!(exec(&two_samples, -1.0), Some(f64::NEG_INFINITY));
+        assert_eq!(exec(&two_samples, 2.0), Some(f64::INFINITY));
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::{DataFusionError, Result};
+
+use crate::service::promql::value::Value;
+
+/// Given a single-element input vector, returns the sample value of that
+/// single element as a scalar. If the input vector does not have exactly one
+/// element, returns NaN.
+pub(crate) fn scalar(data: &Value) -> Result<Value> {
+    match data {
+        Value::Vector(v) if v.len() == 1 => Ok(Value::Float(v[0].sample.value)),
+        Value::Vector(_) | Value::None => Ok(Value::Float(f64::NAN)),
+        _ => Err(DataFusionError::Plan(
+            "scalar: vector argument expected".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{InstantValue, Label, Sample};
+    use std::sync::Arc;
+
+    fn vector(values: &[f64]) -> Value {
+        Value::Vector(
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| InstantValue {
+                    labels: vec![Arc::new(Label {
+                        name: "instance".to_string(),
+                        value: i.to_string(),
+                    })],
+                    sample: Sample::new(1_000_000, *v),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_scalar() {
+        // as Prometheus, NaN unless the vector has exactly one element
+        assert!(matches!(scalar(&vector(&[3.5])), Ok(Value::Float(v)) if v == 3.5));
+        assert!(matches!(scalar(&vector(&[1.0, 2.0])), Ok(Value::Float(v)) if v.is_nan()));
+        assert!(matches!(scalar(&vector(&[])), Ok(Value::Float(v)) if v.is_nan()));
+        assert!(matches!(scalar(&Value::None), Ok(Value::Float(v)) if v.is_nan()));
+        assert!(scalar(&Value::Float(1.0)).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::{DataFusionError, Result};
+use std::cmp::Ordering;
+
+use crate::service::promql::value::{InstantValue, Value};
+
+/// Returns vector elements sorted by their sample values, in ascending
+/// order. NaN values are sorted to the end.
+pub(crate) fn sort(data: Value) -> Result<Value> {
+    eval_sort(data, "sort", |a, b| a.partial_cmp(&b))
+}
+
+/// Same as [`sort`], but sorts in descending order.
+pub(crate) fn sort_desc(data: Value) -> Result<Value> {
+    eval_sort(data, "sort_desc", |a, b| b.partial_cmp(&a))
+}
+
+fn eval_sort(data: Value, fn_name: &str, cmp: fn(f64, f64) -> Option<Ordering>) -> Result<Value> {
+    let mut data: Vec<InstantValue> = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::Plan(format!(
+                "{fn_name}: vector argument expected"
+            )))
+        }
+    };
+    data.sort_by(|a, b| {
+        let (a, b) = (a.sample.value, b.sample.value);
+        match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => cmp(a, b).unwrap_or(Ordering::Equal),
+        }
+    });
+    Ok(Value::Vector(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::{Label, Sample};
+    use std::sync::Arc;
+
+    fn vector(values: &[f64]) -> Value {
+        Value::Vector(
+            values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| InstantValue {
+                    labels: vec![Arc::new(Label {
+                        name: "instance".to_string(),
+                        value: i.to_string(),
+                    })],
+                    sample: Sample::new(1_000_000, *v),
+                })
+                .collect(),
+        )
+    }
+
+    fn values(value: Value) -> Vec<String> {
+        match value {
+            Value::Vector(v) => v.iter().map(|v| v.sample.value.to_string()).collect(),
+            _ => vec![],
+        }
+    }
+
+    #[test]
+    fn test_sort() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test
+        // NaN is last in both orders
+        let data = [300.0, f64::NAN, 100.0, 800.0, 200.0];
+        assert_eq!(
+            values(sort(vector(&data)).unwrap()),
+            vec!["100", "200", "300", "800", "NaN"]
+        );
+        assert_eq!(
+            values(sort_desc(vector(&data)).unwrap()),
+            vec!["800", "300", "200", "100", "NaN"]
+        );
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use datafusion::error::{DataFusionError, Result};
+
+use crate::meta::prom::NAME_LABEL;
+use crate::service::promql::value::{InstantValue, Sample, Value};
+
+/// Returns the number of seconds since January 1, 1970 UTC of the
+/// evaluation timestamp.
+pub(crate) fn time(eval_ts: i64) -> Value {
+    Value::Float(eval_ts as f64 / 1_000_000.0)
+}
+
+/// Returns the timestamp of each of the samples of the given vector as the
+/// number of seconds since January 1, 1970 UTC.
+pub(crate) fn timestamp(eval_ts: i64, data: &Value) -> Result<Value> {
+    let data = match data {
+        Value::Vector(v) => v,
+        Value::None => return Ok(Value::None),
+        _ => {
+            return Err(DataFusionError::Plan(
+                "timestamp: vector argument expected".to_string(),
+            ))
+        }
+    };
+    let values = data
+        .iter()
+        .map(|item| {
+            let mut labels = item.labels.clone();
+            labels.retain(|l| l.name != NAME_LABEL);
+            InstantValue {
+                labels,
+                sample: Sample::new(eval_ts, item.sample.timestamp as f64 / 1_000_000.0),
+            }
+        })
+        .collect();
+    Ok(Value::Vector(values))
+}
+
+pub(crate) fn day_of_month(data: &Value) -> Result<Value> {
+    eval_date(data, "day_of_month", |t| t.day() as f64)
+}
+
+/// Sunday is 0, Monday is 1, etc.
+pub(crate) fn day_of_week(data: &Value) -> Result<Value> {
+    eval_date(data, "day_of_week", |t| {
+        t.weekday().num_days_from_sunday() as f64
+    })
+}
+
+pub(crate) fn day_of_year(data: &Value) -> Result<Value> {
+    eval_date(data, "day_of_year", |t| t.ordinal() as f64)
+}
+
+pub(crate) fn days_in_month(data: &Value) -> Result<Value> {
+    eval_date(data, "days_in_month", |t| {
+        let (year, month) = if t.month() == 12 {
+            (t.year() + 1, 1)
+        } else {
+            (t.year(), t.month() + 1)
+        };
+        let first_of_next_month = NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+        first_of_next_month.pred_opt().unwrap().day() as f64
+    })
+}
+
+pub(crate) fn hour(data: &Value) -> Result<Value> {
+    eval_date(data, "hour", |t| t.hour() as f64)
+}
+
+pub(crate) fn minute(data: &Value) -> Result<Value> {
+    eval_date(data, "minute", |t| t.minute() as f64)
+}
+
+pub(crate) fn month(data: &Value) -> Result<Value> {
+    eval_date(data, "month", |t| t.month() as f64)
+}
+
+pub(crate) fn year(data: &Value) -> Result<Value> {
+    eval_date(data, "year", |t| t.year() as f64)
+}
+
+/// Interprets every sample value as the number of seconds since January 1,
+/// 1970 UTC and applies `fn_handler` to the corresponding UTC date and time.
+fn eval_date(
+    data: &Value,
+    fn_name: &str,
+    fn_handler: impl Fn(NaiveDateTime) -> f64,
+) -> Result<Value> {
+    super::eval_instant(data, fn_name, |v| {
+        if !v.is_finite() {
+            return f64::NAN;
+        }
+        match NaiveDateTime::from_timestamp_opt(v as i64, 0) {
+            Some(t) => fn_handler(t),
+            None => f64::NAN,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::promql::value::Labels;
+
+    fn eval(f: fn(&Value) -> Result<Value>, ts: f64) -> f64 {
+        let data = Value::Vector(vec![InstantValue {
+            labels: Labels::default(),
+            sample: Sample::new(1_000_000, ts),
+        }]);
+        match f(&data).unwrap() {
+            Value::Vector(v) => v[0].sample.value,
+            v => panic!("not a vector: {v:?}"),
+        }
+    }
+
+    #[test]
+    fn test_date_functions() {
+        // cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/testdata/functions.test#L760-L826
+        assert_eq!(eval(day_of_month, 0.0), 1.0);
+        assert_eq!(eval(day_of_month, 1136239445.0), 2.0);
+        assert_eq!(eval(day_of_week, 1136239445.0), 1.0);
+        assert_eq!(eval(hour, 1136239445.0), 22.0);
+        assert_eq!(eval(minute, 1136239445.0), 4.0);
+        assert_eq!(eval(month, 1136239445.0), 1.0);
+        assert_eq!(eval(year, 1136239445.0), 2006.0);
+        assert_eq!(eval(day_of_year, 1136239445.0), 2.0);
+
+        // 2008-12-31 23:59:59 just before leap second.
+        assert_eq!(eval(year, 1230767999.0), 2008.0);
+        assert_eq!(eval(day_of_year, 1230767999.0), 366.0);
+        // 2009-01-01 00:00:00 just after leap second.
+        assert_eq!(eval(year, 1230768000.0), 2009.0);
+
+        // February 2016 has 29 days, February 2017 has 28 days.
+        assert_eq!(eval(days_in_month, 1454284800.0), 29.0);
+        assert_eq!(eval(days_in_month, 1485907200.0), 28.0);
+        assert_eq!(eval(days_in_month, 1230767999.0), 31.0);
+
+        assert!(eval(year, f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let data = Value::Vector(vec![InstantValue {
+            labels: Labels::default(),
+            sample: Sample::new(1_500_000, 1.0),
+        }]);
+        match timestamp(3_000_000, &data).unwrap() {
+            Value::Vector(v) => {
+                assert_eq!(v[0].sample.timestamp, 3_000_000);
+                assert_eq!(v[0].sample.value, 1.5);
+            }
+            v => panic!("not a vector: {v:?}"),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use datafusion::error::{DataFusionError, Result};
+
+use crate::service::promql::value::{InstantValue, Labels, Sample, Value};
+
+/// Returns the scalar as a vector with no labels.
+pub(crate) fn vector(eval_ts: i64, data: &Value) -> Result<Value> {
+    match data {
+        Value::Float(v) => Ok(Value::Vector(vec![InstantValue {
+            labels: Labels::default(),
+            sample: Sample::new(eval_ts, *v),
+        }])),
+        _ => Err(DataFusionError::Plan(
+            "vector: scalar argument expected".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector() {
+        // `vector(1)` is `{} 1`
+        let Value::Vector(v) = vector(1_000_000, &Value::Float(1.0)).unwrap() else {
+            panic!("vector expected");
+        };
+        assert_eq!(v.len(), 1);
+        assert!(v[0].labels.is_empty());
+        assert_eq!(v[0].sample.timestamp, 1_000_000);
+        assert_eq!(v[0].sample.value, 1.0);
+        assert!(vector(1_000_000, &Value::None).is_err());
+    }
+}
@@ -25,12 +25,14 @@ mod aggregations;
 mod binaries;
 mod engine;
 mod exec;
+mod format;
 mod functions;
 pub mod search;
 pub mod value;
 
 pub use engine::Engine;
 pub use exec::Query;
+pub use format::format_expr;
 
 pub(crate) const DEFAULT_LOOKBACK: Duration = Duration::from_secs(300); // 5m
 pub(crate) const MINIMAL_INTERVAL: Duration = Duration::from_secs(10); // 10s
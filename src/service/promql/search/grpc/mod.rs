@@ -124,6 +124,7 @@ pub async fn search(
                 samples: vec![],
                 sample: Some((&v.sample).into()),
                 scalar: None,
+                stringliteral: None,
             });
         }
         value::Value::Range(v) => {
@@ -132,6 +133,7 @@ pub async fn search(
                 samples: v.samples.iter().map(|x| x.into()).collect(),
                 sample: None,
                 scalar: None,
+                stringliteral: None,
             });
         }
         value::Value::Vector(v) => {
@@ -141,6 +143,7 @@ pub async fn search(
                     samples: vec![],
                     sample: Some((&v.sample).into()),
                     scalar: None,
+                    stringliteral: None,
                 });
             });
         }
@@ -151,6 +154,7 @@ pub async fn search(
                     samples: v.samples.iter().map(|x| x.into()).collect(),
                     sample: None,
                     scalar: None,
+                    stringliteral: None,
                 });
             });
         }
@@ -160,6 +164,7 @@ pub async fn search(
                 samples: vec![],
                 sample: Some((&v).into()),
                 scalar: None,
+                stringliteral: None,
             });
         }
         value::Value::Float(v) => {
@@ -168,6 +173,25 @@ pub async fn search(
                 samples: vec![],
                 sample: None,
                 scalar: Some(v),
+                stringliteral: None,
+            });
+        }
+        value::Value::StringSample(v) => {
+            resp.result.push(cluster_rpc::Series {
+                metric: vec![],
+                samples: vec![],
+                sample: Some((&value::Sample::new(v.timestamp, 0.0)).into()),
+                scalar: None,
+                stringliteral: Some(v.value),
+            });
+        }
+        value::Value::String(v) => {
+            resp.result.push(cluster_rpc::Series {
+                metric: vec![],
+                samples: vec![],
+                sample: None,
+                scalar: None,
+                stringliteral: Some(v),
             });
         }
     }
@@ -239,6 +239,8 @@ async fn search_in_cluster(req: cluster_rpc::MetricsQueryRequest) -> Result<Valu
         merge_vector_query(&series_data)
     } else if result_type == "scalar" {
         merge_scalar_query(&series_data)
+    } else if result_type == "string" {
+        merge_string_query(&series_data)
     } else {
         return Err(server_internal_error("invalid result type"));
     };
@@ -329,3 +331,16 @@ fn merge_scalar_query(series: &[cluster_rpc::Series]) -> Value {
     }
     Value::Sample(sample)
 }
+
+fn merge_string_query(series: &[cluster_rpc::Series]) -> Value {
+    let mut data = StringSample::default();
+    for ser in series {
+        if let Some(x) = ser.stringliteral.as_ref() {
+            data.value = x.to_string();
+            if let Some(sample) = ser.sample.as_ref() {
+                data.timestamp = Sample::from(sample).timestamp;
+            }
+        }
+    }
+    Value::StringSample(data)
+}
@@ -55,6 +55,32 @@ impl Sample {
     }
 }
 
+/// The string result of an instant query, at the evaluation time.
+#[derive(Debug, Default, Clone)]
+pub struct StringSample {
+    /// Time in microseconds
+    pub timestamp: i64,
+    pub value: String,
+}
+
+impl Serialize for StringSample {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(2))?;
+        seq.serialize_element(&(self.timestamp / 1_000_000))?;
+        seq.serialize_element(&self.value)?;
+        seq.end()
+    }
+}
+
+impl StringSample {
+    pub(crate) fn new(timestamp: i64, value: String) -> Self {
+        Self { timestamp, value }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct InstantValue {
     pub labels: Labels,
@@ -83,11 +109,12 @@ pub struct TimeWindow {
     /// Evaluation timestamp, microseconds.
     pub eval_ts: i64,
     pub range: Duration,
-    /// The offset used during the query execution.
-    /// We don't use it (yet), so its value is always zero.
+    /// How far the time window is shifted back from the evaluation timestamp
+    /// by the `offset` and `@` modifiers, microseconds. Negative values shift
+    /// it into the future.
     //
     // See https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/parser/ast.go#L192-L198
-    pub offset: Duration,
+    pub offset: i64,
 }
 
 impl TimeWindow {
@@ -96,7 +123,7 @@ impl TimeWindow {
         Self {
             eval_ts,
             range,
-            offset: Duration::ZERO,
+            offset: 0,
         }
     }
 }
@@ -178,29 +205,19 @@ pub(crate) fn extrapolated_rate(
     samples: &[Sample],
     eval_ts: i64,
     range: Duration,
-    offset: Duration,
+    offset: i64,
     kind: ExtrapolationKind,
 ) -> Option<f64> {
-    let start = {
-        let range_plus_offset = range
-            .checked_add(offset)
-            .expect("BUG: overflow")
-            .as_micros()
-            .try_into()
-            .expect("BUG: integer conversion failed");
-        eval_ts
-            .checked_sub(range_plus_offset)
-            .expect("BUG: overflow")
-    };
-    assert!(start > 0);
-    let end = eval_ts
+    let end = eval_ts.checked_sub(offset).expect("BUG: overflow");
+    let start = end
         .checked_sub(
-            offset
+            range
                 .as_micros()
                 .try_into()
                 .expect("BUG: integer conversion failed"),
         )
         .expect("BUG: overflow");
+    assert!(start > 0);
     assert!(end > 0);
     assert!(start <= end);
 
@@ -277,6 +294,45 @@ pub(crate) fn extrapolated_rate(
     Some(result)
 }
 
+/// `quantile` calculates the given quantile of `values`. It is a utility
+/// function for the `quantile` aggregation and `quantile_over_time`.
+///
+/// The values are sorted in place. If `phi` is out of the `[0, 1]` range,
+/// `-Inf` or `+Inf` is returned; if `values` is empty, `NaN` is returned.
+//
+// cf. https://github.com/prometheus/prometheus/blob/80b7f73d267a812b3689321554aec637b75f468d/promql/quantile.go#L330
+pub(crate) fn quantile(values: &mut [f64], phi: f64) -> f64 {
+    if values.is_empty() || phi.is_nan() {
+        return f64::NAN;
+    }
+    if phi < 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if phi > 1.0 {
+        return f64::INFINITY;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+    let n = values.len() as f64;
+    // When the quantile lies between two samples,
+    // we use a weighted average of the two samples.
+    let rank = phi * (n - 1.0);
+    let lower_index = rank.floor().max(0.0);
+    let upper_index = (lower_index + 1.0).min(n - 1.0);
+    let weight = rank - rank.floor();
+    values[lower_index as usize] * (1.0 - weight) + values[upper_index as usize] * weight
+}
+
+/// Label names must match `[a-zA-Z_][a-zA-Z0-9_]*`.
+pub(crate) fn is_valid_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 pub fn labels_value(labels: &Labels, name: &str) -> Option<String> {
     labels
         .binary_search_by_key(&name, |label| label.name.as_str())
@@ -291,8 +347,10 @@ pub enum Value {
     Range(RangeValue),
     Vector(Vec<InstantValue>),
     Matrix(Vec<RangeValue>),
-    Sample(Sample), // only used for return literal value
+    Sample(Sample),             // only used for return literal value
+    StringSample(StringSample), // only used for return string literal
     Float(f64),
+    String(String),
     None,
 }
 
@@ -312,6 +370,8 @@ impl Value {
             Value::Matrix(_) => "matrix",
             Value::Sample(_) => "scalar",
             Value::Float(_) => "scalar",
+            Value::StringSample(_) => "string",
+            Value::String(_) => "string",
             Value::None => "scalar",
         }
     }
@@ -408,17 +468,31 @@ mod tests {
         assert_eq!(signature(&labels), signature_without_labels(&labels, &[]));
     }
 
+    #[test]
+    fn test_string_sample_serialize() {
+        let value = Value::StringSample(StringSample::new(1_681_000_000_000_000, "foo".into()));
+        assert_eq!(
+            crate::common::json::to_string(&value).unwrap(),
+            r#"[1681000000,"foo"]"#
+        );
+    }
+
+    #[test]
+    fn test_quantile() {
+        let mut values = [3.0, 1.0, 4.0, 1.0, 5.0];
+        assert_eq!(quantile(&mut values, 0.0), 1.0);
+        assert_eq!(quantile(&mut values, 0.5), 3.0);
+        assert_eq!(quantile(&mut values, 0.9), 4.6);
+        assert_eq!(quantile(&mut values, 1.0), 5.0);
+        assert_eq!(quantile(&mut values, -1.0), f64::NEG_INFINITY);
+        assert_eq!(quantile(&mut values, 1.5), f64::INFINITY);
+        assert!(quantile(&mut [], 0.5).is_nan());
+    }
+
     #[test]
     fn test_extrapolated_rate() {
         fn extrapolate(samples: &[Sample], kind: ExtrapolationKind) -> f64 {
-            extrapolated_rate(
-                samples,
-                75_000_000,
-                Duration::from_secs(60),
-                Duration::ZERO,
-                kind,
-            )
-            .unwrap()
+            extrapolated_rate(samples, 75_000_000, Duration::from_secs(60), 0, kind).unwrap()
         }
 
         // See the diagrams at
@@ -472,5 +546,16 @@ mod tests {
 
         let delta = extrapolate(&samples, ExtrapolationKind::Delta);
         assert!(approx_eq!(f64, dbg!(delta), 4.0));
+
+        // The same window, evaluated one minute later with `offset 1m`
+        let shifted_rate = extrapolated_rate(
+            &samples,
+            135_000_000,
+            Duration::from_secs(60),
+            60_000_000,
+            ExtrapolationKind::Rate,
+        )
+        .unwrap();
+        assert!(approx_eq!(f64, shifted_rate, rate));
     }
 }
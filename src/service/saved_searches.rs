@@ -0,0 +1,487 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Saved searches are named searches of an organization. A saved search with
+//! a schedule is run by the alert manager at a regular interval, and its
+//! result is sent to an alert destination, see
+//! [`send_report_notification`]. A report is claimed under a cluster lock,
+//! so it is sent by one node, and `last_sent_at` is only set once it was
+//! sent.
+
+use actix_web::{http, HttpResponse};
+use bytes::Bytes;
+use chrono::Utc;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::{collections::HashMap, io::Error};
+
+use crate::common::{
+    base64, functions,
+    json::{self, Value},
+    notification::send_report_notification,
+};
+use crate::infra::{
+    config::{CONFIG, SAVED_SEARCHES},
+    db::etcd,
+    errors, storage,
+};
+use crate::meta::{
+    http::HttpResponse as MetaHttpResponse,
+    saved_searches::{ReportFormat, SavedSearch, SavedSearchList},
+    search,
+};
+use crate::service::{db, search as SearchService};
+
+/// Rows of the inline table of a report, the CSV report has all of them.
+const REPORT_TABLE_ROWS: usize = 20;
+
+/// The time the last report of a saved search was started, on this node or
+/// another one, keyed by `{org_id}/{name}`.
+static LAST_CLAIMED: Lazy<DashMap<String, i64>> = Lazy::new(DashMap::new);
+
+#[tracing::instrument(skip(search))]
+pub async fn save(
+    org_id: &str,
+    name: &str,
+    owner: &str,
+    mut search: SavedSearch,
+) -> Result<HttpResponse, Error> {
+    search.name = name.to_string();
+    if let Err(e) = validate(org_id, &search).await {
+        return Ok(HttpResponse::BadRequest().json(MetaHttpResponse::error(
+            http::StatusCode::BAD_REQUEST.into(),
+            e,
+        )));
+    }
+
+    let now = Utc::now().timestamp_micros();
+    search.updated_at = now;
+    match db::saved_searches::get(org_id, name).await {
+        Ok(Some(old)) => {
+            search.owner = old.owner;
+            search.created_at = old.created_at;
+            // an update doesn't send the report again
+            if let (Some(schedule), Some(old_schedule)) =
+                (search.schedule.as_mut(), old.schedule.as_ref())
+            {
+                schedule.last_sent_at = old_schedule.last_sent_at;
+            }
+        }
+        _ => {
+            search.owner = owner.to_string();
+            search.created_at = now;
+        }
+    }
+
+    if let Err(e) = db::saved_searches::set(org_id, &search).await {
+        return Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        );
+    }
+    Ok(HttpResponse::Ok().json(search))
+}
+
+#[tracing::instrument]
+pub async fn list(org_id: &str) -> Result<HttpResponse, Error> {
+    match db::saved_searches::list(org_id).await {
+        Ok(list) => Ok(HttpResponse::Ok().json(SavedSearchList { list })),
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+#[tracing::instrument]
+pub async fn get(org_id: &str, name: &str) -> Result<HttpResponse, Error> {
+    match db::saved_searches::get(org_id, name).await {
+        Ok(Some(search)) => Ok(HttpResponse::Ok().json(search)),
+        _ => Ok(not_found()),
+    }
+}
+
+#[tracing::instrument]
+pub async fn delete(org_id: &str, name: &str) -> Result<HttpResponse, Error> {
+    if !matches!(db::saved_searches::get(org_id, name).await, Ok(Some(_))) {
+        return Ok(not_found());
+    }
+    if let Err(e) = db::saved_searches::delete(org_id, name).await {
+        return Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        );
+    }
+    // the stored reports go with the search
+    if let Ok(files) = storage::list(&report_prefix(org_id, name)).await {
+        let files = files.iter().map(|f| f.as_str()).collect::<Vec<_>>();
+        if let Err(e) = storage::del(&files).await {
+            log::error!("[SAVED SEARCH] delete reports of {org_id}/{name} error: {e}");
+        }
+    }
+    Ok(HttpResponse::Ok().json(MetaHttpResponse::message(
+        http::StatusCode::OK.into(),
+        "Saved search deleted".to_string(),
+    )))
+}
+
+/// Runs a saved search now, over the last `duration` minutes.
+#[tracing::instrument]
+pub async fn run(org_id: &str, name: &str) -> Result<HttpResponse, Error> {
+    let search = match db::saved_searches::get(org_id, name).await {
+        Ok(Some(search)) => search,
+        _ => return Ok(not_found()),
+    };
+    match search_saved(
+        org_id,
+        &search,
+        Utc::now().timestamp_micros(),
+        SearchService::Priority::Interactive,
+    )
+    .await
+    {
+        Ok(res) => Ok(HttpResponse::Ok().json(res)),
+        Err(errors::Error::ErrorCode(code)) => {
+            Ok(HttpResponse::InternalServerError().json(MetaHttpResponse::error_code(code)))
+        }
+        Err(e) => Ok(
+            HttpResponse::InternalServerError().json(MetaHttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                e.to_string(),
+            )),
+        ),
+    }
+}
+
+/// Returns a CSV report, `None` if it doesn't exist.
+pub async fn get_report(
+    org_id: &str,
+    name: &str,
+    report: &str,
+) -> Result<Option<Bytes>, anyhow::Error> {
+    let valid = report
+        .strip_suffix(".csv")
+        .map_or(false, |ts| ts.parse::<i64>().is_ok());
+    if !valid {
+        return Ok(None);
+    }
+    let key = format!("{}{report}", report_prefix(org_id, name));
+    match storage::list(&key).await?.is_empty() {
+        true => Ok(None),
+        false => Ok(Some(storage::get(&key).await?)),
+    }
+}
+
+/// Starts the reports of the scheduled saved searches which are due.
+#[cfg_attr(coverage_nightly, no_coverage)]
+pub async fn run_reports() -> Result<(), anyhow::Error> {
+    let now = Utc::now().timestamp_micros();
+    LAST_CLAIMED.retain(|key, _| SAVED_SEARCHES.contains_key(key));
+    for item in SAVED_SEARCHES.iter() {
+        let Some(schedule) = item.schedule.as_ref() else {
+            continue;
+        };
+        let last_claimed = LAST_CLAIMED.get(item.key()).map(|v| *v).unwrap_or_default();
+        let last_started = schedule.last_sent_at.max(last_claimed);
+        if now - last_started < schedule.frequency * 60 * 1_000_000 {
+            continue;
+        }
+        // mark it at once, so that it isn't started again before it is done
+        LAST_CLAIMED.insert(item.key().clone(), now);
+        let org_id = item.key().split('/').next().unwrap().to_string();
+        let search = item.value().clone();
+        tokio::task::spawn(async move {
+            match claim_report(&org_id, &search, now).await {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => {
+                    log::error!(
+                        "[SAVED SEARCH] claim report of {org_id}/{} error: {e}",
+                        search.name
+                    );
+                    return;
+                }
+            }
+            if let Err(e) = send_report(&org_id, &search, now).await {
+                log::error!(
+                    "[SAVED SEARCH] report of {org_id}/{} error: {e}",
+                    search.name
+                );
+                return;
+            }
+            if let Err(e) = set_last_sent_at(&org_id, &search.name, now).await {
+                log::error!(
+                    "[SAVED SEARCH] save report time of {org_id}/{} error: {e}",
+                    search.name
+                );
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Claims the report of a saved search at `now` for this node. The time of
+/// the last report started is kept in the meta store and checked and updated
+/// under a cluster lock, so a report is sent by one node per interval.
+#[cfg_attr(coverage_nightly, no_coverage)]
+async fn claim_report(org_id: &str, search: &SavedSearch, now: i64) -> Result<bool, anyhow::Error> {
+    let Some(schedule) = search.schedule.as_ref() else {
+        return Ok(false);
+    };
+    let mut locker = None;
+    if !CONFIG.common.local_mode {
+        // get a cluster lock for the saved search
+        let lock_key = format!("saved_searches/{org_id}/{}", search.name);
+        let mut lock = etcd::Locker::new(&lock_key);
+        if lock.lock(CONFIG.etcd.command_timeout).await.is_err() {
+            return Ok(false); // lock failed, another node is claiming it
+        }
+        locker = Some(lock);
+    }
+
+    let ret = match db::saved_searches::get_report_claimed_at(org_id, &search.name).await {
+        Ok(claimed_at) if now - claimed_at < schedule.frequency * 60 * 1_000_000 => {
+            // started by another node, wait for the next interval from there
+            LAST_CLAIMED.insert(format!("{org_id}/{}", search.name), claimed_at);
+            Ok(false)
+        }
+        Ok(_) => db::saved_searches::set_report_claimed_at(org_id, &search.name, now)
+            .await
+            .map(|_| true),
+        Err(e) => Err(e),
+    };
+
+    if let Some(mut lock) = locker {
+        // release cluster lock
+        lock.unlock().await?;
+    }
+    ret
+}
+
+/// Stores the time of the last report sent, on the latest version of the
+/// saved search.
+async fn set_last_sent_at(org_id: &str, name: &str, now: i64) -> Result<(), anyhow::Error> {
+    let Some(mut search) = db::saved_searches::get(org_id, name).await? else {
+        return Ok(()); // deleted in the meantime
+    };
+    let Some(schedule) = search.schedule.as_mut() else {
+        return Ok(());
+    };
+    schedule.last_sent_at = now;
+    db::saved_searches::set(org_id, &search).await
+}
+
+#[cfg_attr(coverage_nightly, no_coverage)]
+async fn send_report(org_id: &str, search: &SavedSearch, now: i64) -> Result<(), anyhow::Error> {
+    let res = search_saved(org_id, search, now, SearchService::Priority::Background).await?;
+    let (columns, rows) = to_rows(&res.hits);
+    let mut vars = vec![("report_rows".to_string(), rows.len().to_string())];
+    let format = search.schedule.as_ref().map(|s| s.format);
+    match format.unwrap_or_default() {
+        ReportFormat::Table => {
+            let shown = rows.len().min(REPORT_TABLE_ROWS);
+            vars.push((
+                "report_table".to_string(),
+                format_table(&columns, &rows[..shown]),
+            ));
+            vars.push(("report_link".to_string(), "".to_string()));
+        }
+        ReportFormat::Csv => {
+            let report = format!("{now}.csv");
+            let key = format!("{}{report}", report_prefix(org_id, &search.name));
+            storage::put(&key, format_csv(&columns, &rows)?.into()).await?;
+            vars.push(("report_table".to_string(), "".to_string()));
+            vars.push((
+                "report_link".to_string(),
+                report_link(org_id, &search.name, &report),
+            ));
+        }
+    }
+    send_report_notification(org_id, search, &vars)
+        .await
+        .map_err(|e| anyhow::anyhow!("send report error: {e}"))
+}
+
+async fn search_saved(
+    org_id: &str,
+    saved: &SavedSearch,
+    now: i64,
+    priority: SearchService::Priority,
+) -> Result<search::Response, errors::Error> {
+    let mut req = search::Request {
+        query: search::Query {
+            sql: saved.sql.clone(),
+            size: saved.size,
+            start_time: now - saved.duration * 60 * 1_000_000,
+            end_time: now,
+            query_fn: saved.query_fn.as_ref().and_then(|v| base64::decode(v).ok()),
+            ..Default::default()
+        },
+        aggs: HashMap::new(),
+        encoding: search::RequestEncoding::Empty,
+    };
+    for fn_name in functions::get_all_transform_keys(org_id).await {
+        if req.query.sql.contains(&fn_name) {
+            req.query.uses_zo_fn = true;
+            break;
+        }
+    }
+    SearchService::search_with_priority(org_id, saved.stream_type, &req, priority).await
+}
+
+async fn validate(org_id: &str, search: &SavedSearch) -> Result<(), String> {
+    if search.name.is_empty() || search.name.contains('/') {
+        return Err(format!("Invalid saved search name: {:?}", search.name));
+    }
+    if let Err(e) = crate::meta::sql::Sql::new(&search.sql) {
+        return Err(format!("Invalid SQL: {e}"));
+    }
+    if search.duration <= 0 {
+        return Err(format!(
+            "Saved search duration must be positive, got {}",
+            search.duration
+        ));
+    }
+    if let Some(query_fn) = search.query_fn.as_ref() {
+        if base64::decode(query_fn).is_err() {
+            return Err("Query function must be base64 encoded".to_string());
+        }
+    }
+    if let Some(schedule) = search.schedule.as_ref() {
+        if schedule.frequency <= 0 {
+            return Err(format!(
+                "Report frequency must be positive, got {}",
+                schedule.frequency
+            ));
+        }
+        if !matches!(
+            db::alerts::destinations::get(org_id, &schedule.destination).await,
+            Ok(Some(_))
+        ) {
+            return Err(format!("Destination not found: {:?}", schedule.destination));
+        }
+    }
+    Ok(())
+}
+
+fn report_prefix(org_id: &str, name: &str) -> String {
+    format!("saved_searches/{org_id}/{name}/")
+}
+
+fn report_link(org_id: &str, name: &str, report: &str) -> String {
+    format!(
+        "{}{}/api/{org_id}/saved_searches/{name}/reports/{report}",
+        CONFIG.common.web_url, CONFIG.common.base_uri
+    )
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound().json(MetaHttpResponse::error(
+        http::StatusCode::NOT_FOUND.into(),
+        "Saved search not found".to_string(),
+    ))
+}
+
+/// Returns the columns of the hits, in the order they first appear, and the
+/// values of every hit in that order.
+fn to_rows(hits: &[Value]) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut columns: Vec<String> = Vec::new();
+    for hit in hits {
+        if let Some(hit) = hit.as_object() {
+            for key in hit.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+    }
+    let rows = hits
+        .iter()
+        .map(|hit| {
+            columns
+                .iter()
+                .map(|column| match hit.get(column) {
+                    None | Some(Value::Null) => "".to_string(),
+                    Some(Value::String(v)) => v.clone(),
+                    Some(v) => json::to_string(v).unwrap_or_default(),
+                })
+                .collect()
+        })
+        .collect();
+    (columns, rows)
+}
+
+/// Formats the rows as a markdown table, which chat tools render.
+fn format_table(columns: &[String], rows: &[Vec<String>]) -> String {
+    let escape = |v: &String| v.replace('|', "\\|").replace('\n', " ");
+    let mut lines = Vec::with_capacity(rows.len() + 2);
+    lines.push(format!(
+        "| {} |",
+        columns.iter().map(escape).collect::<Vec<_>>().join(" | ")
+    ));
+    lines.push(format!("|{}", "---|".repeat(columns.len())));
+    for row in rows {
+        lines.push(format!(
+            "| {} |",
+            row.iter().map(escape).collect::<Vec<_>>().join(" | ")
+        ));
+    }
+    lines.join("\n")
+}
+
+fn format_csv(columns: &[String], rows: &[Vec<String>]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(columns)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits() -> Vec<Value> {
+        vec![
+            json::json!({"host": "a", "num": 3}),
+            json::json!({"host": "b|c", "num": 5, "msg": "x, \"y\""}),
+        ]
+    }
+
+    #[test]
+    fn test_to_rows() {
+        let (columns, rows) = to_rows(&hits());
+        assert_eq!(columns, vec!["host", "num", "msg"]);
+        assert_eq!(rows[0], vec!["a", "3", ""]);
+        assert_eq!(rows[1], vec!["b|c", "5", "x, \"y\""]);
+    }
+
+    #[test]
+    fn test_format_report() {
+        let (columns, rows) = to_rows(&hits());
+        assert_eq!(
+            format_table(&columns, &rows),
+            "| host | num | msg |\n|---|---|---|\n| a | 3 |  |\n| b\\|c | 5 | x, \"y\" |"
+        );
+        let csv = String::from_utf8(format_csv(&columns, &rows).unwrap()).unwrap();
+        assert_eq!(csv, "host,num,msg\na,3,\nb|c,5,\"x, \"\"y\"\"\"\n");
+    }
+}
@@ -0,0 +1,429 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Result cache of searches.
+//!
+//! The time range of a search is split at buckets aligned to
+//! `ZO_RESULT_CACHE_BUCKET`. The merged record batches of the buckets which
+//! ended before `ZO_INGEST_ALLOWED_UPTO`, so which no late record can be
+//! ingested into anymore, are cached, keyed by the org, the stream
+//! and the normalized query. A repeated search reuses them and only searches
+//! the open tail, the unaligned head and the buckets which are not cached
+//! yet. The partial results are merged with the query, the same way the
+//! results of the nodes of the cluster are merged.
+
+use ::datafusion::arrow::record_batch::RecordBatch;
+use ahash::AHashMap as HashMap;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{sql::Sql, Priority};
+use crate::handler::grpc::cluster_rpc;
+use crate::infra::{config::CONFIG, errors::Error};
+use crate::meta::{search, sql::Sql as MetaSql, StreamType};
+
+type Batches = HashMap<String, Vec<Vec<RecordBatch>>>;
+
+static CACHE: Lazy<DashMap<String, Entry>> = Lazy::new(DashMap::new);
+static CACHE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+struct Entry {
+    segments: Vec<Segment>,
+    accessed: i64,
+}
+
+/// The merged record batches of the aligned time range `[start, end)`.
+struct Segment {
+    start: i64,
+    end: i64,
+    batches: Batches,
+    size: usize,
+}
+
+/// The ranges a search is served from.
+#[derive(Debug, Default, PartialEq)]
+struct Plan {
+    /// The cached segments.
+    cached: Vec<(i64, i64)>,
+    /// The closed ranges which are searched and cached.
+    missing: Vec<(i64, i64)>,
+    /// The ranges which are searched only.
+    live: Vec<(i64, i64)>,
+}
+
+/// Returns the cached range of a search, the buckets of its time range which
+/// are closed, if there is at least one. Records are ingested up to
+/// `ZO_INGEST_ALLOWED_UPTO` in the past, so only older buckets are closed.
+fn cache_range(time_range: (i64, i64), now: i64) -> Option<(i64, i64)> {
+    let bucket = CONFIG.limit.result_cache_bucket.max(1) as i64 * 1_000_000;
+    let delay = CONFIG.limit.ingest_allowed_upto * 3600 * 1_000_000;
+    let start = (time_range.0 + bucket - 1) / bucket * bucket;
+    let end = time_range.1.min(now - delay) / bucket * bucket;
+    if end - start < bucket {
+        return None;
+    }
+    Some((start, end))
+}
+
+/// Splits the time range of a search into the cached segments, the missing
+/// closed ranges and the open ranges.
+fn plan_ranges(time_range: (i64, i64), cache_range: (i64, i64), segments: &[(i64, i64)]) -> Plan {
+    let mut plan = Plan::default();
+    if time_range.0 < cache_range.0 {
+        plan.live.push((time_range.0, cache_range.0));
+    }
+    let mut segments = segments
+        .iter()
+        .filter(|(start, end)| *start >= cache_range.0 && *end <= cache_range.1)
+        .copied()
+        .collect::<Vec<_>>();
+    segments.sort();
+    let mut pos = cache_range.0;
+    for (start, end) in segments {
+        if start < pos {
+            continue;
+        }
+        if start > pos {
+            plan.missing.push((pos, start));
+        }
+        plan.cached.push((start, end));
+        pos = end;
+    }
+    if pos < cache_range.1 {
+        plan.missing.push((pos, cache_range.1));
+    }
+    if cache_range.1 < time_range.1 {
+        plan.live.push((cache_range.1, time_range.1));
+    }
+    plan
+}
+
+fn cache_key(req: &cluster_rpc::SearchRequest, sql: &Sql) -> String {
+    let query = req.query.as_ref().unwrap();
+    let mut aggs = req
+        .aggs
+        .iter()
+        .map(|agg| format!("{}={}", agg.name, normalize(&agg.sql)))
+        .collect::<Vec<_>>();
+    aggs.sort();
+    let mut hasher = ahash::AHasher::default();
+    (
+        normalize(&query.sql),
+        aggs,
+        &query.sql_mode,
+        &query.query_type,
+        &query.query_context,
+        &query.query_fn,
+        query.uses_zo_fn,
+        query.track_total_hits,
+        query.size,
+    )
+        .hash(&mut hasher);
+    format!(
+        "{}/{}/{}/{:x}",
+        sql.org_id,
+        req.stream_type,
+        sql.stream_name,
+        hasher.finish()
+    )
+}
+
+fn normalize(sql: &str) -> String {
+    sql.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(';')
+        .to_string()
+}
+
+/// Returns true if a search can be served from the cache: a user search of
+/// a closed bucket, without a time range in its query.
+pub(crate) fn is_cacheable(req: &cluster_rpc::SearchRequest) -> bool {
+    if !CONFIG.common.result_cache_enabled
+        || req.stype != cluster_rpc::SearchType::User as i32
+        || !req.file_list.is_empty()
+    {
+        return false;
+    }
+    let query = req.query.as_ref().unwrap();
+    // a profile has to show the real work of the search
+    if query.start_time == 0 || query.profile {
+        return false;
+    }
+    let now = chrono::Utc::now().timestamp_micros();
+    let end_time = if query.end_time == 0 {
+        now
+    } else {
+        query.end_time
+    };
+    if cache_range((query.start_time, end_time), now).is_none() {
+        return false;
+    }
+    // the time range of the query can't be split
+    match MetaSql::new(&query.sql) {
+        Ok(meta) => meta.time_range.map_or(true, |v| v == (0, 0)),
+        Err(_) => false,
+    }
+}
+
+/// Searches the cluster, reusing the cached results of the closed buckets.
+#[tracing::instrument(name = "service:search:cache", skip_all)]
+pub(crate) async fn search(
+    mut req: cluster_rpc::SearchRequest,
+    priority: Priority,
+) -> Result<search::Response, Error> {
+    let start = std::time::Instant::now();
+    let now = chrono::Utc::now().timestamp_micros();
+    let query = req.query.as_mut().unwrap();
+    if query.end_time == 0 {
+        query.end_time = now;
+    }
+    let time_range = (query.start_time, query.end_time);
+    let Some(cache_range) = cache_range(time_range, now) else {
+        return super::search_in_cluster(req, priority).await;
+    };
+    query.sql = super::sql::fix_histogram_interval(&query.sql, time_range);
+    let query_type = query.query_type.to_lowercase();
+    // the partial results are merged with each other, so the offset is
+    // applied at the end
+    let (from, size) = (query.from as usize, query.size as usize);
+    query.size += query.from;
+    query.from = 0;
+    super::cost::check(&req).await?;
+    let sql = Sql::new(&req).await?;
+
+    let key = cache_key(&req, &sql);
+    let mut batches = Batches::new();
+    let plan = match CACHE.get_mut(&key) {
+        Some(mut entry) => {
+            entry.accessed = now;
+            let segments = entry
+                .segments
+                .iter()
+                .map(|v| (v.start, v.end))
+                .collect::<Vec<_>>();
+            let plan = plan_ranges(time_range, cache_range, &segments);
+            for segment in entry.segments.iter() {
+                if plan.cached.contains(&(segment.start, segment.end)) {
+                    extend(&mut batches, segment.batches.clone());
+                }
+            }
+            plan
+        }
+        None => plan_ranges(time_range, cache_range, &[]),
+    };
+
+    let (mut file_count, mut scan_size, mut took_wait) = (0, 0, 0);
+    let ranges = plan
+        .missing
+        .iter()
+        .map(|v| (*v, true))
+        .chain(plan.live.iter().map(|v| (*v, false)));
+    for ((start_time, end_time), closed) in ranges {
+        let mut req = req.clone();
+        let query = req.query.as_mut().unwrap();
+        query.start_time = start_time;
+        query.end_time = end_time;
+        let res = super::search_in_cluster_batches(req, priority).await?;
+        file_count += res.file_count;
+        scan_size += res.scan_size;
+        took_wait += res.took_wait;
+        if closed {
+            insert(&key, (start_time, end_time), res.batches.clone(), now);
+        }
+        extend(&mut batches, res.batches);
+    }
+    super::merge_batches(&sql, &mut batches).await?;
+
+    let mut result = super::build_response(&sql, batches, &query_type)?;
+    result.hits.drain(..from.min(result.hits.len()));
+    result.from = from;
+    result.size = size;
+    result.set_cluster_took(start.elapsed().as_millis() as usize, took_wait);
+    result.set_file_count(file_count);
+    result.set_scan_size(scan_size);
+
+    log::info!(
+        "search->cache: cached: {}, missing: {}, live: {}, total: {}, took: {}, scan_size: {}",
+        plan.cached.len(),
+        plan.missing.len(),
+        plan.live.len(),
+        result.total,
+        result.took,
+        result.scan_size,
+    );
+
+    Ok(result)
+}
+
+fn extend(batches: &mut Batches, other: Batches) {
+    for (name, batch) in other {
+        batches.entry(name).or_default().extend(batch);
+    }
+}
+
+fn insert(key: &str, range: (i64, i64), batches: Batches, now: i64) {
+    let size = batches
+        .values()
+        .flatten()
+        .flatten()
+        .map(|v| v.get_array_memory_size())
+        .sum::<usize>();
+    let max_size = CONFIG.limit.result_cache_max_size * 1024 * 1024;
+    if size > max_size / 10 {
+        return;
+    }
+    {
+        let mut entry = CACHE.entry(key.to_string()).or_default();
+        // another search may have cached the range meanwhile
+        if entry
+            .segments
+            .iter()
+            .any(|v| v.start < range.1 && range.0 < v.end)
+        {
+            return;
+        }
+        entry.accessed = now;
+        entry.segments.push(Segment {
+            start: range.0,
+            end: range.1,
+            batches,
+            size,
+        });
+    }
+    if CACHE_SIZE.fetch_add(size, Ordering::Relaxed) + size > max_size {
+        release(max_size - max_size / 10);
+    }
+}
+
+/// Removes the least recently used entries until the cache is not greater
+/// than the given size.
+fn release(size: usize) {
+    let mut entries = CACHE
+        .iter()
+        .map(|v| (v.accessed, v.key().clone()))
+        .collect::<Vec<_>>();
+    entries.sort();
+    for (_, key) in entries {
+        if CACHE_SIZE.load(Ordering::Relaxed) <= size {
+            break;
+        }
+        if let Some((_, entry)) = CACHE.remove(&key) {
+            let entry_size = entry.segments.iter().map(|v| v.size).sum();
+            CACHE_SIZE.fetch_sub(entry_size, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Removes the cached results of a stream which overlap with the time range,
+/// or all of them if there is no time range.
+pub fn invalidate(
+    org_id: &str,
+    stream_type: StreamType,
+    stream_name: &str,
+    time_range: Option<(i64, i64)>,
+) {
+    let prefix = format!("{org_id}/{stream_type}/{stream_name}/");
+    let mut released = 0;
+    CACHE.retain(|key, entry| {
+        if !key.starts_with(&prefix) {
+            return true;
+        }
+        entry.segments.retain(|v| match time_range {
+            Some((start, end)) if v.end <= start || end <= v.start => true,
+            _ => {
+                released += v.size;
+                false
+            }
+        });
+        !entry.segments.is_empty()
+    });
+    CACHE_SIZE.fetch_sub(released, Ordering::Relaxed);
+    if released > 0 {
+        log::info!("search->cache: invalidated {prefix}, released: {released}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(
+            plan_ranges((5, 95), (10, 90), &[]),
+            Plan {
+                cached: vec![],
+                missing: vec![(10, 90)],
+                live: vec![(5, 10), (90, 95)],
+            }
+        );
+        assert_eq!(
+            plan_ranges((10, 90), (10, 80), &[(30, 50), (0, 10), (10, 30), (70, 90)]),
+            Plan {
+                cached: vec![(10, 30), (30, 50)],
+                missing: vec![(50, 80)],
+                live: vec![(80, 90)],
+            }
+        );
+        assert_eq!(
+            plan_ranges((10, 40), (10, 40), &[(20, 30)]),
+            Plan {
+                cached: vec![(20, 30)],
+                missing: vec![(10, 20), (30, 40)],
+                live: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_cache_range() {
+        let bucket = CONFIG.limit.result_cache_bucket.max(1) as i64 * 1_000_000;
+        let delay = CONFIG.limit.ingest_allowed_upto * 3600 * 1_000_000;
+        let now = 1000 * bucket + delay;
+        assert_eq!(cache_range((0, now), now), Some((0, 1000 * bucket)));
+        // buckets which can still receive late records are not cached
+        assert_eq!(cache_range((999 * bucket + 1, now), now), None);
+        assert_eq!(
+            cache_range((bucket / 2, 3 * bucket + 1), now),
+            Some((bucket, 3 * bucket))
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("SELECT *\n  FROM t   WHERE a = 1;"),
+            "SELECT * FROM t WHERE a = 1"
+        );
+    }
+
+    #[test]
+    fn test_invalidate() {
+        let key = "org1/logs/stream1/1";
+        insert(key, (0, 10), Batches::new(), 1);
+        insert(key, (10, 20), Batches::new(), 1);
+        insert("org1/logs/stream2/1", (0, 10), Batches::new(), 1);
+        assert_eq!(CACHE.get(key).unwrap().segments.len(), 2);
+
+        invalidate("org1", StreamType::Logs, "stream1", Some((15, 30)));
+        assert_eq!(CACHE.get(key).unwrap().segments.len(), 1);
+        invalidate("org1", StreamType::Logs, "stream1", None);
+        assert!(CACHE.get(key).is_none());
+        assert!(CACHE.get("org1/logs/stream2/1").is_some());
+    }
+}
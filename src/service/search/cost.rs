@@ -0,0 +1,171 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cost estimation of searches and the search limits of organizations.
+//!
+//! The cost of a search is estimated from the metadata of the files in the
+//! file list cache, before any file is scanned. A search which scans more
+//! than `ZO_QUERY_MAX_SCAN_SIZE` or has a longer time range than
+//! `ZO_QUERY_MAX_TIME_RANGE` is rejected, unless the organization has its own
+//! limits.
+
+use crate::handler::grpc::cluster_rpc;
+use crate::infra::{
+    config::CONFIG,
+    errors::{Error, ErrorCodes},
+};
+use crate::meta::{
+    search::{Cost, Request as SearchRequest},
+    StreamType,
+};
+use crate::service::{db, file_list};
+
+const HOUR_MICROS: i64 = 3600 * 1_000_000;
+
+/// Returns the maximum scan size, in MB, and the maximum time range, in
+/// hours, of the searches of an organization.
+pub fn get_limits(org_id: &str) -> (u64, i64) {
+    let limits = db::organization::get_search_limits(org_id).unwrap_or_default();
+    (
+        limits
+            .max_scan_size
+            .unwrap_or(CONFIG.limit.query_max_scan_size),
+        limits
+            .max_time_range
+            .unwrap_or(CONFIG.limit.query_max_time_range),
+    )
+}
+
+/// Estimates the cost of a search without running it.
+#[tracing::instrument(name = "service:search:cost:estimate", skip(req))]
+pub async fn estimate(
+    org_id: &str,
+    stream_type: StreamType,
+    req: &SearchRequest,
+) -> Result<Cost, Error> {
+    let mut req: cluster_rpc::SearchRequest = req.to_owned().into();
+    req.org_id = org_id.to_string();
+    req.stype = cluster_rpc::SearchType::User as i32;
+    req.stream_type = stream_type.to_string();
+    estimate_request(&req).await
+}
+
+async fn estimate_request(req: &cluster_rpc::SearchRequest) -> Result<Cost, Error> {
+    let stream_type = StreamType::from(req.stream_type.as_str());
+    let sql = super::sql::Sql::new(req).await?;
+    let (start_time, end_time) = super::get_times(&sql, stream_type).await;
+    let files = if req.file_list.is_empty() {
+        super::get_file_list(&sql, stream_type).await
+    } else {
+        req.file_list.clone()
+    };
+
+    let (max_scan_size, max_time_range) = get_limits(&req.org_id);
+    let mut cost = Cost {
+        start_time,
+        end_time,
+        files: files.len(),
+        max_scan_size,
+        max_time_range,
+        ..Default::default()
+    };
+    for file in files.iter() {
+        let meta = file_list::get_file_meta(file).await.unwrap_or_default();
+        cost.records += meta.records;
+        cost.original_size += meta.original_size;
+        cost.compressed_size += meta.compressed_size;
+    }
+    let reason = check_cost(&cost).err().map(|e| match e {
+        Error::ErrorCode(code) => code.get_message(),
+        e => e.to_string(),
+    });
+    cost.allowed = reason.is_none();
+    cost.reason = reason;
+    Ok(cost)
+}
+
+fn check_cost(cost: &Cost) -> Result<(), Error> {
+    if cost.max_time_range > 0
+        && cost.end_time - cost.start_time > cost.max_time_range * HOUR_MICROS
+    {
+        return Err(Error::ErrorCode(ErrorCodes::SearchTimeRangeExceeded(
+            format!(
+                "time range {} hours, limit {} hours",
+                (cost.end_time - cost.start_time + HOUR_MICROS - 1) / HOUR_MICROS,
+                cost.max_time_range
+            ),
+        )));
+    }
+    let scan_size = cost.original_size / 1024 / 1024;
+    if cost.max_scan_size > 0 && scan_size > cost.max_scan_size {
+        return Err(Error::ErrorCode(ErrorCodes::SearchScanSizeExceeded(
+            format!(
+                "scan size {} MB, limit {} MB",
+                scan_size, cost.max_scan_size
+            ),
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects a search which exceeds the limits of its organization.
+#[tracing::instrument(name = "service:search:cost:check", skip_all)]
+pub(crate) async fn check(req: &cluster_rpc::SearchRequest) -> Result<(), Error> {
+    let (max_scan_size, max_time_range) = get_limits(&req.org_id);
+    if max_scan_size == 0 && max_time_range == 0 {
+        return Ok(());
+    }
+    let cost = estimate_request(req).await?;
+    if let Err(e) = check_cost(&cost) {
+        log::warn!(
+            "search->cost: org: {}, rejected: {}",
+            req.org_id,
+            e.to_string()
+        );
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_cost() {
+        let mut cost = Cost {
+            start_time: 0,
+            end_time: 48 * HOUR_MICROS,
+            original_size: 2048 * 1024 * 1024,
+            ..Default::default()
+        };
+        assert!(check_cost(&cost).is_ok());
+
+        cost.max_time_range = 24;
+        assert!(matches!(
+            check_cost(&cost),
+            Err(Error::ErrorCode(ErrorCodes::SearchTimeRangeExceeded(_)))
+        ));
+        cost.max_time_range = 48;
+        assert!(check_cost(&cost).is_ok());
+
+        cost.max_scan_size = 1024;
+        assert!(matches!(
+            check_cost(&cost),
+            Err(Error::ErrorCode(ErrorCodes::SearchScanSizeExceeded(_)))
+        ));
+        cost.max_scan_size = 2048;
+        assert!(check_cost(&cost).is_ok());
+    }
+}
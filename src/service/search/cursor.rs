@@ -0,0 +1,683 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Server-side search cursors.
+//!
+//! A cursor pages through the results of a search: it keeps the search with
+//! its time range fixed, so records ingested later don't show up, and the
+//! position of the next page. Cursors are kept in the meta store, so any
+//! querier can search the next page, and expire after a TTL.
+//!
+//! Searches are paged by sort key, so that a page doesn't recompute the pages
+//! before it: searches sorted by `_timestamp` only search the time range after
+//! the `_timestamp` of the last record returned, searches sorted by any other
+//! `ORDER BY` columns get `_timestamp` as a tiebreaker and a filter on the
+//! records sorted after the sort key of the last record returned. Searches
+//! without an order, and pages whose records miss a sort key or have a null
+//! one, are paged by offset: every page recomputes the records of the pages
+//! before it, which gets slower the further the cursor goes.
+//!
+//! The file list is resolved again for every page, so files merged by the
+//! compactor in between are searched through the files they were merged
+//! into.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlparser::{
+    ast::{BinaryOperator, Expr, Ident, OrderByExpr, SetExpr, Statement},
+    dialect::GenericDialect,
+    parser::Parser,
+};
+use tokio::time;
+use uuid::Uuid;
+
+use crate::common::json;
+use crate::handler::grpc::cluster_rpc;
+use crate::infra::{
+    config::CONFIG,
+    errors::{Error, ErrorCodes},
+};
+use crate::meta::{search, sql::Sql as MetaSql, StreamType};
+use crate::service::db;
+
+use super::Priority;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Cursor {
+    pub(crate) id: String,
+    org_id: String,
+    stream_type: StreamType,
+    /// The search of the next pages, with its time range fixed.
+    req: search::Request,
+    sort: CursorSort,
+    /// Number of records returned so far.
+    returned: usize,
+    /// Seconds.
+    ttl: u64,
+    /// Microseconds.
+    expires_at: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum CursorSort {
+    /// Records are sorted by `_timestamp`, the next page starts after the
+    /// last timestamp returned. `ties` records with that timestamp were
+    /// already returned.
+    Timestamp {
+        desc: bool,
+        last: Option<i64>,
+        ties: usize,
+    },
+    /// Records are sorted by the `ORDER BY` columns, as written in the
+    /// query, and `_timestamp` as the tiebreaker; `true` is descending. The
+    /// next page starts after the sort key of the last record returned, `ties`
+    /// records with that key were already returned.
+    Key {
+        columns: Vec<(String, bool)>,
+        last: Option<Vec<json::Value>>,
+        ties: usize,
+    },
+    /// No order, the next page is found by offset.
+    Offset,
+}
+
+impl Cursor {
+    /// Validates a search for paging and fixes its time range. Returns the
+    /// cursor, positioned before the first page, and the request of the first
+    /// page. `ttl` is in seconds.
+    pub(crate) fn new(
+        org_id: &str,
+        stream_type: StreamType,
+        req: &search::Request,
+        ttl: u64,
+    ) -> Result<(Self, cluster_rpc::SearchRequest), Error> {
+        let meta = MetaSql::new(&req.query.sql)
+            .map_err(|_| Error::ErrorCode(ErrorCodes::SearchSQLNotValid(req.query.sql.clone())))?;
+        if !can_page(&meta) {
+            return Err(Error::ErrorCode(ErrorCodes::SearchSQLNotValid(
+                "Search cursor does not support [limit|offset|group by]".to_string(),
+            )));
+        }
+
+        let mut req = req.clone();
+        let from = req.query.from;
+        let mut sort = get_sort(&meta, &req.query.sql_mode);
+        if let CursorSort::Key { columns, .. } = &sort {
+            match with_order_by(&req.query.sql, columns) {
+                Some(sql) => req.query.sql = sql,
+                None => sort = CursorSort::Offset,
+            }
+        }
+        // records ingested later must not show up in the next pages
+        if req.query.end_time == 0 {
+            req.query.end_time = Utc::now().timestamp_micros();
+        }
+        let first = to_rpc(org_id, stream_type, req.clone());
+
+        // the next pages only search hits
+        req.aggs.clear();
+        req.query.from = 0;
+        req.query.track_total_hits = false;
+        let cursor = Cursor {
+            id: Uuid::new_v4().to_string(),
+            org_id: org_id.to_string(),
+            stream_type,
+            req,
+            sort,
+            returned: from,
+            ttl,
+            expires_at: 0,
+        };
+        Ok((cursor, first))
+    }
+
+    /// Returns the request of the next page.
+    pub(crate) fn next_request(&self, size: usize) -> cluster_rpc::SearchRequest {
+        let mut req = self.req.clone();
+        req.query.size = size;
+        match &self.sort {
+            CursorSort::Timestamp {
+                desc,
+                last: Some(last),
+                ties,
+            } => {
+                if *desc {
+                    req.query.end_time = last + 1;
+                } else {
+                    req.query.start_time = *last;
+                }
+                req.query.from = *ties;
+            }
+            CursorSort::Key {
+                columns,
+                last: Some(last),
+                ties,
+            } => match after_key(&req.query.sql, columns, last) {
+                Some(sql) => {
+                    req.query.sql = sql;
+                    req.query.from = *ties;
+                }
+                None => req.query.from = self.returned,
+            },
+            _ => req.query.from = self.returned,
+        }
+        to_rpc(&self.org_id, self.stream_type, req)
+    }
+
+    /// Moves the cursor past a page of records.
+    pub(crate) fn advance(&mut self, hits: &[json::Value]) {
+        self.returned += hits.len();
+        if hits.is_empty() {
+            return;
+        }
+        // without the sort key of the records we can only page by offset
+        self.sort = match std::mem::replace(&mut self.sort, CursorSort::Offset) {
+            CursorSort::Timestamp { desc, last, ties } => match hit_timestamps(hits) {
+                Some(keys) => {
+                    let (last, ties) = last_key(&keys, last, ties);
+                    CursorSort::Timestamp {
+                        desc,
+                        last: Some(last),
+                        ties,
+                    }
+                }
+                None => CursorSort::Offset,
+            },
+            CursorSort::Key {
+                columns,
+                last,
+                ties,
+            } => match hit_keys(hits, &columns) {
+                Some(keys) => {
+                    let (last, ties) = last_key(&keys, last, ties);
+                    CursorSort::Key {
+                        columns,
+                        last: Some(last),
+                        ties,
+                    }
+                }
+                None => CursorSort::Offset,
+            },
+            CursorSort::Offset => CursorSort::Offset,
+        };
+    }
+
+    /// Saves the cursor for the next page and extends its TTL.
+    async fn save(&mut self) -> Result<(), Error> {
+        self.expires_at = Utc::now().timestamp_micros() + self.ttl as i64 * 1_000_000;
+        db::search_cursors::set(&self.org_id, self)
+            .await
+            .map_err(|e| Error::Message(e.to_string()))
+    }
+}
+
+/// Returns whether the results of a query can be paged, queries with a
+/// `LIMIT`, an `OFFSET` or a `GROUP BY` can't.
+pub(crate) fn can_page(meta: &MetaSql) -> bool {
+    meta.limit == 0 && meta.offset == 0 && meta.group_by.is_empty()
+}
+
+/// The search request of a page. The file list is left empty, so that it is
+/// resolved when the page is searched.
+fn to_rpc(
+    org_id: &str,
+    stream_type: StreamType,
+    req: search::Request,
+) -> cluster_rpc::SearchRequest {
+    let mut req: cluster_rpc::SearchRequest = req.into();
+    req.org_id = org_id.to_string();
+    req.stype = cluster_rpc::SearchType::User as i32;
+    req.stream_type = stream_type.to_string();
+    req
+}
+
+/// Returns the `_timestamp` of every hit, or `None` if any hit has none.
+fn hit_timestamps(hits: &[json::Value]) -> Option<Vec<i64>> {
+    hits.iter()
+        .map(|hit| hit.get(&CONFIG.common.column_timestamp)?.as_i64())
+        .collect()
+}
+
+/// Returns the sort key of every hit, or `None` if any hit misses a key or
+/// has a null one, which can't be compared in a filter.
+fn hit_keys(hits: &[json::Value], columns: &[(String, bool)]) -> Option<Vec<Vec<json::Value>>> {
+    hits.iter()
+        .map(|hit| {
+            columns
+                .iter()
+                .map(|(field, _)| match hit.get(field.trim_matches('"'))? {
+                    json::Value::Null => None,
+                    v => Some(v.clone()),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Returns the sort key of the last record of a page and how many records of
+/// the page have it. A page of a single key adds to the `ties` of the previous
+/// page if it has the same key.
+fn last_key<T: Clone + PartialEq>(keys: &[T], last: Option<T>, ties: usize) -> (T, usize) {
+    let page_last = keys.last().unwrap();
+    let page_ties = keys.iter().rev().take_while(|k| *k == page_last).count();
+    if page_ties == keys.len() && last.as_ref() == Some(page_last) {
+        (page_last.clone(), ties + page_ties)
+    } else {
+        (page_last.clone(), page_ties)
+    }
+}
+
+/// Returns how the records of a search are sorted. Paging by the time range
+/// of the search is only possible if the query has no time range of its own,
+/// otherwise `_timestamp` is paged by key as any other column.
+fn get_sort(meta: &MetaSql, sql_mode: &str) -> CursorSort {
+    let timestamp = &CONFIG.common.column_timestamp;
+    let has_time_range = meta.time_range.map_or(false, |v| v != (0, 0));
+    let mut columns = match meta.order_by.as_slice() {
+        // context mode sorts by _timestamp by default
+        [] if sql_mode.to_lowercase() != "full" => vec![(timestamp.clone(), true)],
+        [] => return CursorSort::Offset,
+        v => v.to_vec(),
+    };
+    if let [(field, desc)] = columns.as_slice() {
+        if !has_time_range && field == timestamp {
+            return CursorSort::Timestamp {
+                desc: *desc,
+                last: None,
+                ties: 0,
+            };
+        }
+    }
+    // aliases can't be filtered on
+    let is_alias = |field: &str| meta.field_alias.iter().any(|(_, alias)| alias == field);
+    if columns
+        .iter()
+        .any(|(field, _)| is_alias(field.trim_matches('"')))
+    {
+        return CursorSort::Offset;
+    }
+    if !columns
+        .iter()
+        .any(|(field, _)| field.trim_matches('"') == timestamp)
+    {
+        columns.push((timestamp.clone(), true));
+    }
+    CursorSort::Key {
+        columns,
+        last: None,
+        ties: 0,
+    }
+}
+
+/// Adds the missing sort columns, i.e. the `_timestamp` tiebreaker, to the
+/// `ORDER BY` of a query. Returns `None` if the query sets the order of nulls,
+/// which the filter of the next page doesn't follow.
+fn with_order_by(sql: &str, columns: &[(String, bool)]) -> Option<String> {
+    let mut statement = parse_query(sql)?;
+    let Statement::Query(query) = &mut statement else {
+        return None;
+    };
+    if query.order_by.iter().any(|v| v.nulls_first.is_some()) {
+        return None;
+    }
+    for (field, desc) in columns.iter().skip(query.order_by.len()) {
+        query.order_by.push(OrderByExpr {
+            expr: Expr::Identifier(Ident::new(field)),
+            asc: Some(!desc),
+            nulls_first: None,
+        });
+    }
+    Some(statement.to_string())
+}
+
+/// Adds a filter on the records sorted from the sort key `last` on to a
+/// query. Nulls are sorted last in ascending order and first in descending
+/// order, so they follow any key only in ascending order.
+///
+/// The filter is wrapped in `IS TRUE`, so that its comparisons aren't taken
+/// for the time range or partition filters of the query.
+fn after_key(sql: &str, columns: &[(String, bool)], last: &[json::Value]) -> Option<String> {
+    let values = last.iter().map(to_literal).collect::<Option<Vec<_>>>()?;
+    let equal = |n: usize| {
+        columns
+            .iter()
+            .zip(values.iter())
+            .take(n)
+            .map(|((field, _), value)| format!("{field} = {value}"))
+            .collect::<Vec<_>>()
+    };
+    let mut alternatives = Vec::with_capacity(columns.len() + 1);
+    for (i, ((field, desc), value)) in columns.iter().zip(values.iter()).enumerate() {
+        let mut cond = equal(i);
+        cond.push(if *desc {
+            format!("{field} < {value}")
+        } else {
+            format!("({field} > {value} OR {field} IS NULL)")
+        });
+        alternatives.push(format!("({})", cond.join(" AND ")));
+    }
+    alternatives.push(format!("({})", equal(columns.len()).join(" AND ")));
+    let filter = format!("({}) IS TRUE", alternatives.join(" OR "));
+    let filter = Parser::new(&GenericDialect {})
+        .try_with_sql(&filter)
+        .ok()?
+        .parse_expr()
+        .ok()?;
+
+    let mut statement = parse_query(sql)?;
+    let Statement::Query(query) = &mut statement else {
+        return None;
+    };
+    let SetExpr::Select(select) = query.body.as_mut() else {
+        return None;
+    };
+    select.selection = Some(match select.selection.take() {
+        Some(selection) => Expr::BinaryOp {
+            left: Box::new(Expr::Nested(Box::new(selection))),
+            op: BinaryOperator::And,
+            right: Box::new(filter),
+        },
+        None => filter,
+    });
+    Some(statement.to_string())
+}
+
+fn parse_query(sql: &str) -> Option<Statement> {
+    Parser::parse_sql(&GenericDialect {}, sql)
+        .ok()?
+        .into_iter()
+        .next()
+}
+
+fn to_literal(value: &json::Value) -> Option<String> {
+    match value {
+        json::Value::Bool(v) => Some(v.to_string()),
+        json::Value::Number(v) => Some(v.to_string()),
+        json::Value::String(v) => Some(format!("'{}'", v.replace('\'', "''"))),
+        _ => None,
+    }
+}
+
+/// Searches the first page and opens a cursor for the next ones, unless all
+/// the results fit in the first page. `ttl` is in seconds.
+#[tracing::instrument(name = "service:search:cursor:open", skip(req))]
+pub async fn open(
+    org_id: &str,
+    stream_type: StreamType,
+    req: &search::Request,
+    ttl: Option<u64>,
+) -> Result<search::Response, Error> {
+    let ttl = ttl
+        .unwrap_or(CONFIG.limit.search_cursor_ttl)
+        .min(CONFIG.limit.search_cursor_max_ttl);
+    let (mut cursor, first) = Cursor::new(org_id, stream_type, req, ttl)?;
+    let size = req.query.size;
+    let mut res = super::search_in_cluster(first, Priority::Interactive).await?;
+    cursor.advance(&res.hits);
+    if size > 0 && res.hits.len() >= size {
+        cursor.save().await?;
+        res.cursor_id = Some(cursor.id);
+    }
+    Ok(res)
+}
+
+/// Searches the next page of a cursor. The cursor is closed after the last
+/// page.
+#[tracing::instrument(name = "service:search:cursor:next", skip(size))]
+pub async fn next(
+    org_id: &str,
+    cursor_id: &str,
+    size: Option<usize>,
+) -> Result<search::Response, Error> {
+    let mut cursor = match db::search_cursors::get(org_id, cursor_id).await {
+        Ok(Some(cursor)) if cursor.expires_at > Utc::now().timestamp_micros() => cursor,
+        _ => return Err(cursor_not_found(cursor_id)),
+    };
+    // take the cursor out, so that the same page isn't searched twice
+    if db::search_cursors::delete(org_id, cursor_id).await.is_err() {
+        return Err(cursor_not_found(cursor_id));
+    }
+
+    let size = size.unwrap_or(cursor.req.query.size);
+    let mut res =
+        match super::search_in_cluster(cursor.next_request(size), Priority::Interactive).await {
+            Ok(res) => res,
+            Err(err) => {
+                // keep the cursor, the page can be retried
+                if let Err(e) = cursor.save().await {
+                    log::error!("search->cursor: save cursor {} error: {}", cursor_id, e);
+                }
+                return Err(err);
+            }
+        };
+    res.from = cursor.returned;
+    res.size = size;
+    cursor.advance(&res.hits);
+    if size > 0 && res.hits.len() >= size {
+        cursor.save().await?;
+        res.cursor_id = Some(cursor_id.to_string());
+    }
+    Ok(res)
+}
+
+/// Closes a cursor, returns false if it doesn't exist.
+pub async fn close(org_id: &str, cursor_id: &str) -> bool {
+    db::search_cursors::delete(org_id, cursor_id).await.is_ok()
+}
+
+/// Removes the expired cursors periodically.
+pub async fn run() {
+    let mut interval = time::interval(time::Duration::from_secs(60));
+    interval.tick().await; // trigger the first run
+    loop {
+        interval.tick().await;
+        let cursors = match db::search_cursors::list().await {
+            Ok(cursors) => cursors,
+            Err(e) => {
+                log::error!("search->cursor: list cursors error: {}", e);
+                continue;
+            }
+        };
+        let now = Utc::now().timestamp_micros();
+        for cursor in cursors.iter().filter(|c| c.expires_at <= now) {
+            if let Err(e) = db::search_cursors::delete_if_exists(&cursor.org_id, &cursor.id).await {
+                log::error!("search->cursor: delete cursor {} error: {}", cursor.id, e);
+            }
+        }
+    }
+}
+
+fn cursor_not_found(cursor_id: &str) -> Error {
+    Error::ErrorCode(ErrorCodes::SearchCursorNotFound(cursor_id.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_cursor(sort: CursorSort) -> Cursor {
+        let req = search::Request {
+            query: search::Query {
+                sql: "select * from olympics".to_string(),
+                start_time: 1_000_000,
+                end_time: 9_000_000,
+                ..Default::default()
+            },
+            aggs: Default::default(),
+            encoding: search::RequestEncoding::Empty,
+        };
+        Cursor {
+            id: "test".to_string(),
+            org_id: "default".to_string(),
+            stream_type: StreamType::Logs,
+            req,
+            sort,
+            returned: 0,
+            ttl: 60,
+            expires_at: 0,
+        }
+    }
+
+    fn hits(timestamps: &[i64]) -> Vec<json::Value> {
+        timestamps
+            .iter()
+            .map(|ts| json::json!({ "_timestamp": ts, "log": "x" }))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_sort() {
+        let timestamp = |desc| CursorSort::Timestamp {
+            desc,
+            last: None,
+            ties: 0,
+        };
+        let key = |columns: &[(&str, bool)]| CursorSort::Key {
+            columns: columns
+                .iter()
+                .map(|(field, desc)| (field.to_string(), *desc))
+                .collect(),
+            last: None,
+            ties: 0,
+        };
+        let sort = |sql, mode| get_sort(&MetaSql::new(sql).unwrap(), mode);
+        assert_eq!(sort("select * from t", "context"), timestamp(true));
+        assert_eq!(sort("select * from t", "full"), CursorSort::Offset);
+        assert_eq!(
+            sort("select * from t order by _timestamp asc", "full"),
+            timestamp(false)
+        );
+        assert_eq!(
+            sort("select * from t order by code desc", "context"),
+            key(&[("code", true), ("_timestamp", true)])
+        );
+        assert_eq!(
+            sort("select * from t order by code, _timestamp asc", "full"),
+            key(&[("code", false), ("_timestamp", false)])
+        );
+        assert_eq!(
+            sort(
+                "select * from t where time_range(_timestamp, 1666192764587000, 1666193664587000)",
+                "context"
+            ),
+            key(&[("_timestamp", true)])
+        );
+        assert_eq!(
+            sort("select code as c from t order by c", "full"),
+            CursorSort::Offset
+        );
+    }
+
+    #[test]
+    fn test_cursor_timestamp() {
+        let mut cursor = new_cursor(CursorSort::Timestamp {
+            desc: true,
+            last: None,
+            ties: 0,
+        });
+        cursor.advance(&hits(&[8_000_000, 7_000_000, 7_000_000]));
+        let req = cursor.next_request(3);
+        let query = req.query.as_ref().unwrap();
+        assert_eq!(
+            (query.start_time, query.end_time, query.from, query.size),
+            (1_000_000, 7_000_001, 2, 3)
+        );
+
+        // a page of the same timestamp adds to the ties
+        cursor.advance(&hits(&[7_000_000, 7_000_000, 7_000_000]));
+        let req = cursor.next_request(3);
+        let query = req.query.as_ref().unwrap();
+        assert_eq!((query.end_time, query.from), (7_000_001, 5));
+        assert_eq!(cursor.returned, 6);
+
+        cursor.advance(&[json::json!({ "log": "x" })]);
+        assert_eq!(cursor.sort, CursorSort::Offset);
+        let req = cursor.next_request(3);
+        let query = req.query.as_ref().unwrap();
+        assert_eq!((query.end_time, query.from), (9_000_000, 7));
+    }
+
+    #[test]
+    fn test_cursor_store() {
+        let mut cursor = new_cursor(CursorSort::Offset);
+        cursor.advance(&hits(&[2_000_000, 3_000_000]));
+        let cursor: Cursor = json::from_slice(&json::to_vec(&cursor).unwrap()).unwrap();
+        let req = cursor.next_request(2);
+        assert_eq!(req.org_id, "default");
+        assert_eq!(req.stream_type, "logs");
+        assert!(req.file_list.is_empty());
+        let query = req.query.as_ref().unwrap();
+        assert_eq!((query.end_time, query.from, query.size), (9_000_000, 2, 2));
+    }
+
+    #[test]
+    fn test_cursor_timestamp_asc() {
+        let mut cursor = new_cursor(CursorSort::Timestamp {
+            desc: false,
+            last: None,
+            ties: 0,
+        });
+        cursor.advance(&hits(&[2_000_000, 3_000_000]));
+        let req = cursor.next_request(2);
+        let query = req.query.as_ref().unwrap();
+        assert_eq!(
+            (query.start_time, query.end_time, query.from),
+            (3_000_000, 9_000_000, 1)
+        );
+    }
+
+    #[test]
+    fn test_cursor_key() {
+        let req = search::Request {
+            query: search::Query {
+                sql: "select * from olympics where year > 2000 order by medal".to_string(),
+                start_time: 1_000_000,
+                end_time: 9_000_000,
+                size: 2,
+                ..Default::default()
+            },
+            aggs: Default::default(),
+            encoding: search::RequestEncoding::Empty,
+        };
+        let (mut cursor, first) = Cursor::new("default", StreamType::Logs, &req, 60).unwrap();
+        let sql = &first.query.as_ref().unwrap().sql;
+        assert!(sql.ends_with("ORDER BY medal, _timestamp DESC"), "{sql}");
+
+        let hit = |medal: &str, ts: i64| json::json!({ "medal": medal, "_timestamp": ts });
+        cursor.advance(&[hit("bronze", 3), hit("gold's", 2)]);
+        let req = cursor.next_request(2);
+        let query = req.query.as_ref().unwrap();
+        assert!(
+            query.sql.contains(
+                "WHERE (year > 2000) AND ((medal > 'gold''s' OR medal IS NULL) \
+                 OR (medal = 'gold''s' AND _timestamp < 2) \
+                 OR (medal = 'gold''s' AND _timestamp = 2)) IS TRUE"
+            ),
+            "{}",
+            query.sql
+        );
+        assert_eq!((query.from, query.end_time), (1, 9_000_000));
+        // the filter doesn't narrow the time range of the search
+        assert_eq!(MetaSql::new(&query.sql).unwrap().time_range, Some((0, 0)));
+
+        // a null key can't be filtered on
+        cursor.advance(&[
+            hit("silver", 1),
+            json::json!({ "medal": null, "_timestamp": 1 }),
+        ]);
+        assert_eq!(cursor.sort, CursorSort::Offset);
+        let req = cursor.next_request(2);
+        assert_eq!(req.query.as_ref().unwrap().from, 4);
+    }
+}
@@ -27,14 +27,15 @@ use datafusion::{
         },
         listing::{ListingOptions, ListingTable, ListingTableConfig, ListingTableUrl},
         object_store::{DefaultObjectStoreRegistry, ObjectStoreRegistry},
-        TableProvider,
+        MemTable, TableProvider,
     },
     error::{DataFusionError, Result},
     execution::{
         context::SessionConfig,
         runtime_env::{RuntimeConfig, RuntimeEnv},
     },
-    prelude::{cast, col, lit, Expr, SessionContext},
+    physical_plan::{collect, display::DisplayableExecutionPlan},
+    prelude::{cast, col, lit, DataFrame, Expr, SessionContext},
     scalar::ScalarValue,
 };
 use parquet::arrow::ArrowWriter;
@@ -53,6 +54,9 @@ use crate::service::search::sql::Sql;
 
 const AGGREGATE_UDF_LIST: [&str; 6] = ["min", "max", "count", "avg", "sum", "array_agg"];
 
+/// Executes the query and the aggregations of a search on the given files,
+/// returns the record batches keyed by `query` and `agg_{name}`, and, when
+/// profiling, the physical plans with their metrics keyed the same way.
 pub async fn sql(
     session: &SearchSession,
     stream_type: StreamType,
@@ -61,9 +65,9 @@ pub async fn sql(
     sql: &Arc<Sql>,
     files: &[String],
     file_type: FileType,
-) -> Result<HashMap<String, Vec<RecordBatch>>> {
+) -> Result<(HashMap<String, Vec<RecordBatch>>, Vec<(String, String)>)> {
     if files.is_empty() {
-        return Ok(HashMap::new());
+        return Ok((HashMap::new(), vec![]));
     }
 
     let start = std::time::Instant::now();
@@ -109,6 +113,8 @@ pub async fn sql(
     }
 
     let mut result: HashMap<String, Vec<RecordBatch>> = HashMap::new();
+    let mut plans = vec![];
+    let mut plans_ref = sql.profile.then_some(&mut plans);
 
     // query
     let query = if !&sql.query_context.is_empty() {
@@ -165,7 +171,7 @@ pub async fn sql(
 
     let mut resp = None;
     if field_fns.is_empty() || sql.query_fn.is_some() {
-        let batches = df.clone().collect().await?;
+        let batches = collect_df(df.clone(), "query", plans_ref.as_deref_mut()).await?;
         if sql.query_fn.is_some() {
             resp = handle_query_fn(sql.query_fn.clone().unwrap(), batches, &sql.org_id);
         } else {
@@ -235,7 +241,7 @@ pub async fn sql(
                 return Err(e);
             }
         };
-        let batches = df.clone().collect().await?;
+        let batches = collect_df(df, "query", plans_ref.as_deref_mut()).await?;
         result.insert("query".to_string(), batches);
         log::info!("Query took {:.3} seconds.", start.elapsed().as_secs_f64());
     }
@@ -289,8 +295,9 @@ pub async fn sql(
             }
             df = df.select(exprs)?;
         }
-        let batches = df.collect().await?;
-        result.insert(format!("agg_{name}"), batches);
+        let key = format!("agg_{name}");
+        let batches = collect_df(df, &key, plans_ref.as_deref_mut()).await?;
+        result.insert(key, batches);
         log::info!(
             "Query agg:{name} took {:.3} seconds.",
             start.elapsed().as_secs_f64()
@@ -305,7 +312,29 @@ pub async fn sql(
         start.elapsed().as_secs_f64()
     );
 
-    Ok(result)
+    Ok((result, plans))
+}
+
+/// Collects the record batches of a dataframe, when `plans` is given also
+/// records its physical plan annotated with the metrics of the execution.
+async fn collect_df(
+    df: DataFrame,
+    name: &str,
+    plans: Option<&mut Vec<(String, String)>>,
+) -> Result<Vec<RecordBatch>> {
+    let Some(plans) = plans else {
+        return df.collect().await;
+    };
+    let task_ctx = Arc::new(df.task_ctx());
+    let plan = df.create_physical_plan().await?;
+    let batches = collect(plan.clone(), task_ctx).await?;
+    plans.push((
+        name.to_string(),
+        DisplayableExecutionPlan::with_metrics(plan.as_ref())
+            .indent()
+            .to_string(),
+    ));
+    Ok(batches)
 }
 
 fn replace_in_query(replace_pat: &String, where_query: &mut String, is_alias: bool) {
@@ -438,6 +467,28 @@ pub async fn merge(
     Ok(vec![batches])
 }
 
+/// Runs a query on record batches which are already in memory, every table
+/// is registered with its schema and its record batches.
+pub async fn query_batches(
+    org_id: &str,
+    tables: Vec<(String, Arc<Schema>, Vec<RecordBatch>)>,
+    sql: &str,
+) -> Result<Vec<RecordBatch>> {
+    let start = Instant::now();
+    let mut ctx = prepare_datafusion_context()?;
+    register_udf(&mut ctx, org_id).await;
+    for (name, schema, batches) in tables {
+        let table = MemTable::try_new(schema, vec![batches])?;
+        ctx.register_table(name.as_str(), Arc::new(table))?;
+    }
+    let batches = ctx.sql(sql).await?.collect().await?;
+    log::info!(
+        "Query batches took {:.3} seconds.",
+        start.elapsed().as_secs_f64()
+    );
+    Ok(batches)
+}
+
 fn merge_write_recordbatch(batches: &[Vec<RecordBatch>]) -> Result<(Option<Arc<Schema>>, String)> {
     let mut schema = None;
     let work_dir = format!("/tmp/merge/{}/", chrono::Utc::now().timestamp_micros());
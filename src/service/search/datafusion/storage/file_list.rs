@@ -44,11 +44,33 @@ pub async fn set(session_id: &str, files: &[String]) -> Result<(), anyhow::Error
     Ok(())
 }
 
+/// Clears the file list of a session, also the lists of its schema versions,
+/// which are kept as `{session_id}-{version}`.
 pub async fn clear(session_id: &str) -> Result<(), anyhow::Error> {
-    FILES.remove(session_id);
+    remove(session_id);
     Ok(())
 }
 
+fn remove(session_id: &str) {
+    let prefix = format!("{session_id}-");
+    FILES.retain(|k, _| k != session_id && !k.starts_with(&prefix));
+}
+
+/// Clears the file list of a session when it is dropped.
+pub struct SessionGuard(String);
+
+impl SessionGuard {
+    pub fn new(session_id: &str) -> Self {
+        Self(session_id.to_string())
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        remove(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,4 +97,17 @@ mod tests {
         let resp = clear(session_id).await;
         assert!(resp.is_ok());
     }
+
+    #[actix_web::test]
+    async fn test_storage_file_list_guard() {
+        let session_id = "5678";
+        set(session_id, &[]).await.unwrap();
+        set(&format!("{session_id}-0"), &[]).await.unwrap();
+        set("56789", &[]).await.unwrap();
+
+        drop(SessionGuard::new(session_id));
+        assert!(get(session_id).await.is_err());
+        assert!(get(&format!("{session_id}-0")).await.is_err());
+        assert!(get("56789").await.is_ok());
+    }
 }
@@ -0,0 +1,532 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exports the results of a search as CSV, newline-delimited JSON or Parquet.
+//!
+//! The search goes through the cluster search like any other search, a page
+//! of `ZO_EXPORT_PAGE_SIZE` records at a time, so the records of the WAL are
+//! searched with the query of the export and only one page is kept in memory.
+//! Pages are searched with a search cursor, see `cursor`, so that a page
+//! doesn't recompute the pages before it. Queries with `GROUP BY`, `LIMIT` or
+//! `OFFSET` can't be paged and are searched at once.
+//!
+//! The records of `SELECT *` queries are written with the schema of the
+//! stream, so records without a column have it empty.
+
+use ::datafusion::arrow::{
+    array::new_null_array,
+    compute::cast,
+    csv::WriterBuilder,
+    datatypes::{Field, Schema, SchemaRef},
+    error::ArrowError,
+    json as arrow_json,
+    record_batch::RecordBatch,
+};
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{stream, Stream};
+use parquet::{arrow::ArrowWriter, file::properties::WriterProperties};
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use super::{cursor, sql::Sql, Priority};
+use crate::common::json;
+use crate::handler::grpc::cluster_rpc;
+use crate::infra::{
+    config::{get_parquet_compression, CONFIG},
+    errors::{Error, ErrorCodes},
+};
+use crate::meta::{search, sql::Sql as MetaSql, StreamType};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(anyhow::anyhow!(
+                "invalid export format: {s}, supported: csv, ndjson, parquet"
+            )),
+        }
+    }
+}
+
+/// Searches the first page, and returns the stream of the encoded records.
+/// At most `limit` records are exported, all of them if it is zero.
+#[tracing::instrument(name = "service:search:export", skip(req))]
+pub async fn export(
+    org_id: &str,
+    stream_type: StreamType,
+    req: &search::Request,
+    format: ExportFormat,
+    limit: usize,
+) -> Result<impl Stream<Item = Result<Bytes, io::Error>>, Error> {
+    let mut req = req.clone();
+    // aggregations aren't exported
+    req.aggs.clear();
+    req.query.from = 0;
+    req.query.track_total_hits = false;
+    if req.query.end_time == 0 {
+        req.query.end_time = Utc::now().timestamp_micros();
+    }
+    let meta = MetaSql::new(&req.query.sql)
+        .map_err(|_| Error::ErrorCode(ErrorCodes::SearchSQLNotValid(req.query.sql.clone())))?;
+    let (cursor, first) = if cursor::can_page(&meta) {
+        req.query.size = page_size(limit, 0);
+        let (cursor, first) = cursor::Cursor::new(org_id, stream_type, &req, 0)?;
+        (Some(cursor), first)
+    } else {
+        req.query.size = if limit == 0 { i32::MAX as usize } else { limit };
+        let mut first: cluster_rpc::SearchRequest = req.into();
+        first.org_id = org_id.to_string();
+        first.stype = cluster_rpc::SearchType::User as i32;
+        first.stream_type = stream_type.to_string();
+        (None, first)
+    };
+    super::cost::check(&first).await?;
+
+    let sql = Sql::new(&first).await?;
+    if sql.query_fn.is_some() {
+        return Err(Error::ErrorCode(ErrorCodes::SearchSQLNotValid(
+            "Export does not support query functions".to_string(),
+        )));
+    }
+    if sql.schema.fields().is_empty() {
+        return Err(Error::ErrorCode(ErrorCodes::SearchStreamNotFound(
+            sql.stream_name.clone(),
+        )));
+    }
+    let select_all = sql.meta.fields.is_empty() && sql.meta.field_alias.is_empty();
+
+    let mut export = Export {
+        cursor,
+        next: Some(first),
+        pending: VecDeque::new(),
+        format,
+        writer: None,
+        schema: nullable_schema(&sql.schema),
+        select_all,
+        exported: 0,
+        limit,
+        finished: false,
+    };
+    // the first page is searched before the response, so that an error of the
+    // search is returned as the response
+    export.next_page().await?;
+
+    Ok(stream::unfold(export, |mut export| async move {
+        match export.next_chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), export)),
+            Ok(None) => None,
+            Err(e) => {
+                log::error!("search->export: error: {}", e);
+                // stop after the error
+                export.finished = true;
+                Some((
+                    Err(io::Error::new(io::ErrorKind::Other, e.to_string())),
+                    export,
+                ))
+            }
+        }
+    }))
+}
+
+/// Returns the size of the next page, up to the limit.
+fn page_size(limit: usize, exported: usize) -> usize {
+    let size = CONFIG.limit.export_page_size.max(1);
+    if limit == 0 {
+        size
+    } else {
+        size.min(limit - exported)
+    }
+}
+
+struct Export {
+    /// The cursor of the pages, `None` if the search is searched at once.
+    cursor: Option<cursor::Cursor>,
+    /// The request of the next page, `None` after the last page.
+    next: Option<cluster_rpc::SearchRequest>,
+    /// The records of the last page which weren't written yet.
+    pending: VecDeque<RecordBatch>,
+    format: ExportFormat,
+    writer: Option<ExportWriter>,
+    /// The schema of the stream.
+    schema: SchemaRef,
+    /// The query selects all the columns, which are written with the schema
+    /// of the stream, otherwise with the schema of the results.
+    select_all: bool,
+    exported: usize,
+    limit: usize,
+    /// All the records were written.
+    finished: bool,
+}
+
+impl Export {
+    /// Searches the next page, if any, and queues its records.
+    async fn next_page(&mut self) -> Result<(), Error> {
+        let Some(req) = self.next.take() else {
+            return Ok(());
+        };
+        let size = req.query.as_ref().map_or(0, |q| q.size as usize);
+        let mut res = super::search_in_cluster_batches(req, Priority::Background).await?;
+        let batches = res
+            .batches
+            .remove("query")
+            .map(|batches| batches.into_iter().flatten().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let num = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+        self.exported += num;
+
+        let more = self.limit == 0 || self.exported < self.limit;
+        if let Some(cursor) = self.cursor.as_mut().filter(|_| more && num >= size) {
+            let hits = arrow_json::writer::record_batches_to_json_rows(&batches)
+                .map_err(|e| Error::Message(e.to_string()))?
+                .into_iter()
+                .map(json::Value::Object)
+                .collect::<Vec<_>>();
+            cursor.advance(&hits);
+            self.next = Some(cursor.next_request(page_size(self.limit, self.exported)));
+        }
+        self.pending
+            .extend(batches.into_iter().filter(|b| b.num_rows() > 0));
+        Ok(())
+    }
+
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, anyhow::Error> {
+        loop {
+            if self.finished {
+                return Ok(None);
+            }
+            if self.pending.is_empty() {
+                self.next_page().await?;
+            }
+            let Some(batch) = self.pending.pop_front() else {
+                self.finished = true;
+                let schema = self.schema.clone();
+                return Ok(Some(self.writer(schema).finish()?));
+            };
+            let schema = if self.select_all {
+                self.schema.clone()
+            } else {
+                batch.schema()
+            };
+            let chunk = self.writer(schema).write(&[batch])?;
+            if !chunk.is_empty() {
+                return Ok(Some(chunk));
+            }
+        }
+    }
+
+    /// Returns the writer, created with the given schema for the first
+    /// records.
+    fn writer(&mut self, schema: SchemaRef) -> &mut ExportWriter {
+        let format = self.format;
+        self.writer
+            .get_or_insert_with(|| ExportWriter::new(format, schema))
+    }
+}
+
+/// A buffer the parquet writer writes to, which is drained after every write.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct ExportWriter {
+    format: ExportFormat,
+    /// The schema of the results, every record is written with it.
+    schema: SchemaRef,
+    /// The CSV header was written.
+    header: bool,
+    buf: SharedBuffer,
+    parquet: Option<ArrowWriter<SharedBuffer>>,
+}
+
+impl ExportWriter {
+    fn new(format: ExportFormat, schema: SchemaRef) -> Self {
+        Self {
+            format,
+            schema,
+            header: false,
+            buf: SharedBuffer::default(),
+            parquet: None,
+        }
+    }
+
+    /// Encodes some records.
+    fn write(&mut self, batches: &[RecordBatch]) -> Result<Bytes, anyhow::Error> {
+        let aligned = batches
+            .iter()
+            .filter(|b| b.num_rows() > 0)
+            .map(|b| align_batch(&self.schema, b))
+            .collect::<Result<Vec<_>, _>>()?;
+        if aligned.is_empty() {
+            return Ok(Bytes::new());
+        }
+
+        let mut buf = Vec::new();
+        match self.format {
+            ExportFormat::Csv => {
+                let mut writer = WriterBuilder::new()
+                    .has_headers(!self.header)
+                    .build(&mut buf);
+                for batch in aligned.iter() {
+                    writer.write(batch)?;
+                }
+                self.header = true;
+            }
+            ExportFormat::Ndjson => {
+                for row in arrow_json::writer::record_batches_to_json_rows(&aligned)? {
+                    buf.extend_from_slice(&json::to_vec(&row)?);
+                    buf.push(b'\n');
+                }
+            }
+            ExportFormat::Parquet => {
+                let writer = self.parquet_writer()?;
+                for batch in aligned.iter() {
+                    writer.write(batch)?;
+                }
+                // write out a row group per write
+                writer.flush()?;
+                buf = self.buf.take();
+            }
+        }
+        Ok(buf.into())
+    }
+
+    /// Returns the end of the output, the header of a CSV file without
+    /// records or the footer of a parquet file.
+    fn finish(&mut self) -> Result<Bytes, anyhow::Error> {
+        match self.format {
+            ExportFormat::Csv if !self.header => {
+                let mut buf = Vec::new();
+                let mut writer = WriterBuilder::new().build(&mut buf);
+                writer.write(&RecordBatch::new_empty(self.schema.clone()))?;
+                drop(writer);
+                self.header = true;
+                Ok(buf.into())
+            }
+            ExportFormat::Parquet => {
+                self.parquet_writer()?;
+                if let Some(writer) = self.parquet.take() {
+                    writer.close()?;
+                }
+                Ok(self.buf.take().into())
+            }
+            _ => Ok(Bytes::new()),
+        }
+    }
+
+    fn parquet_writer(&mut self) -> Result<&mut ArrowWriter<SharedBuffer>, anyhow::Error> {
+        if self.parquet.is_none() {
+            let props = WriterProperties::builder()
+                .set_compression(get_parquet_compression())
+                .build();
+            let writer = ArrowWriter::try_new(self.buf.clone(), self.schema.clone(), Some(props))?;
+            self.parquet = Some(writer);
+        }
+        Ok(self.parquet.as_mut().unwrap())
+    }
+}
+
+/// Records may not have all the columns, so every column is nullable.
+pub(crate) fn nullable_schema(schema: &Schema) -> SchemaRef {
+    Arc::new(Schema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|f| Field::new(f.name(), f.data_type().clone(), true))
+            .collect(),
+    ))
+}
+
+/// Converts a record batch to the export schema. Columns which aren't in the
+/// schema are dropped.
+pub(crate) fn align_batch(
+    schema: &SchemaRef,
+    batch: &RecordBatch,
+) -> Result<RecordBatch, ArrowError> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) if column.data_type() == field.data_type() => Ok(column.clone()),
+            Some(column) => cast(column, field.data_type()),
+            None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::datafusion::arrow::{
+        array::{Int64Array, StringArray},
+        datatypes::DataType,
+    };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn batch(fields: &[&str], rows: &[(i64, &str)]) -> RecordBatch {
+        let schema = Schema::new(vec![
+            Field::new(fields[0], DataType::Int64, false),
+            Field::new(fields[1], DataType::Utf8, false),
+        ]);
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(Int64Array::from_iter_values(rows.iter().map(|r| r.0))),
+                Arc::new(StringArray::from_iter_values(rows.iter().map(|r| r.1))),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_export_format() {
+        assert_eq!("CSV".parse::<ExportFormat>().unwrap(), ExportFormat::Csv);
+        assert_eq!(
+            "jsonl".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Ndjson
+        );
+        assert!("xlsx".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn test_page_size() {
+        let size = CONFIG.limit.export_page_size;
+        assert_eq!(page_size(0, 5), size);
+        assert_eq!(page_size(size + 10, size), 10);
+    }
+
+    fn export_schema(fields: &[&str]) -> SchemaRef {
+        let fields = fields
+            .iter()
+            .map(|name| match *name {
+                "_timestamp" => Field::new(*name, DataType::Int64, false),
+                _ => Field::new(*name, DataType::Utf8, false),
+            })
+            .collect();
+        nullable_schema(&Schema::new(fields))
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let schema = export_schema(&["_timestamp", "log", "code"]);
+        let mut writer = ExportWriter::new(ExportFormat::Csv, schema.clone());
+        let page1 = writer
+            .write(&[batch(&["_timestamp", "log"], &[(2, "b"), (1, "a")])])
+            .unwrap();
+        // records without `log` and with `code`
+        let page2 = writer
+            .write(&[batch(&["_timestamp", "code"], &[(0, "200")])])
+            .unwrap();
+        assert!(writer.finish().unwrap().is_empty());
+        assert_eq!(page1, "_timestamp,log,code\n2,b,\n1,a,\n");
+        assert_eq!(page2, "0,,200\n");
+
+        // the header is written without records
+        let mut writer = ExportWriter::new(ExportFormat::Csv, schema);
+        assert!(writer.write(&[]).unwrap().is_empty());
+        assert_eq!(writer.finish().unwrap(), "_timestamp,log,code\n");
+    }
+
+    #[test]
+    fn test_export_ndjson() {
+        let mut writer =
+            ExportWriter::new(ExportFormat::Ndjson, export_schema(&["_timestamp", "log"]));
+        let page = writer
+            .write(&[batch(&["_timestamp", "log"], &[(2, "b"), (1, "a")])])
+            .unwrap();
+        assert_eq!(
+            page,
+            "{\"_timestamp\":2,\"log\":\"b\"}\n{\"_timestamp\":1,\"log\":\"a\"}\n"
+        );
+        assert!(writer.write(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_export_parquet() {
+        let schema = export_schema(&["_timestamp", "log", "code"]);
+        let mut writer = ExportWriter::new(ExportFormat::Parquet, schema.clone());
+        let mut data = Vec::new();
+        data.extend_from_slice(
+            &writer
+                .write(&[batch(&["_timestamp", "log"], &[(2, "b"), (1, "a")])])
+                .unwrap(),
+        );
+        data.extend_from_slice(
+            &writer
+                .write(&[batch(&["_timestamp", "code"], &[(0, "200")])])
+                .unwrap(),
+        );
+        data.extend_from_slice(&writer.finish().unwrap());
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(data))
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.map(|b| b.unwrap()).collect::<Vec<_>>();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 3);
+        assert!(batches
+            .iter()
+            .all(|b| b.schema().fields() == schema.fields()));
+    }
+}
@@ -14,7 +14,10 @@
 
 use ::datafusion::arrow::{ipc, record_batch::RecordBatch};
 use ahash::AHashMap as HashMap;
+use dashmap::DashMap;
 use datafusion_common::DataFusionError;
+use futures::future::{AbortHandle, Abortable};
+use once_cell::sync::Lazy;
 use std::sync::Arc;
 use tracing::{info_span, Instrument};
 
@@ -27,7 +30,42 @@ use crate::meta::StreamType;
 mod storage;
 mod wal;
 
-pub type SearchResult = Result<(HashMap<String, Vec<RecordBatch>>, usize, usize), Error>;
+pub type SearchResult = Result<
+    (
+        HashMap<String, Vec<RecordBatch>>,
+        usize,
+        usize,
+        cluster_rpc::SearchProfile,
+    ),
+    Error,
+>;
+
+/// The running searches, keyed by session and partition, with their job and
+/// the handles to abort them.
+static RUNNING: Lazy<DashMap<String, (String, Vec<AbortHandle>)>> = Lazy::new(DashMap::new);
+
+/// Removes a search from `RUNNING` when it is done.
+struct RunningGuard(String);
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        RUNNING.remove(&self.0);
+    }
+}
+
+/// Cancels the running searches of a job, returns how many were cancelled.
+pub fn cancel(job: &str) -> usize {
+    let mut canceled = 0;
+    RUNNING.retain(|_, (running_job, handles)| {
+        if running_job != job {
+            return true;
+        }
+        handles.iter().for_each(|handle| handle.abort());
+        canceled += 1;
+        false
+    });
+    canceled
+}
 
 #[tracing::instrument(name = "service:search:grpc:search", skip_all)]
 pub async fn search(
@@ -36,7 +74,20 @@ pub async fn search(
     let start = std::time::Instant::now();
     let sql = Arc::new(super::sql::Sql::new(req).await?);
     let stream_type = StreamType::from(req.stream_type.as_str());
-    let session_id = Arc::new(req.job.as_ref().unwrap().session_id.to_string());
+    let job = req.job.as_ref().unwrap();
+    let session_id = Arc::new(job.session_id.to_string());
+
+    // register the search, so that it can be cancelled
+    let (abort_wal, abort_wal_reg) = AbortHandle::new_pair();
+    let (abort_storage, abort_storage_reg) = AbortHandle::new_pair();
+    let running_key = format!("{}/{}", job.session_id, job.partition);
+    RUNNING.insert(
+        running_key.clone(),
+        (job.job.clone(), vec![abort_wal, abort_storage]),
+    );
+    let _running = RunningGuard(running_key);
+    // clear the session data however the search ends
+    let _session = datafusion::storage::file_list::SessionGuard::new(&session_id);
 
     let mut results = HashMap::new();
     let mut file_count = 0;
@@ -45,41 +96,43 @@ pub async fn search(
     // search in WAL
     let session_id1 = session_id.clone();
     let sql1 = sql.clone();
+    let req_stype = req.stype;
     let wal_span = info_span!("service:search:grpc:in_wal");
-    let task1 = tokio::task::spawn(
+    let task1 = tokio::task::spawn(Abortable::new(
         async move {
-            if cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
+            if cluster::is_ingester(&cluster::LOCAL_NODE_ROLE)
+                && req_stype != cluster_rpc::SearchType::StorageOnly as i32
+            {
                 wal::search(&session_id1, sql1, stream_type).await
             } else {
-                Ok((HashMap::new(), 0, 0))
+                Ok((HashMap::new(), 0, 0, cluster_rpc::SearchProfile::default()))
             }
         }
         .instrument(wal_span),
-    );
+        abort_wal_reg,
+    ));
 
     // search in object storage
-    let req_stype = req.stype;
     let session_id2 = session_id.clone();
     let sql2 = sql.clone();
     let file_list = req.file_list.to_owned();
     let storage_span = info_span!("service:search:grpc:in_storage");
-    let task2 = tokio::task::spawn(
+    let task2 = tokio::task::spawn(Abortable::new(
         async move {
             if req_stype == cluster_rpc::SearchType::WalOnly as i32 {
-                Ok((HashMap::new(), 0, 0))
+                Ok((HashMap::new(), 0, 0, cluster_rpc::SearchProfile::default()))
             } else {
                 storage::search(&session_id2, sql2, file_list.as_slice(), stream_type).await
             }
         }
         .instrument(storage_span),
-    );
+        abort_storage_reg,
+    ));
 
     // merge data from local WAL
-    let (batches1, file_count1, scan_size1) = match task1.await {
-        Ok(result) => match result {
-            Ok((search_result, file_count, scan_size)) => (search_result, file_count, scan_size),
-            Err(err) => return Err(err),
-        },
+    let (batches1, file_count1, scan_size1, profile1) = match task1.await {
+        Ok(Ok(result)) => result?,
+        Ok(Err(_)) => return Err(cancelled_error(&job.job)),
         Err(err) => {
             return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
                 err.to_string(),
@@ -99,11 +152,9 @@ pub async fn search(
     scan_size += scan_size1;
 
     // merge data from object storage search
-    let (batches2, file_count2, scan_size2) = match task2.await {
-        Ok(result) => match result {
-            Ok((search_result, file_count, scan_size)) => (search_result, file_count, scan_size),
-            Err(err) => return Err(err),
-        },
+    let (batches2, file_count2, scan_size2, mut profile) = match task2.await {
+        Ok(Ok(result)) => result?,
+        Ok(Err(_)) => return Err(cancelled_error(&job.job)),
         Err(err) => {
             return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
                 err.to_string(),
@@ -121,8 +172,12 @@ pub async fn search(
     }
     file_count += file_count2;
     scan_size += scan_size2;
+    profile.wal_took = profile1.wal_took;
+    profile.wal_files = profile1.wal_files;
+    profile.plans.extend(profile1.plans);
 
     // merge all batches
+    let merge_start = std::time::Instant::now();
     let (offset, limit) = (0, sql.meta.offset + sql.meta.limit);
     for (name, batches) in results.iter_mut() {
         let merge_sql = if name == "query" {
@@ -146,10 +201,7 @@ pub async fn search(
             };
     }
 
-    // clear session data
-    datafusion::storage::file_list::clear(&session_id)
-        .await
-        .unwrap();
+    profile.merge_took = merge_start.elapsed().as_millis() as i32;
 
     // final result
     let mut hits_buf = Vec::new();
@@ -209,11 +261,16 @@ pub async fn search(
         scan_size: scan_size as i32,
         hits: hits_buf,
         aggs: aggs_buf,
+        profile: sql.profile.then_some(profile),
     };
 
     Ok(result)
 }
 
+fn cancelled_error(job: &str) -> Error {
+    Error::ErrorCode(ErrorCodes::SearchCancelled(job.to_string()))
+}
+
 pub fn handle_datafusion_error(err: DataFusionError) -> Error {
     let err = err.to_string();
     if err.contains("Schema error: No field named") {
@@ -19,11 +19,13 @@ use std::sync::Arc;
 use tokio::sync::Semaphore;
 use tracing::{info_span, Instrument};
 
+use crate::handler::grpc::cluster_rpc;
 use crate::infra::cache::file_data;
 use crate::infra::config::CONFIG;
 use crate::infra::errors::{Error, ErrorCodes};
 use crate::meta;
 use crate::service::search::datafusion::storage::StorageType;
+use crate::service::search::index;
 use crate::service::search::sql::Sql;
 use crate::service::{db, file_list};
 
@@ -35,15 +37,22 @@ pub async fn search(
     file_list: &[String],
     stream_type: meta::StreamType,
 ) -> super::SearchResult {
+    let mut profile = cluster_rpc::SearchProfile::default();
+
     // get file list
+    let start = std::time::Instant::now();
     let files = match file_list.is_empty() {
         true => get_file_list(&sql, stream_type).await?,
         false => file_list.to_vec(),
     };
+    profile.files = files.len() as i32;
+    let files = index::filter_files(&sql, files).await;
     let file_count = files.len();
+    profile.files_scanned = file_count as i32;
+    profile.file_list_took = start.elapsed().as_millis() as i32;
 
     if file_count == 0 {
-        return Ok((HashMap::new(), 0, 0));
+        return Ok((HashMap::new(), 0, 0, profile));
     }
 
     // fetch all schema versions, group files by version
@@ -128,13 +137,16 @@ pub async fn search(
 
     // load files to local cache
     if storage_type == StorageType::FsMemory {
+        let start = std::time::Instant::now();
         cache_parquet_files(&files).await?;
+        profile.cache_took = start.elapsed().as_millis() as i32;
         log::info!(
             "search->storage: load files {}, into memory cache done",
             file_count
         );
     }
 
+    let start = std::time::Instant::now();
     let mut tasks = Vec::new();
     for (ver, files) in files_group {
         let schema = Arc::new(
@@ -182,11 +194,18 @@ pub async fn search(
     for task in tasks {
         match task.await {
             Ok(ret) => match ret {
-                Ok(ret) => {
+                Ok((ret, plans)) => {
                     for (k, v) in ret {
                         let group = results.entry(k).or_insert_with(Vec::new);
                         group.extend(v);
                     }
+                    profile.plans.extend(plans.into_iter().map(|(name, plan)| {
+                        cluster_rpc::SearchPlan {
+                            source: "storage".to_string(),
+                            name,
+                            plan,
+                        }
+                    }));
                 }
                 Err(err) => {
                     log::error!("datafusion execute error: {}", err);
@@ -201,7 +220,9 @@ pub async fn search(
         };
     }
 
-    Ok((results, file_count, scan_original_size as usize))
+    profile.scan_took = start.elapsed().as_millis() as i32;
+
+    Ok((results, file_count, scan_original_size as usize, profile))
 }
 
 #[tracing::instrument(name = "service:search:grpc:storage:get_file_list", skip_all)]
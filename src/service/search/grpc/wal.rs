@@ -21,6 +21,7 @@ use std::{
 };
 
 use crate::common::file::{get_file_contents, get_file_meta, scan_files};
+use crate::handler::grpc::cluster_rpc;
 use crate::infra::{
     cache::tmpfs,
     config::{self, CONFIG},
@@ -40,6 +41,9 @@ pub async fn search(
     sql: Arc<Sql>,
     stream_type: meta::StreamType,
 ) -> super::SearchResult {
+    let start = std::time::Instant::now();
+    let mut profile = cluster_rpc::SearchProfile::default();
+
     // mark searching in WAL
     let searching = Searching::new();
 
@@ -71,7 +75,7 @@ pub async fn search(
 
     let file_count = files.len();
     if file_count == 0 {
-        return Ok((HashMap::new(), 0, 0));
+        return Ok((HashMap::new(), 0, 0, profile));
     }
     log::info!("wal->search: load files {file_count}, scan_size {scan_size}");
 
@@ -95,7 +99,7 @@ pub async fn search(
         id: session_id.to_string(),
         storage_type: StorageType::Tmpfs,
     };
-    let result = match super::datafusion::exec::sql(
+    let (result, plans) = match super::datafusion::exec::sql(
         &session,
         stream_type,
         Some(schema),
@@ -119,7 +123,18 @@ pub async fn search(
     // clear tmpfs
     tmpfs::delete(&format!("/{}/", session_id), true).unwrap();
 
-    Ok((result, file_count, scan_size))
+    profile.wal_took = start.elapsed().as_millis() as i32;
+    profile.wal_files = file_count as i32;
+    profile.plans = plans
+        .into_iter()
+        .map(|(name, plan)| cluster_rpc::SearchPlan {
+            source: "wal".to_string(),
+            name,
+            plan,
+        })
+        .collect();
+
+    Ok((result, file_count, scan_size, profile))
 }
 
 /// get file list from local wal, no need match_source, each file will be searched
@@ -0,0 +1,510 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Full-text index files, stored next to the parquet files they describe.
+//!
+//! `match_all('foo')` is rewritten into `LIKE '%foo%'` over the full-text
+//! fields, so a record can only match if those fields contain every trigram
+//! of `foo`. The index is a bloom filter of the trigrams of a file, which
+//! lets a search skip the files that can't contain a term.
+//!
+//! Trigrams are ASCII-lowercased and only trigrams made of ASCII characters
+//! are indexed, which keeps `ILIKE` and non-ASCII text safe: they are simply
+//! not used to skip files.
+
+use bytes::Bytes;
+use chrono::Utc;
+use dashmap::DashMap;
+use datafusion::arrow::{
+    array::{Array, StringArray},
+    datatypes::Schema,
+    record_batch::RecordBatch,
+};
+use futures::{stream, StreamExt};
+use once_cell::sync::Lazy;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+use crate::infra::{cache::file_data, config::CONFIG, storage};
+use crate::meta::StreamType;
+use crate::service::{db, search::sql::Sql, stream::get_stream_setting_fts_fields};
+
+const MAGIC: &[u8; 4] = b"ZOFI";
+const VERSION: u8 = 1;
+
+/// Number of distinct ASCII trigrams, the size of the exact bitmap.
+const TRIGRAM_SPACE: usize = 1 << 21;
+/// Bloom filter bits per trigram, for a false positive rate of 1%.
+const BITS_PER_TRIGRAM: usize = 10;
+const NUM_HASHES: u8 = 7;
+
+/// How long an index file is known not to exist, microseconds.
+const MISSING_TTL: i64 = 3_600_000_000;
+/// Maximum number of index files known not to exist.
+const MISSING_MAX_ENTRIES: usize = 100_000;
+
+/// key — index files which don't exist, e.g. of files written by older
+/// versions; value — when it was found missing, microseconds.
+static MISSING: Lazy<DashMap<String, i64>> = Lazy::new(DashMap::new);
+
+#[derive(Debug, PartialEq)]
+pub struct FulltextIndex {
+    /// The fields whose trigrams are in the index.
+    fields: Vec<String>,
+    /// The other fields of the file. A query which searches any of them
+    /// can't use the index.
+    skipped_fields: Vec<String>,
+    bloom: BloomFilter,
+}
+
+impl FulltextIndex {
+    /// Whether a record of the file may contain all `trigrams` in one of
+    /// `fields`.
+    fn may_contain(&self, fields: &[String], trigrams: &[u32]) -> bool {
+        if fields.iter().any(|f| self.skipped_fields.contains(f)) {
+            return true;
+        }
+        trigrams.iter().all(|t| self.bloom.contains(*t))
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.bloom.bits.len() * 8 + 64);
+        buf.extend_from_slice(MAGIC);
+        buf.push(VERSION);
+        for fields in [&self.fields, &self.skipped_fields] {
+            buf.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+            for field in fields.iter() {
+                buf.extend_from_slice(&(field.len() as u32).to_le_bytes());
+                buf.extend_from_slice(field.as_bytes());
+            }
+        }
+        buf.push(self.bloom.num_hashes);
+        buf.extend_from_slice(&(self.bloom.bits.len() as u32).to_le_bytes());
+        for word in self.bloom.bits.iter() {
+            buf.extend_from_slice(&word.to_le_bytes());
+        }
+        buf
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut r = Reader(data);
+        if r.take(4)? != MAGIC || r.take(1)?[0] != VERSION {
+            return None;
+        }
+        let mut read_fields = || -> Option<Vec<String>> {
+            let n = r.read_u32()?;
+            (0..n)
+                .map(|_| {
+                    let len = r.read_u32()? as usize;
+                    String::from_utf8(r.take(len)?.to_vec()).ok()
+                })
+                .collect()
+        };
+        let fields = read_fields()?;
+        let skipped_fields = read_fields()?;
+        let num_hashes = r.take(1)?[0];
+        let words = r.read_u32()? as usize;
+        let bits = r
+            .take(words * 8)?
+            .chunks_exact(8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        if bits.is_empty() || (num_hashes == 0 && bits.len() * 64 != TRIGRAM_SPACE) {
+            return None;
+        }
+        Some(Self {
+            fields,
+            skipped_fields,
+            bloom: BloomFilter { num_hashes, bits },
+        })
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Some(head)
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+/// Bloom filter of trigrams. With zero hashes it is an exact bitmap of all
+/// the possible trigrams instead, which is smaller than a bloom filter for
+/// files with a lot of distinct trigrams.
+#[derive(Debug, PartialEq)]
+struct BloomFilter {
+    num_hashes: u8,
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    /// Builds the filter from the bitmap of the trigrams of a file.
+    fn from_trigrams(trigrams: Vec<u64>) -> Self {
+        let count = trigrams
+            .iter()
+            .map(|w| w.count_ones() as usize)
+            .sum::<usize>();
+        let num_bits = count * BITS_PER_TRIGRAM;
+        if num_bits >= TRIGRAM_SPACE {
+            return Self {
+                num_hashes: 0,
+                bits: trigrams,
+            };
+        }
+        let mut bloom = Self {
+            num_hashes: NUM_HASHES,
+            bits: vec![0; num_bits / 64 + 1],
+        };
+        for (i, word) in trigrams.iter().enumerate() {
+            let mut word = *word;
+            while word != 0 {
+                let bit = word.trailing_zeros();
+                bloom.insert((i * 64) as u32 + bit);
+                word &= word - 1;
+            }
+        }
+        bloom
+    }
+
+    fn insert(&mut self, trigram: u32) {
+        for pos in self.positions(trigram) {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+    }
+
+    fn contains(&self, trigram: u32) -> bool {
+        self.positions(trigram)
+            .all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    /// Double hashing, see Kirsch and Mitzenmacher, "Less Hashing, Same
+    /// Performance: Building a Better Bloom Filter".
+    fn positions(&self, trigram: u32) -> impl Iterator<Item = usize> {
+        let num_bits = self.bits.len() as u64 * 64;
+        let (num_hashes, h1, h2) = if self.num_hashes == 0 {
+            (1, trigram as u64, 0)
+        } else {
+            let h = mix64(trigram as u64);
+            (self.num_hashes as u64, h & 0xffff_ffff, (h >> 32) | 1)
+        };
+        (0..num_hashes).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+}
+
+/// The finalizer of SplitMix64. The hash has to be stable across versions
+/// and nodes, as the filters are persisted.
+fn mix64(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Calls `f` with every ASCII trigram of `text`, lowercased.
+fn for_each_trigram(text: &[u8], mut f: impl FnMut(u32)) {
+    for w in text.windows(3) {
+        if w.iter().all(|b| b.is_ascii()) {
+            let [a, b, c] = [w[0], w[1], w[2]].map(|b| b.to_ascii_lowercase() as u32);
+            f((a << 14) | (b << 7) | c);
+        }
+    }
+}
+
+/// Returns the trigrams any text matching `%term%` contains. The `LIKE`
+/// wildcards and the escape character can stand for anything, so trigrams
+/// spanning them are left out.
+fn term_trigrams(term: &str) -> Vec<u32> {
+    let mut trigrams = Vec::new();
+    for part in term.split(['%', '_', '\\']) {
+        for_each_trigram(part.as_bytes(), |t| trigrams.push(t));
+    }
+    trigrams
+}
+
+/// The key of the index file of a parquet file.
+pub fn index_key(file: &str) -> String {
+    let name = file
+        .strip_suffix(&CONFIG.common.file_ext_parquet)
+        .unwrap_or(file);
+    format!("{name}.idx")
+}
+
+/// Builds the full-text index of a file, returns `None` if the feature is
+/// disabled.
+pub async fn build(
+    org_id: &str,
+    stream_name: &str,
+    stream_type: StreamType,
+    schema: &Schema,
+    batches: &[RecordBatch],
+) -> Option<Vec<u8>> {
+    if !CONFIG.common.feature_fulltext_index {
+        return None;
+    }
+    // the full-text search fields are stream settings
+    let stream_schema = db::schema::get(org_id, stream_name, Some(stream_type))
+        .await
+        .unwrap_or_else(|_| Schema::empty());
+    let fts_fields = get_stream_setting_fts_fields(&stream_schema).unwrap_or_default();
+    let fields = super::sql::get_fulltext_fields(schema.fields(), &fts_fields);
+    let skipped_fields = schema
+        .fields()
+        .iter()
+        .map(|f| f.name().to_string())
+        .filter(|name| !fields.contains(name))
+        .collect();
+
+    let mut trigrams = vec![0u64; TRIGRAM_SPACE / 64];
+    for batch in batches {
+        for field in fields.iter() {
+            let Some(column) = batch.column_by_name(field) else {
+                continue;
+            };
+            let Some(values) = column.as_any().downcast_ref::<StringArray>() else {
+                continue;
+            };
+            for i in 0..values.len() {
+                if values.is_null(i) {
+                    continue;
+                }
+                for_each_trigram(values.value(i).as_bytes(), |t| {
+                    trigrams[t as usize / 64] |= 1 << (t % 64);
+                });
+            }
+        }
+    }
+
+    let index = FulltextIndex {
+        fields,
+        skipped_fields,
+        bloom: BloomFilter::from_trigrams(trigrams),
+    };
+    Some(index.encode())
+}
+
+/// Builds the full-text index of a parquet file.
+pub async fn build_from_parquet(
+    org_id: &str,
+    stream_name: &str,
+    stream_type: StreamType,
+    data: Bytes,
+) -> Result<Option<Vec<u8>>, anyhow::Error> {
+    if !CONFIG.common.feature_fulltext_index {
+        return Ok(None);
+    }
+    let reader = ParquetRecordBatchReaderBuilder::try_new(data)?.build()?;
+    let schema = reader.schema();
+    let batches = reader.collect::<Result<Vec<_>, _>>()?;
+    Ok(build(org_id, stream_name, stream_type, &schema, &batches).await)
+}
+
+/// Uploads the full-text index of a file. The index is optional, so errors
+/// are only logged.
+pub async fn upload(file: &str, index: Vec<u8>) {
+    let key = index_key(file);
+    if let Err(e) = storage::put(&key, index.into()).await {
+        log::error!("upload fulltext index {} error: {}", key, e);
+    }
+}
+
+async fn load(file: &str) -> Option<FulltextIndex> {
+    let key = index_key(file);
+    let now = Utc::now().timestamp_micros();
+    if is_missing(&key, now) {
+        return None;
+    }
+    let data = match file_data::get(&key) {
+        Ok(data) => data,
+        Err(_) => match storage::get(&key).await {
+            Ok(data) => {
+                let _ = file_data::set(&key, data.clone());
+                data
+            }
+            Err(e) => {
+                if is_not_found(&e) {
+                    set_missing(key, now);
+                } else {
+                    log::error!("load fulltext index {} error: {}", key, e);
+                }
+                return None;
+            }
+        },
+    };
+    FulltextIndex::decode(&data)
+}
+
+fn is_missing(key: &str, now: i64) -> bool {
+    match MISSING.get(key).map(|v| *v) {
+        Some(missing_at) if now - missing_at < MISSING_TTL => true,
+        Some(_) => {
+            MISSING.remove(key);
+            false
+        }
+        None => false,
+    }
+}
+
+fn set_missing(key: String, now: i64) {
+    if MISSING.len() >= MISSING_MAX_ENTRIES {
+        MISSING.retain(|_, missing_at| now - *missing_at < MISSING_TTL);
+        if MISSING.len() >= MISSING_MAX_ENTRIES {
+            MISSING.clear();
+        }
+    }
+    MISSING.insert(key, now);
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<object_store::Error>(),
+        Some(object_store::Error::NotFound { .. })
+    )
+}
+
+/// Removes the files which can't contain the required `match_all` terms of
+/// `sql`. Files without an index are kept.
+#[tracing::instrument(name = "service:search:index:filter_files", skip_all)]
+pub async fn filter_files(sql: &Sql, files: Vec<String>) -> Vec<String> {
+    if !CONFIG.common.feature_fulltext_index || sql.fulltext_terms.is_empty() {
+        return files;
+    }
+    let mut trigrams = sql
+        .fulltext_terms
+        .iter()
+        .flat_map(|term| term_trigrams(term))
+        .collect::<Vec<_>>();
+    if trigrams.is_empty() {
+        return files;
+    }
+    trigrams.sort_unstable();
+    trigrams.dedup();
+
+    let total = files.len();
+    let trigrams = &trigrams;
+    let files = stream::iter(files)
+        .map(|file| async move {
+            let keep = match load(&file).await {
+                Some(index) => index.may_contain(&sql.fulltext_fields, trigrams),
+                None => true,
+            };
+            (file, keep)
+        })
+        .buffered(CONFIG.limit.query_thread_num)
+        .filter_map(|(file, keep)| async move { keep.then_some(file) })
+        .collect::<Vec<_>>()
+        .await;
+    log::info!(
+        "search->index: skipped {} of {} files by fulltext index",
+        total - files.len(),
+        total
+    );
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigrams_of(texts: &[&str]) -> Vec<u64> {
+        let mut trigrams = vec![0u64; TRIGRAM_SPACE / 64];
+        for text in texts {
+            for_each_trigram(text.as_bytes(), |t| {
+                trigrams[t as usize / 64] |= 1 << (t % 64)
+            });
+        }
+        trigrams
+    }
+
+    #[test]
+    fn test_bloom_filter() {
+        let bloom = BloomFilter::from_trigrams(trigrams_of(&["GET /api/users 200", "timeout"]));
+        assert_eq!(bloom.num_hashes, NUM_HASHES);
+        for term in ["api/user", "TimeOut", "users 2", "%out"] {
+            let trigrams = term_trigrams(term);
+            assert!(trigrams.iter().all(|t| bloom.contains(*t)), "{term}");
+        }
+        assert!(!term_trigrams("panic").iter().all(|t| bloom.contains(*t)));
+        assert!(term_trigrams("a_c").is_empty());
+        assert!(term_trigrams("héé").is_empty());
+    }
+
+    #[test]
+    fn test_exact_bitmap() {
+        // every printable ASCII trigram is too many for a bloom filter
+        let text = (0x20u8..0x7f).collect::<Vec<_>>();
+        let texts = (0x20u8..0x7f)
+            .flat_map(|a| (0x20u8..0x7f).map(move |b| [a, b]))
+            .map(|[a, b]| {
+                let mut t = vec![a, b];
+                t.extend_from_slice(&text);
+                String::from_utf8(t).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let texts = texts.iter().map(|t| t.as_str()).collect::<Vec<_>>();
+        let bloom = BloomFilter::from_trigrams(trigrams_of(&texts));
+        assert_eq!(bloom.num_hashes, 0);
+        assert!(term_trigrams("abc").iter().all(|t| bloom.contains(*t)));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let index = FulltextIndex {
+            fields: vec!["log".to_string(), "message".to_string()],
+            skipped_fields: vec!["code".to_string()],
+            bloom: BloomFilter::from_trigrams(trigrams_of(&["connection refused"])),
+        };
+        let decoded = FulltextIndex::decode(&index.encode()).unwrap();
+        assert_eq!(decoded, index);
+        assert!(FulltextIndex::decode(&index.encode()[..20]).is_none());
+
+        let fields = vec!["log".to_string()];
+        assert!(decoded.may_contain(&fields, &term_trigrams("refused")));
+        assert!(!decoded.may_contain(&fields, &term_trigrams("accepted")));
+        // `code` isn't indexed, so it may contain anything
+        let fields = vec!["log".to_string(), "code".to_string()];
+        assert!(decoded.may_contain(&fields, &term_trigrams("accepted")));
+    }
+
+    #[test]
+    fn test_index_key() {
+        assert_eq!(
+            index_key("files/default/logs/olympics/2022/10/03/10/6982652937134804993_1.parquet"),
+            "files/default/logs/olympics/2022/10/03/10/6982652937134804993_1.idx"
+        );
+    }
+
+    #[test]
+    fn test_missing() {
+        let key = "files/default/logs/test_missing/1.idx";
+        assert!(!is_missing(key, 0));
+        set_missing(key.to_string(), 0);
+        assert!(is_missing(key, MISSING_TTL - 1));
+        assert!(!is_missing(key, MISSING_TTL));
+        assert!(!MISSING.contains_key(key));
+
+        let not_found = anyhow::Error::from(object_store::Error::NotFound {
+            path: key.to_string(),
+            source: "not found".into(),
+        });
+        assert!(is_not_found(&not_found));
+        assert!(!is_not_found(&anyhow::anyhow!("connection reset")));
+    }
+}
@@ -0,0 +1,380 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Search jobs run long searches in the background.
+//!
+//! The files of a job are searched in chunks, so that no request to a querier
+//! runs into the gRPC timeout, and the progress is stored in the metadata
+//! after every chunk. The response is stored in object storage. Cancelling a
+//! job aborts its running searches on every node.
+//!
+//! The status of a job is checked and set under a lock, so a job cancelled
+//! while it finishes stays cancelled. A job whose node went offline before it
+//! finished is marked as failed.
+
+use ::datafusion::arrow::record_batch::RecordBatch;
+use ahash::AHashMap as HashMap;
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use std::time::Duration;
+use tokio::{sync::Mutex, time};
+use tonic::{metadata::MetadataValue, transport::Channel, Request};
+
+use super::{server_internal_error, Priority};
+use crate::common::json;
+use crate::handler::grpc::cluster_rpc;
+use crate::infra::{
+    cluster,
+    config::CONFIG,
+    db::etcd,
+    errors::{Error, ErrorCodes},
+    ider, storage,
+};
+use crate::meta::{
+    search::{Job, JobStatus, Request as SearchRequest},
+    StreamType,
+};
+use crate::service::db;
+
+/// Serializes the status updates of jobs in local mode, where there is no
+/// cluster lock.
+static LOCAL_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// The object storage key of the response of a job.
+fn result_key(org_id: &str, id: &str) -> String {
+    format!("search_jobs/{org_id}/{id}.json")
+}
+
+/// Stores a new search job and runs it in the background.
+#[tracing::instrument(name = "service:search:job:submit", skip(req))]
+pub async fn submit(
+    org_id: &str,
+    stream_type: StreamType,
+    req: &SearchRequest,
+) -> Result<Job, Error> {
+    // check the query and its cost before accepting the job
+    let mut cluster_req: cluster_rpc::SearchRequest = req.to_owned().into();
+    cluster_req.org_id = org_id.to_string();
+    cluster_req.stream_type = stream_type.to_string();
+    super::sql::Sql::new(&cluster_req).await?;
+    super::cost::check(&cluster_req).await?;
+
+    let job = Job {
+        id: ider::generate(),
+        stream_type,
+        request: req.to_owned(),
+        status: JobStatus::Pending,
+        node: cluster::LOCAL_NODE_UUID.to_string(),
+        created_at: chrono::Utc::now().timestamp_micros(),
+        started_at: 0,
+        finished_at: 0,
+        total_files: 0,
+        scanned_files: 0,
+        scan_size: 0,
+        total: 0,
+        error: None,
+    };
+    db::search_jobs::set(org_id, &job)
+        .await
+        .map_err(server_internal_error)?;
+
+    let org_id = org_id.to_string();
+    let running = job.clone();
+    tokio::task::spawn(async move { run(&org_id, running).await });
+    Ok(job)
+}
+
+async fn run(org_id: &str, mut job: Job) {
+    job.status = JobStatus::Running;
+    job.started_at = chrono::Utc::now().timestamp_micros();
+    if let Err(e) = update_progress(org_id, &job).await {
+        log::error!("search->job: {} update error: {}", job.id, e);
+        return;
+    }
+
+    let result = search(org_id, &mut job).await;
+    job.finished_at = chrono::Utc::now().timestamp_micros();
+    match result {
+        Ok(_) => job.status = JobStatus::Finished,
+        Err(Error::ErrorCode(ErrorCodes::SearchCancelled(_))) => job.status = JobStatus::Cancelled,
+        Err(e) => {
+            log::error!("search->job: {} error: {}", job.id, e);
+            job.status = JobStatus::Failed;
+            job.error = Some(match e {
+                Error::ErrorCode(code) => code.get_message(),
+                e => e.to_string(),
+            });
+        }
+    }
+    // a job cancelled while it finished stays cancelled
+    match set_unless_cancelled(org_id, &job).await {
+        Ok(true) => log::info!(
+            "search->job: {} {:?}, files: {}/{}, scan_size: {}",
+            job.id,
+            job.status,
+            job.scanned_files,
+            job.total_files,
+            job.scan_size
+        ),
+        Ok(false) => log::info!("search->job: {} cancelled", job.id),
+        Err(e) => log::error!("search->job: {} update error: {}", job.id, e),
+    }
+}
+
+async fn search(org_id: &str, job: &mut Job) -> Result<(), Error> {
+    let start = std::time::Instant::now();
+    let mut req: cluster_rpc::SearchRequest = job.request.clone().into();
+    req.org_id = org_id.to_string();
+    req.stype = cluster_rpc::SearchType::User as i32;
+    req.stream_type = job.stream_type.to_string();
+    req.job.as_mut().unwrap().job = job.id.clone();
+    let query = req.query.as_mut().unwrap();
+    let query_type = query.query_type.to_lowercase();
+    // the chunks are merged with each other, so the offset is applied at the end
+    let (from, size) = (query.from as usize, query.size as usize);
+    query.size += query.from;
+    query.from = 0;
+    if query.end_time == 0 {
+        query.end_time = chrono::Utc::now().timestamp_micros();
+    }
+    let sql = super::sql::Sql::new(&req).await?;
+
+    let files = super::get_file_list(&sql, job.stream_type).await;
+    job.total_files = files.len();
+    update_progress(org_id, job).await?;
+
+    let chunks = if files.is_empty() {
+        vec![&files[..]]
+    } else {
+        files
+            .chunks(CONFIG.limit.search_job_chunk_files.max(1))
+            .collect()
+    };
+    let mut batches: HashMap<String, Vec<Vec<RecordBatch>>> = HashMap::new();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let mut req = req.clone();
+        req.file_list = chunk.to_vec();
+        // the WAL is searched with the first chunk only
+        if i > 0 {
+            req.stype = cluster_rpc::SearchType::StorageOnly as i32;
+        }
+        let res = super::search_in_cluster_batches(req, Priority::Background).await?;
+        for (name, batch) in res.batches {
+            batches.entry(name).or_default().extend(batch);
+        }
+        super::merge_batches(&sql, &mut batches).await?;
+
+        job.scanned_files += chunk.len();
+        job.scan_size += res.scan_size;
+        update_progress(org_id, job).await?;
+    }
+
+    let mut res = super::build_response(&sql, batches, &query_type)?;
+    res.hits.drain(..from.min(res.hits.len()));
+    res.from = from;
+    res.size = size;
+    res.took = start.elapsed().as_millis() as usize;
+    res.set_file_count(job.scanned_files);
+    res.set_scan_size(job.scan_size);
+    job.total = res.total;
+
+    let data = json::to_vec(&res).map_err(server_internal_error)?;
+    storage::put(&result_key(org_id, &job.id), data.into())
+        .await
+        .map_err(server_internal_error)
+}
+
+/// Stores the progress of a running job, unless it was cancelled.
+async fn update_progress(org_id: &str, job: &Job) -> Result<(), Error> {
+    match set_unless_cancelled(org_id, job).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Error::ErrorCode(ErrorCodes::SearchCancelled(
+            job.id.clone(),
+        ))),
+        Err(e) => Err(server_internal_error(e)),
+    }
+}
+
+/// Stores a job unless it was cancelled or deleted, returns false if it was.
+async fn set_unless_cancelled(org_id: &str, job: &Job) -> Result<bool, anyhow::Error> {
+    update_locked(org_id, &job.id, |stored| match stored {
+        Some(stored) if stored.status != JobStatus::Cancelled => Some(job.clone()),
+        _ => None,
+    })
+    .await
+    .map(|updated| updated.is_some())
+}
+
+/// Reads a job and stores the job returned by `update`, if any, under the
+/// lock of the job. Returns the stored job.
+async fn update_locked<F>(org_id: &str, id: &str, update: F) -> Result<Option<Job>, anyhow::Error>
+where
+    F: FnOnce(Option<Job>) -> Option<Job>,
+{
+    let mut locker = None;
+    let _local = if CONFIG.common.local_mode {
+        Some(LOCAL_LOCK.lock().await)
+    } else {
+        // get a cluster lock for the job
+        let mut lock = etcd::Locker::new(&format!("search_jobs/{org_id}/{id}"));
+        lock.lock(0).await?;
+        locker = Some(lock);
+        None
+    };
+
+    let ret = match db::search_jobs::get(org_id, id).await {
+        Ok(stored) => match update(stored) {
+            Some(job) => db::search_jobs::set(org_id, &job).await.map(|_| Some(job)),
+            None => Ok(None),
+        },
+        Err(e) => Err(e),
+    };
+
+    if let Some(mut lock) = locker {
+        // release cluster lock
+        lock.unlock().await?;
+    }
+    ret
+}
+
+pub async fn get(org_id: &str, id: &str) -> Result<Option<Job>, anyhow::Error> {
+    db::search_jobs::get(org_id, id).await
+}
+
+pub async fn list(org_id: &str) -> Result<Vec<Job>, anyhow::Error> {
+    db::search_jobs::list(org_id).await
+}
+
+/// Returns the response of a finished job.
+pub async fn get_result(org_id: &str, id: &str) -> Result<Option<Bytes>, anyhow::Error> {
+    match db::search_jobs::get(org_id, id).await? {
+        Some(job) if job.status == JobStatus::Finished => {
+            Ok(Some(storage::get(&result_key(org_id, id)).await?))
+        }
+        Some(job) => Err(anyhow::anyhow!(
+            "search job {id} is {:?}, it has no result",
+            job.status
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Cancels a job and aborts its running searches.
+#[tracing::instrument(name = "service:search:job:cancel")]
+pub async fn cancel(org_id: &str, id: &str) -> Result<Option<Job>, anyhow::Error> {
+    let mut done = None;
+    let cancelled = update_locked(org_id, id, |stored| {
+        let mut job = stored?;
+        if job.status.is_done() {
+            done = Some(job);
+            return None;
+        }
+        job.status = JobStatus::Cancelled;
+        job.finished_at = chrono::Utc::now().timestamp_micros();
+        Some(job)
+    })
+    .await?;
+    if cancelled.is_some() {
+        cancel_in_cluster(org_id, id).await;
+    }
+    Ok(cancelled.or(done))
+}
+
+/// Deletes a job and its response, returns false if it doesn't exist.
+pub async fn delete(org_id: &str, id: &str) -> Result<bool, anyhow::Error> {
+    let Some(job) = cancel(org_id, id).await? else {
+        return Ok(false);
+    };
+    if job.status == JobStatus::Finished {
+        storage::del(&[&result_key(org_id, id)]).await?;
+    }
+    db::search_jobs::delete(org_id, id).await?;
+    Ok(true)
+}
+
+/// Marks the unfinished jobs of nodes which are offline as failed, their
+/// searches stopped with the node.
+pub async fn run_sweeper() {
+    let mut interval = time::interval(time::Duration::from_secs(60));
+    interval.tick().await; // trigger the first run
+    loop {
+        interval.tick().await;
+        let jobs = match db::search_jobs::list_all().await {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                log::error!("search->job: list jobs error: {}", e);
+                continue;
+            }
+        };
+        let Some(nodes) = cluster::get_cached_online_nodes() else {
+            continue;
+        };
+        for (org_id, job) in jobs {
+            if job.status.is_done() || nodes.iter().any(|node| node.uuid == job.node) {
+                continue;
+            }
+            let failed = update_locked(&org_id, &job.id, |stored| {
+                let mut job = stored.filter(|job| !job.status.is_done())?;
+                job.status = JobStatus::Failed;
+                job.finished_at = chrono::Utc::now().timestamp_micros();
+                job.error = Some(format!("node {} of the job is offline", job.node));
+                Some(job)
+            })
+            .await;
+            match failed {
+                Ok(Some(_)) => log::info!("search->job: {} failed, its node is offline", job.id),
+                Ok(None) => {}
+                Err(e) => log::error!("search->job: {} update error: {}", job.id, e),
+            }
+        }
+    }
+}
+
+/// Sends the cancel request of a job to every node which may run its
+/// searches.
+async fn cancel_in_cluster(org_id: &str, id: &str) {
+    let nodes = cluster::get_cached_online_query_nodes().unwrap_or_default();
+    let tasks = nodes.into_iter().map(|node| async move {
+        let canceled = async {
+            let token: MetadataValue<_> = cluster::get_internal_grpc_token().parse()?;
+            let org_id: MetadataValue<_> = org_id.parse()?;
+            let channel = Channel::from_shared(node.grpc_addr.clone())?
+                .connect()
+                .await?;
+            let mut client = cluster_rpc::search_client::SearchClient::with_interceptor(
+                channel,
+                move |mut req: Request<()>| {
+                    req.metadata_mut().insert("authorization", token.clone());
+                    req.metadata_mut()
+                        .insert(CONFIG.grpc.org_header_key.as_str(), org_id.clone());
+                    Ok(req)
+                },
+            );
+            let mut request = Request::new(cluster_rpc::CancelRequest {
+                job: id.to_string(),
+            });
+            request.set_timeout(Duration::from_secs(CONFIG.grpc.timeout));
+            let response = client.cancel(request).await?;
+            Ok::<_, anyhow::Error>(response.into_inner().canceled)
+        };
+        match canceled.await {
+            Ok(n) => log::info!(
+                "search->job: {id} cancelled {n} searches on node {}",
+                node.id
+            ),
+            Err(e) => log::error!("search->job: {id} cancel on node {} error: {}", node.id, e),
+        }
+    });
+    futures::future::join_all(tasks).await;
+}
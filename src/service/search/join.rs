@@ -0,0 +1,852 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JOIN and UNION queries across the streams of an organization.
+//!
+//! The queriers can't run a query which reads more than one stream on their
+//! part of the files, so the query is run in two steps:
+//!
+//! 1. every stream the query reads is searched on its own, as a full mode
+//!    search of the columns the query may read, with the filters of the query
+//!    which only read this stream. It has its own file list and partition
+//!    pruning, and is spread across the queriers and the ingesters like any
+//!    search.
+//! 2. the leader registers the records of every stream as a table and runs the
+//!    query on them.
+//!
+//! The streams have the stream type of the search, unless the name is
+//! qualified with a stream type, like `traces.default`. The streams of
+//! subqueries, like `IN (SELECT ...)`, are searched the same way.
+
+use ::datafusion::arrow::{
+    datatypes::{Schema, SchemaRef},
+    json as arrow_json,
+    record_batch::RecordBatch,
+};
+use futures::future::try_join_all;
+use sqlparser::{
+    ast::{
+        BinaryOperator, Expr, FunctionArg, FunctionArgExpr, Ident, JoinOperator, ObjectName, Query,
+        Select, SelectItem, SetExpr, Statement, TableAlias, TableFactor, TableWithJoins, Visit,
+        VisitMut, Visitor, VisitorMut,
+    },
+    dialect::GenericDialect,
+    parser::Parser,
+};
+use std::ops::ControlFlow;
+
+use super::{
+    cost,
+    datafusion::exec,
+    export::{align_batch, nullable_schema},
+    server_internal_error, Priority,
+};
+use crate::common::json;
+use crate::handler::grpc::cluster_rpc;
+use crate::infra::{
+    config::CONFIG,
+    errors::{Error, ErrorCodes},
+};
+use crate::meta::{search, StreamType};
+use crate::service::db;
+
+/// A stream read by a query, with the filters of the query which only read
+/// this stream.
+#[derive(Clone, Debug, PartialEq)]
+struct JoinTable {
+    stream_type: StreamType,
+    stream_name: String,
+    filters: Vec<String>,
+    /// The columns the query may read, all of them if None. The names may
+    /// also be aliases or columns of other streams, so only the ones in the
+    /// schema of the stream are searched.
+    columns: Option<Vec<String>>,
+}
+
+/// A query rewritten to read the tables `tbl_0`, `tbl_1`, ..., one for every
+/// element of `tables`.
+#[derive(Debug)]
+struct JoinPlan {
+    sql: String,
+    tables: Vec<JoinTable>,
+    has_limit: bool,
+}
+
+/// The records of a stream searched for a query.
+struct TableBatches {
+    schema: SchemaRef,
+    batches: Vec<RecordBatch>,
+    file_count: usize,
+    scan_size: usize,
+    took_wait: usize,
+}
+
+/// Returns true if the query of the search reads more than one stream, or
+/// one stream more than once.
+pub(crate) fn is_join(req: &cluster_rpc::SearchRequest) -> bool {
+    let stream_type = StreamType::from(req.stream_type.as_str());
+    !matches!(plan(&query_sql(req), stream_type), Ok(None))
+}
+
+#[tracing::instrument(name = "service:search:join", skip_all)]
+pub(crate) async fn search(
+    req: cluster_rpc::SearchRequest,
+    priority: Priority,
+) -> Result<search::Response, Error> {
+    let start = std::time::Instant::now();
+    let stream_type = StreamType::from(req.stream_type.as_str());
+    let Some(plan) = plan(&query_sql(&req), stream_type)? else {
+        return Err(sql_not_valid("Query SQL doesn't read more than one stream"));
+    };
+    let query = req.query.as_ref().unwrap();
+    if !req.aggs.is_empty() {
+        return Err(sql_not_valid(
+            "JOIN and UNION queries don't support aggs, please use GROUP BY in the query",
+        ));
+    }
+    if !query.query_fn.is_empty() {
+        return Err(sql_not_valid(
+            "JOIN and UNION queries don't support query functions",
+        ));
+    }
+
+    let results = try_join_all(
+        plan.tables
+            .iter()
+            .map(|table| search_table(&req, table, priority)),
+    )
+    .await?;
+
+    let mut file_count = 0;
+    let mut scan_size = 0;
+    let mut took_wait = 0;
+    let mut tables = Vec::with_capacity(results.len());
+    for (i, result) in results.into_iter().enumerate() {
+        file_count += result.file_count;
+        scan_size += result.scan_size;
+        took_wait += result.took_wait;
+        tables.push((format!("tbl_{i}"), result.schema, result.batches));
+    }
+    let batches = exec::query_batches(&req.org_id, tables, &plan.sql).await?;
+
+    let json_rows =
+        arrow_json::writer::record_batches_to_json_rows(&batches).map_err(server_internal_error)?;
+    let total = json_rows.len();
+    let (from, size) = (query.from as usize, query.size as usize);
+    // a query without a limit is paged with the size of the search
+    let (skip, take) = if plan.has_limit {
+        (0, total)
+    } else {
+        (from, size)
+    };
+    let mut result = search::Response::new(from, size);
+    for row in json_rows.into_iter().skip(skip).take(take) {
+        result.add_hit(&json::Value::Object(row));
+    }
+    result.set_total(total);
+    result.set_cluster_took(start.elapsed().as_millis() as usize, took_wait);
+    result.set_file_count(file_count);
+    result.set_scan_size(scan_size);
+
+    log::info!(
+        "search->join: streams: {}, total: {}, took: {}, scan_size: {}",
+        plan.tables.len(),
+        result.total,
+        result.took,
+        result.scan_size,
+    );
+
+    Ok(result)
+}
+
+/// Searches the records of a stream which the query may read, there can't be
+/// more than `ZO_QUERY_JOIN_MAX_ROWS` of them.
+async fn search_table(
+    req: &cluster_rpc::SearchRequest,
+    table: &JoinTable,
+    priority: Priority,
+) -> Result<TableBatches, Error> {
+    let max_rows = CONFIG.limit.query_join_max_rows;
+    let query = req.query.as_ref().unwrap();
+    let stream_schema = db::schema::get(&req.org_id, &table.stream_name, Some(table.stream_type))
+        .await
+        .unwrap_or_else(|_| Schema::empty());
+    let columns = table.columns.as_ref().map(|columns| {
+        let mut names = stream_schema
+            .fields()
+            .iter()
+            .filter(|f| columns.iter().any(|c| c.eq_ignore_ascii_case(f.name())))
+            .map(|f| f.name().to_string())
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            // the query only counts the records
+            names.push(CONFIG.common.column_timestamp.clone());
+        }
+        names
+    });
+    let projection = columns.as_ref().map_or("*".to_string(), |columns| {
+        columns
+            .iter()
+            .map(|c| format!("\"{c}\""))
+            .collect::<Vec<_>>()
+            .join(", ")
+    });
+    let mut sql = format!("SELECT {projection} FROM \"{}\"", table.stream_name);
+    if !table.filters.is_empty() {
+        sql = format!("{sql} WHERE ({})", table.filters.join(") AND ("));
+    }
+    let table_req = cluster_rpc::SearchRequest {
+        job: req.job.clone(),
+        org_id: req.org_id.clone(),
+        stream_type: table.stream_type.to_string(),
+        stype: req.stype,
+        query: Some(cluster_rpc::SearchQuery {
+            sql,
+            sql_mode: "full".to_string(),
+            from: 0,
+            size: (max_rows + 1) as i32,
+            start_time: query.start_time,
+            end_time: query.end_time,
+            ..Default::default()
+        }),
+        file_list: vec![],
+        aggs: vec![],
+    };
+    cost::check(&table_req).await?;
+    let mut res = super::search_in_cluster_batches(table_req, priority).await?;
+    let batches = res
+        .batches
+        .remove("query")
+        .and_then(|batches| batches.into_iter().next())
+        .unwrap_or_default();
+    if batches.iter().map(|b| b.num_rows()).sum::<usize>() > max_rows {
+        return Err(Error::ErrorCode(ErrorCodes::SearchJoinRowsExceeded(
+            format!(
+                "stream {} has more than {max_rows} records in the time range, please add filters",
+                table.stream_name
+            ),
+        )));
+    }
+
+    // the query may read columns which none of the records has
+    let mut fields = batches
+        .first()
+        .map(|batch| batch.schema().fields().to_vec())
+        .unwrap_or_default();
+    for field in stream_schema.fields() {
+        let searched = columns
+            .as_ref()
+            .map_or(true, |columns| columns.contains(field.name()));
+        if searched && !fields.iter().any(|f| f.name() == field.name()) {
+            fields.push(field.clone());
+        }
+    }
+    let schema = nullable_schema(&Schema::new(fields));
+    let batches = batches
+        .iter()
+        .map(|batch| align_batch(&schema, batch))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(server_internal_error)?;
+
+    Ok(TableBatches {
+        schema,
+        batches,
+        file_count: res.file_count,
+        scan_size: res.scan_size,
+        took_wait: res.took_wait,
+    })
+}
+
+fn query_sql(req: &cluster_rpc::SearchRequest) -> String {
+    let sql = req.query.as_ref().unwrap().sql.replace('\n', " ");
+    let sql = sql.trim();
+    sql.strip_suffix(';').unwrap_or(sql).to_string()
+}
+
+fn sql_not_valid(msg: impl ToString) -> Error {
+    Error::ErrorCode(ErrorCodes::SearchSQLNotValid(msg.to_string()))
+}
+
+/// Plans a query which reads more than one stream, or one stream more than
+/// once, returns None for the queries of a single stream.
+fn plan(sql: &str, stream_type: StreamType) -> Result<Option<JoinPlan>, Error> {
+    let Ok(mut statements) = Parser::parse_sql(&GenericDialect {}, sql) else {
+        return Ok(None);
+    };
+    if statements.len() != 1 {
+        return Ok(None);
+    }
+    let Statement::Query(query) = &mut statements[0] else {
+        return Ok(None);
+    };
+    if is_single_stream(query) {
+        return Ok(None);
+    }
+    let has_limit = query.limit.is_some();
+    let mut planner = Planner {
+        stream_type,
+        ctes: Vec::new(),
+        tables: Vec::new(),
+        qualifiers: Vec::new(),
+    };
+    planner.query(query)?;
+    // the subqueries of expressions are planned in the order they are visited
+    if let ControlFlow::Break(e) = VisitMut::visit(
+        &mut statements[0],
+        &mut Subqueries {
+            planner: &mut planner,
+        },
+    ) {
+        return Err(e);
+    }
+    if planner.tables.is_empty() {
+        return Ok(None);
+    }
+
+    let mut columns = Columns::default();
+    let _ = Visit::visit(&statements[0], &mut columns);
+    for (idx, table) in planner.tables.iter_mut().enumerate() {
+        let Some(table_columns) = table.columns.as_mut() else {
+            continue;
+        };
+        let qualifiers = planner
+            .qualifiers
+            .iter()
+            .filter(|(_, i)| *i == idx)
+            .map(|(qualifier, _)| qualifier)
+            .collect::<Vec<_>>();
+        table_columns.extend(columns.unqualified.iter().cloned());
+        table_columns.extend(
+            columns
+                .qualified
+                .iter()
+                .filter(|(qualifier, _)| qualifiers.contains(&qualifier))
+                .map(|(_, column)| column.clone()),
+        );
+        table_columns.sort();
+        table_columns.dedup();
+    }
+
+    Ok(Some(JoinPlan {
+        sql: statements[0].to_string(),
+        tables: planner.tables,
+        has_limit,
+    }))
+}
+
+fn is_single_stream(query: &Query) -> bool {
+    if query.with.is_some() || Visit::visit(query, &mut HasSubquery).is_break() {
+        return false;
+    }
+    match query.body.as_ref() {
+        SetExpr::Select(select) => {
+            select.from.len() == 1
+                && select.from[0].joins.is_empty()
+                && matches!(select.from[0].relation, TableFactor::Table { .. })
+        }
+        _ => false,
+    }
+}
+
+/// Returns true for the subqueries of expressions, like `IN (SELECT ...)`.
+fn is_subquery(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Subquery(_) | Expr::InSubquery { .. } | Expr::Exists { .. }
+    )
+}
+
+struct HasSubquery;
+
+impl Visitor for HasSubquery {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        match is_subquery(expr) {
+            true => ControlFlow::Break(()),
+            false => ControlFlow::Continue(()),
+        }
+    }
+}
+
+/// Plans the subqueries of expressions, which the planner doesn't walk.
+struct Subqueries<'a> {
+    planner: &'a mut Planner,
+}
+
+impl VisitorMut for Subqueries<'_> {
+    type Break = Error;
+
+    fn pre_visit_expr(&mut self, expr: &mut Expr) -> ControlFlow<Self::Break> {
+        let query = match expr {
+            Expr::Subquery(query)
+            | Expr::InSubquery {
+                subquery: query, ..
+            }
+            | Expr::Exists {
+                subquery: query, ..
+            } => query,
+            _ => return ControlFlow::Continue(()),
+        };
+        match self.planner.query(query) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => ControlFlow::Break(e),
+        }
+    }
+}
+
+/// The columns a query reads, with their qualifier, and without one, which
+/// may be columns of any stream.
+#[derive(Default)]
+struct Columns {
+    qualified: Vec<(String, String)>,
+    unqualified: Vec<String>,
+}
+
+impl Visitor for Columns {
+    type Break = ();
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<Self::Break> {
+        match expr {
+            Expr::Identifier(ident) => self.unqualified.push(ident.value.clone()),
+            Expr::CompoundIdentifier(idents) if idents.len() >= 2 => {
+                let n = idents.len();
+                self.qualified
+                    .push((idents[n - 2].value.clone(), idents[n - 1].value.clone()));
+            }
+            _ => {}
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+struct Planner {
+    /// The stream type of the streams which aren't qualified with one.
+    stream_type: StreamType,
+    /// Names of the common table expressions, which aren't streams.
+    ctes: Vec<String>,
+    tables: Vec<JoinTable>,
+    /// The qualifiers of the tables, the alias or the name of the stream,
+    /// with the index of the table.
+    qualifiers: Vec<(String, usize)>,
+}
+
+impl Planner {
+    fn query(&mut self, query: &mut Query) -> Result<(), Error> {
+        if let Some(with) = query.with.as_mut() {
+            for cte in with.cte_tables.iter_mut() {
+                self.query(&mut cte.query)?;
+                self.ctes.push(cte.alias.name.value.clone());
+            }
+        }
+        self.set_expr(&mut query.body)
+    }
+
+    fn set_expr(&mut self, expr: &mut SetExpr) -> Result<(), Error> {
+        match expr {
+            SetExpr::Select(select) => self.select(select),
+            SetExpr::Query(query) => self.query(query),
+            SetExpr::SetOperation { left, right, .. } => {
+                self.set_expr(left)?;
+                self.set_expr(right)
+            }
+            _ => Err(sql_not_valid(
+                "JOIN and UNION queries only support SELECT queries",
+            )),
+        }
+    }
+
+    /// Moves the filters of a select which only read one of its streams to
+    /// the search of this stream, and renames the streams to their tables.
+    fn select(&mut self, select: &mut Select) -> Result<(), Error> {
+        let mut factors = Vec::new();
+        for table in select.from.iter_mut() {
+            self.table_with_joins(table, &mut factors)?;
+        }
+
+        let only_table = factors.len() == 1;
+        // a wildcard reads all the columns of the streams
+        let wildcard = select
+            .projection
+            .iter()
+            .any(|item| matches!(item, SelectItem::Wildcard(..)));
+        let qualified_wildcards = select
+            .projection
+            .iter()
+            .filter_map(|item| match item {
+                SelectItem::QualifiedWildcard(name, ..) => name.0.last().map(|v| v.value.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut conjuncts = select
+            .selection
+            .take()
+            .map(split_conjuncts)
+            .unwrap_or_default();
+        for (factor, nullable) in factors {
+            let TableFactor::Table { name, alias, .. } = factor else {
+                unreachable!()
+            };
+            let (stream_type, stream_name) = self.stream(name)?;
+            let qualifier = alias
+                .as_ref()
+                .map_or(stream_name.as_str(), |alias| alias.name.value.as_str())
+                .to_string();
+            // a filter of the nullable side of an outer join also drops the
+            // rows it doesn't match, so it isn't moved
+            let mut filters = Vec::new();
+            if !nullable {
+                conjuncts.retain(|expr| {
+                    let mut filter = expr.clone();
+                    if pushdown(&mut filter, &qualifier, only_table) {
+                        filters.push(filter.to_string());
+                        false
+                    } else {
+                        true
+                    }
+                });
+            }
+            let idx = self.table(JoinTable {
+                stream_type,
+                stream_name,
+                filters,
+                columns: Some(vec![]),
+            });
+            if wildcard || qualified_wildcards.contains(&qualifier) {
+                self.tables[idx].columns = None;
+            }
+            self.qualifiers.push((qualifier, idx));
+            if alias.is_none() {
+                *alias = Some(TableAlias {
+                    name: name.0.last().unwrap().clone(),
+                    columns: vec![],
+                });
+            }
+            *name = ObjectName(vec![Ident::new(format!("tbl_{idx}"))]);
+        }
+        select.selection = conjuncts.into_iter().reduce(|left, right| Expr::BinaryOp {
+            left: Box::new(left),
+            op: BinaryOperator::And,
+            right: Box::new(right),
+        });
+        Ok(())
+    }
+
+    /// Collects the streams of a FROM item, with whether they are on the
+    /// nullable side of an outer join.
+    fn table_with_joins<'a>(
+        &mut self,
+        table: &'a mut TableWithJoins,
+        factors: &mut Vec<(&'a mut TableFactor, bool)>,
+    ) -> Result<(), Error> {
+        let start = factors.len();
+        self.table_factor(&mut table.relation, factors)?;
+        for join in table.joins.iter_mut() {
+            let (left, right) = match join.join_operator {
+                JoinOperator::LeftOuter(_) => (false, true),
+                JoinOperator::RightOuter(_) => (true, false),
+                JoinOperator::FullOuter(_) => (true, true),
+                _ => (false, false),
+            };
+            if left {
+                factors[start..].iter_mut().for_each(|f| f.1 = true);
+            }
+            let end = factors.len();
+            self.table_factor(&mut join.relation, factors)?;
+            if right {
+                factors[end..].iter_mut().for_each(|f| f.1 = true);
+            }
+        }
+        Ok(())
+    }
+
+    fn table_factor<'a>(
+        &mut self,
+        factor: &'a mut TableFactor,
+        factors: &mut Vec<(&'a mut TableFactor, bool)>,
+    ) -> Result<(), Error> {
+        let is_stream = matches!(
+            factor,
+            TableFactor::Table { name, .. } if !(name.0.len() == 1 && self.ctes.contains(&name.0[0].value))
+        );
+        if is_stream {
+            factors.push((factor, false));
+            return Ok(());
+        }
+        match factor {
+            TableFactor::Table { .. } => Ok(()),
+            TableFactor::Derived { subquery, .. } => self.query(subquery),
+            TableFactor::NestedJoin {
+                table_with_joins, ..
+            } => self.table_with_joins(&mut **table_with_joins, factors),
+            _ => Err(sql_not_valid(format!(
+                "JOIN and UNION queries don't support table: {factor}"
+            ))),
+        }
+    }
+
+    /// Returns the stream type and the name of a stream.
+    fn stream(&self, name: &ObjectName) -> Result<(StreamType, String), Error> {
+        match name.0.as_slice() {
+            [stream] => Ok((self.stream_type, stream.value.clone())),
+            [stream_type, stream]
+                if ["logs", "metrics", "traces"]
+                    .contains(&stream_type.value.to_lowercase().as_str()) =>
+            {
+                Ok((
+                    StreamType::from(stream_type.value.as_str()),
+                    stream.value.clone(),
+                ))
+            }
+            _ => Err(sql_not_valid(format!("Query SQL stream not valid: {name}"))),
+        }
+    }
+
+    /// Returns the index of the table of a stream, a stream which is read
+    /// more than once with the same filters is searched once.
+    fn table(&mut self, table: JoinTable) -> usize {
+        let same = |t: &JoinTable| {
+            t.stream_type == table.stream_type
+                && t.stream_name == table.stream_name
+                && t.filters == table.filters
+        };
+        match self.tables.iter().position(same) {
+            Some(idx) => idx,
+            None => {
+                self.tables.push(table);
+                self.tables.len() - 1
+            }
+        }
+    }
+}
+
+fn split_conjuncts(expr: Expr) -> Vec<Expr> {
+    match expr {
+        Expr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            let mut conjuncts = split_conjuncts(*left);
+            conjuncts.extend(split_conjuncts(*right));
+            conjuncts
+        }
+        expr => vec![expr],
+    }
+}
+
+/// Rewrites a filter of a select to a filter of one of its streams, whose
+/// columns lose their qualifier. Returns false if the filter reads another
+/// stream, or has an expression which isn't pushed down.
+fn pushdown(expr: &mut Expr, qualifier: &str, only_table: bool) -> bool {
+    let mut pushdown = Pushdown {
+        qualifier,
+        only_table,
+        columns: 0,
+    };
+    pushdown.expr(expr) && (only_table || pushdown.columns > 0)
+}
+
+struct Pushdown<'a> {
+    qualifier: &'a str,
+    /// The select reads a single stream, so the columns may be unqualified.
+    only_table: bool,
+    columns: usize,
+}
+
+impl Pushdown<'_> {
+    fn expr(&mut self, expr: &mut Expr) -> bool {
+        match expr {
+            Expr::Identifier(_) => {
+                self.columns += 1;
+                self.only_table
+            }
+            Expr::CompoundIdentifier(idents) => {
+                if idents.len() != 2 || idents[0].value != self.qualifier {
+                    return false;
+                }
+                let column = idents[1].clone();
+                *expr = Expr::Identifier(column);
+                self.columns += 1;
+                true
+            }
+            Expr::Value(_) => true,
+            Expr::Nested(expr)
+            | Expr::IsNull(expr)
+            | Expr::IsNotNull(expr)
+            | Expr::UnaryOp { expr, .. }
+            | Expr::Cast { expr, .. } => self.expr(expr),
+            Expr::BinaryOp { left, right, .. } => self.expr(left) && self.expr(right),
+            Expr::Like { expr, pattern, .. } | Expr::ILike { expr, pattern, .. } => {
+                self.expr(expr) && self.expr(pattern)
+            }
+            Expr::InList { expr, list, .. } => {
+                self.expr(expr) && list.iter_mut().all(|item| self.expr(item))
+            }
+            Expr::Between {
+                expr, low, high, ..
+            } => self.expr(expr) && self.expr(low) && self.expr(high),
+            Expr::Function(func) => func.args.iter_mut().all(|arg| match arg {
+                FunctionArg::Named {
+                    arg: FunctionArgExpr::Expr(expr),
+                    ..
+                }
+                | FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => self.expr(expr),
+                _ => false,
+            }),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(stream_type: StreamType, stream_name: &str, filters: &[&str]) -> JoinTable {
+        JoinTable {
+            stream_type,
+            stream_name: stream_name.to_string(),
+            filters: filters.iter().map(|f| f.to_string()).collect(),
+            columns: None,
+        }
+    }
+
+    fn columns(mut table: JoinTable, columns: &[&str]) -> JoinTable {
+        table.columns = Some(columns.iter().map(|c| c.to_string()).collect());
+        table
+    }
+
+    #[test]
+    fn test_plan_single_stream() {
+        for sql in [
+            "select * from k8s where level = 'error'",
+            "select count(*) as num from k8s group by level",
+        ] {
+            assert!(plan(sql, StreamType::Logs).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_plan_join() {
+        let plan = plan(
+            "SELECT l.message, t.duration FROM k8s l JOIN traces.default t ON l.trace_id = t.trace_id \
+             WHERE l.level = 'error' AND t.duration > 100 AND l.code = t.code",
+            StreamType::Logs,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            plan.sql,
+            "SELECT l.message, t.duration FROM tbl_0 AS l JOIN tbl_1 AS t ON l.trace_id = t.trace_id \
+             WHERE l.code = t.code"
+        );
+        assert_eq!(
+            plan.tables,
+            vec![
+                columns(
+                    table(StreamType::Logs, "k8s", &["level = 'error'"]),
+                    &["code", "message", "trace_id"]
+                ),
+                columns(
+                    table(StreamType::Traces, "default", &["duration > 100"]),
+                    &["code", "duration", "trace_id"]
+                ),
+            ]
+        );
+        assert!(!plan.has_limit);
+    }
+
+    #[test]
+    fn test_plan_outer_join() {
+        let plan = plan(
+            "SELECT * FROM k8s LEFT JOIN nginx ON k8s.id = nginx.id \
+             WHERE nginx.status IS NULL AND k8s.level = 'error' LIMIT 10",
+            StreamType::Logs,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            plan.sql,
+            "SELECT * FROM tbl_0 AS k8s LEFT JOIN tbl_1 AS nginx ON k8s.id = nginx.id \
+             WHERE nginx.status IS NULL LIMIT 10"
+        );
+        assert_eq!(
+            plan.tables,
+            vec![
+                table(StreamType::Logs, "k8s", &["level = 'error'"]),
+                table(StreamType::Logs, "nginx", &[]),
+            ]
+        );
+        assert!(plan.has_limit);
+    }
+
+    #[test]
+    fn test_plan_union() {
+        let plan = plan(
+            "SELECT message FROM k8s WHERE level = 'error' \
+             UNION ALL SELECT message FROM nginx \
+             UNION ALL SELECT message FROM k8s WHERE level = 'error'",
+            StreamType::Logs,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            plan.sql,
+            "SELECT message FROM tbl_0 AS k8s \
+             UNION ALL SELECT message FROM tbl_1 AS nginx \
+             UNION ALL SELECT message FROM tbl_0 AS k8s"
+        );
+        assert_eq!(
+            plan.tables,
+            vec![
+                columns(
+                    table(StreamType::Logs, "k8s", &["level = 'error'"]),
+                    &["message"]
+                ),
+                columns(table(StreamType::Logs, "nginx", &[]), &["message"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_subquery() {
+        let plan = plan(
+            "SELECT * FROM k8s WHERE level = 'error' AND id IN (SELECT id FROM nginx WHERE status = 500)",
+            StreamType::Logs,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            plan.sql,
+            "SELECT * FROM tbl_0 AS k8s WHERE id IN (SELECT id FROM tbl_1 AS nginx)"
+        );
+        assert_eq!(
+            plan.tables,
+            vec![
+                table(StreamType::Logs, "k8s", &["level = 'error'"]),
+                columns(table(StreamType::Logs, "nginx", &["status = 500"]), &["id"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_stream_not_valid() {
+        assert!(plan(
+            "SELECT * FROM a.b.c JOIN k8s ON a.id = k8s.id",
+            StreamType::Logs
+        )
+        .is_err());
+    }
+}
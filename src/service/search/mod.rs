@@ -14,9 +14,7 @@
 
 use ::datafusion::arrow::{datatypes::Schema, ipc, json as arrow_json, record_batch::RecordBatch};
 use ahash::AHashMap as HashMap;
-use once_cell::sync::Lazy;
 use std::{cmp::min, io::Cursor, sync::Arc, time::Duration};
-use tokio::sync::Mutex;
 use tonic::{codec::CompressionEncoding, metadata::MetadataValue, transport::Channel, Request};
 use tracing::{info_span, Instrument};
 use tracing_opentelemetry::OpenTelemetrySpanExt;
@@ -27,39 +25,55 @@ use crate::handler::grpc::cluster_rpc;
 use crate::infra::{
     cluster,
     config::CONFIG,
-    db::etcd,
     errors::{Error, ErrorCodes},
 };
 use crate::meta::{search, stream::StreamParams, StreamType};
 use crate::service::{db, file_list};
 
+pub use scheduler::Priority;
+
 use super::get_partition_key_query;
 
+pub(crate) mod cache;
+pub(crate) mod cost;
+pub(crate) mod cursor;
 pub(crate) mod datafusion;
+pub(crate) mod export;
 pub(crate) mod grpc;
+pub(crate) mod index;
+pub(crate) mod job;
+pub(crate) mod join;
+pub(crate) mod scheduler;
 pub(crate) mod sql;
 
-pub(crate) static QUEUE_LOCKER: Lazy<Arc<Mutex<bool>>> =
-    Lazy::new(|| Arc::new(Mutex::const_new(false)));
-
 #[tracing::instrument(name = "service:search:enter", skip_all)]
 pub async fn search(
     org_id: &str,
     stream_type: StreamType,
     req: &search::Request,
+) -> Result<search::Response, Error> {
+    search_with_priority(org_id, stream_type, req, Priority::Interactive).await
+}
+
+/// Searches with the given priority in the search queue, see `scheduler`.
+#[tracing::instrument(name = "service:search:enter", skip_all)]
+pub async fn search_with_priority(
+    org_id: &str,
+    stream_type: StreamType,
+    req: &search::Request,
+    priority: Priority,
 ) -> Result<search::Response, Error> {
     let mut req: cluster_rpc::SearchRequest = req.to_owned().into();
     req.org_id = org_id.to_string();
     req.stype = cluster_rpc::SearchType::User as i32;
     req.stream_type = stream_type.to_string();
-    search_in_cluster(req).await
-}
-
-#[inline(always)]
-async fn get_queue_lock() -> Result<etcd::Locker, Error> {
-    let mut lock = etcd::Locker::new("search/cluster_queue");
-    lock.lock(0).await.map_err(server_internal_error)?;
-    Ok(lock)
+    if join::is_join(&req) {
+        return join::search(req, priority).await;
+    }
+    if cache::is_cacheable(&req) {
+        return cache::search(req, priority).await;
+    }
+    search_in_cluster(req, priority).await
 }
 
 async fn get_times(sql: &sql::Sql, stream_type: StreamType) -> (i64, i64) {
@@ -108,16 +122,140 @@ async fn get_file_list(sql: &sql::Sql, stream_type: StreamType) -> Vec<String> {
     }
 }
 
+/// The merged record batches of a search, keyed by `query` for the hits and
+/// `agg_{name}` for the aggregations.
+pub(crate) struct ClusterBatches {
+    pub(crate) sql: Arc<sql::Sql>,
+    pub(crate) batches: HashMap<String, Vec<Vec<RecordBatch>>>,
+    pub(crate) file_count: usize,
+    pub(crate) scan_size: usize,
+    pub(crate) took_wait: usize,
+    /// Set when the query asks for the profile.
+    pub(crate) profile: Option<search::Profile>,
+}
+
 #[tracing::instrument(name = "service:search:cluster", skip_all)]
-async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Response, Error> {
+async fn search_in_cluster(
+    req: cluster_rpc::SearchRequest,
+    priority: Priority,
+) -> Result<search::Response, Error> {
     let start = std::time::Instant::now();
+    cost::check(&req).await?;
+    let query_type = req.query.as_ref().unwrap().query_type.to_lowercase();
+    let ClusterBatches {
+        sql,
+        batches,
+        file_count,
+        scan_size,
+        took_wait,
+        profile,
+    } = search_in_cluster_batches(req, priority).await?;
+
+    let mut result = build_response(&sql, batches, &query_type)?;
+    result.set_cluster_took(start.elapsed().as_millis() as usize, took_wait);
+    result.profile = profile;
+    result.set_file_count(file_count);
+    result.set_scan_size(scan_size);
 
-    // get a cluster search queue lock
-    let locker = if CONFIG.common.local_mode {
-        None
-    } else {
-        Some(get_queue_lock().await?)
+    log::info!(
+        "search->result: total: {}, took: {}, scan_size: {}",
+        result.total,
+        result.took,
+        result.scan_size,
+    );
+
+    Ok(result)
+}
+
+/// Converts the merged record batches of a search to the response.
+pub(crate) fn build_response(
+    sql: &sql::Sql,
+    batches: HashMap<String, Vec<Vec<RecordBatch>>>,
+    query_type: &str,
+) -> Result<search::Response, Error> {
+    let mut result = search::Response::new(sql.meta.offset, sql.meta.limit);
+
+    // hits
+    let batches_query = match batches.get("query") {
+        Some(batches) => batches.to_owned(),
+        None => Vec::new(),
     };
+    if !batches_query.is_empty() {
+        let json_rows = match arrow_json::writer::record_batches_to_json_rows(&batches_query[0][..])
+        {
+            Ok(res) => res,
+            Err(err) => {
+                return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
+                    err.to_string(),
+                )))
+            }
+        };
+        let mut sources: Vec<json::Value> =
+            json_rows.into_iter().map(json::Value::Object).collect();
+
+        // handle metrics response
+        if query_type == "metrics" {
+            sources = handle_metrics_response(sources);
+        }
+
+        /*  #[cfg(feature = "zo_functions")]
+        let sources = apply_query_fn(&req, &sources); */
+
+        if sql.uses_zo_fn {
+            for source in sources {
+                result.add_hit(&json::flatten_json_and_format_field(&source));
+            }
+        } else {
+            for source in sources {
+                result.add_hit(&source);
+            }
+        }
+    }
+
+    // aggs
+    for (name, batch) in batches {
+        if name == "query" || batch.is_empty() {
+            continue;
+        }
+        let name = name.strip_prefix("agg_").unwrap().to_string();
+        let json_rows = match arrow_json::writer::record_batches_to_json_rows(&batch[0][..]) {
+            Ok(res) => res,
+            Err(err) => {
+                return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
+                    err.to_string(),
+                )));
+            }
+        };
+        let sources: Vec<json::Value> = json_rows.into_iter().map(json::Value::Object).collect();
+        for source in sources {
+            result.add_agg(&name, &source);
+        }
+    }
+
+    // total
+    let total = match result.aggs.get("_count") {
+        Some(v) => v.get(0).unwrap().get("num").unwrap().as_u64().unwrap() as usize,
+        None => result.hits.len(),
+    };
+    result.aggs.remove("_count");
+
+    result.set_total(total);
+    if query_type == "metrics" {
+        result.response_type = "matrix".to_string();
+    }
+    Ok(result)
+}
+
+/// Searches the nodes of the cluster and merges their record batches.
+#[tracing::instrument(name = "service:search:cluster:batches", skip_all)]
+pub(crate) async fn search_in_cluster_batches(
+    req: cluster_rpc::SearchRequest,
+    priority: Priority,
+) -> Result<ClusterBatches, Error> {
+    let start = std::time::Instant::now();
+
+    // wait for the turn of the search
+    let permit = scheduler::acquire(&req.org_id, priority).await?;
     let took_wait = start.elapsed().as_millis() as usize;
 
     // get nodes from cluster
@@ -137,21 +275,23 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
 
     // handle request time range
     let stream_type = StreamType::from(req.stream_type.as_str());
-    let meta = match sql::Sql::new(&req).await {
-        Ok(meta) => meta,
-        Err(err) => {
-            // search done, release lock
-            if locker.is_some() {
-                if let Err(e) = locker.unwrap().unlock().await {
-                    log::error!("search in cluster unlock error: {}", e);
-                }
+    let meta = sql::Sql::new(&req).await?;
+
+    // a search job searches its files in chunks, see `job`
+    let file_list_start = std::time::Instant::now();
+    let file_list = if req.file_list.is_empty() {
+        get_file_list(&meta, stream_type).await
+    } else {
+        let mut files = Vec::with_capacity(req.file_list.len());
+        for file in req.file_list.iter() {
+            if meta.match_source(file, false, false, stream_type).await {
+                files.push(file.clone());
             }
-            return Err(err);
         }
+        files
     };
-
-    let file_list = get_file_list(&meta, stream_type).await;
     let file_num = file_list.len();
+    let file_list_took = file_list_start.elapsed().as_millis() as usize;
     let offset = if querier_num >= file_num {
         1
     } else {
@@ -163,12 +303,17 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
     let mut session_id = Uuid::new_v4().to_string();
     let job = cluster_rpc::Job {
         session_id: session_id.clone(),
-        job: session_id.split_off(30), // take the last 6 characters as job id
+        // search jobs set their id, so that they can be cancelled
+        job: match req.job.as_ref().filter(|job| !job.job.is_empty()) {
+            Some(job) => job.job.clone(),
+            None => session_id.split_off(30), // take the last 6 characters as job id
+        },
         stage: 0,
         partition: 0,
     };
 
     // make cluster request
+    let storage_only = req.stype == cluster_rpc::SearchType::StorageOnly as i32;
     let mut tasks = Vec::new();
     let mut offset_start: usize = 0;
     for (partition_no, node) in nodes.iter().cloned().enumerate() {
@@ -177,10 +322,15 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
         job.partition = partition_no as i32;
         req.job = Some(job);
         req.stype = cluster_rpc::SearchType::WalOnly as i32;
+        req.file_list = vec![];
         let is_querier = cluster::is_querier(&node.role);
         if is_querier {
             if offset_start < file_num {
-                req.stype = cluster_rpc::SearchType::Cluster as i32;
+                req.stype = if storage_only {
+                    cluster_rpc::SearchType::StorageOnly as i32
+                } else {
+                    cluster_rpc::SearchType::Cluster as i32
+                };
                 req.file_list =
                     file_list[offset_start..min(offset_start + offset, file_num)].to_vec();
                 offset_start += offset;
@@ -188,7 +338,11 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
                 continue; // no need more querier
             }
         }
+        if storage_only && req.stype == cluster_rpc::SearchType::WalOnly as i32 {
+            continue; // no need to search WAL
+        }
 
+        let node_name = node.name.clone();
         let node_addr = node.grpc_addr.clone();
         let grpc_span = info_span!("service:search:cluster:grpc_search");
         let task = tokio::task::spawn(
@@ -250,7 +404,7 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
                     response.took,
                     response.file_count
                 );
-                Ok(response)
+                Ok((node_name, response))
             }
             .instrument(grpc_span),
         );
@@ -262,31 +416,27 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
         let result = task
             .await
             .map_err(|err| Error::ErrorCode(ErrorCodes::ServerInternalError(err.to_string())))?;
-        match result {
-            Ok(res) => results.push(res),
-            Err(err) => {
-                // search done, release lock
-                if locker.is_some() {
-                    if let Err(e) = locker.unwrap().unlock().await {
-                        log::error!("search in cluster unlock error: {}", e);
-                    }
-                }
-                return Err(err);
-            }
-        }
-    }
-    // search done, release lock
-    if locker.is_some() {
-        if let Err(e) = locker.unwrap().unlock().await {
-            log::error!("search in cluster unlock error: {}", e);
-        }
+        results.push(result?);
     }
+    // search done, start the next one
+    drop(permit);
     // merge multiple instances data
     let mut file_count = 0;
     let mut scan_size = 0;
     let mut batches: HashMap<String, Vec<Vec<RecordBatch>>> = HashMap::new();
     let sql = Arc::new(meta);
-    for resp in results {
+    let mut profile = sql.profile.then(|| search::Profile {
+        file_list_took,
+        files: file_num,
+        ..Default::default()
+    });
+    for (node_name, mut resp) in results {
+        if let (Some(profile), Some(node_profile)) = (profile.as_mut(), resp.profile.take()) {
+            let mut node_profile = search::NodeProfile::from(node_profile);
+            node_profile.node = node_name;
+            node_profile.took = resp.took as usize;
+            profile.nodes.push(node_profile);
+        }
         file_count += resp.file_count;
         scan_size += resp.scan_size;
         // handle hits
@@ -318,83 +468,47 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
         }
 
         // merge all batches
-        for (name, batch) in batches.iter_mut() {
-            let merge_sql = if name == "query" {
-                sql.origin_sql.clone()
-            } else {
-                sql.aggs
-                    .get(name.strip_prefix("agg_").unwrap())
-                    .unwrap()
-                    .0
-                    .clone()
-            };
-            *batch = match datafusion::exec::merge(
-                &sql.org_id,
-                sql.meta.offset,
-                sql.meta.limit,
-                &merge_sql,
-                batch,
-            )
-            .await
-            {
-                Ok(res) => res,
-                Err(err) => {
-                    return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
-                        err.to_string(),
-                    )));
-                }
-            };
+        let merge_start = std::time::Instant::now();
+        merge_batches(&sql, &mut batches).await?;
+        if let Some(profile) = profile.as_mut() {
+            profile.merge_took += merge_start.elapsed().as_millis() as usize;
         }
     }
 
-    // final result
-    let mut result = search::Response::new(sql.meta.offset, sql.meta.limit);
-
-    // hits
-    let query_type = req.query.as_ref().unwrap().query_type.to_lowercase();
-    let batches_query = match batches.get("query") {
-        Some(batches) => batches.to_owned(),
-        None => Vec::new(),
-    };
-    if !batches_query.is_empty() {
-        let json_rows = match arrow_json::writer::record_batches_to_json_rows(&batches_query[0][..])
-        {
-            Ok(res) => res,
-            Err(err) => {
-                return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
-                    err.to_string(),
-                )))
-            }
-        };
-        let mut sources: Vec<json::Value> =
-            json_rows.into_iter().map(json::Value::Object).collect();
-
-        // handle metrics response
-        if query_type == "metrics" {
-            sources = handle_metrics_response(sources);
-        }
-
-        /*  #[cfg(feature = "zo_functions")]
-        let sources = apply_query_fn(&req, &sources); */
+    Ok(ClusterBatches {
+        sql,
+        batches,
+        file_count: file_count as usize,
+        scan_size: scan_size as usize,
+        took_wait,
+        profile,
+    })
+}
 
-        if sql.uses_zo_fn {
-            for source in sources {
-                result.add_hit(&json::flatten_json_and_format_field(&source));
-            }
+/// Merges the record batches of every key into one.
+pub(crate) async fn merge_batches(
+    sql: &sql::Sql,
+    batches: &mut HashMap<String, Vec<Vec<RecordBatch>>>,
+) -> Result<(), Error> {
+    for (name, batch) in batches.iter_mut() {
+        let merge_sql = if name == "query" {
+            sql.origin_sql.clone()
         } else {
-            for source in sources {
-                result.add_hit(&source);
-            }
-        }
-    }
-
-    // aggs
-    for (name, batch) in batches {
-        if name == "query" || batch.is_empty() {
-            continue;
-        }
-        let name = name.strip_prefix("agg_").unwrap().to_string();
-        let json_rows = match arrow_json::writer::record_batches_to_json_rows(&batch[0][..]) {
+            sql.aggs
+                .get(name.strip_prefix("agg_").unwrap())
+                .unwrap()
+                .0
+                .clone()
+        };
+        *batch = match datafusion::exec::merge(
+            &sql.org_id,
+            sql.meta.offset,
+            sql.meta.limit,
+            &merge_sql,
+            batch,
+        )
+        .await
+        {
             Ok(res) => res,
             Err(err) => {
                 return Err(Error::ErrorCode(ErrorCodes::ServerInternalError(
@@ -402,36 +516,8 @@ async fn search_in_cluster(req: cluster_rpc::SearchRequest) -> Result<search::Re
                 )));
             }
         };
-        let sources: Vec<json::Value> = json_rows.into_iter().map(json::Value::Object).collect();
-        for source in sources {
-            result.add_agg(&name, &source);
-        }
-    }
-
-    // total
-    let total = match result.aggs.get("_count") {
-        Some(v) => v.get(0).unwrap().get("num").unwrap().as_u64().unwrap() as usize,
-        None => result.hits.len(),
-    };
-    result.aggs.remove("_count");
-
-    result.set_total(total);
-    result.set_cluster_took(start.elapsed().as_millis() as usize, took_wait);
-    result.set_file_count(file_count as usize);
-    result.set_scan_size(scan_size as usize);
-
-    if query_type == "metrics" {
-        result.response_type = "matrix".to_string();
     }
-
-    log::info!(
-        "search->result: total: {}, took: {}, scan_size: {}",
-        result.total,
-        result.took,
-        result.scan_size,
-    );
-
-    Ok(result)
+    Ok(())
 }
 
 fn handle_metrics_response(sources: Vec<json::Value>) -> Vec<json::Value> {
@@ -624,4 +710,26 @@ mod tests {
             ],
         ));
     }
+
+    #[test]
+    fn test_node_profile() {
+        let profile = search::NodeProfile::from(cluster_rpc::SearchProfile {
+            file_list_took: 3,
+            cache_took: 20,
+            scan_took: 100,
+            files: 10,
+            files_scanned: 4,
+            plans: vec![cluster_rpc::SearchPlan {
+                source: "storage".to_string(),
+                name: "query".to_string(),
+                plan: "ProjectionExec".to_string(),
+            }],
+            ..Default::default()
+        });
+        assert_eq!(profile.files_pruned, 6);
+        assert_eq!(profile.files_scanned, 4);
+        assert_eq!(profile.scan_took, 100);
+        assert_eq!(profile.plans.len(), 1);
+        assert_eq!(profile.plans[0].source, "storage");
+    }
 }
@@ -0,0 +1,317 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scheduler of the searches of a node.
+//!
+//! At most `ZO_QUERY_MAX_CONCURRENT` searches run at the same time, and at
+//! most `ZO_QUERY_MAX_CONCURRENT_PER_ORG` of one organization. The waiting
+//! searches are queued by priority, and within a priority the organizations
+//! take turns, so that an organization with many searches doesn't hold up the
+//! searches of the others.
+
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+use super::server_internal_error;
+use crate::infra::{config::CONFIG, errors::Error, metrics};
+
+static SCHEDULER: Lazy<Arc<Scheduler>> = Lazy::new(|| {
+    Arc::new(Scheduler::new(
+        CONFIG.limit.query_max_concurrent,
+        CONFIG.limit.query_max_concurrent_per_org,
+    ))
+});
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Searches of users.
+    Interactive = 0,
+    /// Searches of alerts.
+    Alert = 1,
+    /// Exports and search jobs.
+    Background = 2,
+}
+
+const PRIORITY_NUM: usize = 3;
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Priority::Interactive => write!(f, "interactive"),
+            Priority::Alert => write!(f, "alert"),
+            Priority::Background => write!(f, "background"),
+        }
+    }
+}
+
+const PRIORITIES: [Priority; PRIORITY_NUM] =
+    [Priority::Interactive, Priority::Alert, Priority::Background];
+
+/// Waits for the turn of a search of the organization.
+pub async fn acquire(org_id: &str, priority: Priority) -> Result<Permit, Error> {
+    let start = std::time::Instant::now();
+    let permit = SCHEDULER.acquire(org_id, priority).await?;
+    metrics::QUERY_QUEUE_TIME
+        .with_label_values(&[org_id, priority.to_string().as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    Ok(permit)
+}
+
+/// A running search, the next one is started when it's dropped.
+pub struct Permit {
+    scheduler: Arc<Scheduler>,
+    org_id: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release(&self.org_id);
+    }
+}
+
+type Waiter = oneshot::Sender<Permit>;
+
+struct Scheduler {
+    max_concurrent: usize,
+    max_concurrent_per_org: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    running: usize,
+    running_orgs: HashMap<String, usize>,
+    /// The organizations with waiting searches, in the order of their turns.
+    turns: [VecDeque<String>; PRIORITY_NUM],
+    /// The waiting searches of the organizations.
+    waiting: [HashMap<String, VecDeque<Waiter>>; PRIORITY_NUM],
+}
+
+impl Scheduler {
+    fn new(max_concurrent: usize, max_concurrent_per_org: usize) -> Self {
+        Self {
+            max_concurrent: max_concurrent.max(1),
+            max_concurrent_per_org,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    async fn acquire(self: &Arc<Self>, org_id: &str, priority: Priority) -> Result<Permit, Error> {
+        let (tx, rx) = oneshot::channel();
+        let grants = {
+            let mut state = self.state.lock().unwrap();
+            let p = priority as usize;
+            let queue = state.waiting[p].entry(org_id.to_string()).or_default();
+            queue.push_back(tx);
+            if queue.len() == 1 {
+                state.turns[p].push_back(org_id.to_string());
+            }
+            metrics::QUERY_QUEUE_PENDING
+                .with_label_values(&[org_id, priority.to_string().as_str()])
+                .inc();
+            self.next_grants(&mut state)
+        };
+        send(grants);
+        rx.await
+            .map_err(|_| server_internal_error("search scheduler dropped the search"))
+    }
+
+    fn release(self: &Arc<Self>, org_id: &str) {
+        let grants = {
+            let mut state = self.state.lock().unwrap();
+            state.running -= 1;
+            if let Some(num) = state.running_orgs.get_mut(org_id) {
+                *num -= 1;
+                if *num == 0 {
+                    state.running_orgs.remove(org_id);
+                }
+            }
+            metrics::QUERY_RUNNING.with_label_values(&[org_id]).dec();
+            self.next_grants(&mut state)
+        };
+        send(grants);
+    }
+
+    /// Starts waiting searches while there are free slots. The permits are
+    /// sent after the state is unlocked, because the permit of a search which
+    /// is no longer waiting is released when it's dropped.
+    fn next_grants(self: &Arc<Self>, state: &mut State) -> Vec<(Waiter, Permit)> {
+        let mut grants = Vec::new();
+        while state.running < self.max_concurrent {
+            match self.next_waiter(state) {
+                Some((org_id, tx)) => {
+                    state.running += 1;
+                    *state.running_orgs.entry(org_id.clone()).or_default() += 1;
+                    metrics::QUERY_RUNNING
+                        .with_label_values(&[org_id.as_str()])
+                        .inc();
+                    grants.push((
+                        tx,
+                        Permit {
+                            scheduler: self.clone(),
+                            org_id,
+                        },
+                    ));
+                }
+                None => break,
+            }
+        }
+        grants
+    }
+
+    /// Returns the next waiting search of the highest priority whose
+    /// organization is below its limit.
+    fn next_waiter(&self, state: &mut State) -> Option<(String, Waiter)> {
+        for priority in PRIORITIES {
+            let p = priority as usize;
+            let mut turns = state.turns[p].len();
+            while turns > 0 {
+                turns -= 1;
+                let org_id = state.turns[p].pop_front().unwrap();
+                if self.max_concurrent_per_org > 0
+                    && state.running_orgs.get(&org_id).copied().unwrap_or_default()
+                        >= self.max_concurrent_per_org
+                {
+                    state.turns[p].push_back(org_id);
+                    continue;
+                }
+                let queue = state.waiting[p].get_mut(&org_id).unwrap();
+                let mut waiter = None;
+                while let Some(tx) = queue.pop_front() {
+                    metrics::QUERY_QUEUE_PENDING
+                        .with_label_values(&[org_id.as_str(), priority.to_string().as_str()])
+                        .dec();
+                    // the search was cancelled while waiting
+                    if !tx.is_closed() {
+                        waiter = Some(tx);
+                        break;
+                    }
+                }
+                if queue.is_empty() {
+                    state.waiting[p].remove(&org_id);
+                } else {
+                    state.turns[p].push_back(org_id.clone());
+                }
+                if let Some(tx) = waiter {
+                    return Some((org_id, tx));
+                }
+            }
+        }
+        None
+    }
+}
+
+fn send(grants: Vec<(Waiter, Permit)>) {
+    for (tx, permit) in grants {
+        // the permit is released if the search is no longer waiting
+        let _ = tx.send(permit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn running_orgs(scheduler: &Scheduler) -> HashMap<String, usize> {
+        scheduler.state.lock().unwrap().running_orgs.clone()
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_limits() {
+        let scheduler = Arc::new(Scheduler::new(2, 1));
+        let p1 = scheduler
+            .acquire("org1", Priority::Interactive)
+            .await
+            .unwrap();
+        let s = scheduler.clone();
+        let p2 = tokio::spawn(async move { s.acquire("org1", Priority::Interactive).await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!p2.is_finished());
+        let p3 = scheduler
+            .acquire("org2", Priority::Interactive)
+            .await
+            .unwrap();
+        assert_eq!(running_orgs(&scheduler).len(), 2);
+
+        drop(p1);
+        let p2 = p2.await.unwrap().unwrap();
+        assert_eq!(running_orgs(&scheduler).get("org1"), Some(&1));
+        drop(p2);
+        drop(p3);
+        assert!(running_orgs(&scheduler).is_empty());
+        assert_eq!(scheduler.state.lock().unwrap().running, 0);
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_fairness() {
+        let scheduler = Arc::new(Scheduler::new(1, 0));
+        let first = scheduler
+            .acquire("noisy", Priority::Interactive)
+            .await
+            .unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut tasks = Vec::new();
+        for (org_id, priority) in [
+            ("noisy", Priority::Interactive),
+            ("noisy", Priority::Interactive),
+            ("noisy", Priority::Interactive),
+            ("export", Priority::Background),
+            ("quiet", Priority::Interactive),
+        ] {
+            let s = scheduler.clone();
+            let tx = tx.clone();
+            tasks.push(tokio::spawn(async move {
+                let permit = s.acquire(org_id, priority).await.unwrap();
+                tx.send(org_id).unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                drop(permit);
+            }));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        drop(first);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        let mut order = Vec::new();
+        while let Ok(org_id) = rx.try_recv() {
+            order.push(org_id);
+        }
+        assert_eq!(order, vec!["noisy", "quiet", "noisy", "noisy", "export"]);
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_cancelled_waiter() {
+        let scheduler = Arc::new(Scheduler::new(1, 0));
+        let first = scheduler
+            .acquire("org1", Priority::Interactive)
+            .await
+            .unwrap();
+        let s = scheduler.clone();
+        let waiting = tokio::spawn(async move { s.acquire("org1", Priority::Interactive).await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        waiting.abort();
+        let _ = waiting.await;
+        drop(first);
+        let permit = scheduler
+            .acquire("org2", Priority::Interactive)
+            .await
+            .unwrap();
+        assert_eq!(running_orgs(&scheduler).get("org2"), Some(&1));
+        drop(permit);
+        assert_eq!(scheduler.state.lock().unwrap().running, 0);
+    }
+}
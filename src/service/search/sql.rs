@@ -14,9 +14,10 @@
 
 use ahash::AHashMap;
 use chrono::Duration;
-use datafusion::arrow::datatypes::{DataType, Schema};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sqlparser::ast::{BinaryOperator, Expr as SqlExpr, FunctionArg, FunctionArgExpr, Value};
 use std::{
     collections::HashMap,
     fmt::{Display, Formatter},
@@ -43,6 +44,11 @@ pub struct Sql {
     pub stream_name: String,
     pub meta: MetaSql,
     pub fulltext: Vec<(String, String)>,
+    /// The fields `match_all` searches in.
+    pub fulltext_fields: Vec<String>,
+    /// The `match_all` terms every matching record contains, i.e. those which
+    /// are only combined with `AND`.
+    pub fulltext_terms: Vec<String>,
     pub aggs: AHashMap<String, (String, MetaSql)>,
     pub fields: Vec<String>,
     pub sql_mode: SqlMode,
@@ -50,6 +56,8 @@ pub struct Sql {
     pub query_context: String,
     pub uses_zo_fn: bool,
     pub query_fn: Option<String>,
+    /// Whether to record the physical plans and the timings of the search.
+    pub profile: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -335,30 +343,19 @@ impl Sql {
         }
         // fetch fts fields
         let fts_fields = get_stream_setting_fts_fields(&schema).unwrap();
-        let match_all_fields = if !fts_fields.is_empty() {
-            fts_fields.iter().map(|v| v.to_lowercase()).collect()
+        let fulltext_fields = if fulltext.is_empty() {
+            vec![]
         } else {
-            crate::common::stream::SQL_FULL_TEXT_SEARCH_FIELDS
-                .iter()
-                .map(|v| v.to_string())
-                .collect::<String>()
+            get_fulltext_fields(&schema_fields, &fts_fields)
         };
         for item in fulltext.iter() {
             let mut fulltext_search = Vec::new();
-            for field in &schema_fields {
-                if !CONFIG.common.feature_fulltext_on_all_fields
-                    && !match_all_fields.contains(&field.name().to_lowercase())
-                {
-                    continue;
-                }
-                if !field.data_type().eq(&DataType::Utf8) || field.name().starts_with('@') {
-                    continue;
-                }
+            for field in &fulltext_fields {
                 let mut func = "LIKE";
                 if item.0.to_lowercase().contains("_ignore_case") {
                     func = "ILIKE";
                 }
-                fulltext_search.push(format!("\"{}\" {} '%{}%'", field.name(), func, item.1));
+                fulltext_search.push(format!("\"{}\" {} '%{}%'", field, func, item.1));
             }
             if fulltext_search.is_empty() {
                 return Err(Error::ErrorCode(ErrorCodes::FullTextSearchFieldNotFound));
@@ -547,12 +544,20 @@ impl Sql {
             Some(req_query.query_fn.clone())
         };
 
+        // terms every record has to contain, used to skip files by their index
+        let mut fulltext_terms = Vec::new();
+        if let Some(selection) = meta.selection.as_ref() {
+            required_fulltext_terms(selection, &mut fulltext_terms);
+        }
+
         let mut sql = Sql {
             origin_sql,
             org_id,
             stream_name,
             meta,
             fulltext,
+            fulltext_fields,
+            fulltext_terms,
             aggs,
             fields: vec![],
             sql_mode,
@@ -560,6 +565,7 @@ impl Sql {
             query_context: req_query.query_context.clone(),
             uses_zo_fn: req_query.uses_zo_fn,
             query_fn,
+            profile: req_query.profile,
         };
 
         // calculate all needs fields
@@ -679,6 +685,41 @@ fn generate_histogram_interval(time_range: Option<(i64, i64)>, num: u16) -> Stri
     "1 second".to_string()
 }
 
+/// Rewrites the `histogram` calls of the select list which have no interval,
+/// or a number of buckets, to use the interval generated for the time range,
+/// so that the query has the same buckets when it searches a part of the time
+/// range.
+pub(crate) fn fix_histogram_interval(sql: &str, time_range: (i64, i64)) -> String {
+    let re_histogram = Regex::new(r"(?i)histogram\(([^\)]*)\)").unwrap();
+    let from_pos = match sql.to_lowercase().find(" from ") {
+        Some(pos) => pos,
+        None => return sql.to_string(),
+    };
+    let mut select_str = sql[0..from_pos].to_string();
+    for cap in re_histogram.captures_iter(&sql[0..from_pos]) {
+        let attrs = cap
+            .get(1)
+            .unwrap()
+            .as_str()
+            .split(',')
+            .map(|v| v.trim().trim_matches(|v| v == '\'' || v == '"'))
+            .collect::<Vec<&str>>();
+        let field = attrs.first().unwrap();
+        let interval = match attrs.get(1) {
+            Some(v) => match v.parse::<u16>() {
+                Ok(v) => generate_histogram_interval(Some(time_range), v),
+                Err(_) => continue,
+            },
+            None => generate_histogram_interval(Some(time_range), 0),
+        };
+        select_str = select_str.replace(
+            cap.get(0).unwrap().as_str(),
+            &format!("histogram({field}, '{interval}')"),
+        );
+    }
+    format!("{select_str}{}", &sql[from_pos..])
+}
+
 fn split_sql_token_unwrap_brace(s: &str) -> Vec<String> {
     if s.is_empty() {
         return vec![];
@@ -689,6 +730,59 @@ fn split_sql_token_unwrap_brace(s: &str) -> Vec<String> {
     split_sql_token(s)
 }
 
+/// Returns the fields `match_all` searches in: the string fields which are
+/// full-text search fields of the stream, or all string fields if
+/// `ZO_FEATURE_FULLTEXT_ON_ALL_FIELDS` is enabled.
+pub(crate) fn get_fulltext_fields(schema_fields: &[Field], fts_fields: &[String]) -> Vec<String> {
+    let match_all_fields = if !fts_fields.is_empty() {
+        fts_fields.iter().map(|v| v.to_lowercase()).collect()
+    } else {
+        crate::common::stream::SQL_FULL_TEXT_SEARCH_FIELDS
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<String>()
+    };
+    schema_fields
+        .iter()
+        .filter(|field| {
+            (CONFIG.common.feature_fulltext_on_all_fields
+                || match_all_fields.contains(&field.name().to_lowercase()))
+                && field.data_type().eq(&DataType::Utf8)
+                && !field.name().starts_with('@')
+        })
+        .map(|field| field.name().to_string())
+        .collect()
+}
+
+/// Collects the terms of the `match_all` calls which are only combined with
+/// `AND`, so that every matching record contains each of them.
+fn required_fulltext_terms(expr: &SqlExpr, terms: &mut Vec<String>) {
+    match expr {
+        SqlExpr::BinaryOp {
+            left,
+            op: BinaryOperator::And,
+            right,
+        } => {
+            required_fulltext_terms(left, terms);
+            required_fulltext_terms(right, terms);
+        }
+        SqlExpr::Nested(expr) => required_fulltext_terms(expr, terms),
+        SqlExpr::Function(f) => {
+            let name = f.name.to_string().to_lowercase();
+            if name != "match_all" && name != "match_all_ignore_case" {
+                return;
+            }
+            if let Some(FunctionArg::Unnamed(FunctionArgExpr::Expr(SqlExpr::Value(
+                Value::SingleQuotedString(term),
+            )))) = f.args.first()
+            {
+                terms.push(term.to_string());
+            }
+        }
+        _ => {}
+    }
+}
+
 fn split_sql_token(text: &str) -> Vec<String> {
     let mut tokens = Vec::new();
     let text_chars = text.chars().collect::<Vec<char>>();
@@ -773,6 +867,7 @@ mod tests {
             query_context: None,
             uses_zo_fn: false,
             query_fn: None,
+            profile: false,
         };
 
         let req: crate::meta::search::Request = crate::meta::search::Request {
@@ -856,6 +951,7 @@ mod tests {
                 query_context: None,
                 uses_zo_fn: false,
                 query_fn: None,
+                profile: false,
             };
             let req: crate::meta::search::Request = crate::meta::search::Request {
                 query: query.clone(),
@@ -942,6 +1038,7 @@ mod tests {
                 query_context: None,
                 uses_zo_fn: false,
                 query_fn: None,
+                profile: false,
             };
             let req: crate::meta::search::Request = crate::meta::search::Request {
                 query: query.clone(),
@@ -973,4 +1070,49 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_required_fulltext_terms() {
+        let tests = [
+            ("select * from t where match_all('foo')", vec!["foo"]),
+            (
+                "select * from t where match_all('foo') and (code = 200 and match_all_ignore_case('bar'))",
+                vec!["foo", "bar"],
+            ),
+            ("select * from t where match_all('foo') or match_all('bar')", vec![]),
+            ("select * from t where not match_all('foo')", vec![]),
+            ("select * from t where code = 200", vec![]),
+        ];
+        for (sql, expected) in tests {
+            let meta = MetaSql::new(sql).unwrap();
+            let mut terms = Vec::new();
+            required_fulltext_terms(meta.selection.as_ref().unwrap(), &mut terms);
+            assert_eq!(terms, expected, "sql: {sql}");
+        }
+    }
+
+    #[test]
+    fn test_fix_histogram_interval() {
+        let time_range = (1680307200000000, 1680307200000000 + 15 * 60 * 1000000);
+        assert_eq!(
+            fix_histogram_interval(
+                "SELECT histogram(_timestamp) AS key, COUNT(*) AS num FROM t GROUP BY key",
+                time_range
+            ),
+            "SELECT histogram(_timestamp, '5 second') AS key, COUNT(*) AS num FROM t GROUP BY key"
+        );
+        assert_eq!(
+            fix_histogram_interval(
+                "SELECT histogram(_timestamp, 100) AS key FROM t GROUP BY key",
+                time_range
+            ),
+            "SELECT histogram(_timestamp, '9 second') AS key FROM t GROUP BY key"
+        );
+        let sql = "SELECT histogram(_timestamp, '1 minute') AS key FROM t GROUP BY key";
+        assert_eq!(fix_histogram_interval(sql, time_range), sql);
+        assert_eq!(
+            fix_histogram_interval("SELECT * FROM t", time_range),
+            "SELECT * FROM t"
+        );
+    }
 }
@@ -0,0 +1,425 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The JSON model of the Jaeger query HTTP API, which the Jaeger UI and the
+//! Grafana Jaeger data source use, and the conversion of stored spans to it.
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::query::{
+    i64_field, str_field, END_TIME, OPERATION_NAME, PARENT_SPAN_ID, SERVICE_NAME, SPAN_ID,
+    SPAN_KIND, START_TIME, TRACE_ID,
+};
+use super::span_metrics::{STATUS_ERROR, STATUS_UNSET};
+use crate::common::json;
+use crate::infra::config::CONFIG;
+
+/// Columns of a span which aren't attributes.
+const SPAN_COLUMNS: [&str; 9] = [
+    TRACE_ID,
+    SPAN_ID,
+    SPAN_KIND,
+    SPAN_STATUS,
+    OPERATION_NAME,
+    START_TIME,
+    END_TIME,
+    "duration",
+    "flags",
+];
+const SPAN_STATUS: &str = "span_status";
+const EVENTS: &str = "events";
+const REFERENCE_PREFIX: &str = "reference_";
+const SERVICE_PREFIX: &str = "service_";
+
+#[derive(Debug, Serialize)]
+pub struct Response<T: Serialize> {
+    pub data: T,
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+    pub errors: Option<Vec<ResponseError>>,
+}
+
+impl<T: Serialize> Response<T> {
+    pub fn new(data: T, total: usize) -> Self {
+        Response {
+            data,
+            total,
+            limit: 0,
+            offset: 0,
+            errors: None,
+        }
+    }
+}
+
+impl<T: Serialize + Default> Response<T> {
+    pub fn error(code: u16, msg: String) -> Self {
+        Response {
+            data: T::default(),
+            total: 0,
+            limit: 0,
+            offset: 0,
+            errors: Some(vec![ResponseError { code, msg }]),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseError {
+    pub code: u16,
+    pub msg: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    pub name: String,
+    pub span_kind: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trace {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    pub spans: Vec<Span>,
+    pub processes: BTreeMap<String, Process>,
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Span {
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+    pub flags: u64,
+    pub operation_name: String,
+    pub references: Vec<Reference>,
+    /// Microseconds.
+    pub start_time: i64,
+    /// Microseconds.
+    pub duration: i64,
+    pub tags: Vec<KeyValue>,
+    pub logs: Vec<Log>,
+    #[serde(rename = "processID")]
+    pub process_id: String,
+    pub warnings: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reference {
+    pub ref_type: String,
+    #[serde(rename = "traceID")]
+    pub trace_id: String,
+    #[serde(rename = "spanID")]
+    pub span_id: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Process {
+    pub service_name: String,
+    pub tags: Vec<KeyValue>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Log {
+    /// Microseconds.
+    pub timestamp: i64,
+    pub fields: Vec<KeyValue>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct KeyValue {
+    pub key: String,
+    #[serde(rename = "type")]
+    pub value_type: &'static str,
+    pub value: json::Value,
+}
+
+impl KeyValue {
+    fn new(key: &str, value: &json::Value) -> Option<Self> {
+        let value_type = match value {
+            json::Value::Null => return None,
+            json::Value::Bool(_) => "bool",
+            json::Value::Number(v) if v.is_f64() => "float64",
+            json::Value::Number(_) => "int64",
+            json::Value::String(_) => "string",
+            _ => {
+                return Some(KeyValue {
+                    key: key.to_string(),
+                    value_type: "string",
+                    value: json::Value::String(value.to_string()),
+                })
+            }
+        };
+        Some(KeyValue {
+            key: key.to_string(),
+            value_type,
+            value: value.clone(),
+        })
+    }
+}
+
+/// Returns the Jaeger name of an OTLP span kind.
+pub fn span_kind_name(span_kind: &str) -> &str {
+    match span_kind {
+        "1" => "internal",
+        "2" => "server",
+        "3" => "client",
+        "4" => "producer",
+        "5" => "consumer",
+        _ => "unspecified",
+    }
+}
+
+/// Converts the stored spans of a trace. A process is a service, with its
+/// resource attributes as tags.
+pub fn trace(trace_id: &str, spans: &[json::Map<String, json::Value>]) -> Trace {
+    let mut processes = BTreeMap::new();
+    let mut process_ids: BTreeMap<&str, String> = BTreeMap::new();
+    let mut jaeger_spans = Vec::with_capacity(spans.len());
+    for span in spans {
+        let service_name = str_field(span, SERVICE_NAME);
+        let process_id = match process_ids.get(service_name) {
+            Some(process_id) => process_id.clone(),
+            None => {
+                let process_id = format!("p{}", process_ids.len() + 1);
+                process_ids.insert(service_name, process_id.clone());
+                processes.insert(
+                    process_id.clone(),
+                    Process {
+                        service_name: service_name.to_string(),
+                        tags: span
+                            .iter()
+                            .filter(|(k, _)| k.as_str() != SERVICE_NAME)
+                            .filter_map(|(k, v)| KeyValue::new(k.strip_prefix(SERVICE_PREFIX)?, v))
+                            .collect(),
+                    },
+                );
+                process_id
+            }
+        };
+        jaeger_spans.push(self::span(span, process_id));
+    }
+    Trace {
+        trace_id: trace_id.to_string(),
+        spans: jaeger_spans,
+        processes,
+        warnings: None,
+    }
+}
+
+fn span(span: &json::Map<String, json::Value>, process_id: String) -> Span {
+    let trace_id = str_field(span, TRACE_ID).to_string();
+    let parent_span_id = str_field(span, PARENT_SPAN_ID);
+    let references = if parent_span_id.is_empty() {
+        vec![]
+    } else {
+        vec![Reference {
+            ref_type: "CHILD_OF".to_string(),
+            trace_id: trace_id.clone(),
+            span_id: parent_span_id.to_string(),
+        }]
+    };
+    let mut tags = vec![KeyValue {
+        key: "span.kind".to_string(),
+        value_type: "string",
+        value: json::Value::String(span_kind_name(str_field(span, SPAN_KIND)).to_string()),
+    }];
+    // the tags by which Jaeger shows the status of a span
+    match str_field(span, SPAN_STATUS) {
+        "" | STATUS_UNSET => {}
+        status => {
+            tags.push(KeyValue {
+                key: "otel.status_code".to_string(),
+                value_type: "string",
+                value: json::Value::String(status.to_string()),
+            });
+            if status == STATUS_ERROR {
+                tags.push(KeyValue {
+                    key: "error".to_string(),
+                    value_type: "bool",
+                    value: json::Value::Bool(true),
+                });
+            }
+        }
+    }
+    tags.extend(
+        span.iter()
+            .filter(|(k, _)| {
+                !SPAN_COLUMNS.contains(&k.as_str())
+                    && k.as_str() != EVENTS
+                    && k.as_str() != SERVICE_NAME
+                    && *k != &CONFIG.common.column_timestamp
+                    && !k.starts_with(REFERENCE_PREFIX)
+                    && !k.starts_with(SERVICE_PREFIX)
+            })
+            .filter_map(|(k, v)| KeyValue::new(k, v)),
+    );
+    let start_time = i64_field(span, START_TIME);
+    Span {
+        trace_id,
+        span_id: str_field(span, SPAN_ID).to_string(),
+        flags: span.get("flags").and_then(|v| v.as_u64()).unwrap_or(1),
+        operation_name: str_field(span, OPERATION_NAME).to_string(),
+        references,
+        start_time: start_time / 1000,
+        duration: (i64_field(span, END_TIME) - start_time) / 1000,
+        tags,
+        logs: logs(str_field(span, EVENTS)),
+        process_id,
+        warnings: None,
+    }
+}
+
+/// Converts the span events, stored as a JSON array, to logs.
+fn logs(events: &str) -> Vec<Log> {
+    let events: Vec<json::Map<String, json::Value>> = json::from_str(events).unwrap_or_default();
+    events
+        .into_iter()
+        .map(|event| {
+            let timestamp = i64_field(&event, "_timestamp") / 1000;
+            let fields = event
+                .iter()
+                .filter(|(k, _)| k.as_str() != "_timestamp")
+                .filter_map(|(k, v)| {
+                    if k == "name" {
+                        KeyValue::new("event", v)
+                    } else {
+                        KeyValue::new(k, v)
+                    }
+                })
+                .collect();
+            Log { timestamp, fields }
+        })
+        .collect()
+}
+
+/// Parses a Go duration, as `minDuration` in the Jaeger API, e.g. `1.5s` or
+/// `1m30s`, to microseconds.
+pub fn parse_duration(s: &str) -> Option<i64> {
+    let mut total = 0.0;
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let num_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let value: f64 = rest[..num_end].parse().ok()?;
+        rest = &rest[num_end..];
+        let unit_end = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let micros = match &rest[..unit_end] {
+            "ns" => 0.001,
+            "us" | "µs" => 1.0,
+            "ms" => 1_000.0,
+            "s" => 1_000_000.0,
+            "m" => 60_000_000.0,
+            "h" => 3_600_000_000.0,
+            _ => return None,
+        };
+        total += value * micros;
+        rest = &rest[unit_end..];
+    }
+    Some(total as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::json::json;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1.5s"), Some(1_500_000));
+        assert_eq!(parse_duration("1m30s"), Some(90_000_000));
+        assert_eq!(parse_duration("100ms"), Some(100_000));
+        assert_eq!(parse_duration("250us"), Some(250));
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn test_trace() {
+        let span = json!({
+            "trace_id": "abc",
+            "span_id": "02",
+            "reference_parent_span_id": "01",
+            "reference_parent_trace_id": "abc",
+            "reference_ref_type": "ChildOf",
+            "service_name": "checkout",
+            "service_host_name": "node-1",
+            "operation_name": "GET /cart",
+            "span_kind": "2",
+            "span_status": "ERROR",
+            "start_time": 1_000_000,
+            "end_time": 3_500_000,
+            "duration": 2,
+            "flags": 1,
+            "http_status_code": 200,
+            "events": r#"[{"name":"retry","_timestamp":2000000,"attempt":2}]"#,
+            "_timestamp": 1000,
+        });
+        let trace = trace("abc", &[span.as_object().unwrap().clone()]);
+        assert_eq!(trace.processes["p1"].service_name, "checkout");
+        assert_eq!(
+            trace.processes["p1"].tags,
+            vec![KeyValue {
+                key: "host_name".to_string(),
+                value_type: "string",
+                value: json!("node-1"),
+            }]
+        );
+        let span = &trace.spans[0];
+        assert_eq!(span.process_id, "p1");
+        assert_eq!(span.start_time, 1_000);
+        assert_eq!(span.duration, 2_500);
+        assert_eq!(span.references[0].span_id, "01");
+        assert_eq!(
+            span.tags,
+            vec![
+                KeyValue {
+                    key: "span.kind".to_string(),
+                    value_type: "string",
+                    value: json!("server"),
+                },
+                KeyValue {
+                    key: "otel.status_code".to_string(),
+                    value_type: "string",
+                    value: json!("ERROR"),
+                },
+                KeyValue {
+                    key: "error".to_string(),
+                    value_type: "bool",
+                    value: json!(true),
+                },
+                KeyValue {
+                    key: "http_status_code".to_string(),
+                    value_type: "int64",
+                    value: json!(200),
+                },
+            ]
+        );
+        assert_eq!(span.logs[0].timestamp, 2_000);
+        assert_eq!(span.logs[0].fields.len(), 2);
+    }
+}
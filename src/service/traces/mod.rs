@@ -21,7 +21,7 @@ use opentelemetry::trace::{SpanId, TraceId};
 use opentelemetry_proto::tonic::common::v1::AnyValue;
 use opentelemetry_proto::tonic::{
     collector::trace::v1::ExportTraceServiceRequest,
-    collector::trace::v1::ExportTraceServiceResponse,
+    collector::trace::v1::ExportTraceServiceResponse, trace::v1::ResourceSpans,
 };
 use prost::Message;
 use std::fs::OpenOptions;
@@ -46,16 +46,21 @@ use crate::{
 use super::ingestion::{format_stream_name, get_partition_key_record};
 use super::schema::add_stream_schema;
 
+pub mod jaeger;
 pub mod otlp_http;
+pub mod query;
+pub mod span_metrics;
 
 const PARENT_SPAN_ID: &str = "reference.parent_span_id";
 const PARENT_TRACE_ID: &str = "reference.parent_trace_id";
 const REF_TYPE: &str = "reference.ref_type";
 const SERVICE_NAME: &str = "service.name";
 const SERVICE: &str = "service";
+const DEFAULT_STREAM: &str = "default";
 
 pub async fn handle_trace_request(
     org_id: &str,
+    in_stream_name: Option<&str>,
     thread_id: std::sync::Arc<usize>,
     request: ExportTraceServiceRequest,
 ) -> Result<HttpResponse, Error> {
@@ -67,8 +72,102 @@ pub async fn handle_trace_request(
             )),
         );
     }
-    let traces_stream_name = "default";
 
+    let mut stream_spans: AHashMap<String, Vec<ResourceSpans>> = AHashMap::new();
+    for res_span in request.resource_spans {
+        let attributes = match &res_span.resource {
+            Some(resource) => resource_attributes(
+                resource
+                    .attributes
+                    .iter()
+                    .map(|attr| (attr.key.as_str(), get_val(attr.value.clone()))),
+            ),
+            None => AHashMap::new(),
+        };
+        let stream_name = get_stream_name(org_id, in_stream_name, &attributes);
+        stream_spans.entry(stream_name).or_default().push(res_span);
+    }
+
+    // check if we are allowed to ingest
+    if let Some(stream_name) = deleting_stream(org_id, stream_spans.keys()) {
+        return Ok(
+            HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                format!("stream [{stream_name}] is being deleted"),
+            )),
+        );
+    }
+    for (traces_stream_name, res_spans) in stream_spans {
+        ingest_spans(org_id, *thread_id, &traces_stream_name, res_spans).await;
+    }
+
+    let res = ExportTraceServiceResponse {};
+    let mut out = BytesMut::with_capacity(res.encoded_len());
+    res.encode(&mut out).expect("Out of memory");
+
+    Ok(HttpResponse::Ok()
+        .status(http::StatusCode::OK)
+        .content_type("application/x-protobuf")
+        .body(out))
+}
+
+/// Returns the stream of the spans of a resource: the stream named by the
+/// request, or else the stream of the first trace route of the organization
+/// matching the resource attributes, or else `default`.
+pub(crate) fn get_stream_name(
+    org_id: &str,
+    in_stream_name: Option<&str>,
+    resource_attributes: &AHashMap<String, String>,
+) -> String {
+    if let Some(stream_name) = in_stream_name.filter(|v| !v.is_empty()) {
+        return format_stream_name(stream_name);
+    }
+    crate::service::db::organization::get_trace_routes(org_id)
+        .and_then(|routes| {
+            routes
+                .routes
+                .into_iter()
+                .find(|route| route.matches(resource_attributes))
+        })
+        .map(|route| format_stream_name(&route.stream_name))
+        .unwrap_or_else(|| DEFAULT_STREAM.to_string())
+}
+
+/// Returns the first of the streams which is being deleted.
+pub(crate) fn deleting_stream<'a>(
+    org_id: &str,
+    mut stream_names: impl Iterator<Item = &'a String>,
+) -> Option<&'a String> {
+    stream_names.find(|stream_name| {
+        super::db::compact::delete::is_deleting_stream(
+            org_id,
+            stream_name,
+            StreamType::Traces,
+            None,
+        )
+    })
+}
+
+/// Returns the resource attributes with their values as strings, for the
+/// trace routes.
+pub(crate) fn resource_attributes<'a>(
+    attributes: impl Iterator<Item = (&'a str, Value)>,
+) -> AHashMap<String, String> {
+    attributes
+        .filter_map(|(key, value)| match value {
+            Value::Null => None,
+            Value::String(v) => Some((key.to_string(), v)),
+            v => Some((key.to_string(), v.to_string())),
+        })
+        .collect()
+}
+
+async fn ingest_spans(
+    org_id: &str,
+    thread_id: usize,
+    traces_stream_name: &str,
+    res_spans: Vec<ResourceSpans>,
+) {
     let mut trace_meta_coll: AHashMap<String, Vec<json::Map<String, Value>>> = AHashMap::new();
     let mut traces_schema_map: AHashMap<String, Schema> = AHashMap::new();
     let mut stream_alerts_map: AHashMap<String, Vec<Alert>> = AHashMap::new();
@@ -101,7 +200,6 @@ pub async fn handle_trace_request(
     let mut min_ts =
         (Utc::now() + Duration::hours(CONFIG.limit.ingest_allowed_upto)).timestamp_micros();
     let mut service_name: String = traces_stream_name.to_string();
-    let res_spans = request.resource_spans;
     for res_span in res_spans {
         let mut service_att_map: AHashMap<String, Value> = AHashMap::new();
         let resource = res_span.resource.unwrap();
@@ -179,6 +277,10 @@ pub async fn handle_trace_request(
                     trace_id: trace_id.clone(),
                     span_id,
                     span_kind: span.kind.to_string(),
+                    span_status: span_metrics::span_status(
+                        span.status.as_ref().map_or(0, |status| status.code as i64),
+                    )
+                    .to_string(),
                     operation_name: span.name.clone(),
                     start_time,
                     end_time,
@@ -192,6 +294,8 @@ pub async fn handle_trace_request(
                     events: json::to_string(&events).unwrap(),
                 };
 
+                span_metrics::record(org_id, traces_stream_name, &local_val);
+
                 let value: json::Value = json::to_value(local_val).unwrap();
 
                 //JSON Flattening
@@ -233,6 +337,7 @@ pub async fn handle_trace_request(
                                         last_sent_at: 0,
                                         count: 0,
                                         is_ingest_time: true,
+                                        series: Default::default(),
                                     });
                                 }
                             }
@@ -278,7 +383,7 @@ pub async fn handle_trace_request(
             write_buf.put("\n".as_bytes());
         }
         let file = file_lock::get_or_create(
-            *thread_id.as_ref(),
+            thread_id,
             org_id,
             traces_stream_name,
             StreamType::Traces,
@@ -306,7 +411,7 @@ pub async fn handle_trace_request(
         }
     }
 
-    // only one trigger per request, as it updates etcd
+    // only one trigger per stream, as it updates etcd
     if trigger.is_some() {
         let val = trigger.unwrap();
         let mut alerts = stream_alerts_map
@@ -328,17 +433,9 @@ pub async fn handle_trace_request(
             .await;
         }
     }
-    let res = ExportTraceServiceResponse {};
-    let mut out = BytesMut::with_capacity(res.encoded_len());
-    res.encode(&mut out).expect("Out of memory");
-
-    return Ok(HttpResponse::Ok()
-        .status(http::StatusCode::OK)
-        .content_type("application/x-protobuf")
-        .body(out));
 }
 
-fn get_val(attr_val: Option<AnyValue>) -> Value {
+pub(crate) fn get_val(attr_val: Option<AnyValue>) -> Value {
     match attr_val {
         Some(local_val) => match local_val.value {
             Some(val) => match val {
@@ -29,6 +29,7 @@ use crate::meta::alert::{Alert, Trigger};
 use crate::meta::traces::Event;
 use crate::service::ingestion::{format_stream_name, get_partition_key_record};
 use crate::service::schema::{add_stream_schema, stream_schema_exists};
+use crate::service::traces::span_metrics;
 use crate::{
     common::json,
     infra::cluster,
@@ -47,15 +48,17 @@ const SERVICE: &str = "service";
 
 pub async fn traces_proto(
     org_id: &str,
+    in_stream_name: Option<&str>,
     thread_id: std::sync::Arc<usize>,
     body: actix_web::web::Bytes,
 ) -> Result<HttpResponse, Error> {
     let request = ExportTraceServiceRequest::decode(body).expect("Invalid protobuf");
-    super::handle_trace_request(org_id, thread_id, request).await
+    super::handle_trace_request(org_id, in_stream_name, thread_id, request).await
 }
 
 pub async fn traces_json(
     org_id: &str,
+    in_stream_name: Option<&str>,
     thread_id: std::sync::Arc<usize>,
     body: actix_web::web::Bytes,
 ) -> Result<HttpResponse, Error> {
@@ -67,8 +70,71 @@ pub async fn traces_json(
             )),
         );
     }
-    let traces_stream_name = "default";
 
+    let mut stream_spans: AHashMap<String, Vec<Value>> = AHashMap::new();
+    let reader = BufReader::new(body.as_ref());
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut traces: Value = json::from_slice(line.as_bytes()).unwrap();
+        let Some(res_spans) = traces
+            .get_mut("resourceSpans")
+            .and_then(|v| v.as_array_mut()) else {
+            return Ok(
+                HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
+                    http::StatusCode::BAD_REQUEST.into(),
+                    "Bad Request".to_string(),
+                )),
+            );
+        };
+        for res_span in res_spans.drain(..) {
+            let attributes = super::resource_attributes(
+                res_span
+                    .get("resource")
+                    .and_then(|resource| resource.get("attributes"))
+                    .and_then(|attributes| attributes.as_array())
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|attr| {
+                        Some((
+                            attr.get("key")?.as_str()?,
+                            get_val_for_attr(attr.get("value")?.clone()),
+                        ))
+                    }),
+            );
+            let stream_name = super::get_stream_name(org_id, in_stream_name, &attributes);
+            stream_spans.entry(stream_name).or_default().push(res_span);
+        }
+    }
+
+    // check if we are allowed to ingest
+    if let Some(stream_name) = super::deleting_stream(org_id, stream_spans.keys()) {
+        return Ok(
+            HttpResponse::InternalServerError().json(meta::http::HttpResponse::error(
+                http::StatusCode::INTERNAL_SERVER_ERROR.into(),
+                format!("stream [{stream_name}] is being deleted"),
+            )),
+        );
+    }
+    for (traces_stream_name, res_spans) in stream_spans {
+        ingest_spans(org_id, *thread_id, &traces_stream_name, res_spans).await;
+    }
+
+    Ok(HttpResponse::Ok().json(meta::http::HttpResponse::message(
+        http::StatusCode::OK.into(),
+        "request processed".to_string(),
+    )))
+}
+
+async fn ingest_spans(
+    org_id: &str,
+    thread_id: usize,
+    traces_stream_name: &str,
+    res_spans: Vec<Value>,
+) {
     let mut trace_meta_coll: AHashMap<String, Vec<json::Map<String, Value>>> = AHashMap::new();
     let mut stream_alerts_map: AHashMap<String, Vec<Alert>> = AHashMap::new();
     let mut traces_schema_map: AHashMap<String, Schema> = AHashMap::new();
@@ -113,255 +179,214 @@ pub async fn traces_json(
     // End Register Transforms for stream */
 
     let mut service_name: String = traces_stream_name.to_string();
-    let reader = BufReader::new(body.as_ref());
-    for line in reader.lines() {
-        let line = line?;
-        if line.is_empty() {
-            continue;
+    for res_span in res_spans {
+        let mut service_att_map: AHashMap<String, Value> = AHashMap::new();
+        if res_span.get("resource").is_some() {
+            let resource = res_span.get("resource").unwrap().as_object().unwrap();
+            if resource.get("attributes").is_some() {
+                let attributes = resource.get("attributes").unwrap().as_array().unwrap();
+                for res_attr in attributes {
+                    let local_attr = res_attr.as_object().unwrap();
+                    if local_attr
+                        .get("key")
+                        .unwrap()
+                        .as_str()
+                        .unwrap()
+                        .eq(SERVICE_NAME)
+                    {
+                        let loc_service_name =
+                            local_attr.get("value").unwrap().as_object().unwrap();
+                        for item in loc_service_name {
+                            service_name = item.1.as_str().unwrap().to_string();
+                            service_att_map.insert(SERVICE_NAME.to_string(), item.1.clone());
+                        }
+                    } else {
+                        service_att_map.insert(
+                            format!(
+                                "{}.{}",
+                                SERVICE,
+                                local_attr.get("key").unwrap().as_str().unwrap()
+                            ),
+                            get_val_for_attr(local_attr.get("value").unwrap().clone()),
+                        );
+                    }
+                }
+            }
         }
-
-        let traces: Value = json::from_slice(line.as_bytes()).unwrap();
-        if traces.get("resourceSpans").is_some() {
-            let res_spans = traces.get("resourceSpans").unwrap().as_array().unwrap();
-            for res_span in res_spans {
-                let mut service_att_map: AHashMap<String, Value> = AHashMap::new();
-                if res_span.get("resource").is_some() {
-                    let resource = res_span.get("resource").unwrap().as_object().unwrap();
-                    if resource.get("attributes").is_some() {
-                        let attributes = resource.get("attributes").unwrap().as_array().unwrap();
-                        for res_attr in attributes {
-                            let local_attr = res_attr.as_object().unwrap();
-                            if local_attr
-                                .get("key")
+        let scope_resources = res_span.get("scopeSpans");
+        let inst_resources = if scope_resources.is_some() {
+            scope_resources.unwrap().as_array().unwrap()
+        } else {
+            res_span
+                .get("instrumentationLibrarySpans")
+                .unwrap()
+                .as_array()
+                .unwrap()
+        };
+        for inst_span in inst_resources {
+            if inst_span.get("spans").is_some() {
+                let spans = inst_span.get("spans").unwrap().as_array().unwrap();
+                for span in spans {
+                    let span_id: String = span.get("spanId").unwrap().as_str().unwrap().to_string();
+                    let trace_id: String =
+                        span.get("traceId").unwrap().as_str().unwrap().to_string();
+
+                    let mut span_ref = AHashMap::new();
+                    if span.get("parentSpanId").is_some() {
+                        span_ref.insert(
+                            PARENT_SPAN_ID.to_string(),
+                            span.get("parentSpanId")
                                 .unwrap()
                                 .as_str()
                                 .unwrap()
-                                .eq(SERVICE_NAME)
-                            {
-                                let loc_service_name =
-                                    local_attr.get("value").unwrap().as_object().unwrap();
-                                for item in loc_service_name {
-                                    service_name = item.1.as_str().unwrap().to_string();
-                                    service_att_map
-                                        .insert(SERVICE_NAME.to_string(), item.1.clone());
-                                }
-                            } else {
-                                service_att_map.insert(
-                                    format!(
-                                        "{}.{}",
-                                        SERVICE,
-                                        local_attr.get("key").unwrap().as_str().unwrap()
-                                    ),
-                                    get_val_for_attr(local_attr.get("value").unwrap().clone()),
-                                );
-                            }
-                        }
+                                .to_string(),
+                        );
+                        span_ref.insert(PARENT_TRACE_ID.to_string(), trace_id.clone());
+                        span_ref
+                            .insert(REF_TYPE.to_string(), format!("{:?}", SpanRefType::ChildOf));
                     }
-                }
-                let scope_resources = res_span.get("scopeSpans");
-                let inst_resources = if scope_resources.is_some() {
-                    scope_resources.unwrap().as_array().unwrap()
-                } else {
-                    res_span
-                        .get("instrumentationLibrarySpans")
-                        .unwrap()
-                        .as_array()
-                        .unwrap()
-                };
-                for inst_span in inst_resources {
-                    if inst_span.get("spans").is_some() {
-                        let spans = inst_span.get("spans").unwrap().as_array().unwrap();
-                        for span in spans {
-                            let span_id: String =
-                                span.get("spanId").unwrap().as_str().unwrap().to_string();
-                            let trace_id: String =
-                                span.get("traceId").unwrap().as_str().unwrap().to_string();
-
-                            let mut span_ref = AHashMap::new();
-                            if span.get("parentSpanId").is_some() {
-                                span_ref.insert(
-                                    PARENT_SPAN_ID.to_string(),
-                                    span.get("parentSpanId")
-                                        .unwrap()
-                                        .as_str()
-                                        .unwrap()
-                                        .to_string(),
-                                );
-                                span_ref.insert(PARENT_TRACE_ID.to_string(), trace_id.clone());
-                                span_ref.insert(
-                                    REF_TYPE.to_string(),
-                                    format!("{:?}", SpanRefType::ChildOf),
-                                );
-                            }
-
-                            let start_time: u64 =
-                                span.get("startTimeUnixNano").unwrap().as_u64().unwrap();
-                            let end_time: u64 =
-                                span.get("endTimeUnixNano").unwrap().as_u64().unwrap();
-                            let mut span_att_map: AHashMap<String, Value> = AHashMap::new();
-                            let attributes = span.get("attributes").unwrap().as_array().unwrap();
-                            for span_att in attributes {
-                                span_att_map.insert(
-                                    span_att.get("key").unwrap().as_str().unwrap().to_string(),
-                                    get_val_for_attr(span_att.get("value").unwrap().clone()),
-                                );
-                            }
-
-                            let mut events = vec![];
-                            let mut event_att_map: AHashMap<String, Value> = AHashMap::new();
-
-                            let span_events = span.get("events").unwrap().as_array().unwrap();
-                            for event in span_events {
-                                let attributes =
-                                    event.get("attributes").unwrap().as_array().unwrap();
-                                for event_att in attributes {
-                                    event_att_map.insert(
-                                        event_att.get("key").unwrap().as_str().unwrap().to_string(),
-                                        get_val_for_attr(event_att.get("value").unwrap().clone()),
-                                    );
-                                }
-                                events.push(Event {
-                                    name: event.get("name").unwrap().as_str().unwrap().to_string(),
-                                    _timestamp: event
-                                        .get("timeUnixNano")
-                                        .unwrap()
-                                        .as_u64()
-                                        .unwrap(),
-                                    attributes: event_att_map.clone(),
-                                })
-                            }
 
-                            let timestamp = start_time / 1000;
-                            let local_val = Span {
-                                trace_id: trace_id.clone(),
-                                span_id,
-                                span_kind: span.get("kind").unwrap().to_string(),
-                                operation_name: span
-                                    .get("name")
-                                    .unwrap()
-                                    .as_str()
-                                    .unwrap()
-                                    .to_string(),
-                                start_time,
-                                end_time,
-                                duration: (end_time - start_time) / 1000000,
-                                reference: span_ref,
-                                service_name: service_name.clone(),
-                                attributes: span_att_map,
-                                service: service_att_map.clone(),
-                                flags: 1, //TODO add appropriate value
-                                events: json::to_string(&events).unwrap(),
-                            };
-                            if timestamp < min_ts.try_into().unwrap() {
-                                min_ts = timestamp as i64;
-                            }
-
-                            let mut value: json::Value = json::to_value(local_val).unwrap();
-
-                            //JSON Flattening
-                            value = json::flatten_json_and_format_field(&value);
-
-                            /*     // Start row based transform
-                            #[cfg(feature = "zo_functions")]
-                            let mut value = crate::service::ingestion::apply_stream_transform(
-                                &local_tans,
-                                &value,
-                                &lua,
-                                &stream_lua_map,
-                                &stream_vrl_map,
-                                traces_stream_name,
-                                &mut runtime,
-                            );
-                            #[cfg(feature = "zo_functions")]
-                            if value.is_null() || !value.is_object() {
-                                continue;
-                            }
-                            // End row based transform */
+                    let start_time: u64 = span.get("startTimeUnixNano").unwrap().as_u64().unwrap();
+                    let end_time: u64 = span.get("endTimeUnixNano").unwrap().as_u64().unwrap();
+                    let mut span_att_map: AHashMap<String, Value> = AHashMap::new();
+                    let attributes = span.get("attributes").unwrap().as_array().unwrap();
+                    for span_att in attributes {
+                        span_att_map.insert(
+                            span_att.get("key").unwrap().as_str().unwrap().to_string(),
+                            get_val_for_attr(span_att.get("value").unwrap().clone()),
+                        );
+                    }
 
-                            // get json object
-                            let val_map = value.as_object_mut().unwrap();
+                    let mut events = vec![];
+                    let mut event_att_map: AHashMap<String, Value> = AHashMap::new();
 
-                            val_map.insert(
-                                CONFIG.common.column_timestamp.clone(),
-                                json::Value::Number(timestamp.into()),
+                    let span_events = span.get("events").unwrap().as_array().unwrap();
+                    for event in span_events {
+                        let attributes = event.get("attributes").unwrap().as_array().unwrap();
+                        for event_att in attributes {
+                            event_att_map.insert(
+                                event_att.get("key").unwrap().as_str().unwrap().to_string(),
+                                get_val_for_attr(event_att.get("value").unwrap().clone()),
                             );
+                        }
+                        events.push(Event {
+                            name: event.get("name").unwrap().as_str().unwrap().to_string(),
+                            _timestamp: event.get("timeUnixNano").unwrap().as_u64().unwrap(),
+                            attributes: event_att_map.clone(),
+                        })
+                    }
 
-                            let value_str = crate::common::json::to_string(&val_map).unwrap();
-                            // get hour key
-                            let mut hour_key = crate::service::ingestion::get_hour_key(
-                                timestamp.try_into().unwrap(),
-                                partition_keys.clone(),
-                                value.as_object().unwrap().clone(),
-                            );
+                    let timestamp = start_time / 1000;
+                    let local_val = Span {
+                        trace_id: trace_id.clone(),
+                        span_id,
+                        span_kind: span.get("kind").unwrap().to_string(),
+                        span_status: get_span_status(span.get("status")).to_string(),
+                        operation_name: span.get("name").unwrap().as_str().unwrap().to_string(),
+                        start_time,
+                        end_time,
+                        duration: (end_time - start_time) / 1000000,
+                        reference: span_ref,
+                        service_name: service_name.clone(),
+                        attributes: span_att_map,
+                        service: service_att_map.clone(),
+                        flags: 1, //TODO add appropriate value
+                        events: json::to_string(&events).unwrap(),
+                    };
+                    if timestamp < min_ts.try_into().unwrap() {
+                        min_ts = timestamp as i64;
+                    }
 
-                            if !stream_alerts_map.is_empty() {
-                                // Start check for alert trigger
-                                let key = format!(
-                                    "{}/{}/{}",
-                                    &org_id,
-                                    StreamType::Traces,
-                                    traces_stream_name
-                                );
-                                if let Some(alerts) = stream_alerts_map.get(&key) {
-                                    for alert in alerts {
-                                        if alert.is_real_time {
-                                            let set_trigger = meta::alert::Evaluate::evaluate(
-                                                &alert.condition,
-                                                value.as_object().unwrap().clone(),
-                                            );
-                                            if set_trigger {
-                                                trigger = Some(Trigger {
-                                                    timestamp: timestamp.try_into().unwrap(),
-                                                    is_valid: true,
-                                                    alert_name: alert.name.clone(),
-                                                    stream: traces_stream_name.to_string(),
-                                                    org: org_id.to_string(),
-                                                    stream_type: StreamType::Traces,
-                                                    last_sent_at: 0,
-                                                    count: 0,
-                                                    is_ingest_time: true,
-                                                });
-                                            }
-                                        }
+                    span_metrics::record(org_id, traces_stream_name, &local_val);
+
+                    let mut value: json::Value = json::to_value(local_val).unwrap();
+
+                    //JSON Flattening
+                    value = json::flatten_json_and_format_field(&value);
+
+                    /*     // Start row based transform
+                    #[cfg(feature = "zo_functions")]
+                    let mut value = crate::service::ingestion::apply_stream_transform(
+                        &local_tans,
+                        &value,
+                        &lua,
+                        &stream_lua_map,
+                        &stream_vrl_map,
+                        traces_stream_name,
+                        &mut runtime,
+                    );
+                    #[cfg(feature = "zo_functions")]
+                    if value.is_null() || !value.is_object() {
+                        continue;
+                    }
+                    // End row based transform */
+
+                    // get json object
+                    let val_map = value.as_object_mut().unwrap();
+
+                    val_map.insert(
+                        CONFIG.common.column_timestamp.clone(),
+                        json::Value::Number(timestamp.into()),
+                    );
+
+                    let value_str = crate::common::json::to_string(&val_map).unwrap();
+                    // get hour key
+                    let mut hour_key = crate::service::ingestion::get_hour_key(
+                        timestamp.try_into().unwrap(),
+                        partition_keys.clone(),
+                        value.as_object().unwrap().clone(),
+                    );
+
+                    if !stream_alerts_map.is_empty() {
+                        // Start check for alert trigger
+                        let key =
+                            format!("{}/{}/{}", &org_id, StreamType::Traces, traces_stream_name);
+                        if let Some(alerts) = stream_alerts_map.get(&key) {
+                            for alert in alerts {
+                                if alert.is_real_time {
+                                    let set_trigger = meta::alert::Evaluate::evaluate(
+                                        &alert.condition,
+                                        value.as_object().unwrap().clone(),
+                                    );
+                                    if set_trigger {
+                                        trigger = Some(Trigger {
+                                            timestamp: timestamp.try_into().unwrap(),
+                                            is_valid: true,
+                                            alert_name: alert.name.clone(),
+                                            stream: traces_stream_name.to_string(),
+                                            org: org_id.to_string(),
+                                            stream_type: StreamType::Traces,
+                                            last_sent_at: 0,
+                                            count: 0,
+                                            is_ingest_time: true,
+                                            series: Default::default(),
+                                        });
                                     }
                                 }
-                                // End check for alert trigger
                             }
+                        }
+                        // End check for alert trigger
+                    }
 
-                            if partition_keys.is_empty() {
-                                let partition_key =
-                                    format!("service_name={}", format_stream_name(&service_name));
-                                hour_key.push_str(&format!(
-                                    "_{}",
-                                    get_partition_key_record(&partition_key)
-                                ));
-                            }
+                    if partition_keys.is_empty() {
+                        let partition_key =
+                            format!("service_name={}", format_stream_name(&service_name));
+                        hour_key
+                            .push_str(&format!("_{}", get_partition_key_record(&partition_key)));
+                    }
 
-                            let hour_buf = data_buf.entry(hour_key.clone()).or_default();
+                    let hour_buf = data_buf.entry(hour_key.clone()).or_default();
 
-                            hour_buf.push(value_str);
-                            //Trace Metadata
-                            let mut trace_meta = Map::new();
-                            trace_meta.insert(
-                                "trace_id".to_owned(),
-                                json::Value::String(trace_id.clone()),
-                            );
-                            trace_meta.insert("_timestamp".to_owned(), start_time.into());
+                    hour_buf.push(value_str);
+                    //Trace Metadata
+                    let mut trace_meta = Map::new();
+                    trace_meta.insert("trace_id".to_owned(), json::Value::String(trace_id.clone()));
+                    trace_meta.insert("_timestamp".to_owned(), start_time.into());
 
-                            let hour_meta_buf =
-                                trace_meta_coll.entry(hour_key.clone()).or_default();
-                            hour_meta_buf.push(trace_meta);
-                        }
-                    }
+                    let hour_meta_buf = trace_meta_coll.entry(hour_key.clone()).or_default();
+                    hour_meta_buf.push(trace_meta);
                 }
             }
-        } else {
-            return Ok(
-                HttpResponse::BadRequest().json(meta::http::HttpResponse::error(
-                    http::StatusCode::BAD_REQUEST.into(),
-                    "Bad Request".to_string(),
-                )),
-            );
         }
     }
     let mut write_buf = BytesMut::new();
@@ -376,7 +401,7 @@ pub async fn traces_json(
             write_buf.put("\n".as_bytes());
         }
         let file = file_lock::get_or_create(
-            *thread_id.as_ref(),
+            thread_id,
             org_id,
             traces_stream_name,
             StreamType::Traces,
@@ -411,7 +436,7 @@ pub async fn traces_json(
         }
     }
 
-    // only one trigger per request, as it updates etcd
+    // only one trigger per stream, as it updates etcd
     if trigger.is_some() {
         let val = trigger.unwrap();
         let mut alerts = stream_alerts_map
@@ -433,13 +458,20 @@ pub async fn traces_json(
             .await;
         }
     }
+}
 
-    Ok(HttpResponse::Ok().json(meta::http::HttpResponse::message(
-        http::StatusCode::OK.into(),
-        "request processed".to_string(),
-    )))
-
-    //Ok(HttpResponse::Ok().into())
+/// Returns the status of a span, whose code is a number or, as the protobuf
+/// JSON mapping of enums allows, a name.
+fn get_span_status(status: Option<&Value>) -> &'static str {
+    match status.and_then(|status| status.get("code")) {
+        Some(Value::Number(code)) => span_metrics::span_status(code.as_i64().unwrap_or_default()),
+        Some(Value::String(code)) => match code.as_str() {
+            "STATUS_CODE_OK" => span_metrics::STATUS_OK,
+            "STATUS_CODE_ERROR" => span_metrics::STATUS_ERROR,
+            _ => span_metrics::STATUS_UNSET,
+        },
+        _ => span_metrics::STATUS_UNSET,
+    }
 }
 
 fn get_val_for_attr(attr_val: Value) -> Value {
@@ -462,4 +494,13 @@ mod tests {
         let resp = get_val_for_attr(input);
         assert_eq!(resp.as_f64().unwrap(), in_val);
     }
+
+    #[test]
+    fn test_get_span_status() {
+        let status = json!({ "code": 2, "message": "timeout" });
+        assert_eq!(get_span_status(Some(&status)), span_metrics::STATUS_ERROR);
+        let status = json!({ "code": "STATUS_CODE_OK" });
+        assert_eq!(get_span_status(Some(&status)), span_metrics::STATUS_OK);
+        assert_eq!(get_span_status(None), span_metrics::STATUS_UNSET);
+    }
 }
@@ -0,0 +1,604 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Trace queries over a traces stream: a trace by id, the traces matching
+//! service, operation, duration and attribute filters, and the services and
+//! operations seen. They all run as searches of the stream, the spans are
+//! the stored records.
+
+use ahash::{AHashMap, AHashSet};
+use chrono::{Duration, Utc};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::common::json;
+use crate::infra::{
+    config::CONFIG,
+    errors::{Error, ErrorCodes},
+};
+use crate::meta::{
+    search,
+    traces::{Operation, SpanNode, Trace, TraceSearch, TraceSummary},
+    StreamType,
+};
+use crate::service::{db, search as SearchService};
+
+pub(crate) const TRACE_ID: &str = "trace_id";
+pub(crate) const SPAN_ID: &str = "span_id";
+pub(crate) const PARENT_SPAN_ID: &str = "reference_parent_span_id";
+pub(crate) const SERVICE_NAME: &str = "service_name";
+pub(crate) const OPERATION_NAME: &str = "operation_name";
+pub(crate) const SPAN_KIND: &str = "span_kind";
+pub(crate) const START_TIME: &str = "start_time";
+pub(crate) const END_TIME: &str = "end_time";
+
+/// Traces are searched, and services and operations are listed, from the
+/// spans of the last day, unless a time range is given.
+const LOOKBACK_HOURS: i64 = 24;
+const CATALOG_MAX_SIZE: usize = 10000;
+
+/// Returns the spans of a trace, `None` if there are none.
+pub async fn get_trace(
+    org_id: &str,
+    stream_name: &str,
+    trace_id: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Option<Vec<json::Map<String, json::Value>>>, Error> {
+    if trace_id.is_empty() || !trace_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(None);
+    }
+    let mut traces = get_spans(
+        org_id,
+        stream_name,
+        &[trace_id.to_lowercase()],
+        time_range(start_time, end_time),
+    )
+    .await?;
+    Ok(traces.pop().map(|(_, spans)| spans))
+}
+
+/// Returns the spans of the latest traces with a span matching the search, at
+/// most `ZO_QUERY_TRACE_MAX_SIZE` traces.
+pub async fn search_traces(
+    org_id: &str,
+    stream_name: &str,
+    query: &TraceSearch,
+) -> Result<Vec<(String, Vec<json::Map<String, json::Value>>)>, Error> {
+    let fields = stream_fields(org_id, stream_name).await?;
+    let Some(filter) = search_filter(query, &fields) else {
+        return Ok(vec![]);
+    };
+    let size = query.size.min(CONFIG.limit.query_trace_max_size);
+    let sql = format!(
+        "SELECT {TRACE_ID}, MIN({START_TIME}) AS zo_trace_start FROM \"{stream_name}\"{filter} GROUP BY {TRACE_ID} ORDER BY zo_trace_start DESC LIMIT {size}"
+    );
+    let time_range = time_range(query.start_time, query.end_time);
+    let hits = search(org_id, sql, time_range, size).await?;
+    let trace_ids = hits
+        .iter()
+        .filter_map(|hit| hit.get(TRACE_ID).and_then(|v| v.as_str()))
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    // latest first, as found
+    get_spans(org_id, stream_name, &trace_ids, time_range).await
+}
+
+/// Returns the names of the services with spans in the time range.
+pub async fn services(
+    org_id: &str,
+    stream_name: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Vec<String>, Error> {
+    let fields = stream_fields(org_id, stream_name).await?;
+    if !fields.contains(SERVICE_NAME) {
+        return Ok(vec![]);
+    }
+    let sql = format!(
+        "SELECT {SERVICE_NAME} FROM \"{stream_name}\" GROUP BY {SERVICE_NAME} ORDER BY {SERVICE_NAME}"
+    );
+    let hits = search(
+        org_id,
+        sql,
+        time_range(start_time, end_time),
+        CATALOG_MAX_SIZE,
+    )
+    .await?;
+    Ok(hits
+        .iter()
+        .filter_map(|hit| hit.get(SERVICE_NAME).and_then(|v| v.as_str()))
+        .map(|v| v.to_string())
+        .collect())
+}
+
+/// Returns the operations of a service, or of all the services, with spans
+/// in the time range.
+pub async fn operations(
+    org_id: &str,
+    stream_name: &str,
+    service: Option<&str>,
+    start_time: i64,
+    end_time: i64,
+) -> Result<Vec<Operation>, Error> {
+    let fields = stream_fields(org_id, stream_name).await?;
+    if !fields.contains(OPERATION_NAME) {
+        return Ok(vec![]);
+    }
+    let filter = match service {
+        Some(service) => format!(" WHERE {SERVICE_NAME} = {}", quote(service)),
+        None => String::new(),
+    };
+    let sql = format!(
+        "SELECT {OPERATION_NAME}, {SPAN_KIND} FROM \"{stream_name}\"{filter} GROUP BY {OPERATION_NAME}, {SPAN_KIND} ORDER BY {OPERATION_NAME}"
+    );
+    let hits = search(
+        org_id,
+        sql,
+        time_range(start_time, end_time),
+        CATALOG_MAX_SIZE,
+    )
+    .await?;
+    Ok(hits
+        .iter()
+        .map(|hit| Operation {
+            name: str_field(hit, OPERATION_NAME).to_string(),
+            span_kind: str_field(hit, SPAN_KIND).to_string(),
+        })
+        .collect())
+}
+
+/// Builds the span tree of a trace. Spans whose parent isn't in the trace
+/// are roots.
+pub fn build_trace(trace_id: &str, mut spans: Vec<json::Map<String, json::Value>>) -> Trace {
+    spans.sort_by_key(|span| i64_field(span, START_TIME));
+    let start_time = spans
+        .iter()
+        .map(|span| i64_field(span, START_TIME))
+        .min()
+        .unwrap_or_default()
+        / 1000;
+    let end_time = spans
+        .iter()
+        .map(|span| i64_field(span, END_TIME))
+        .max()
+        .unwrap_or_default()
+        / 1000;
+
+    let span_ids: AHashSet<String> = spans
+        .iter()
+        .map(|span| str_field(span, SPAN_ID).to_string())
+        .collect();
+    let mut roots = vec![];
+    let mut children: AHashMap<String, Vec<usize>> = AHashMap::new();
+    for (i, span) in spans.iter().enumerate() {
+        let parent_id = str_field(span, PARENT_SPAN_ID);
+        if span_ids.contains(parent_id) && parent_id != str_field(span, SPAN_ID) {
+            children.entry(parent_id.to_string()).or_default().push(i);
+        } else {
+            roots.push(i);
+        }
+    }
+
+    let mut slots = spans.into_iter().map(Some).collect::<Vec<_>>();
+    let mut tree = roots
+        .into_iter()
+        .filter_map(|i| span_node(i, &mut slots, &children))
+        .collect::<Vec<_>>();
+    // spans in a cycle of parents aren't reachable from a root
+    for i in 0..slots.len() {
+        if let Some(node) = span_node(i, &mut slots, &children) {
+            tree.push(node);
+        }
+    }
+
+    Trace {
+        trace_id: trace_id.to_string(),
+        start_time,
+        end_time,
+        duration: end_time - start_time,
+        spans: tree,
+    }
+}
+
+fn span_node(
+    i: usize,
+    slots: &mut [Option<json::Map<String, json::Value>>],
+    children: &AHashMap<String, Vec<usize>>,
+) -> Option<SpanNode> {
+    let span = slots[i].take()?;
+    let span_children = children
+        .get(str_field(&span, SPAN_ID))
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|child| span_node(*child, slots, children))
+                .collect()
+        })
+        .unwrap_or_default();
+    Some(SpanNode {
+        span,
+        children: span_children,
+    })
+}
+
+pub fn summary(trace_id: &str, spans: &[json::Map<String, json::Value>]) -> TraceSummary {
+    let span_ids: AHashSet<&str> = spans.iter().map(|span| str_field(span, SPAN_ID)).collect();
+    let root = spans
+        .iter()
+        .filter(|span| !span_ids.contains(str_field(span, PARENT_SPAN_ID)))
+        .min_by_key(|span| i64_field(span, START_TIME))
+        .or_else(|| spans.iter().min_by_key(|span| i64_field(span, START_TIME)));
+    let start_time = spans
+        .iter()
+        .map(|span| i64_field(span, START_TIME))
+        .min()
+        .unwrap_or_default();
+    let end_time = spans
+        .iter()
+        .map(|span| i64_field(span, END_TIME))
+        .max()
+        .unwrap_or_default();
+    let services: BTreeSet<&str> = spans
+        .iter()
+        .map(|span| str_field(span, SERVICE_NAME))
+        .collect();
+    TraceSummary {
+        trace_id: trace_id.to_string(),
+        service_name: root
+            .map(|span| str_field(span, SERVICE_NAME).to_string())
+            .unwrap_or_default(),
+        operation_name: root
+            .map(|span| str_field(span, OPERATION_NAME).to_string())
+            .unwrap_or_default(),
+        start_time: start_time / 1000,
+        duration: (end_time - start_time) / 1000,
+        spans: spans.len(),
+        services: services.into_iter().map(|v| v.to_string()).collect(),
+    }
+}
+
+/// Parses span attribute filters, either a JSON object as in the Jaeger API,
+/// or `key=value` pairs separated by spaces.
+pub fn parse_tags(tags: &str) -> Result<HashMap<String, String>, String> {
+    let tags = tags.trim();
+    if tags.is_empty() {
+        return Ok(HashMap::new());
+    }
+    if tags.starts_with('{') {
+        let map: json::Map<String, json::Value> =
+            json::from_str(tags).map_err(|e| format!("Invalid tags: {e}"))?;
+        return Ok(map
+            .into_iter()
+            .map(|(k, v)| match v {
+                json::Value::String(v) => (k, v),
+                v => (k, v.to_string()),
+            })
+            .collect());
+    }
+    tags.split_whitespace()
+        .map(|pair| match pair.split_once('=') {
+            Some((k, v)) if !k.is_empty() => Ok((k.to_string(), v.to_string())),
+            _ => Err(format!("Invalid tag: {pair}, expected key=value")),
+        })
+        .collect()
+}
+
+/// Returns the spans of the traces with spans, in the order of the ids. The
+/// traces are searched at once, and `ZO_QUERY_TRACE_MAX_SPANS` applies to
+/// the spans of all of them.
+async fn get_spans(
+    org_id: &str,
+    stream_name: &str,
+    trace_ids: &[String],
+    time_range: (i64, i64),
+) -> Result<Vec<(String, Vec<json::Map<String, json::Value>>)>, Error> {
+    if trace_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let sql = format!(
+        "SELECT * FROM \"{stream_name}\" WHERE {TRACE_ID} IN ({}) ORDER BY {START_TIME}",
+        trace_ids
+            .iter()
+            .map(|trace_id| quote(trace_id))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let hits = search(org_id, sql, time_range, CONFIG.limit.query_trace_max_spans).await?;
+    Ok(group_spans(trace_ids, hits))
+}
+
+/// Groups the spans by trace, in the order of the ids, without the traces
+/// without spans.
+fn group_spans(
+    trace_ids: &[String],
+    hits: Vec<json::Value>,
+) -> Vec<(String, Vec<json::Map<String, json::Value>>)> {
+    let mut traces: AHashMap<String, Vec<json::Map<String, json::Value>>> = AHashMap::new();
+    for hit in hits {
+        if let json::Value::Object(span) = hit {
+            traces
+                .entry(str_field(&span, TRACE_ID).to_string())
+                .or_default()
+                .push(span);
+        }
+    }
+    trace_ids
+        .iter()
+        .filter_map(|trace_id| {
+            traces
+                .remove(trace_id)
+                .map(|spans| (trace_id.clone(), spans))
+        })
+        .collect()
+}
+
+async fn search(
+    org_id: &str,
+    sql: String,
+    (start_time, end_time): (i64, i64),
+    size: usize,
+) -> Result<Vec<json::Value>, Error> {
+    let req = search::Request {
+        query: search::Query {
+            sql,
+            size,
+            start_time,
+            end_time,
+            sql_mode: "full".to_string(),
+            query_type: "traces".to_string(),
+            ..Default::default()
+        },
+        aggs: HashMap::new(),
+        encoding: search::RequestEncoding::Empty,
+    };
+    Ok(SearchService::search(org_id, StreamType::Traces, &req)
+        .await?
+        .hits)
+}
+
+async fn stream_fields(org_id: &str, stream_name: &str) -> Result<AHashSet<String>, Error> {
+    let schema = db::schema::get(org_id, stream_name, Some(StreamType::Traces))
+        .await
+        .map_err(|e| Error::ErrorCode(ErrorCodes::ServerInternalError(e.to_string())))?;
+    Ok(schema
+        .fields()
+        .iter()
+        .map(|field| field.name().to_string())
+        .collect())
+}
+
+/// Returns the WHERE clause of a search, `None` if it can't match as it
+/// filters on fields the stream doesn't have.
+fn search_filter(query: &TraceSearch, fields: &AHashSet<String>) -> Option<String> {
+    if !fields.contains(TRACE_ID) {
+        return None;
+    }
+    let mut conditions = vec![];
+    if let Some(service) = &query.service {
+        conditions.push(format!("{SERVICE_NAME} = {}", quote(service)));
+    }
+    if let Some(operation) = &query.operation {
+        conditions.push(format!("{OPERATION_NAME} = {}", quote(operation)));
+    }
+    if let Some(min_duration) = query.min_duration {
+        conditions.push(format!(
+            "{END_TIME} - {START_TIME} >= {}",
+            min_duration * 1000
+        ));
+    }
+    if let Some(max_duration) = query.max_duration {
+        conditions.push(format!(
+            "{END_TIME} - {START_TIME} <= {}",
+            max_duration * 1000
+        ));
+    }
+    let mut tags = query.tags.iter().collect::<Vec<_>>();
+    tags.sort();
+    for (key, value) in tags {
+        let column = column_name(key);
+        if !fields.contains(&column) {
+            return None;
+        }
+        conditions.push(format!("\"{column}\" = {}", quote(value)));
+    }
+    if conditions.is_empty() {
+        Some(String::new())
+    } else {
+        Some(format!(" WHERE {}", conditions.join(" AND ")))
+    }
+}
+
+/// Returns the column of a span attribute, as named by
+/// `json::flatten_json_and_format_field` during ingestion.
+fn column_name(key: &str) -> String {
+    key.chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_lowercase().next().unwrap()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Returns the time range of a search, the missing end is now and the missing
+/// start is `LOOKBACK_HOURS` before the end.
+fn time_range(start_time: i64, end_time: i64) -> (i64, i64) {
+    let end_time = if end_time > 0 {
+        end_time
+    } else {
+        Utc::now().timestamp_micros()
+    };
+    let start_time = if start_time > 0 {
+        start_time
+    } else {
+        end_time - Duration::hours(LOOKBACK_HOURS).num_microseconds().unwrap()
+    };
+    (start_time, end_time)
+}
+
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+pub(crate) fn str_field<'a>(span: &'a json::Map<String, json::Value>, key: &str) -> &'a str {
+    span.get(key).and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+pub(crate) fn i64_field(span: &json::Map<String, json::Value>, key: &str) -> i64 {
+    span.get(key).and_then(|v| v.as_i64()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::json::json;
+
+    fn span(span_id: &str, parent_id: &str, start_time: i64) -> json::Map<String, json::Value> {
+        let mut span = json!({
+            "trace_id": "abc",
+            "span_id": span_id,
+            "service_name": "checkout",
+            "operation_name": format!("op-{span_id}"),
+            "start_time": start_time,
+            "end_time": start_time + 1_000_000,
+        });
+        if !parent_id.is_empty() {
+            span[PARENT_SPAN_ID] = json!(parent_id);
+        }
+        span.as_object().unwrap().clone()
+    }
+
+    fn ids(nodes: &[SpanNode]) -> Vec<String> {
+        nodes
+            .iter()
+            .map(|node| {
+                let id = str_field(&node.span, SPAN_ID);
+                if node.children.is_empty() {
+                    id.to_string()
+                } else {
+                    format!("{id}({})", ids(&node.children).join(","))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_build_trace() {
+        let trace = build_trace(
+            "abc",
+            vec![
+                span("c", "a", 3_000_000),
+                span("b", "a", 2_000_000),
+                span("a", "", 1_000_000),
+                span("d", "b", 2_500_000),
+                // parent not in the trace
+                span("e", "z", 5_000_000),
+            ],
+        );
+        assert_eq!(ids(&trace.spans), vec!["a(b(d),c)", "e"]);
+        assert_eq!(trace.start_time, 1_000);
+        assert_eq!(trace.end_time, 6_000);
+        assert_eq!(trace.duration, 5_000);
+    }
+
+    #[test]
+    fn test_build_trace_cycle() {
+        let trace = build_trace(
+            "abc",
+            vec![span("a", "b", 1_000_000), span("b", "a", 2_000_000)],
+        );
+        assert_eq!(ids(&trace.spans), vec!["a(b)"]);
+    }
+
+    #[test]
+    fn test_time_range() {
+        assert_eq!(time_range(1_000, 2_000), (1_000, 2_000));
+        let lookback = Duration::hours(LOOKBACK_HOURS).num_microseconds().unwrap();
+        assert_eq!(time_range(0, lookback * 2), (lookback, lookback * 2));
+        let (start_time, end_time) = time_range(0, 0);
+        assert_eq!(end_time - start_time, lookback);
+    }
+
+    #[test]
+    fn test_group_spans() {
+        let mut other = span("x", "", 1_500_000);
+        other[TRACE_ID] = json!("def");
+        let hits = vec![
+            json!(span("a", "", 1_000_000)),
+            json!(other),
+            json!(span("b", "a", 2_000_000)),
+        ];
+        let ids = ["def", "abc", "missing"].map(|v| v.to_string());
+        let traces = group_spans(&ids, hits);
+        assert_eq!(
+            traces
+                .iter()
+                .map(|(trace_id, spans)| (trace_id.as_str(), spans.len()))
+                .collect::<Vec<_>>(),
+            vec![("def", 1), ("abc", 2)]
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let spans = vec![span("b", "a", 2_000_000), span("a", "", 1_000_000)];
+        let summary = summary("abc", &spans);
+        assert_eq!(summary.operation_name, "op-a");
+        assert_eq!(summary.service_name, "checkout");
+        assert_eq!(summary.start_time, 1_000);
+        assert_eq!(summary.duration, 2_000);
+        assert_eq!(summary.spans, 2);
+        assert_eq!(summary.services, vec!["checkout"]);
+    }
+
+    #[test]
+    fn test_search_filter() {
+        let fields: AHashSet<String> = ["trace_id", "service_name", "http_method"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        let mut query = TraceSearch {
+            service: Some("o'neil".to_string()),
+            min_duration: Some(1500),
+            tags: [("http.method".to_string(), "GET".to_string())].into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            search_filter(&query, &fields).unwrap(),
+            " WHERE service_name = 'o''neil' AND end_time - start_time >= 1500000 AND \"http_method\" = 'GET'"
+        );
+        query.tags.insert("missing".to_string(), "1".to_string());
+        assert_eq!(search_filter(&query, &fields), None);
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        assert_eq!(
+            parse_tags(r#"{"http.status_code": 500, "error": "true"}"#).unwrap(),
+            HashMap::from([
+                ("http.status_code".to_string(), "500".to_string()),
+                ("error".to_string(), "true".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse_tags("http.method=GET  error=true").unwrap(),
+            HashMap::from([
+                ("http.method".to_string(), "GET".to_string()),
+                ("error".to_string(), "true".to_string()),
+            ])
+        );
+        assert!(parse_tags("error").is_err());
+    }
+}
@@ -0,0 +1,671 @@
+// Copyright 2022 Zinc Labs Inc. and Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Span metrics and the service graph, generated from the ingested spans.
+//!
+//! An ingester counts the spans it ingests by stream, service, operation,
+//! span kind and status, with a histogram of their durations, and pairs the
+//! client span of a call with the server span whose parent it is, each pair
+//! being a request on an edge of the service graph. The counters are
+//! cumulative and are written every `ZO_TRACES_SPAN_METRICS_INTERVAL`
+//! seconds to the metrics streams of the organization, with the ingester as
+//! the `instance` label, so e.g. the request rate of the services is
+//! `sum by (service) (rate(traces_spanmetrics_calls_total[5m]))`. The counters
+//! of a series without spans for `ZO_TRACES_SPAN_METRICS_IDLE` seconds are
+//! dropped, and restart from zero if it has spans again. Once an organization
+//! has `ZO_TRACES_SPAN_METRICS_MAX_SERIES` span metrics series, the spans
+//! which would add a series are counted with the `__overflow__` operation
+//! instead, e.g. when the span names contain ids.
+//!
+//! The client and server spans of a request are only paired when the same
+//! ingester ingests both, e.g. when the collector sends the spans of a trace
+//! to the same ingester, as the `loadbalancing` exporter by trace id does.
+//! The requests whose spans land on different ingesters are missing from the
+//! service graph.
+//!
+//! It is disabled by default, `ZO_TRACES_SPAN_METRICS_ENABLED` enables it.
+
+use actix_web::web;
+use ahash::AHashMap;
+use chrono::Utc;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use opentelemetry_proto::tonic::{
+    collector::metrics::v1::ExportMetricsServiceRequest,
+    common::v1::{any_value, AnyValue, KeyValue},
+    metrics::v1::{
+        metric::Data, number_data_point, AggregationTemporality, Histogram as HistogramData,
+        HistogramDataPoint, InstrumentationLibraryMetrics, Metric, NumberDataPoint,
+        ResourceMetrics, Sum,
+    },
+    resource::v1::Resource,
+};
+use std::collections::BTreeMap;
+use tokio::time;
+
+use super::jaeger::span_kind_name;
+use super::PARENT_SPAN_ID;
+use crate::infra::{cluster, config::CONFIG, errors};
+use crate::meta::traces::{ServiceGraph, ServiceGraphEdge, Span};
+use crate::service::promql::{
+    self,
+    value::{Labels, Value},
+};
+
+pub const CALLS_TOTAL: &str = "traces_spanmetrics_calls_total";
+pub const LATENCY: &str = "traces_spanmetrics_latency";
+pub const REQUEST_TOTAL: &str = "traces_service_graph_request_total";
+pub const REQUEST_FAILED_TOTAL: &str = "traces_service_graph_request_failed_total";
+pub const REQUEST_SERVER_SECONDS: &str = "traces_service_graph_request_server_seconds";
+
+/// The operation of the spans counted beyond the series limit.
+pub const OVERFLOW_OPERATION: &str = "__overflow__";
+
+pub(crate) const STATUS_UNSET: &str = "UNSET";
+pub(crate) const STATUS_OK: &str = "OK";
+pub(crate) const STATUS_ERROR: &str = "ERROR";
+
+const SPAN_KIND_SERVER: &str = "2";
+const SPAN_KIND_CLIENT: &str = "3";
+const SPAN_KIND_PRODUCER: &str = "4";
+const SPAN_KIND_CONSUMER: &str = "5";
+
+/// Upper bounds of the duration buckets, in seconds.
+const BUCKETS: [f64; 14] = [
+    0.002, 0.004, 0.008, 0.016, 0.032, 0.064, 0.128, 0.256, 0.512, 1.024, 2.048, 4.096, 8.192,
+    16.384,
+];
+
+static METRICS: Lazy<DashMap<String, OrgMetrics>> = Lazy::new(DashMap::new);
+
+/// Returns the status of a span from its OTLP status code.
+pub(crate) fn span_status(code: i64) -> &'static str {
+    match code {
+        1 => STATUS_OK,
+        2 => STATUS_ERROR,
+        _ => STATUS_UNSET,
+    }
+}
+
+/// Counts an ingested span.
+pub(crate) fn record(org_id: &str, stream_name: &str, span: &Span) {
+    if !CONFIG.common.traces_span_metrics_enabled {
+        return;
+    }
+    METRICS.entry(org_id.to_string()).or_default().record(
+        stream_name,
+        span,
+        CONFIG.limit.traces_span_metrics_max_series,
+        Utc::now().timestamp_micros(),
+    );
+}
+
+pub async fn run() -> Result<(), anyhow::Error> {
+    if !cluster::is_ingester(&cluster::LOCAL_NODE_ROLE) {
+        return Ok(()); // not an ingester, no need to init job
+    }
+
+    if !CONFIG.common.traces_span_metrics_enabled {
+        return Ok(());
+    }
+
+    let mut interval = time::interval(time::Duration::from_secs(
+        CONFIG.limit.traces_span_metrics_interval,
+    ));
+    interval.tick().await; // trigger the first run
+    loop {
+        interval.tick().await;
+        flush().await;
+    }
+}
+
+/// Writes the counters of all the organizations, and drops the client and
+/// server spans which waited too long for the other span of their request,
+/// and the counters of the idle series.
+async fn flush() {
+    let now = Utc::now().timestamp_micros();
+    let expire_before = now - CONFIG.limit.traces_service_graph_wait * 1_000_000;
+    let idle_before = now - CONFIG.limit.traces_span_metrics_idle * 1_000_000;
+    let mut requests = Vec::new();
+    for mut entry in METRICS.iter_mut() {
+        entry.expire(expire_before, idle_before);
+        if let Some(request) = entry.to_request(now as u64 * 1000) {
+            requests.push((entry.key().clone(), request));
+        }
+    }
+    METRICS.retain(|_, metrics| !metrics.is_empty());
+    for (org_id, request) in requests {
+        if let Err(e) =
+            crate::service::metrics::otlp::ingest(&org_id, web::Data::new(0), request).await
+        {
+            log::error!("Error writing span metrics of org {}: {}", org_id, e);
+        }
+    }
+}
+
+/// Returns the edges of the service graph in a time range, in microseconds,
+/// default the last hour, from the request counters of all the ingesters.
+pub async fn service_graph(
+    org_id: &str,
+    start_time: i64,
+    end_time: i64,
+) -> Result<ServiceGraph, errors::Error> {
+    let end_time = if end_time > 0 {
+        end_time
+    } else {
+        Utc::now().timestamp_micros()
+    };
+    let start_time = if start_time > 0 {
+        start_time
+    } else {
+        end_time - 3_600_000_000
+    };
+    let range = std::cmp::max((end_time - start_time) / 1_000_000, 1);
+
+    let mut edges: BTreeMap<(String, String), ServiceGraphEdge> = BTreeMap::new();
+    for metric_name in [REQUEST_TOTAL, REQUEST_FAILED_TOTAL] {
+        let req = promql::MetricsQueryRequest {
+            query: format!("sum by (client, server) (increase({metric_name}[{range}s]))"),
+            start: end_time,
+            end: end_time,
+            step: 300_000_000, // 5m
+        };
+        let Value::Vector(series) = promql::search::search(org_id, &req).await? else {
+            continue;
+        };
+        for series in series {
+            let client = label(&series.labels, "client");
+            let server = label(&series.labels, "server");
+            let edge = edges
+                .entry((client.clone(), server.clone()))
+                .or_insert_with(|| ServiceGraphEdge {
+                    client,
+                    server,
+                    requests: 0.0,
+                    failed: 0.0,
+                });
+            if metric_name == REQUEST_TOTAL {
+                edge.requests = series.sample.value;
+            } else {
+                edge.failed = series.sample.value;
+            }
+        }
+    }
+    Ok(ServiceGraph {
+        edges: edges
+            .into_values()
+            .filter(|edge| edge.requests > 0.0)
+            .collect(),
+    })
+}
+
+fn label(labels: &Labels, name: &str) -> String {
+    labels
+        .iter()
+        .find(|label| label.name == name)
+        .map(|label| label.value.clone())
+        .unwrap_or_default()
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Histogram {
+    /// Observations by bucket, the last bucket is `+Inf`.
+    bucket_counts: Vec<u64>,
+    count: u64,
+    /// Seconds.
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if self.bucket_counts.is_empty() {
+            self.bucket_counts = vec![0; BUCKETS.len() + 1];
+        }
+        let i = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.bucket_counts[i] += 1;
+        self.count += 1;
+        self.sum += seconds;
+    }
+
+    fn data_point(&self, attributes: Vec<KeyValue>, time_unix_nano: u64) -> HistogramDataPoint {
+        HistogramDataPoint {
+            attributes,
+            time_unix_nano,
+            count: self.count,
+            sum: self.sum.into(),
+            bucket_counts: self.bucket_counts.clone(),
+            explicit_bounds: BUCKETS.to_vec(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct CallKey {
+    stream_name: String,
+    service: String,
+    operation: String,
+    span_kind: String,
+    status: String,
+}
+
+#[derive(Debug, Default)]
+struct Call {
+    latency: Histogram,
+    /// Microseconds.
+    updated_at: i64,
+}
+
+#[derive(Debug, Default)]
+struct Edge {
+    requests: u64,
+    failed: u64,
+    server_seconds: Histogram,
+    /// Microseconds.
+    updated_at: i64,
+}
+
+/// The client or server span of a request, waiting for the other one.
+#[derive(Debug)]
+struct CallSpan {
+    service: String,
+    failed: bool,
+    /// Seconds.
+    duration: f64,
+    /// Microseconds.
+    seen_at: i64,
+}
+
+#[derive(Debug, Default)]
+struct OrgMetrics {
+    calls: AHashMap<CallKey, Call>,
+    /// By client and server service.
+    edges: AHashMap<(String, String), Edge>,
+    /// Client spans by trace id and span id.
+    clients: AHashMap<(String, String), CallSpan>,
+    /// Server spans by trace id and parent span id.
+    servers: AHashMap<(String, String), CallSpan>,
+}
+
+impl OrgMetrics {
+    /// Counts a span, with the `OVERFLOW_OPERATION` if its series would be
+    /// beyond `max_series`, 0 is unlimited.
+    fn record(&mut self, stream_name: &str, span: &Span, max_series: usize, now: i64) {
+        let duration = span.end_time.saturating_sub(span.start_time) as f64 / 1e9;
+        let mut key = CallKey {
+            stream_name: stream_name.to_string(),
+            service: span.service_name.clone(),
+            operation: span.operation_name.clone(),
+            span_kind: span.span_kind.clone(),
+            status: span.span_status.clone(),
+        };
+        if max_series > 0 && self.calls.len() >= max_series && !self.calls.contains_key(&key) {
+            key.operation = OVERFLOW_OPERATION.to_string();
+        }
+        let call = self.calls.entry(key).or_default();
+        call.latency.observe(duration);
+        call.updated_at = now;
+
+        let call_span = CallSpan {
+            service: span.service_name.clone(),
+            failed: span.span_status == STATUS_ERROR,
+            duration,
+            seen_at: now,
+        };
+        match span.span_kind.as_str() {
+            SPAN_KIND_CLIENT | SPAN_KIND_PRODUCER => {
+                let key = (span.trace_id.clone(), span.span_id.clone());
+                match self.servers.remove(&key) {
+                    Some(server) => self.add_request(call_span, server, now),
+                    None => {
+                        self.clients.insert(key, call_span);
+                    }
+                }
+            }
+            SPAN_KIND_SERVER | SPAN_KIND_CONSUMER => {
+                let Some(parent_span_id) = span.reference.get(PARENT_SPAN_ID) else {
+                    return; // a root span isn't called by a service
+                };
+                let key = (span.trace_id.clone(), parent_span_id.clone());
+                match self.clients.remove(&key) {
+                    Some(client) => self.add_request(client, call_span, now),
+                    None => {
+                        self.servers.insert(key, call_span);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn add_request(&mut self, client: CallSpan, server: CallSpan, now: i64) {
+        let edge = self
+            .edges
+            .entry((client.service, server.service))
+            .or_default();
+        edge.requests += 1;
+        if client.failed || server.failed {
+            edge.failed += 1;
+        }
+        edge.server_seconds.observe(server.duration);
+        edge.updated_at = now;
+    }
+
+    /// Drops the client and server spans seen before `before`, and the
+    /// counters not updated since `idle_before`.
+    fn expire(&mut self, before: i64, idle_before: i64) {
+        self.clients.retain(|_, span| span.seen_at >= before);
+        self.servers.retain(|_, span| span.seen_at >= before);
+        self.calls.retain(|_, call| call.updated_at >= idle_before);
+        self.edges.retain(|_, edge| edge.updated_at >= idle_before);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+            && self.edges.is_empty()
+            && self.clients.is_empty()
+            && self.servers.is_empty()
+    }
+
+    fn to_request(&self, time_unix_nano: u64) -> Option<ExportMetricsServiceRequest> {
+        if self.calls.is_empty() && self.edges.is_empty() {
+            return None;
+        }
+
+        let mut calls = Vec::with_capacity(self.calls.len());
+        let mut latencies = Vec::with_capacity(self.calls.len());
+        for (key, call) in self.calls.iter() {
+            let attributes = vec![
+                attribute("stream", &key.stream_name),
+                attribute("service", &key.service),
+                attribute("span_name", &key.operation),
+                attribute("span_kind", span_kind_name(&key.span_kind)),
+                attribute("status_code", &key.status),
+            ];
+            calls.push(number_data_point(
+                attributes.clone(),
+                call.latency.count,
+                time_unix_nano,
+            ));
+            latencies.push(call.latency.data_point(attributes, time_unix_nano));
+        }
+
+        let mut requests = Vec::with_capacity(self.edges.len());
+        let mut failed = Vec::with_capacity(self.edges.len());
+        let mut server_seconds = Vec::with_capacity(self.edges.len());
+        for ((client, server), edge) in self.edges.iter() {
+            let attributes = vec![attribute("client", client), attribute("server", server)];
+            requests.push(number_data_point(
+                attributes.clone(),
+                edge.requests,
+                time_unix_nano,
+            ));
+            failed.push(number_data_point(
+                attributes.clone(),
+                edge.failed,
+                time_unix_nano,
+            ));
+            server_seconds.push(edge.server_seconds.data_point(attributes, time_unix_nano));
+        }
+
+        let metrics = [
+            counter(
+                CALLS_TOTAL,
+                "Spans by service, operation, kind and status",
+                calls,
+            ),
+            histogram(LATENCY, "Durations of the spans", latencies),
+            counter(REQUEST_TOTAL, "Requests between services", requests),
+            counter(
+                REQUEST_FAILED_TOTAL,
+                "Failed requests between services",
+                failed,
+            ),
+            histogram(
+                REQUEST_SERVER_SECONDS,
+                "Durations of the server spans of the requests between services",
+                server_seconds,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        Some(ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(Resource {
+                    attributes: vec![attribute(
+                        "service.instance.id",
+                        &CONFIG.common.instance_name,
+                    )],
+                    ..Default::default()
+                }),
+                instrumentation_library_metrics: vec![InstrumentationLibraryMetrics {
+                    metrics,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        })
+    }
+}
+
+fn attribute(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+fn number_data_point(
+    attributes: Vec<KeyValue>,
+    value: u64,
+    time_unix_nano: u64,
+) -> NumberDataPoint {
+    NumberDataPoint {
+        attributes,
+        time_unix_nano,
+        value: Some(number_data_point::Value::AsInt(value as i64)),
+        ..Default::default()
+    }
+}
+
+fn counter(name: &str, description: &str, data_points: Vec<NumberDataPoint>) -> Option<Metric> {
+    if data_points.is_empty() {
+        return None;
+    }
+    Some(Metric {
+        name: name.to_string(),
+        description: description.to_string(),
+        data: Some(Data::Sum(Sum {
+            data_points,
+            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+            is_monotonic: true,
+        })),
+        ..Default::default()
+    })
+}
+
+fn histogram(
+    name: &str,
+    description: &str,
+    data_points: Vec<HistogramDataPoint>,
+) -> Option<Metric> {
+    if data_points.is_empty() {
+        return None;
+    }
+    Some(Metric {
+        name: name.to_string(),
+        description: description.to_string(),
+        unit: "s".to_string(),
+        data: Some(Data::Histogram(HistogramData {
+            data_points,
+            aggregation_temporality: AggregationTemporality::Cumulative as i32,
+        })),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(span_id: &str, parent_span_id: &str, service: &str, kind: &str, status: &str) -> Span {
+        let mut reference = AHashMap::new();
+        if !parent_span_id.is_empty() {
+            reference.insert(PARENT_SPAN_ID.to_string(), parent_span_id.to_string());
+        }
+        Span {
+            trace_id: "abc".to_string(),
+            span_id: span_id.to_string(),
+            flags: 1,
+            span_kind: kind.to_string(),
+            span_status: status.to_string(),
+            operation_name: "GET /cart".to_string(),
+            start_time: 1_000_000_000,
+            end_time: 1_050_000_000,
+            duration: 50,
+            reference,
+            service_name: service.to_string(),
+            attributes: AHashMap::new(),
+            service: AHashMap::new(),
+            events: "[]".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_span_status() {
+        assert_eq!(span_status(0), STATUS_UNSET);
+        assert_eq!(span_status(1), STATUS_OK);
+        assert_eq!(span_status(2), STATUS_ERROR);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.001);
+        histogram.observe(0.05);
+        histogram.observe(60.0);
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.bucket_counts.len(), BUCKETS.len() + 1);
+        assert_eq!(histogram.bucket_counts[0], 1);
+        assert_eq!(histogram.bucket_counts[5], 1);
+        assert_eq!(histogram.bucket_counts[BUCKETS.len()], 1);
+        assert!((histogram.sum - 60.051).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut metrics = OrgMetrics::default();
+        // the server span is ingested before the client span of its request
+        metrics.record(
+            "default",
+            &span("02", "01", "cart", SPAN_KIND_SERVER, STATUS_ERROR),
+            0,
+            100,
+        );
+        assert_eq!(metrics.servers.len(), 1);
+        metrics.record(
+            "default",
+            &span("01", "00", "frontend", SPAN_KIND_CLIENT, STATUS_UNSET),
+            0,
+            200,
+        );
+        assert!(metrics.clients.is_empty() && metrics.servers.is_empty());
+        let edge = &metrics.edges[&("frontend".to_string(), "cart".to_string())];
+        assert_eq!(edge.requests, 1);
+        assert_eq!(edge.failed, 1);
+        assert_eq!(edge.server_seconds.count, 1);
+        assert_eq!(metrics.calls.len(), 2);
+        let key = CallKey {
+            stream_name: "default".to_string(),
+            service: "cart".to_string(),
+            operation: "GET /cart".to_string(),
+            span_kind: SPAN_KIND_SERVER.to_string(),
+            status: STATUS_ERROR.to_string(),
+        };
+        assert!((metrics.calls[&key].latency.sum - 0.05).abs() < 1e-9);
+
+        // a client span without a server span expires
+        metrics.record(
+            "default",
+            &span("03", "00", "frontend", SPAN_KIND_CLIENT, STATUS_OK),
+            0,
+            300,
+        );
+        metrics.expire(250, 0);
+        assert_eq!(metrics.clients.len(), 1);
+        metrics.expire(301, 0);
+        assert!(metrics.clients.is_empty());
+
+        let request = metrics.to_request(1_000).unwrap();
+        let names = request.resource_metrics[0].instrumentation_library_metrics[0]
+            .metrics
+            .iter()
+            .map(|metric| metric.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                CALLS_TOTAL,
+                LATENCY,
+                REQUEST_TOTAL,
+                REQUEST_FAILED_TOTAL,
+                REQUEST_SERVER_SECONDS
+            ]
+        );
+        assert!(OrgMetrics::default().to_request(1_000).is_none());
+
+        // the counters of the series without spans are dropped
+        metrics.expire(301, 250);
+        assert_eq!(metrics.calls.len(), 1);
+        assert!(metrics.edges.is_empty());
+        metrics.expire(301, 301);
+        assert!(metrics.is_empty());
+    }
+
+    #[test]
+    fn test_record_max_series() {
+        let mut metrics = OrgMetrics::default();
+        let mut cart = span("01", "", "cart", SPAN_KIND_SERVER, STATUS_OK);
+        metrics.record("default", &cart, 2, 100);
+        cart.operation_name = "GET /cart/1".to_string();
+        metrics.record("default", &cart, 2, 100);
+        // beyond the limit, new operations are folded, known ones aren't
+        cart.operation_name = "GET /cart/2".to_string();
+        metrics.record("default", &cart, 2, 100);
+        cart.operation_name = "GET /cart/3".to_string();
+        metrics.record("default", &cart, 2, 100);
+        cart.operation_name = "GET /cart".to_string();
+        metrics.record("default", &cart, 2, 100);
+        let mut operations = metrics
+            .calls
+            .iter()
+            .map(|(key, call)| (key.operation.as_str(), call.latency.count))
+            .collect::<Vec<_>>();
+        operations.sort();
+        assert_eq!(
+            operations,
+            vec![
+                ("GET /cart", 2),
+                ("GET /cart/1", 1),
+                (OVERFLOW_OPERATION, 2)
+            ]
+        );
+    }
+}
@@ -67,6 +67,7 @@ mod tests {
                 stream_type: crate::meta::StreamType::Logs,
                 count: 0,
                 is_ingest_time: false,
+                series: Default::default(),
             },
         )
         .await;